@@ -27,6 +27,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             println!("Turn 1 response: {:?}", msg);
         }
     }
+    println!("Session ID: {:?}", client.session_id());
 
     // Send another query (Turn 2) - Context is maintained
     println!("Sending turn 2...");
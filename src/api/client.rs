@@ -1,9 +1,19 @@
 //! Interactive client for bidirectional conversations.
 
+use std::sync::Arc;
+use std::time::Duration;
+
 use futures::stream::BoxStream;
+use futures::StreamExt;
 
-use crate::core::{ClaudeAgent, ControlResponse};
-use crate::types::{ClaudeAgentError, ClaudeAgentOptions, Message};
+use crate::core::{
+    ClaudeAgent, ControlResponse, QueryHandle, SessionStats, Timestamped, ToolCallLogger,
+};
+use crate::mcp::{McpServer, McpServerManager};
+use crate::transport::{CliVersion, SubprocessTransport};
+use crate::types::config::McpServerConfig;
+use crate::types::message::{ContentBlock, ResultMessage, ToolResultContent};
+use crate::types::{ClaudeAgentError, ClaudeAgentOptions, Message, QueryOverrides};
 
 /// Client for bidirectional, interactive conversations with Claude Code.
 ///
@@ -44,6 +54,21 @@ impl ClaudeAgentClient {
         Self { agent: ClaudeAgent::new(opts) }
     }
 
+    /// Create an offline client that replays `responses` for every query.
+    ///
+    /// Wires a [`MockTransport`](crate::transport::MockTransport), so no CLI is
+    /// discovered or spawned, and pins the session id to `"test-session"` so runs
+    /// are deterministic.
+    pub fn for_testing(responses: Vec<serde_json::Value>) -> Self {
+        let options = ClaudeAgentOptions {
+            session_id: Some("test-session".to_string()),
+            ..Default::default()
+        };
+        let mut client = Self::new(Some(options));
+        client.set_transport(Box::new(crate::transport::MockTransport::new(responses)));
+        client
+    }
+
     /// Set the transport implementation.
     ///
     /// Useful for testing with mock transports or using custom transport implementations.
@@ -51,11 +76,99 @@ impl ClaudeAgentClient {
         self.agent.set_transport(transport);
     }
 
+    /// Throttle queries with `limiter`; see [`ClaudeAgent::set_rate_limiter`].
+    ///
+    /// Depending on [`RateLimitConfig::wait`](crate::mcp::RateLimitConfig::wait),
+    /// `query()` either waits for a permit or fails with
+    /// `ClaudeAgentError::RateLimited` when the limit is exhausted.
+    pub fn with_rate_limiter(mut self, limiter: crate::mcp::RateLimiter) -> Self {
+        self.agent.set_rate_limiter(Some(limiter));
+        self
+    }
+
+    /// Log every tool call in query streams; see [`ClaudeAgent::set_tool_call_logger`].
+    pub fn with_tool_call_logger(mut self, logger: Arc<ToolCallLogger>) -> Self {
+        self.agent.set_tool_call_logger(Some(logger));
+        self
+    }
+
+    /// Send a prompt made of content blocks, such as text plus an image.
+    ///
+    /// See [`ClaudeAgent::query_blocks`].
+    pub async fn query_blocks(
+        &mut self,
+        blocks: Vec<ContentBlock>,
+    ) -> Result<BoxStream<'_, Result<Message, ClaudeAgentError>>, ClaudeAgentError> {
+        self.agent.query_blocks(blocks).await
+    }
+
+    /// Write a prompt without reading the reply, for pipelined input.
+    ///
+    /// See [`ClaudeAgent::send`]; read replies with [`responses`](Self::responses).
+    pub async fn send(&self, prompt: &str) -> Result<(), ClaudeAgentError> {
+        self.agent.send(prompt).await
+    }
+
+    /// Messages from the CLI across turns; see [`ClaudeAgent::responses`].
+    pub async fn responses(
+        &self,
+    ) -> Result<BoxStream<'static, Result<Message, ClaudeAgentError>>, ClaudeAgentError> {
+        self.agent.responses().await
+    }
+
+    /// Answer a tool call Claude made with `content`.
+    ///
+    /// Streams from [`query`](Self::query) borrow the client, so read the turn
+    /// through [`query_handle`](Self::query_handle) to call this mid-turn. See
+    /// [`ClaudeAgent::submit_tool_result`].
+    pub async fn submit_tool_result(
+        &self,
+        tool_use_id: &str,
+        content: ToolResultContent,
+        is_error: bool,
+    ) -> Result<(), ClaudeAgentError> {
+        self.agent.submit_tool_result(tool_use_id, content, is_error).await
+    }
+
+    /// Host `server` in this process and expose its tools to the CLI.
+    ///
+    /// Call before [`connect`](Self::connect); see [`ClaudeAgent::add_mcp_server`].
+    pub async fn add_mcp_server(
+        &mut self,
+        server: Box<dyn McpServer>,
+    ) -> Result<(), ClaudeAgentError> {
+        self.agent.add_mcp_server(server).await
+    }
+
+    /// Connect to an external MCP server and declare it to the CLI.
+    ///
+    /// Call before [`connect`](Self::connect); see
+    /// [`ClaudeAgent::add_mcp_server_from_config`].
+    pub async fn add_mcp_server_from_config(
+        &mut self,
+        name: impl Into<String>,
+        config: McpServerConfig,
+    ) -> Result<(), ClaudeAgentError> {
+        self.agent.add_mcp_server_from_config(name, config).await
+    }
+
+    /// Get the MCP manager holding the servers added to this client.
+    pub fn mcp_manager(&self) -> &McpServerManager {
+        self.agent.mcp_manager()
+    }
+
     /// Connect to Claude Code.
     pub async fn connect(&mut self) -> Result<(), ClaudeAgentError> {
         self.agent.connect(None).await
     }
 
+    /// Every JSON value the CLI emits, including control messages.
+    ///
+    /// See [`ClaudeAgent::raw_messages`].
+    pub fn raw_messages(&self) -> BoxStream<'static, Result<serde_json::Value, ClaudeAgentError>> {
+        self.agent.raw_messages()
+    }
+
     /// Send a query and receive a stream of messages.
     pub async fn query(
         &mut self,
@@ -64,6 +177,107 @@ impl ClaudeAgentClient {
         self.agent.query(prompt).await
     }
 
+    /// Send a query and receive a stream that does not borrow the client.
+    ///
+    /// See [`ClaudeAgent::query_handle`].
+    pub async fn query_handle(&mut self, prompt: &str) -> Result<QueryHandle, ClaudeAgentError> {
+        self.agent.query_handle(prompt).await
+    }
+
+    /// Send a query whose messages carry their receive time; see
+    /// [`ClaudeAgent::query_timestamped`].
+    pub async fn query_timestamped(
+        &mut self,
+        prompt: &str,
+    ) -> Result<BoxStream<'_, Result<Timestamped<Message>, ClaudeAgentError>>, ClaudeAgentError>
+    {
+        self.agent.query_timestamped(prompt).await
+    }
+
+    /// Send a query with options overridden for this turn; see [`ClaudeAgent::query_with`].
+    pub async fn query_with(
+        &mut self,
+        prompt: &str,
+        overrides: QueryOverrides,
+    ) -> Result<BoxStream<'_, Result<Message, ClaudeAgentError>>, ClaudeAgentError> {
+        self.agent.query_with(prompt, overrides).await
+    }
+
+    /// Send a query whose stream fails if it goes quiet for longer than `idle_timeout`.
+    ///
+    /// The timer restarts with every message. When it expires the turn is
+    /// interrupted and read to its end, then the stream yields
+    /// `ClaudeAgentError::Timeout` and ends.
+    pub async fn query_with_timeout(
+        &mut self,
+        prompt: &str,
+        idle_timeout: Duration,
+    ) -> Result<BoxStream<'_, Result<Message, ClaudeAgentError>>, ClaudeAgentError> {
+        let mut turn = self.agent.query_turn(prompt).await?;
+        let stream = async_stream::stream! {
+            loop {
+                match tokio::time::timeout(idle_timeout, turn.next()).await {
+                    Ok(Some(item)) => yield item.map(|message| message.value),
+                    Ok(None) => break,
+                    Err(_) => {
+                        turn.stop().await;
+                        yield Err(ClaudeAgentError::Timeout(idle_timeout));
+                        break;
+                    },
+                }
+            }
+        };
+        Ok(Box::pin(stream))
+    }
+
+    /// Send a query and stream only the assistant's text, ending at the result.
+    ///
+    /// See [`text_stream`](crate::api::text_stream) for the one-shot equivalent.
+    pub async fn text_stream(
+        &mut self,
+        prompt: &str,
+    ) -> Result<BoxStream<'_, Result<String, ClaudeAgentError>>, ClaudeAgentError> {
+        let messages = self.agent.query(prompt).await?;
+        Ok(crate::api::query::assistant_text(messages))
+    }
+
+    /// Send a query and return the concatenated assistant text of the turn.
+    ///
+    /// Fails with the first stream error, with `ClaudeAgentError::Process` if the
+    /// CLI reports an error event, or if the result message is flagged as an error.
+    /// A turn that fails before its result is interrupted and read to its end
+    /// before the error is returned.
+    pub async fn collect_text(&mut self, prompt: &str) -> Result<String, ClaudeAgentError> {
+        let mut turn = self.agent.query_turn(prompt).await?;
+        let mut text = String::new();
+        while let Some(message) = turn.next().await {
+            let error = match message.map(|message| message.value) {
+                Ok(Message::Result(result)) if result.is_error => {
+                    return Err(ClaudeAgentError::Process(format!(
+                        "Query failed ({}): {}",
+                        result.subtype,
+                        result.result.as_deref().unwrap_or("no details")
+                    )));
+                },
+                Ok(Message::Result(_)) => break,
+                Ok(Message::Error(event)) => ClaudeAgentError::Process(format!(
+                    "{}: {}",
+                    event.error.error_type, event.error.message
+                )),
+                Ok(message) => {
+                    if let Some(chunk) = message.text() {
+                        text.push_str(&chunk);
+                    }
+                    continue;
+                },
+                Err(e) => e,
+            };
+            turn.stop().await;
+            return Err(error);
+        }
+        Ok(text)
+    }
+
     /// Send interrupt signal.
     pub async fn interrupt(&self) -> Result<ControlResponse, ClaudeAgentError> {
         self.agent.interrupt().await
@@ -95,14 +309,68 @@ impl ClaudeAgentClient {
         self.agent.set_model(model).await
     }
 
+    /// Check whether the client is connected and the transport is still alive.
+    ///
+    /// Useful for deciding whether a long-lived client needs to be recreated.
+    pub async fn is_connected(&self) -> bool {
+        self.agent.is_connected().await
+    }
+
+    /// Respawn the CLI after it died, resuming the conversation.
+    ///
+    /// See [`ClaudeAgent::reconnect`].
+    pub async fn reconnect(&mut self) -> Result<(), ClaudeAgentError> {
+        self.agent.reconnect().await
+    }
+
     /// Disconnect from Claude Code.
     pub async fn disconnect(&mut self) -> Result<(), ClaudeAgentError> {
         self.agent.disconnect().await
     }
 
-    /// Get the current session ID.
-    pub fn session_id(&self) -> Option<&str> {
-        self.agent.current_session().map(|s| s.id.as_str())
+    /// The CLI version detected on connect; see [`ClaudeAgent::cli_version`].
+    pub async fn cli_version(&self) -> Option<CliVersion> {
+        self.agent.cli_version().await
+    }
+
+    /// The CLI command [`connect`](Self::connect) would run, without spawning it.
+    ///
+    /// See [`SubprocessTransport::command_preview`]. Ignores any transport set
+    /// with [`set_transport`](Self::set_transport).
+    pub fn command_preview(&self) -> Result<Vec<String>, ClaudeAgentError> {
+        SubprocessTransport::new(None, self.agent.options().clone()).command_preview()
+    }
+
+    /// Stop the CLI immediately instead of shutting it down gracefully.
+    ///
+    /// See [`ClaudeAgent::kill`].
+    pub async fn kill(&mut self) -> Result<(), ClaudeAgentError> {
+        self.agent.kill().await
+    }
+
+    /// Cumulative turn counts, timings and cost from the results seen so far.
+    pub fn session_stats(&self) -> SessionStats {
+        self.agent.session_stats()
+    }
+
+    /// The latest result message; see [`ClaudeAgent::last_result`].
+    pub fn last_result(&self) -> Option<ResultMessage> {
+        self.agent.last_result()
+    }
+
+    /// Why the model stopped in the latest turn, e.g. `"end_turn"` or `"tool_use"`.
+    ///
+    /// See [`ClaudeAgent::last_stop_reason`].
+    pub fn last_stop_reason(&self) -> Option<String> {
+        self.agent.last_stop_reason()
+    }
+
+    /// Get the session id reported by the CLI.
+    ///
+    /// `None` until the CLI sends its init or result message. The id can be
+    /// passed as `ClaudeAgentOptions::resume` to continue the session later.
+    pub fn session_id(&self) -> Option<String> {
+        self.agent.cli_session_id()
     }
 
     /// Wrap a serializable value into a successful `ControlResponse`.
@@ -120,6 +388,9 @@ impl ClaudeAgentClient {
     /// This undoes all file modifications made since the given user message
     /// was sent, restoring the filesystem to its prior state.
     ///
+    /// Requires `ClaudeAgentOptions::enable_file_checkpointing`; without it the
+    /// CLI records no checkpoints to rewind to.
+    ///
     /// # Arguments
     ///
     /// * `user_message_id` - The ID of the user message to rewind to.
@@ -320,12 +591,12 @@ mod tests {
     // --- Connect / disconnect tests ---
 
     #[tokio::test]
-    async fn connect_with_mock_transport_sets_session_id() {
+    async fn connect_leaves_session_id_unset_until_the_cli_reports_one() {
         let mut client = ClaudeAgentClient::new(None);
         client.set_transport(Box::new(MockTransport::new(vec![])));
-        assert!(client.session_id().is_none());
         client.connect().await.unwrap();
-        assert!(client.session_id().is_some());
+        // The local session id cannot be resumed, so it is not reported
+        assert!(client.session_id().is_none());
     }
 
     #[tokio::test]
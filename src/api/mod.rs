@@ -3,7 +3,13 @@
 pub mod client;
 pub mod query;
 pub mod sessions;
+pub mod subagents;
+pub mod thinking;
+pub mod tool_calls;
 
 pub use client::ClaudeAgentClient;
-pub use query::query;
+pub use query::{ask, query, text_stream};
 pub use sessions::{find_claude_cli, SessionInfo};
+pub use subagents::{with_subagent_lineage, SubagentMessage};
+pub use thinking::split_thinking;
+pub use tool_calls::{ToolCallCorrelator, ToolCallEvent};
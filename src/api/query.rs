@@ -1,7 +1,9 @@
 //! Query function for one-shot interactions.
 
 use futures::stream::BoxStream;
+use futures::StreamExt;
 
+use crate::api::ClaudeAgentClient;
 use crate::core::ClaudeAgent;
 use crate::types::{ClaudeAgentError, ClaudeAgentOptions, Message};
 
@@ -57,6 +59,102 @@ pub async fn query(
     Ok(Box::pin(futures::stream::iter(messages)))
 }
 
+/// Query Claude Code and stream only the assistant's text.
+///
+/// Yields the text of each assistant message (see [`Message::text`]) and ends
+/// when the turn's result message arrives. Errors are yielded in place; the
+/// agent disconnects once the stream finishes.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use claude_agent::api::text_stream;
+/// use futures::StreamExt;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let mut stream = text_stream("Write a haiku", None).await.unwrap();
+///     while let Some(text) = stream.next().await {
+///         println!("{}", text.unwrap());
+///     }
+/// }
+/// ```
+pub async fn text_stream(
+    prompt: &str,
+    options: Option<ClaudeAgentOptions>,
+) -> Result<BoxStream<'static, Result<String, ClaudeAgentError>>, ClaudeAgentError> {
+    let mut agent = ClaudeAgent::new(options.unwrap_or_default());
+    agent.connect(None).await?;
+
+    let prompt = prompt.to_string();
+    let stream = async_stream::stream! {
+        {
+            match agent.query(&prompt).await {
+                Ok(messages) => {
+                    let mut texts = assistant_text(messages);
+                    while let Some(text) = texts.next().await {
+                        yield text;
+                    }
+                },
+                Err(e) => yield Err(e),
+            }
+        }
+        if let Err(e) = agent.disconnect().await {
+            tracing::warn!(error = %e, "Failed to disconnect after text stream");
+        }
+    };
+    Ok(Box::pin(stream))
+}
+
+/// Ask Claude Code a one-shot question and return just the answer.
+///
+/// Runs a single query, joins the assistant text up to the result message
+/// and disconnects. Fails like [`ClaudeAgentClient::collect_text`], including
+/// when the result message is flagged as an error.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use claude_agent::api::ask;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let answer = ask("What is 2+2?", None).await.unwrap();
+///     println!("{}", answer);
+/// }
+/// ```
+pub async fn ask(
+    prompt: &str,
+    options: Option<ClaudeAgentOptions>,
+) -> Result<String, ClaudeAgentError> {
+    let mut client = ClaudeAgentClient::new(options);
+    client.connect().await?;
+    let answer = client.collect_text(prompt).await;
+    if let Err(e) = client.disconnect().await {
+        tracing::warn!(error = %e, "Failed to disconnect after ask");
+    }
+    answer
+}
+
+/// Map a message stream to its assistant text, ending at the result message.
+pub(crate) fn assistant_text<'a>(
+    mut messages: BoxStream<'a, Result<Message, ClaudeAgentError>>,
+) -> BoxStream<'a, Result<String, ClaudeAgentError>> {
+    Box::pin(async_stream::stream! {
+        while let Some(item) = messages.next().await {
+            match item {
+                Ok(Message::Result(_)) => break,
+                Ok(message) => {
+                    if let Some(text) = message.text() {
+                        yield Ok(text);
+                    }
+                },
+                Err(e) => yield Err(e),
+            }
+        }
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
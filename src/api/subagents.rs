@@ -0,0 +1,88 @@
+//! Attribute messages to the subagents that produced them.
+
+use std::collections::HashMap;
+
+use futures::stream::BoxStream;
+use futures::StreamExt;
+
+use crate::types::message::ContentBlock;
+use crate::types::{ClaudeAgentError, Message};
+
+/// A message tagged with the chain of subagents it came from.
+#[derive(Debug, Clone)]
+pub struct SubagentMessage {
+    pub message: Message,
+    /// Ids of the tool uses that spawned each enclosing subagent, outermost
+    /// first. Empty for messages from the main agent.
+    pub lineage: Vec<String>,
+}
+
+impl SubagentMessage {
+    /// Whether the message came from the main agent rather than a subagent.
+    pub fn is_root(&self) -> bool {
+        self.lineage.is_empty()
+    }
+
+    /// The tool use that spawned the innermost subagent, if any.
+    pub fn parent_tool_use_id(&self) -> Option<&str> {
+        self.lineage.last().map(String::as_str)
+    }
+
+    /// How deeply nested the producing subagent is; `0` for the main agent.
+    pub fn depth(&self) -> usize {
+        self.lineage.len()
+    }
+}
+
+/// Tag each message with its subagent lineage.
+///
+/// Messages from a subagent carry the `parent_tool_use_id` of the tool use
+/// that spawned it. The lineage follows those ids back to the main agent by
+/// remembering which agent issued each tool use seen so far. Errors are
+/// passed through unchanged.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use claude_agent::api::{with_subagent_lineage, ClaudeAgentClient};
+/// use futures::StreamExt;
+///
+/// # async fn example(client: &mut ClaudeAgentClient) -> Result<(), Box<dyn std::error::Error>> {
+/// let mut messages = with_subagent_lineage(client.query("Research Rust async").await?);
+/// while let Some(tagged) = messages.next().await {
+///     let tagged = tagged?;
+///     println!("depth {}: {:?}", tagged.depth(), tagged.message.text());
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub fn with_subagent_lineage<'a>(
+    messages: BoxStream<'a, Result<Message, ClaudeAgentError>>,
+) -> BoxStream<'a, Result<SubagentMessage, ClaudeAgentError>> {
+    // Tool use id -> the tool use that spawned the agent which issued it
+    let mut issued_by: HashMap<String, Option<String>> = HashMap::new();
+    Box::pin(messages.map(move |item| {
+        let message = item?;
+        let parent = message.parent_tool_use_id().map(str::to_string);
+        if let Message::Assistant(assistant) = &message {
+            for block in &assistant.content {
+                if let ContentBlock::ToolUse(tool_use) = block {
+                    issued_by.insert(tool_use.id.clone(), parent.clone());
+                }
+            }
+        }
+
+        let mut lineage = Vec::new();
+        let mut next = parent;
+        while let Some(id) = next {
+            // Guard against malformed cycles
+            if lineage.contains(&id) {
+                break;
+            }
+            next = issued_by.get(&id).cloned().flatten();
+            lineage.push(id);
+        }
+        lineage.reverse();
+        Ok(SubagentMessage { message, lineage })
+    }))
+}
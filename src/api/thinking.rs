@@ -0,0 +1,134 @@
+//! Split a message stream into assistant text and thinking.
+
+use std::collections::VecDeque;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+
+use futures::stream::BoxStream;
+use futures::{Stream, StreamExt};
+
+use crate::types::{ClaudeAgentError, Message};
+
+type Chunk = Result<String, ClaudeAgentError>;
+
+const TEXT: usize = 0;
+const THINKING: usize = 1;
+
+/// Split `messages` into a stream of assistant text and a stream of thinking.
+///
+/// Returns `(text, thinking)`. Each assistant message contributes its joined
+/// text (see [`Message::text`]) to the first stream and its joined thinking
+/// (see [`Message::thinking`]) to the second. Both end at the turn's result
+/// message. Errors are yielded on the text stream.
+///
+/// The streams share the source: polling either one pulls messages and queues
+/// chunks for the other, so they can be consumed in any order or concurrently.
+/// Dropping one discards its chunks.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use claude_agent::api::{split_thinking, ClaudeAgentClient};
+/// use futures::StreamExt;
+///
+/// # async fn example(client: &mut ClaudeAgentClient) -> Result<(), Box<dyn std::error::Error>> {
+/// let (text, thinking) = split_thinking(client.query("Plan a trip").await?);
+/// let reasoning: Vec<_> = thinking.collect().await;
+/// let answer: Vec<_> = text.collect().await;
+/// # Ok(())
+/// # }
+/// ```
+pub fn split_thinking<'a>(
+    messages: BoxStream<'a, Result<Message, ClaudeAgentError>>,
+) -> (BoxStream<'a, Chunk>, BoxStream<'a, Chunk>) {
+    let shared = Arc::new(Mutex::new(Shared {
+        source: messages,
+        done: false,
+        queues: [VecDeque::new(), VecDeque::new()],
+        wakers: [None, None],
+        dropped: [false, false],
+    }));
+    let text = Half { shared: shared.clone(), side: TEXT };
+    let thinking = Half { shared, side: THINKING };
+    (Box::pin(text), Box::pin(thinking))
+}
+
+struct Shared<'a> {
+    source: BoxStream<'a, Result<Message, ClaudeAgentError>>,
+    done: bool,
+    queues: [VecDeque<Chunk>; 2],
+    wakers: [Option<Waker>; 2],
+    dropped: [bool; 2],
+}
+
+impl Shared<'_> {
+    fn push(&mut self, side: usize, chunk: Chunk) {
+        if !self.dropped[side] {
+            self.queues[side].push_back(chunk);
+            if let Some(waker) = self.wakers[side].take() {
+                waker.wake();
+            }
+        }
+    }
+
+    fn finish(&mut self) {
+        self.done = true;
+        for waker in self.wakers.iter_mut().filter_map(Option::take) {
+            waker.wake();
+        }
+    }
+}
+
+/// One side of a [`split_thinking`] pair.
+struct Half<'a> {
+    shared: Arc<Mutex<Shared<'a>>>,
+    side: usize,
+}
+
+impl Stream for Half<'_> {
+    type Item = Chunk;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Chunk>> {
+        let Ok(mut shared) = self.shared.lock() else {
+            return Poll::Ready(None);
+        };
+        loop {
+            if let Some(chunk) = shared.queues[self.side].pop_front() {
+                return Poll::Ready(Some(chunk));
+            }
+            if shared.done {
+                return Poll::Ready(None);
+            }
+            match shared.source.poll_next_unpin(cx) {
+                Poll::Ready(Some(Ok(Message::Result(_)))) | Poll::Ready(None) => shared.finish(),
+                Poll::Ready(Some(Ok(message))) => {
+                    if let Some(text) = message.text() {
+                        shared.push(TEXT, Ok(text));
+                    }
+                    if let Some(thinking) = message.thinking() {
+                        shared.push(THINKING, Ok(thinking));
+                    }
+                },
+                Poll::Ready(Some(Err(e))) => shared.push(TEXT, Err(e)),
+                Poll::Pending => {
+                    shared.wakers[self.side] = Some(cx.waker().clone());
+                    return Poll::Pending;
+                },
+            }
+        }
+    }
+}
+
+impl Drop for Half<'_> {
+    fn drop(&mut self) {
+        if let Ok(mut shared) = self.shared.lock() {
+            shared.dropped[self.side] = true;
+            shared.queues[self.side].clear();
+            // The source may hold this side's waker; let the other side re-register
+            if let Some(waker) = shared.wakers[1 - self.side].take() {
+                waker.wake();
+            }
+        }
+    }
+}
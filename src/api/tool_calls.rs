@@ -0,0 +1,128 @@
+//! Pair tool uses with their results.
+
+use std::time::Duration;
+
+use futures::stream::BoxStream;
+use futures::StreamExt;
+
+use crate::core::tool_pairing::ToolCallPairs;
+use crate::types::message::{ContentBlock, MessageContent, ToolResultBlock, ToolUseBlock};
+use crate::types::{ClaudeAgentError, Message};
+
+pub use crate::core::tool_pairing::DEFAULT_TOOL_RESULT_TIMEOUT;
+
+/// A tool use paired with its outcome.
+#[derive(Debug, Clone)]
+pub enum ToolCallEvent {
+    /// The result for `tool_use` arrived.
+    Completed {
+        tool_use: ToolUseBlock,
+        result: ToolResultBlock,
+    },
+    /// No result arrived before the timeout or the end of the stream.
+    Unmatched(ToolUseBlock),
+}
+
+/// Stream adapter that pairs each `tool_use` block with the `tool_result`
+/// referencing its id.
+///
+/// Results may arrive in any order, including before their tool use. Each
+/// tool use waits at most `timeout` for its result; tool uses still pending
+/// when the source ends are reported as [`ToolCallEvent::Unmatched`] too.
+/// A result is likewise dropped if its tool use does not arrive within
+/// `timeout`, so results for timed-out tool uses are not kept around.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use claude_agent::api::{ClaudeAgentClient, ToolCallCorrelator, ToolCallEvent};
+/// use futures::StreamExt;
+///
+/// # async fn example(client: &mut ClaudeAgentClient) -> Result<(), Box<dyn std::error::Error>> {
+/// let mut calls = ToolCallCorrelator::default().correlate(client.query("List files").await?);
+/// while let Some(event) = calls.next().await {
+///     if let ToolCallEvent::Completed { tool_use, result } = event? {
+///         println!("{} -> {:?}", tool_use.name, result.content);
+///     }
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct ToolCallCorrelator {
+    timeout: Duration,
+}
+
+impl Default for ToolCallCorrelator {
+    fn default() -> Self {
+        Self::new(DEFAULT_TOOL_RESULT_TIMEOUT)
+    }
+}
+
+impl ToolCallCorrelator {
+    /// Give up on a tool use after `timeout` without its result.
+    pub fn new(timeout: Duration) -> Self {
+        Self { timeout }
+    }
+
+    /// Turn `messages` into a stream of correlated tool calls.
+    ///
+    /// Messages without tool blocks are consumed silently; errors from the
+    /// source are passed through.
+    pub fn correlate<'a>(
+        &self,
+        mut messages: BoxStream<'a, Result<Message, ClaudeAgentError>>,
+    ) -> BoxStream<'a, Result<ToolCallEvent, ClaudeAgentError>> {
+        let timeout = self.timeout;
+        Box::pin(async_stream::stream! {
+            let mut pairs = ToolCallPairs::new(timeout);
+            loop {
+                for tool_use in pairs.expire() {
+                    yield Ok(ToolCallEvent::Unmatched(tool_use));
+                }
+                let deadline = pairs.next_deadline();
+                let expired = async {
+                    match deadline {
+                        Some(deadline) => tokio::time::sleep_until(deadline).await,
+                        None => std::future::pending().await,
+                    }
+                };
+                let next = tokio::select! {
+                    item = messages.next() => item,
+                    _ = expired => continue,
+                };
+                match next {
+                    None => break,
+                    Some(Err(e)) => yield Err(e),
+                    Some(Ok(Message::Assistant(message))) => {
+                        for block in message.content {
+                            let ContentBlock::ToolUse(tool_use) = block else {
+                                continue;
+                            };
+                            if let Some((tool_use, result)) = pairs.tool_use(tool_use.id.clone(), tool_use) {
+                                yield Ok(ToolCallEvent::Completed { tool_use, result });
+                            }
+                        }
+                    },
+                    Some(Ok(Message::User(message))) => {
+                        let MessageContent::Blocks(blocks) = message.content else {
+                            continue;
+                        };
+                        for block in blocks {
+                            let ContentBlock::ToolResult(result) = block else {
+                                continue;
+                            };
+                            if let Some((tool_use, result)) = pairs.tool_result(result) {
+                                yield Ok(ToolCallEvent::Completed { tool_use, result });
+                            }
+                        }
+                    },
+                    Some(Ok(_)) => {},
+                }
+            }
+            for tool_use in pairs.drain() {
+                yield Ok(ToolCallEvent::Unmatched(tool_use));
+            }
+        })
+    }
+}
@@ -19,20 +19,43 @@
 //! # }
 //! ```
 
+use std::path::Path;
 use std::sync::Arc;
 
 use futures::stream::BoxStream;
 use futures::StreamExt;
-
-use crate::mcp::McpServerManager;
-use crate::transport::{SubprocessTransport, Transport};
-use crate::types::{ClaudeAgentError, ClaudeAgentOptions, Message};
-
-use super::control::{ControlProtocol, ControlResponse};
+use tracing::Instrument;
+
+use crate::mcp::{McpServer, McpServerManager, RateLimiter};
+use crate::transport::{CliVersion, SubprocessTransport, Subscription, Transport};
+use crate::types::config::McpServerConfig;
+use crate::types::hooks::PermissionResult;
+use crate::types::message::{
+    ContentBlock, ResultMessage, SystemInit, ToolResultBlock, ToolResultContent,
+};
+use crate::types::{ClaudeAgentError, ClaudeAgentOptions, Message, QueryOverrides};
+
+use super::control::{ControlProtocol, ControlRequestType, ControlResponse};
 use super::hooks::HookRegistry;
 use super::permissions::PermissionHandler;
+use super::query_handle::QueryHandle;
 use super::server_info::{ContextUsageResponse, McpStatusResponse, ServerInfo};
-use super::session::{Session, SessionManager};
+use super::session::{Session, SessionManager, SessionStats};
+use super::streaming::Timestamped;
+use super::tool_log::ToolCallLogger;
+use super::turn::{Restore, TurnStream};
+
+/// Maximum time to wait for the control loop to finish in-flight work on disconnect.
+const CONTROL_LOOP_SHUTDOWN_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// Default time `ping` waits for the CLI to answer.
+const DEFAULT_PING_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// Consecutive non-fatal read errors after which the control loop gives up.
+const MAX_CONSECUTIVE_READ_ERRORS: u32 = 16;
+
+/// Unparsed CLI output, as read from the transport.
+type RawStream = Subscription;
 
 /// The core Claude Agent — orchestrates transport, sessions, MCP, control protocol, hooks, and permissions.
 #[allow(dead_code)]
@@ -40,7 +63,10 @@ pub struct ClaudeAgent {
     options: ClaudeAgentOptions,
     transport: Option<Arc<tokio::sync::RwLock<Box<dyn Transport>>>>,
 
-    control_loop_abort: Option<tokio::task::AbortHandle>,
+    control_loop_handle: Option<tokio::task::JoinHandle<()>>,
+    control_loop_shutdown: Option<tokio::sync::oneshot::Sender<()>>,
+    /// Why the control loop stopped on its own, if it has.
+    control_loop_exit: Arc<std::sync::Mutex<Option<ClaudeAgentError>>>,
     session_manager: SessionManager,
     hook_registry: HookRegistry,
     permission_handler: PermissionHandler,
@@ -49,40 +75,103 @@ pub struct ClaudeAgent {
     control_rx:
         Arc<tokio::sync::Mutex<tokio::sync::mpsc::Receiver<super::control::ControlRequest>>>,
     initialization_data: Arc<tokio::sync::Mutex<Option<serde_json::Value>>>,
+    /// Typed form of the latest `system`/`init` message.
+    system_init: Arc<std::sync::Mutex<Option<SystemInit>>>,
+    session_stats: Arc<std::sync::Mutex<SessionStats>>,
+    /// Stop reason of the latest `message_delta` seen since the last prompt.
+    last_stop_reason: Arc<std::sync::Mutex<Option<String>>>,
+    cli_session_id: Arc<std::sync::Mutex<Option<String>>>,
+    rate_limiter: Option<RateLimiter>,
+    tool_call_logger: Option<Arc<ToolCallLogger>>,
+    /// Whether the transport came from `set_transport` rather than `connect`.
+    custom_transport: bool,
+    /// Held by a query's stream until its result, so turns never overlap.
+    turn_lock: Arc<tokio::sync::Mutex<()>>,
+    /// The CLI's pid, kept outside the transport lock so `kill` never waits on it.
+    process_id: Option<u32>,
 }
 
 impl ClaudeAgent {
     /// Create a new Claude Agent.
     pub fn new(options: ClaudeAgentOptions) -> Self {
         let (protocol, rx) = ControlProtocol::new();
+        let mut permission_handler = PermissionHandler::new();
+        permission_handler.set_rules(options.permission_rules.clone());
+        let rate_limiter = options.query_rate_limit.clone().map(RateLimiter::new);
         Self {
             options,
             transport: None,
-            control_loop_abort: None,
+            control_loop_handle: None,
+            control_loop_shutdown: None,
+            control_loop_exit: Arc::new(std::sync::Mutex::new(None)),
             session_manager: SessionManager::new(),
             hook_registry: HookRegistry::new(),
-            permission_handler: PermissionHandler::new(),
+            permission_handler,
             mcp_manager: McpServerManager::new(),
             control_protocol: Some(Arc::new(protocol)),
             control_rx: Arc::new(tokio::sync::Mutex::new(rx)),
             initialization_data: Arc::new(tokio::sync::Mutex::new(None)),
+            system_init: Arc::new(std::sync::Mutex::new(None)),
+            session_stats: Arc::new(std::sync::Mutex::new(SessionStats::default())),
+            last_stop_reason: Arc::new(std::sync::Mutex::new(None)),
+            cli_session_id: Arc::new(std::sync::Mutex::new(None)),
+            rate_limiter,
+            tool_call_logger: None,
+            custom_transport: false,
+            turn_lock: Arc::new(tokio::sync::Mutex::new(())),
+            process_id: None,
         }
     }
 
+    /// Throttle outgoing queries with `limiter`, replacing any limiter built from
+    /// `options.query_rate_limit`.
+    ///
+    /// Pass a clone of a shared limiter to apply one budget across several agents.
+    pub fn set_rate_limiter(&mut self, limiter: Option<RateLimiter>) {
+        self.rate_limiter = limiter;
+    }
+
+    /// Log every tool call seen in query streams to `logger`.
+    ///
+    /// Pass `None` to stop logging. Write errors are reported as warnings and
+    /// do not interrupt the stream.
+    pub fn set_tool_call_logger(&mut self, logger: Option<Arc<ToolCallLogger>>) {
+        self.tool_call_logger = logger;
+    }
+
     /// Set the transport implementation.
     ///
     /// Useful for testing with mock transports or using custom transport implementations.
     pub fn set_transport(&mut self, transport: Box<dyn Transport>) {
         self.transport = Some(Arc::new(tokio::sync::RwLock::new(transport)));
+        self.custom_transport = true;
+    }
+
+    /// Whether the control loop started by `connect` is still running.
+    fn is_running(&self) -> bool {
+        self.control_loop_handle.as_ref().is_some_and(|handle| !handle.is_finished())
     }
 
     /// Connect to Claude Code CLI.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ClaudeAgentError::CLIConnection` if the agent is already connected;
+    /// call [`ClaudeAgent::disconnect`] first to start a new CLI process.
+    #[tracing::instrument(skip_all, fields(session_id = ?self.options.session_id))]
     pub async fn connect(&mut self, prompt: Option<&str>) -> Result<(), ClaudeAgentError> {
+        if self.is_running() {
+            return Err(ClaudeAgentError::CLIConnection(
+                "Already connected; call disconnect() before connecting again".to_string(),
+            ));
+        }
+
         // Initialize transport if needed
         if self.transport.is_none() {
             let transport =
                 SubprocessTransport::new(prompt.map(|s| s.to_string()), self.options.clone());
             self.transport = Some(Arc::new(tokio::sync::RwLock::new(Box::new(transport))));
+            self.custom_transport = false;
         }
 
         // Connect
@@ -94,6 +183,7 @@ impl ClaudeAgent {
                 .write()
                 .await;
             guard.connect().await?;
+            self.process_id = guard.process_id();
         }
 
         // Spawn control loop background task
@@ -104,23 +194,42 @@ impl ClaudeAgent {
             .clone();
         let control_rx_mutex = self.control_rx.clone();
         let mcp_manager = self.mcp_manager.clone();
+        let permission_handler = self.permission_handler.clone();
         let control_protocol = self.control_protocol.clone();
         let initialization_data_mutex = self.initialization_data.clone();
-
-        let abort_handle = tokio::spawn(async move {
+        let system_init = self.system_init.clone();
+        let cli_session_id = self.cli_session_id.clone();
+        let loop_exit = self.control_loop_exit.clone();
+        if let Ok(mut exit) = loop_exit.lock() {
+            *exit = None;
+        }
+        let (shutdown_tx, mut shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+        let loop_span = tracing::info_span!(
+            "control_loop",
+            session_id = ?self.options.session_id,
+            cli_session_id = tracing::field::Empty,
+        );
+
+        let handle = tokio::spawn(async move {
             // Get stream of incoming messages
             let stream_transport = transport_arc.read().await;
             let mut incoming_stream = stream_transport.read_messages().await;
+            let mut read_errors = 0u32;
+            let record_exit = |reason: ClaudeAgentError| {
+                if let Ok(mut exit) = loop_exit.lock() {
+                    *exit = Some(reason);
+                }
+            };
 
             loop {
-                // We lock control_rx to wait for outgoing requests
-                // Note: This lock is held while waiting for incoming messages too
-                let mut control_guard = control_rx_mutex.lock().await;
-
                 tokio::select! {
-                    // Handle outgoing control requests
-                    Some(req) = control_guard.recv() => {
-                         use super::control::ControlRequestType;
+                    // Stop accepting new work once disconnect() signals shutdown. An in-flight
+                    // write completes first because select! only races between iterations.
+                    _ = &mut shutdown_rx => break,
+
+                    // Handle outgoing control requests. The receiver is locked only while
+                    // this branch is polled; the guard drops whenever another branch wins.
+                    Some(req) = async { control_rx_mutex.lock().await.recv().await } => {
 
                          let request_payload = match req.request {
                              ControlRequestType::Interrupt => serde_json::json!({"subtype": "interrupt"}),
@@ -146,7 +255,8 @@ impl ClaudeAgent {
                          let req_str = serde_json::to_string(&req_json).unwrap_or_default();
                          // Acquire read lock just for writing
                          if let Err(e) = transport_arc.read().await.write(&req_str).await {
-                             eprintln!("Control loop write error: {}", e);
+                             tracing::error!(error = %e, "Control loop write error");
+                             record_exit(e);
                              break;
                          }
                     }
@@ -155,8 +265,23 @@ impl ClaudeAgent {
                     maybe_msg = incoming_stream.next() => {
                         match maybe_msg {
                             Some(Ok(value)) => {
+                                 read_errors = 0;
                                  let msg_type = value.get("type").and_then(|t| t.as_str()).unwrap_or("unknown");
 
+                                 // Remember the CLI's session id from init and result messages
+                                 if msg_type == "system" || msg_type == "result" {
+                                     let session_id = value
+                                         .get("session_id")
+                                         .or_else(|| value.get("data").and_then(|d| d.get("session_id")))
+                                         .and_then(|s| s.as_str());
+                                     if let (Some(id), Ok(mut guard)) = (session_id, cli_session_id.lock()) {
+                                         if guard.as_deref() != Some(id) {
+                                             tracing::Span::current().record("cli_session_id", id);
+                                         }
+                                         *guard = Some(id.to_string());
+                                     }
+                                 }
+
                                  if msg_type == "control_request" {
                                       let req_id = value.get("request_id").and_then(|s| s.as_str()).unwrap_or("unknown");
                                       let req_payload = value.get("request").cloned().unwrap_or(serde_json::Value::Null);
@@ -168,18 +293,45 @@ impl ClaudeAgent {
                                               let message = req_payload.get("message");
 
                                               if let (Some(name), Some(msg)) = (server_name, message) {
-                                                  if let Some(server) = mcp_manager.get(name).await {
-                                                      match server.handle_client_message(msg.clone()).await {
-                                                          Ok(res) => res,
-                                                          Err(e) => serde_json::json!({"error": e.to_string()})
-                                                      }
-                                                  } else {
-                                                       serde_json::json!({"error": format!("Server not found: {}", name)})
-                                                  }
+                                                  // The CLI expects the JSON-RPC reply under `mcp_response`
+                                                  let reply = match mcp_manager.get(name).await {
+                                                      Some(server) => server.handle_client_message(msg.clone()).await,
+                                                      None => Err(ClaudeAgentError::Mcp(format!("Server not found: {}", name))),
+                                                  };
+                                                  let mcp_response = reply.unwrap_or_else(|e| serde_json::json!({
+                                                      "jsonrpc": "2.0",
+                                                      "id": msg.get("id"),
+                                                      "error": {"code": -32603, "message": e.to_string()}
+                                                  }));
+                                                  serde_json::json!({"mcp_response": mcp_response})
                                               } else {
                                                   serde_json::json!({"error": "Invalid mcp_message payload"})
                                               }
                                           },
+                                          "can_use_tool" => {
+                                              let tool_name = req_payload.get("tool_name").and_then(|s| s.as_str()).unwrap_or_default();
+                                              let input = req_payload.get("input").cloned().unwrap_or(serde_json::Value::Null);
+                                              let suggestions = req_payload
+                                                  .get("permission_suggestions")
+                                                  .and_then(|v| serde_json::from_value(v.clone()).ok())
+                                                  .unwrap_or_default();
+                                              match permission_handler.can_use_tool(tool_name, input.clone(), suggestions).await {
+                                                  Ok(PermissionResult::Allow { updated_input, updated_permissions }) => {
+                                                      let mut allow = serde_json::json!({
+                                                          "behavior": "allow",
+                                                          "updatedInput": updated_input.map(|m| serde_json::json!(m)).unwrap_or(input)
+                                                      });
+                                                      if let Some(perms) = updated_permissions {
+                                                          allow["updatedPermissions"] = serde_json::json!(perms);
+                                                      }
+                                                      allow
+                                                  },
+                                                  Ok(PermissionResult::Deny { message, interrupt }) => {
+                                                      serde_json::json!({"behavior": "deny", "message": message, "interrupt": interrupt})
+                                                  },
+                                                  Err(e) => serde_json::json!({"error": e.to_string()}),
+                                              }
+                                          },
                                           "initialize" | "set_permission_mode" | "set_model"
                                           | "rewind_files" | "stop_task" | "mcp_reconnect"
                                           | "mcp_toggle" | "mcp_status" | "get_context_usage" => {
@@ -201,112 +353,571 @@ impl ClaudeAgent {
 
                                       let response_str = serde_json::to_string(&response).unwrap_or_default();
                                       if let Err(e) = transport_arc.read().await.write(&response_str).await {
-                                           eprintln!("Control loop write response error: {}", e);
+                                           tracing::error!(error = %e, request_id = req_id, "Control loop write response error");
+                                           record_exit(e);
                                            break;
                                       }
                                  } else if msg_type == "control_response" {
                                      if let Some(cp) = &control_protocol {
-                                          let req_id = value.get("request_id").and_then(|s| s.as_str()).unwrap_or("");
+                                          // The CLI nests the outcome, and usually the request id, under `response`
+                                          let outcome = value.get("response");
+                                          let req_id = value
+                                              .get("request_id")
+                                              .or_else(|| outcome.and_then(|r| r.get("request_id")))
+                                              .and_then(|s| s.as_str())
+                                              .unwrap_or("");
+                                          let failed = outcome.and_then(|r| r.get("subtype")).and_then(|s| s.as_str()) == Some("error");
+                                          let error = failed.then(|| {
+                                              outcome
+                                                  .and_then(|r| r.get("error"))
+                                                  .and_then(|e| e.as_str())
+                                                  .unwrap_or("Control request failed")
+                                                  .to_string()
+                                          });
                                           let resp = ControlResponse {
                                               request_id: req_id.to_string(),
-                                              success: true,
+                                              success: !failed,
                                               response: Some(value.clone()),
-                                              error: None
+                                              error,
                                           };
                                           let _ = cp.handle_response(resp).await;
                                      }
-                                 } else if msg_type == "system" && value.get("subtype").and_then(|t| t.as_str()) == Some("init") {
+                                 } else if let Some(init) = SystemInit::from_message(&value) {
+                                     if let Ok(mut typed) = system_init.lock() {
+                                         *typed = Some(init);
+                                     }
                                      let mut init_guard = initialization_data_mutex.lock().await;
-                                     *init_guard = value.get("data").cloned();
+                                     // Older CLIs nest init fields under `data`; current ones put them at top level
+                                     *init_guard = Some(value.get("data").cloned().unwrap_or_else(|| value.clone()));
                                  }
                             }
+                            Some(Err(e)) if e.is_fatal() => {
+                                tracing::error!(error = %e, "Control loop stopped by fatal read error");
+                                record_exit(e);
+                                break;
+                            }
                             Some(Err(e)) => {
-                                eprintln!("Control loop read error: {}", e);
-                                // Don't break on read error, transport might recover or it's transient?
-                                // Actually Transport::read_messages yields errors for fatal things usually?
+                                // Keep reading past a malformed line, but not a transport
+                                // that keeps failing
+                                read_errors += 1;
+                                tracing::warn!(error = %e, read_errors, "Control loop read error");
+                                if read_errors >= MAX_CONSECUTIVE_READ_ERRORS {
+                                    tracing::error!(error = %e, "Control loop stopped after repeated read errors");
+                                    record_exit(ClaudeAgentError::Transport(format!(
+                                        "Control loop stopped after {} consecutive read errors; last: {}",
+                                        read_errors, e
+                                    )));
+                                    break;
+                                }
+                            }
+                            None => {
+                                tracing::debug!("Control loop input stream ended");
+                                record_exit(ClaudeAgentError::Transport("CLI output ended".to_string()));
+                                break;
                             }
-                            None => break, // Stream ended
                         }
                     }
                 }
             }
-        }).abort_handle();
-
-        self.control_loop_abort = Some(abort_handle);
-
-        // Create session
-        self.session_manager.create_session();
+        }
+        .instrument(loop_span));
+
+        self.control_loop_handle = Some(handle);
+        self.control_loop_shutdown = Some(shutdown_tx);
+
+        // Keep an active session chosen with `switch_session`; otherwise create one,
+        // reusing a caller-pinned id when provided
+        if !self.session_manager.current_session().is_some_and(|session| session.is_active) {
+            match &self.options.session_id {
+                Some(id) => self.session_manager.create_session_with_id(id.clone()),
+                None => self.session_manager.create_session(),
+            };
+        }
 
         Ok(())
     }
 
     /// Execute a query and return a stream of messages.
+    ///
+    /// The stream ends after the turn's `Result` message. Errors are yielded in
+    /// place. Non-fatal ones, such as a malformed message, leave the stream
+    /// running; after a fatal one (see [`ClaudeAgentError::is_fatal`]) the
+    /// stream ends.
+    ///
+    /// # Turns
+    ///
+    /// The CLI does not label messages with the prompt they answer, so an agent
+    /// runs one turn at a time. A query started while an earlier query's stream
+    /// is still open waits, before writing its prompt, until that stream has
+    /// yielded its `Result` or been dropped. A stream dropped before its
+    /// `Result` interrupts its turn, and the next query waits until the rest of
+    /// that turn's output has been read and discarded. Consume, drop, or
+    /// [detach](QueryHandle::detach) the earlier stream first when issuing
+    /// queries from a single task.
     pub async fn query(
         &mut self,
         prompt: &str,
     ) -> Result<BoxStream<'_, Result<Message, ClaudeAgentError>>, ClaudeAgentError> {
-        // Connect if not already connected
-        if self.transport.is_none() {
+        let turn = self.query_turn(prompt).await?;
+        Ok(Box::pin(turn.map(|item| item.map(|message| message.value))))
+    }
+
+    /// [`ClaudeAgent::query`], returning the turn so callers can stop it.
+    #[tracing::instrument(name = "query", skip_all, fields(session_id = ?self.current_session_id()))]
+    pub(crate) async fn query_turn(
+        &mut self,
+        prompt: &str,
+    ) -> Result<TurnStream, ClaudeAgentError> {
+        let turn = self.turn_lock.clone().lock_owned().await;
+        self.connect_if_needed().await?;
+        let source = self.subscribe().await?;
+        self.send_prompt(prompt).await?;
+        Ok(self.turn(turn, source))
+    }
+
+    /// Execute a query whose messages carry the time the agent received them.
+    ///
+    /// Each message is stamped by the transport as it arrives, so consumers can
+    /// measure latency without counting their own processing time or how late
+    /// they poll. Transports that do not implement [`Transport::subscribe`] are
+    /// stamped when the agent reads from them instead. Errors are not stamped.
+    /// Otherwise the stream behaves as for [`ClaudeAgent::query`].
+    #[tracing::instrument(skip_all, fields(session_id = ?self.current_session_id()))]
+    pub async fn query_timestamped(
+        &mut self,
+        prompt: &str,
+    ) -> Result<BoxStream<'_, Result<Timestamped<Message>, ClaudeAgentError>>, ClaudeAgentError>
+    {
+        let turn = self.turn_lock.clone().lock_owned().await;
+        self.connect_if_needed().await?;
+        let source = self.subscribe().await?;
+        self.send_prompt(prompt).await?;
+        Ok(self.timestamped_turn_stream(turn, source))
+    }
+
+    /// Execute a query whose prompt is a list of content blocks.
+    ///
+    /// Use this for prompts a plain string cannot express, such as images or
+    /// `tool_result` blocks answering an earlier tool call. The stream behaves
+    /// as for [`ClaudeAgent::query`].
+    #[tracing::instrument(skip_all, fields(session_id = ?self.current_session_id()))]
+    pub async fn query_blocks(
+        &mut self,
+        blocks: Vec<ContentBlock>,
+    ) -> Result<BoxStream<'_, Result<Message, ClaudeAgentError>>, ClaudeAgentError> {
+        let turn = self.turn_lock.clone().lock_owned().await;
+        let summary: String = blocks.iter().filter_map(ContentBlock::as_text).collect();
+        let content = serde_json::to_value(&blocks).map_err(|e| {
+            ClaudeAgentError::JSONDecode(format!("Failed to serialize content blocks: {}", e))
+        })?;
+        self.connect_if_needed().await?;
+        let source = self.subscribe().await?;
+        self.send_content(content, summary).await?;
+        self.turn_stream(turn, source)
+    }
+
+    /// Execute a query with options overridden for this turn only.
+    ///
+    /// A model or permission mode override is sent as a control request before the
+    /// prompt and reverted to the agent's options when the turn ends, before its
+    /// result is yielded or after the stream is dropped. Overrides already sent
+    /// are also reverted if a later step fails before the prompt is written.
+    ///
+    /// If `overrides.cancellation` is cancelled mid-turn, an `interrupt` control
+    /// request is sent and the stream ends without yielding further messages.
+    /// The rest of the interrupted turn is discarded, and the overrides are
+    /// reverted, before the next query starts.
+    #[tracing::instrument(skip_all, fields(session_id = ?self.current_session_id()))]
+    pub async fn query_with(
+        &mut self,
+        prompt: &str,
+        overrides: QueryOverrides,
+    ) -> Result<BoxStream<'_, Result<Message, ClaudeAgentError>>, ClaudeAgentError> {
+        let turn = self.turn_lock.clone().lock_owned().await;
+        if !self.is_running() {
             self.connect(None).await?;
         }
 
+        let mut restore = Restore::default();
+        match self.start_overridden_turn(prompt, &overrides, &mut restore).await {
+            Ok(source) => {
+                let stream = self
+                    .turn(turn, source)
+                    .restore(restore)
+                    .cancellation(overrides.cancellation.clone());
+                Ok(Box::pin(stream.map(|item| item.map(|message| message.value))))
+            },
+            Err(e) => {
+                if let Some(protocol) = &self.control_protocol {
+                    restore.apply(protocol).await;
+                }
+                Err(e)
+            },
+        }
+    }
+
+    /// Apply `overrides` and write the prompt, recording in `restore` each
+    /// setting changed so far.
+    async fn start_overridden_turn(
+        &mut self,
+        prompt: &str,
+        overrides: &QueryOverrides,
+        restore: &mut Restore,
+    ) -> Result<RawStream, ClaudeAgentError> {
+        if let Some(model) = &overrides.model {
+            require_success(self.set_model(Some(model)).await?, "Failed to set model")?;
+            restore.model = Some(self.options.model.clone());
+        }
+        if let Some(mode) = &overrides.permission_mode {
+            let response = self.set_permission_mode(&mode.to_string()).await?;
+            require_success(response, "Failed to set permission mode")?;
+            restore.permission_mode = Some(
+                self.options
+                    .permission_mode
+                    .as_ref()
+                    .map_or("default".to_string(), ToString::to_string),
+            );
+        }
+        let source = self.subscribe().await?;
+        self.send_prompt(prompt).await?;
+        Ok(source)
+    }
+
+    /// Execute a query and return a [`QueryHandle`] that ends at the turn's result.
+    ///
+    /// Unlike [`ClaudeAgent::query`], the handle does not borrow the agent and can be
+    /// detached to finish the turn in the background. Turns are serialized as
+    /// described for [`ClaudeAgent::query`].
+    #[tracing::instrument(skip_all, fields(session_id = ?self.current_session_id()))]
+    pub async fn query_handle(&mut self, prompt: &str) -> Result<QueryHandle, ClaudeAgentError> {
+        let turn = self.turn_lock.clone().lock_owned().await;
+        self.connect_if_needed().await?;
+        let source = self.subscribe().await?;
+        self.send_prompt(prompt).await?;
+        Ok(QueryHandle::new(self.turn_stream(turn, source)?))
+    }
+
+    /// Why the model stopped generating in the latest turn.
+    ///
+    /// One of `end_turn`, `max_tokens`, `tool_use` or `stop_sequence`, taken
+    /// from the last `message_delta` event, bare or wrapped in a `stream_event`
+    /// when `include_partial_messages` is set. Cleared when a prompt is sent;
+    /// `None` if the CLI reported no stop reason for the turn.
+    pub fn last_stop_reason(&self) -> Option<String> {
+        self.last_stop_reason.lock().ok().and_then(|last| last.clone())
+    }
+
+    /// Get cumulative statistics from the result messages seen so far.
+    pub fn session_stats(&self) -> SessionStats {
+        self.session_stats.lock().map(|stats| stats.clone()).unwrap_or_default()
+    }
+
+    /// The latest result message, with that query's turn count and timings.
+    pub fn last_result(&self) -> Option<ResultMessage> {
+        self.session_stats.lock().ok().and_then(|stats| stats.last_result.clone())
+    }
+
+    /// Id used to tag tracing spans: the CLI session id, else the local one.
+    fn current_session_id(&self) -> Option<String> {
+        self.cli_session_id()
+            .or_else(|| self.session_manager.current_session().map(|session| session.id.clone()))
+    }
+
+    /// Serialize a stream-json user message with `content`, tagged with the active session.
+    fn user_message(&self, content: serde_json::Value) -> Result<String, ClaudeAgentError> {
+        let mut user_msg = serde_json::json!({
+            "type": "user",
+            "message": {
+                "role": "user",
+                "content": content
+            }
+        });
+        if let Some(session) = self.session_manager.current_session() {
+            user_msg["session_id"] = serde_json::json!(session.id);
+        }
+        serde_json::to_string(&user_msg)
+            .map_err(|e| ClaudeAgentError::JSONDecode(format!("Failed to serialize prompt: {}", e)))
+    }
+
+    /// Write a user prompt without reading the reply.
+    ///
+    /// Several prompts can be sent back to back; the CLI queues them and
+    /// answers each in turn. Read the replies from [`ClaudeAgent::responses`].
+    /// Pipelined prompts bypass the one-turn-at-a-time ordering of
+    /// [`ClaudeAgent::query`], so do not mix the two on one agent.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ClaudeAgentError::Transport` if the agent is not connected or the
+    /// write fails.
+    pub async fn send(&self, prompt: &str) -> Result<(), ClaudeAgentError> {
+        let transport = self
+            .transport
+            .as_ref()
+            .ok_or_else(|| ClaudeAgentError::Transport("Transport not connected".to_string()))?;
+        if let Some(limiter) = &self.rate_limiter {
+            limiter.acquire().await?;
+        }
+        let message = self.user_message(serde_json::json!([{"type": "text", "text": prompt}]))?;
+        transport.read().await.write(&message).await
+    }
+
+    /// Every message the CLI sends from now on, across turns.
+    ///
+    /// The stream listens from the moment this returns, so replies to prompts
+    /// already passed to [`ClaudeAgent::send`] are only seen if this is called
+    /// first. Unlike a query stream, it does not end at a `Result` message; it
+    /// ends when the transport closes or yields a fatal error. Control traffic
+    /// is filtered out as for [`ClaudeAgent::query`].
+    pub async fn responses(
+        &self,
+    ) -> Result<BoxStream<'static, Result<Message, ClaudeAgentError>>, ClaudeAgentError> {
+        self.message_stream().await
+    }
+
+    /// Send the result of a tool call Claude made, for tools handled by the caller.
+    ///
+    /// Writes a user message holding one `tool_result` block for `tool_use_id`.
+    /// No new turn starts: the CLI continues the current one and Claude's reply
+    /// arrives on the open query stream. A stream from [`ClaudeAgent::query`]
+    /// borrows the agent, so read the turn through [`ClaudeAgent::query_handle`]
+    /// (or [`ClaudeAgent::responses`]) to call this while it is open.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ClaudeAgentError::Transport` if the agent is not connected or the
+    /// write fails.
+    pub async fn submit_tool_result(
+        &self,
+        tool_use_id: &str,
+        content: ToolResultContent,
+        is_error: bool,
+    ) -> Result<(), ClaudeAgentError> {
+        let transport = self
+            .transport
+            .as_ref()
+            .ok_or_else(|| ClaudeAgentError::Transport("Transport not connected".to_string()))?;
+        let block = ContentBlock::ToolResult(ToolResultBlock {
+            tool_use_id: tool_use_id.to_string(),
+            content: Some(content),
+            is_error: Some(is_error),
+        });
+        let content = serde_json::to_value([block]).map_err(|e| {
+            ClaudeAgentError::JSONDecode(format!("Failed to serialize tool result: {}", e))
+        })?;
+        let message = self.user_message(content)?;
+        transport.read().await.write(&message).await
+    }
+
+    /// Write a user prompt to the transport, connecting first if needed.
+    async fn send_prompt(&mut self, prompt: &str) -> Result<(), ClaudeAgentError> {
+        let content = serde_json::json!([{"type": "text", "text": prompt}]);
+        self.send_content(content, prompt.to_string()).await
+    }
+
+    /// Write a user message with `content` blocks; `summary` is recorded as the
+    /// session's last message.
+    async fn send_content(
+        &mut self,
+        content: serde_json::Value,
+        summary: String,
+    ) -> Result<(), ClaudeAgentError> {
+        if let Some(limiter) = &self.rate_limiter {
+            limiter.acquire().await?;
+        }
+
+        self.connect_if_needed().await?;
+
         let transport_arc = self
             .transport
             .as_ref()
             .ok_or_else(|| ClaudeAgentError::Transport("Transport not connected".to_string()))?;
 
         // Write the prompt to the transport
-        use serde_json::json;
+        let msg_str = self.user_message(content)?;
+        let result = transport_arc.read().await.write(&msg_str).await;
+        match &result {
+            Ok(()) => {
+                self.record_cli_session_id();
+                if let Some(session) = self.session_manager.current_session_mut() {
+                    session.last_message = Some(summary);
+                }
+                if let Ok(mut last) = self.last_stop_reason.lock() {
+                    *last = None;
+                }
+            },
+            Err(e) => tracing::error!(error = %e, "Failed to write prompt"),
+        }
+        result
+    }
 
-        // Construct a proper UserMessage for the stream-json protocol
-        let user_msg = json!({
-            "type": "user",
-            "message": {
-                "role": "user",
-                "content": [
-                    {
-                        "type": "text",
-                        "text": prompt
-                    }
-                ]
+    /// Messages for the turn holding `turn`, ending after its `Result`.
+    ///
+    /// The turn lock is released just before the `Result` is yielded, letting
+    /// the next queued query write its prompt. A stream dropped or ended early
+    /// interrupts the turn and reads it to its `Result` first; see
+    /// [`TurnStream`]. With `error_on_result_failure`, an error result is
+    /// yielded as `ClaudeAgentError::Result` instead.
+    fn turn_stream(
+        &self,
+        turn: tokio::sync::OwnedMutexGuard<()>,
+        source: RawStream,
+    ) -> Result<BoxStream<'static, Result<Message, ClaudeAgentError>>, ClaudeAgentError> {
+        let stamped = self.timestamped_turn_stream(turn, source);
+        Ok(Box::pin(stamped.map(|item| item.map(|message| message.value))))
+    }
+
+    /// [`ClaudeAgent::turn_stream`] with each message's receive time.
+    fn timestamped_turn_stream(
+        &self,
+        turn: tokio::sync::OwnedMutexGuard<()>,
+        source: RawStream,
+    ) -> BoxStream<'static, Result<Timestamped<Message>, ClaudeAgentError>> {
+        Box::pin(self.turn(turn, source))
+    }
+
+    fn turn(&self, turn: tokio::sync::OwnedMutexGuard<()>, source: RawStream) -> TurnStream {
+        TurnStream::new(self.timestamped_messages(source), turn, self.control_protocol.clone())
+            .error_on_result_failure(self.options.error_on_result_failure)
+            .max_messages(self.options.max_messages_per_query)
+    }
+
+    /// Every JSON value the CLI emits, unparsed and unfiltered.
+    ///
+    /// Unlike [`ClaudeAgent::query`], this includes control traffic and the
+    /// `system`/`init` message. Each call subscribes separately on first poll.
+    /// The stream holds a read lock on the transport, so drop it before
+    /// calling [`ClaudeAgent::disconnect`]. Yields a single error if there is
+    /// no transport.
+    pub fn raw_messages(&self) -> BoxStream<'static, Result<serde_json::Value, ClaudeAgentError>> {
+        let Some(transport_arc) = self.transport.clone() else {
+            return Box::pin(futures::stream::once(async {
+                Err(ClaudeAgentError::Transport("Transport not connected".to_string()))
+            }));
+        };
+        Box::pin(async_stream::stream! {
+            let transport = transport_arc.read().await;
+            let mut values = transport.read_messages().await;
+            while let Some(value) = values.next().await {
+                yield value;
             }
-        });
+        })
+    }
 
-        let msg_str = serde_json::to_string(&user_msg).unwrap_or_else(|_| prompt.to_string());
+    /// Connect first if no transport has been created or set yet.
+    ///
+    /// Query entry points call this before `subscribe`, which
+    /// needs a transport to listen on.
+    async fn connect_if_needed(&mut self) -> Result<(), ClaudeAgentError> {
+        if self.transport.is_none() {
+            self.connect(None).await?;
+        }
+        Ok(())
+    }
 
-        transport_arc.read().await.write(&msg_str).await?;
+    /// Subscribe to the transport's output before anything is written.
+    ///
+    /// Transports without [`Transport::subscribe`] get a stream that starts
+    /// listening on first poll and stamps messages as it reads them.
+    async fn subscribe(&self) -> Result<RawStream, ClaudeAgentError> {
+        let transport_arc = self
+            .transport
+            .clone()
+            .ok_or_else(|| ClaudeAgentError::Transport("Transport not connected".to_string()))?;
+        if let Some(stream) = transport_arc.read().await.subscribe() {
+            return Ok(stream);
+        }
+        Ok(Box::pin(async_stream::stream! {
+            let transport = transport_arc.read().await;
+            let mut values = transport.read_messages().await;
+            while let Some(value) = values.next().await {
+                yield value.map(Timestamped::now);
+            }
+        }))
+    }
+
+    async fn message_stream(
+        &self,
+    ) -> Result<BoxStream<'static, Result<Message, ClaudeAgentError>>, ClaudeAgentError> {
+        let mut stamped = self.timestamped_messages(self.subscribe().await?);
+        Ok(Box::pin(async_stream::stream! {
+            while let Some(item) = stamped.next().await {
+                let fatal = item.as_ref().is_err_and(ClaudeAgentError::is_fatal);
+                yield item.map(|message| message.value);
+                if fatal {
+                    break;
+                }
+            }
+        }))
+    }
+
+    /// Parse the values of `source`, keeping their receive times.
+    ///
+    /// Ends after a fatal transport error. Errors the CLI reports in its
+    /// output, such as failed authentication, are yielded without ending the
+    /// stream, since the CLI still finishes the turn.
+    fn timestamped_messages(
+        &self,
+        mut json_stream: RawStream,
+    ) -> BoxStream<'static, Result<Timestamped<Message>, ClaudeAgentError>> {
+        let session_stats = self.session_stats.clone();
+        let last_stop_reason = self.last_stop_reason.clone();
+        let tool_call_logger = self.tool_call_logger.clone();
 
         // Use async-stream to transform
         let stream = async_stream::stream! {
-            let stream_transport = transport_arc.read().await;
-            let mut json_stream = stream_transport.read_messages().await;
-
             while let Some(result) = json_stream.next().await {
                 match result {
-                    Ok(value) => {
+                    Ok(Timestamped { value, received_at, received_at_system }) => {
                         let msg_type = value.get("type").and_then(|t| t.as_str()).unwrap_or("unknown");
 
                         // Filter out control messages and system init (handled by background task)
                         if msg_type == "control_request" || msg_type == "control_response" {
                             continue;
                         }
-                        if msg_type == "system" && value.get("subtype").and_then(|t| t.as_str()) == Some("init") {
+                        if SystemInit::from_message(&value).is_some() {
+                            continue;
+                        }
+
+                        if let Some(error) = rate_limit_error(&value).or_else(|| authentication_error(&value)) {
+                            yield Err(error);
                             continue;
                         }
 
-                        match serde_json::from_value(value) {
-                            Ok(msg) => yield Ok(msg),
+                        match serde_json::from_value::<Message>(value) {
+                            Ok(msg) => {
+                                if let Message::Result(result) = &msg {
+                                    if let Ok(mut stats) = session_stats.lock() {
+                                        stats.record_result(result);
+                                    }
+                                }
+                                if let (Some(reason), Ok(mut last)) = (stop_reason(&msg), last_stop_reason.lock()) {
+                                    *last = Some(reason);
+                                }
+                                if let Some(logger) = &tool_call_logger {
+                                    if let Err(e) = logger.observe(&msg) {
+                                        tracing::warn!(error = %e, "Failed to log tool call");
+                                    }
+                                }
+                                yield Ok(Timestamped { value: msg, received_at, received_at_system })
+                            },
                             Err(e) => {
                                 yield Err(ClaudeAgentError::MessageParse(format!("Failed to parse message: {}", e)));
                             }
                         }
                     }
-                    Err(e) => yield Err(e),
+                    Err(e) => {
+                        let fatal = e.is_fatal();
+                        yield Err(e);
+                        if fatal {
+                            break;
+                        }
+                    }
                 }
             }
         };
 
-        Ok(Box::pin(stream))
+        Box::pin(stream)
     }
 
     /// Get the control protocol, returning an error if not initialized.
@@ -322,6 +933,39 @@ impl ClaudeAgent {
         protocol.interrupt().await
     }
 
+    /// Check that the CLI is still answering control requests.
+    ///
+    /// Sends a read-only `mcp_status` request and waits up to five seconds for
+    /// the reply. See [`ClaudeAgent::ping_within`] to choose the timeout.
+    pub async fn ping(&self) -> Result<(), ClaudeAgentError> {
+        self.ping_within(DEFAULT_PING_TIMEOUT).await
+    }
+
+    /// Like [`ClaudeAgent::ping`], but waits at most `timeout` for the reply.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ClaudeAgentError::Transport` if the agent is not connected or
+    /// the CLI does not respond in time.
+    pub async fn ping_within(&self, timeout: std::time::Duration) -> Result<(), ClaudeAgentError> {
+        let protocol = match &self.control_protocol {
+            Some(protocol) if self.is_running() => protocol,
+            _ => {
+                return Err(ClaudeAgentError::Transport(
+                    "Ping failed: agent is not connected".to_string(),
+                ))
+            },
+        };
+        match protocol.send_request_within(ControlRequestType::McpStatus, timeout).await {
+            Ok(Some(_)) => Ok(()),
+            Ok(None) => Err(ClaudeAgentError::Transport(format!(
+                "Ping failed: no response within {:?}",
+                timeout
+            ))),
+            Err(e) => Err(ClaudeAgentError::Transport(format!("Ping failed: {}", e))),
+        }
+    }
+
     /// Set permission mode.
     pub async fn set_permission_mode(
         &self,
@@ -341,6 +985,8 @@ impl ClaudeAgent {
     }
 
     /// Rewind files to a specific user message checkpoint.
+    ///
+    /// Requires `enable_file_checkpointing` in the options so the CLI records checkpoints.
     pub async fn rewind_files(
         &self,
         user_message_id: &str,
@@ -491,13 +1137,47 @@ impl ClaudeAgent {
         guard.as_ref().map(|data| ServerInfo::new(data.clone()))
     }
 
+    /// Get the session id reported by the CLI, once an init or result message has arrived.
+    ///
+    /// Persist this and pass it as `ClaudeAgentOptions::resume` to continue the session later.
+    pub fn cli_session_id(&self) -> Option<String> {
+        self.cli_session_id.lock().ok().and_then(|guard| guard.clone())
+    }
+
+    /// Get the typed `system/init` payload: tools, MCP servers, model, cwd and session id.
+    ///
+    /// `None` until the CLI has sent its init message.
+    pub fn system_init(&self) -> Option<SystemInit> {
+        self.system_init.lock().ok().and_then(|init| init.clone())
+    }
+
+    /// Get the raw initialization data captured from the `system/init` message.
+    pub async fn get_server_info_raw(&self) -> Option<serde_json::Value> {
+        self.initialization_data.lock().await.clone()
+    }
+
     /// Disconnect from Claude Code CLI.
+    ///
+    /// The control loop is signalled to stop first and allowed to finish any
+    /// in-flight write (e.g. a control response) so no partial line is left on
+    /// stdin. Only then is the transport closed. A loop that fails to stop
+    /// within `CONTROL_LOOP_SHUTDOWN_TIMEOUT` is aborted.
+    ///
+    /// Disconnecting an agent that is not connected, including a second call,
+    /// is a no-op that returns `Ok(())`.
     pub async fn disconnect(&mut self) -> Result<(), ClaudeAgentError> {
-        // Abort background control loop
-        if let Some(abort_handle) = self.control_loop_abort.take() {
-            abort_handle.abort();
+        // Signal the control loop to stop, then wait for it to drain
+        if let Some(shutdown) = self.control_loop_shutdown.take() {
+            let _ = shutdown.send(());
+        }
+        if let Some(handle) = self.control_loop_handle.take() {
+            let abort_handle = handle.abort_handle();
+            if tokio::time::timeout(CONTROL_LOOP_SHUTDOWN_TIMEOUT, handle).await.is_err() {
+                abort_handle.abort();
+            }
         }
 
+        self.process_id = None;
         if let Some(transport_arc) = self.transport.take() {
             // We need to acquire write lock to close
             // This waits for any readers (like the background loop or query stream) to drop their locks
@@ -512,11 +1192,148 @@ impl ClaudeAgent {
         Ok(())
     }
 
+    /// The CLI version detected on connect, if the transport probed it.
+    ///
+    /// `None` before connecting, with `skip_version_check` set, or when the
+    /// CLI did not report a parseable version.
+    pub async fn cli_version(&self) -> Option<CliVersion> {
+        self.transport.as_ref()?.read().await.detected_cli_version()
+    }
+
+    /// Stop the CLI immediately.
+    ///
+    /// Unlike [`ClaudeAgent::disconnect`], this sends the CLI `SIGKILL` right
+    /// away, aborts the control loop without letting it drain, and kills the
+    /// transport with [`Transport::kill`] instead of closing it gracefully.
+    /// If a stream still holds the transport, that last step runs in the
+    /// background once the stream lets go of it, so `kill` never waits on it.
+    pub async fn kill(&mut self) -> Result<(), ClaudeAgentError> {
+        if let Some(pid) = self.process_id.take() {
+            crate::transport::process::kill(pid);
+        }
+        if let Some(shutdown) = self.control_loop_shutdown.take() {
+            let _ = shutdown.send(());
+        }
+        if let Some(handle) = self.control_loop_handle.take() {
+            handle.abort();
+            // An aborted task ends at once, releasing its transport lock
+            let _ = handle.await;
+        }
+
+        if let Some(transport_arc) = self.transport.take() {
+            match transport_arc.clone().try_write_owned() {
+                Ok(mut transport) => transport.kill().await?,
+                Err(_) => {
+                    tokio::spawn(async move {
+                        if let Err(e) = transport_arc.write().await.kill().await {
+                            tracing::warn!(error = %e, "Failed to kill transport");
+                        }
+                    });
+                },
+            }
+        }
+
+        if let Some(session) = self.session_manager.current_session_mut() {
+            session.deactivate();
+        }
+
+        Ok(())
+    }
+
+    /// Replace a dead CLI with a new one that resumes the same conversation.
+    ///
+    /// Closes the current transport, ignoring errors since it is usually
+    /// already gone, then spawns a fresh CLI with `resume` pointing at the
+    /// session id the CLI last reported, or with `continue_conversation` set
+    /// when no id is known. The local session stays current, so later queries
+    /// keep accumulating into it. The updated options persist for any later
+    /// reconnects.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ClaudeAgentError::Config` if the transport was installed with
+    /// [`ClaudeAgent::set_transport`], since only the built-in subprocess
+    /// transport can be respawned; set a new transport and call
+    /// [`ClaudeAgent::connect`] instead. Otherwise returns any error from
+    /// [`ClaudeAgent::connect`].
+    pub async fn reconnect(&mut self) -> Result<(), ClaudeAgentError> {
+        if self.custom_transport {
+            return Err(ClaudeAgentError::Config(
+                "reconnect() only respawns the built-in CLI transport; call set_transport() \
+                 and connect() to replace a custom one"
+                    .to_string(),
+            ));
+        }
+        let resume = self.cli_session_id().or_else(|| self.options.resume.clone());
+        if let Err(e) = self.disconnect().await {
+            tracing::warn!(error = %e, "Failed to close transport before reconnecting");
+        }
+        if let Some(session) = self.session_manager.current_session_mut() {
+            session.is_active = true;
+        }
+
+        self.options.continue_conversation = resume.is_none();
+        self.options.resume = resume;
+        self.connect(None).await
+    }
+
+    /// Why the control loop stopped without `disconnect`, if it has.
+    ///
+    /// The loop ends when the CLI's output ends, a write to the CLI fails, a
+    /// fatal read error arrives (see [`ClaudeAgentError::is_fatal`]), or reads fail
+    /// 16 times in a row. Control requests and permission callbacks go
+    /// unanswered from then on; [`ClaudeAgent::reconnect`] starts a new loop.
+    pub fn control_loop_error(&self) -> Option<ClaudeAgentError> {
+        self.control_loop_exit.lock().ok().and_then(|exit| exit.clone())
+    }
+
+    /// Check whether the agent is connected and its transport is still alive.
+    pub async fn is_connected(&self) -> bool {
+        match &self.transport {
+            Some(transport) if self.control_loop_handle.is_some() => {
+                transport.read().await.is_connected()
+            },
+            _ => false,
+        }
+    }
+
     /// Get the current session.
     pub fn current_session(&self) -> Option<&Session> {
         self.session_manager.current_session()
     }
 
+    /// Make the session `id` current, creating it if it does not exist.
+    ///
+    /// Later prompts are tagged with this session and recorded against it, so
+    /// one agent can interleave several conversations.
+    pub fn switch_session(&mut self, id: impl Into<String>) -> &Session {
+        self.session_manager.create_session_with_id(id)
+    }
+
+    /// List all sessions on this agent, ordered by id.
+    pub fn list_sessions(&self) -> Vec<&Session> {
+        self.session_manager.list_sessions()
+    }
+
+    /// Write this agent's sessions to `path`; see [`SessionManager::save_to_path`].
+    ///
+    /// The current session records the session id the CLI last reported, so
+    /// after loading the file it can be passed to `ClaudeAgentOptions::resume`.
+    pub fn save_sessions(&mut self, path: impl AsRef<Path>) -> Result<(), ClaudeAgentError> {
+        self.record_cli_session_id();
+        self.session_manager.save_to_path(path)
+    }
+
+    /// Store the CLI's session id on the current session, once it is known.
+    fn record_cli_session_id(&mut self) {
+        let Some(id) = self.cli_session_id() else {
+            return;
+        };
+        if let Some(session) = self.session_manager.current_session_mut() {
+            session.cli_session_id = Some(id);
+        }
+    }
+
     /// Get a reference to the hook registry.
     pub fn hook_registry(&self) -> &HookRegistry {
         &self.hook_registry
@@ -527,6 +1344,72 @@ impl ClaudeAgent {
         &mut self.hook_registry
     }
 
+    /// Get the options the agent was created with, including registered SDK servers.
+    pub fn options(&self) -> &ClaudeAgentOptions {
+        &self.options
+    }
+
+    /// Host `server` in this process and expose its tools to the CLI.
+    ///
+    /// The server is registered with the MCP manager and declared in
+    /// `options.mcp_servers` as an `sdk` server, so the CLI routes its MCP
+    /// traffic for it through `mcp_message` control requests answered by the
+    /// control loop. This lets plain Rust closures registered on an
+    /// [`SdkMcpServer`](crate::mcp::SdkMcpServer) act as tools without a
+    /// separate MCP binary.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ClaudeAgentError::Config` once connected, since the CLI reads its
+    /// MCP configuration at startup.
+    pub async fn add_mcp_server(
+        &mut self,
+        server: Box<dyn McpServer>,
+    ) -> Result<(), ClaudeAgentError> {
+        self.ensure_not_connected()?;
+        let name = server.name().to_string();
+        self.options
+            .mcp_servers
+            .insert(name.clone(), serde_json::json!({"type": "sdk", "name": name}));
+        self.mcp_manager.register(server).await;
+        Ok(())
+    }
+
+    /// Connect to the MCP server described by `config` and declare it to the CLI.
+    ///
+    /// The server is built with [`create_mcp_server`](crate::mcp::create_mcp_server)
+    /// and registered with the MCP manager under `name`, so its tools can be
+    /// listed and called from Rust. It is also added to `options.mcp_servers`
+    /// in the CLI's own format, so the CLI connects to it as well.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ClaudeAgentError::Config` once connected, or any error from
+    /// building the server from `config`.
+    pub async fn add_mcp_server_from_config(
+        &mut self,
+        name: impl Into<String>,
+        config: McpServerConfig,
+    ) -> Result<(), ClaudeAgentError> {
+        self.ensure_not_connected()?;
+        let name = name.into();
+        let entry = config.cli_config();
+        let server = crate::mcp::create_mcp_server(name.clone(), config)?;
+        self.options.mcp_servers.insert(name, entry);
+        self.mcp_manager.register_shared(server).await;
+        Ok(())
+    }
+
+    /// MCP servers are declared to the CLI at startup, so they must be added first.
+    fn ensure_not_connected(&self) -> Result<(), ClaudeAgentError> {
+        if self.is_running() {
+            return Err(ClaudeAgentError::Config(
+                "MCP servers must be added before connecting".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
     /// Get a reference to the MCP manager.
     pub fn mcp_manager(&self) -> &McpServerManager {
         &self.mcp_manager
@@ -538,6 +1421,108 @@ impl ClaudeAgent {
     }
 }
 
+impl Drop for ClaudeAgent {
+    /// Best-effort cleanup for an agent dropped while still connected.
+    ///
+    /// Aborts the control loop and kills the CLI on the spot, without the
+    /// graceful shutdown of [`ClaudeAgent::disconnect`], then drops the
+    /// transport. Needs no runtime.
+    fn drop(&mut self) {
+        let Some(handle) = self.control_loop_handle.take() else {
+            return;
+        };
+        tracing::warn!(
+            "ClaudeAgent dropped while connected; call disconnect() to shut down the CLI cleanly"
+        );
+        if let Some(shutdown) = self.control_loop_shutdown.take() {
+            let _ = shutdown.send(());
+        }
+        handle.abort();
+        if let Some(pid) = self.process_id.take() {
+            crate::transport::process::kill(pid);
+        }
+        // A subprocess transport kills its CLI when the last reference goes
+        self.transport = None;
+    }
+}
+
+/// Turn a control response the CLI refused into `ClaudeAgentError::ControlProtocol`.
+fn require_success(response: ControlResponse, context: &str) -> Result<(), ClaudeAgentError> {
+    if response.success {
+        return Ok(());
+    }
+    let error_msg = response.error.unwrap_or_else(|| "Unknown error".to_string());
+    Err(ClaudeAgentError::ControlProtocol(format!("{}: {}", context, error_msg)))
+}
+
+/// The stop reason carried by a `message_delta` event, bare or inside a `stream_event`.
+fn stop_reason(message: &Message) -> Option<String> {
+    match message {
+        Message::MessageDelta(delta) => delta.delta.stop_reason.clone(),
+        Message::StreamEvent(event) if event.event["type"] == "message_delta" => {
+            event.event["delta"]["stop_reason"].as_str().map(str::to_string)
+        },
+        _ => None,
+    }
+}
+
+/// Map a CLI rate-limit report to `ClaudeAgentError::RateLimited`.
+///
+/// Recognizes `rate_limit_error` error events and assistant messages flagged with
+/// `"error": "rate_limit"`. The retry delay is read from `retry_after_ms` or
+/// `retry_after` (seconds) on the message or its `error` object.
+fn rate_limit_error(value: &serde_json::Value) -> Option<ClaudeAgentError> {
+    let error = value.get("error");
+    let is_rate_limit = match value.get("type").and_then(|t| t.as_str()) {
+        Some("error") => {
+            error.and_then(|e| e.get("type")).and_then(|t| t.as_str()) == Some("rate_limit_error")
+        },
+        Some("assistant") => error.and_then(|e| e.as_str()) == Some("rate_limit"),
+        _ => false,
+    };
+    if !is_rate_limit {
+        return None;
+    }
+    let retry_after = [Some(value), error].into_iter().flatten().find_map(|source| {
+        if let Some(ms) = source.get("retry_after_ms").and_then(|v| v.as_u64()) {
+            return Some(std::time::Duration::from_millis(ms));
+        }
+        let secs = source.get("retry_after").and_then(|v| v.as_f64())?;
+        std::time::Duration::try_from_secs_f64(secs).ok()
+    });
+    Some(ClaudeAgentError::RateLimited { retry_after })
+}
+
+/// Map a CLI authentication failure to `ClaudeAgentError::Authentication`.
+///
+/// Recognizes assistant messages flagged with `"error": "authentication_failed"`,
+/// using their text as the detail, and `authentication_error` error events
+/// reported when the CLI starts up.
+fn authentication_error(value: &serde_json::Value) -> Option<ClaudeAgentError> {
+    let error = value.get("error");
+    let detail = match value.get("type").and_then(|t| t.as_str()) {
+        Some("assistant") if error.and_then(|e| e.as_str()) == Some("authentication_failed") => {
+            value
+                .pointer("/message/content")
+                .and_then(|c| c.as_array())
+                .into_iter()
+                .flatten()
+                .find_map(|block| block.get("text").and_then(|t| t.as_str()))
+                .map(str::to_string)
+        },
+        Some("error")
+            if error.and_then(|e| e.get("type")).and_then(|t| t.as_str())
+                == Some("authentication_error") =>
+        {
+            error.and_then(|e| e.get("message")).and_then(|m| m.as_str()).map(str::to_string)
+        },
+        _ => return None,
+    };
+    Some(ClaudeAgentError::Authentication(
+        detail.unwrap_or_else(|| "the CLI rejected its credentials".to_string()),
+    ))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -83,6 +83,45 @@ impl ControlProtocol {
         &self,
         request_type: ControlRequestType,
     ) -> Result<ControlResponse, ClaudeAgentError> {
+        let (_, response_rx) = self.start_request(request_type).await?;
+
+        // Wait for response
+        response_rx.await.map_err(|e| {
+            ClaudeAgentError::ControlProtocol(format!("Failed to receive response: {}", e))
+        })
+    }
+
+    /// Like `send_request`, but waits at most `timeout` for the response.
+    ///
+    /// Returns `Ok(None)` on timeout. The request is then forgotten, so a late
+    /// reply is dropped instead of being held for good.
+    pub async fn send_request_within(
+        &self,
+        request_type: ControlRequestType,
+        timeout: std::time::Duration,
+    ) -> Result<Option<ControlResponse>, ClaudeAgentError> {
+        let (request_id, response_rx) = self.start_request(request_type).await?;
+        match tokio::time::timeout(timeout, response_rx).await {
+            Ok(response) => response.map(Some).map_err(|e| {
+                ClaudeAgentError::ControlProtocol(format!("Failed to receive response: {}", e))
+            }),
+            Err(_) => {
+                self.cancel_request(&request_id).await;
+                Ok(None)
+            },
+        }
+    }
+
+    /// Stop waiting for the response to `request_id`.
+    pub async fn cancel_request(&self, request_id: &str) {
+        self.pending_requests.lock().await.remove(request_id);
+    }
+
+    /// Register a pending request and queue it for the CLI.
+    async fn start_request(
+        &self,
+        request_type: ControlRequestType,
+    ) -> Result<(String, oneshot::Receiver<ControlResponse>), ClaudeAgentError> {
         let request_id = Uuid::new_v4().to_string();
         let (response_tx, response_rx) = oneshot::channel();
 
@@ -95,14 +134,14 @@ impl ControlProtocol {
         // Send request
         let request = ControlRequest { request_id: request_id.clone(), request: request_type };
 
-        self.request_tx.send(request).await.map_err(|e| {
-            ClaudeAgentError::ControlProtocol(format!("Failed to send request: {}", e))
-        })?;
-
-        // Wait for response
-        response_rx.await.map_err(|e| {
-            ClaudeAgentError::ControlProtocol(format!("Failed to receive response: {}", e))
-        })
+        if let Err(e) = self.request_tx.send(request).await {
+            self.cancel_request(&request_id).await;
+            return Err(ClaudeAgentError::ControlProtocol(format!(
+                "Failed to send request: {}",
+                e
+            )));
+        }
+        Ok((request_id, response_rx))
     }
 
     /// Handle an incoming control response.
@@ -252,6 +291,30 @@ mod tests {
         assert!(result.success);
     }
 
+    #[tokio::test]
+    async fn send_request_within_forgets_unanswered_request() {
+        let (protocol, _rx) = ControlProtocol::new();
+        let response = protocol
+            .send_request_within(
+                ControlRequestType::McpStatus,
+                std::time::Duration::from_millis(10),
+            )
+            .await
+            .unwrap();
+        assert!(response.is_none());
+        assert!(protocol.pending_requests.lock().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn send_request_within_returns_response() {
+        let protocol = setup_responding_protocol().await;
+        let response = protocol
+            .send_request_within(ControlRequestType::McpStatus, std::time::Duration::from_secs(5))
+            .await
+            .unwrap();
+        assert!(response.is_some_and(|r| r.success));
+    }
+
     #[tokio::test]
     async fn control_request_type_variants_exhaustive() {
         // Verify all variants construct without panic
@@ -4,17 +4,25 @@ pub mod agent;
 pub mod control;
 pub mod hooks;
 pub mod permissions;
+pub mod query_handle;
 pub mod server_info;
 pub mod session;
 pub mod streaming;
+pub mod tool_log;
+pub(crate) mod tool_pairing;
+pub(crate) mod turn;
 
 pub use agent::ClaudeAgent;
 pub use control::{ControlProtocol, ControlRequest, ControlRequestType, ControlResponse};
 pub use hooks::{HookCallback, HookContext, HookInput, HookOutput, HookRegistry};
 pub use permissions::{PermissionCallback, PermissionHandler};
+pub use query_handle::QueryHandle;
 pub use server_info::{
     ContextUsageCategory, ContextUsageResponse, McpConnectionStatus, McpServerStatus,
     McpStatusResponse, McpToolInfo, ServerInfo,
 };
-pub use session::{Session, SessionManager};
-pub use streaming::{message_channel, MessageReceiver, MessageSender};
+pub use session::{Session, SessionManager, SessionStats};
+pub use streaming::{
+    message_channel, MessageReceiver, MessageSender, StreamAccumulator, Timestamped,
+};
+pub use tool_log::{ToolCallLogger, ToolCallRecord};
@@ -4,7 +4,10 @@ use std::future::Future;
 use std::pin::Pin;
 use std::sync::Arc;
 
-use crate::types::hooks::{PermissionResult, PermissionUpdate, ToolPermissionContext};
+use crate::types::hooks::{
+    PermissionBehavior, PermissionResult, PermissionUpdate, ToolPermissionContext,
+    ToolPermissionRule,
+};
 use crate::types::ClaudeAgentError;
 
 /// Type alias for permission callback functions.
@@ -20,14 +23,28 @@ pub type PermissionCallback = Arc<
 >;
 
 /// Permission handler for tool execution.
+///
+/// Rules are evaluated first; requests that no rule decides fall back to the callback.
+#[derive(Clone)]
 pub struct PermissionHandler {
     callback: Option<PermissionCallback>,
+    rules: Vec<ToolPermissionRule>,
 }
 
 impl PermissionHandler {
     /// Create a new permission handler.
     pub fn new() -> Self {
-        Self { callback: None }
+        Self { callback: None, rules: Vec::new() }
+    }
+
+    /// Set the permission rules evaluated before the callback.
+    pub fn set_rules(&mut self, rules: Vec<ToolPermissionRule>) {
+        self.rules = rules;
+    }
+
+    /// Get the configured permission rules.
+    pub fn rules(&self) -> &[ToolPermissionRule] {
+        &self.rules
     }
 
     /// Set the permission callback.
@@ -42,6 +59,23 @@ impl PermissionHandler {
         input: serde_json::Value,
         suggestions: Vec<PermissionUpdate>,
     ) -> Result<PermissionResult, ClaudeAgentError> {
+        let decided = self.rules.iter().find(|rule| rule.matches(tool_name, &input));
+        match decided.map(|rule| &rule.behavior) {
+            Some(PermissionBehavior::Allow) => {
+                return Ok(PermissionResult::Allow {
+                    updated_input: None,
+                    updated_permissions: None,
+                });
+            },
+            Some(PermissionBehavior::Deny) => {
+                return Ok(PermissionResult::Deny {
+                    message: format!("Denied by permission rule for {}", tool_name),
+                    interrupt: false,
+                });
+            },
+            Some(PermissionBehavior::Ask) | None => {},
+        }
+
         match &self.callback {
             Some(callback) => {
                 let context = ToolPermissionContext { suggestions };
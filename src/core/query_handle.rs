@@ -0,0 +1,56 @@
+//! Handle for a single query's message stream.
+
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures::stream::BoxStream;
+use futures::{Stream, StreamExt};
+
+use crate::types::{ClaudeAgentError, Message};
+
+/// Stream of messages for one query, ending after the turn's `Result` message.
+///
+/// Returned by `ClaudeAgent::query_handle()`. Poll it like any other stream, or
+/// call [`QueryHandle::detach`] to stop consuming while the turn finishes in
+/// the background.
+pub struct QueryHandle {
+    inner: BoxStream<'static, Result<Message, ClaudeAgentError>>,
+    finished: bool,
+}
+
+impl QueryHandle {
+    pub(crate) fn new(inner: BoxStream<'static, Result<Message, ClaudeAgentError>>) -> Self {
+        Self { inner, finished: false }
+    }
+
+    /// Stop yielding to the caller but keep draining until the `Result` arrives.
+    ///
+    /// The turn is not interrupted, so session stats still account for it. The
+    /// background task holds a read lock on the transport until it finishes;
+    /// abort the returned handle to stop draining early.
+    pub fn detach(mut self) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(async move {
+            while !self.finished {
+                if self.next().await.is_none() {
+                    break;
+                }
+            }
+        })
+    }
+}
+
+impl Stream for QueryHandle {
+    type Item = Result<Message, ClaudeAgentError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if self.finished {
+            return Poll::Ready(None);
+        }
+        let polled = self.inner.poll_next_unpin(cx);
+        match &polled {
+            Poll::Ready(Some(Ok(Message::Result(_)))) | Poll::Ready(None) => self.finished = true,
+            _ => {},
+        }
+        polled
+    }
+}
@@ -168,10 +168,31 @@ impl Default for ContextUsageResponse {
 /// Server initialization information from Claude Code CLI.
 ///
 /// Returned by `ClaudeAgent::get_server_info()`. Contains metadata about
-/// the connected Claude Code server, including available commands,
-/// output styles, and server capabilities.
+/// the connected Claude Code server, including the session id, model,
+/// available tools and commands, output styles, and server capabilities.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ServerInfo {
+    /// Session id assigned by the CLI.
+    #[serde(default, alias = "sessionId", skip_serializing_if = "Option::is_none")]
+    pub session_id: Option<String>,
+    /// Model in use for the session.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    /// Names of the tools available to the model.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tools: Vec<String>,
+    /// Working directory of the CLI process.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cwd: Option<String>,
+    /// Where the API key came from (e.g. "ANTHROPIC_API_KEY", "none").
+    #[serde(default, rename = "apiKeySource", skip_serializing_if = "Option::is_none")]
+    pub api_key_source: Option<String>,
+    /// Active permission mode.
+    #[serde(default, rename = "permissionMode", skip_serializing_if = "Option::is_none")]
+    pub permission_mode: Option<String>,
+    /// Available slash commands.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub slash_commands: Vec<String>,
     /// Output style configured for the server (e.g. "concise", "verbose").
     #[serde(default, rename = "outputStyle", skip_serializing_if = "Option::is_none")]
     pub output_style: Option<String>,
@@ -181,6 +202,9 @@ pub struct ServerInfo {
     /// Additional fields not explicitly modeled, preserved as-is.
     #[serde(flatten)]
     pub extra: serde_json::Value,
+    /// The JSON this info was built from, for [`ServerInfo::get`].
+    #[serde(skip)]
+    raw: serde_json::Value,
 }
 
 impl ServerInfo {
@@ -189,7 +213,9 @@ impl ServerInfo {
     /// Accepts a JSON value and deserializes it into the typed struct.
     /// Returns a default `ServerInfo` if deserialization fails.
     pub fn new(data: serde_json::Value) -> Self {
-        serde_json::from_value(data).unwrap_or_default()
+        let mut info: Self = serde_json::from_value(data.clone()).unwrap_or_default();
+        info.raw = data;
+        info
     }
 
     /// Get the output style, if present.
@@ -202,21 +228,34 @@ impl ServerInfo {
         self.commands.as_ref().map(|cmds| cmds.iter().map(String::as_str).collect())
     }
 
-    /// Get a specific field from the extra (untyped) server info data.
+    /// Get the raw JSON this info was built from.
+    pub fn raw(&self) -> &serde_json::Value {
+        &self.raw
+    }
+
+    /// Get a specific field from the server info data by its wire name.
     ///
-    /// For typed fields, use the `output_style` and `commands` accessors directly.
-    /// This method looks up unknown/arbitrary keys in the extra data.
+    /// Looks up the original JSON first, so typed fields remain reachable by
+    /// key, then falls back to the extra (untyped) data.
     pub fn get(&self, key: &str) -> Option<&serde_json::Value> {
-        self.extra.get(key)
+        self.raw.get(key).or_else(|| self.extra.get(key))
     }
 }
 
 impl Default for ServerInfo {
     fn default() -> Self {
         Self {
+            session_id: None,
+            model: None,
+            tools: Vec::new(),
+            cwd: None,
+            api_key_source: None,
+            permission_mode: None,
+            slash_commands: Vec::new(),
             output_style: None,
             commands: None,
             extra: serde_json::Value::Object(serde_json::Map::new()),
+            raw: serde_json::Value::Null,
         }
     }
 }
@@ -310,6 +349,33 @@ mod tests {
         assert!(info.get("missing").is_none());
     }
 
+    #[test]
+    fn server_info_from_realistic_init() {
+        let data = serde_json::json!({
+            "type": "system",
+            "subtype": "init",
+            "cwd": "/work/project",
+            "session_id": "2f1c9a52-7d0e-4c8b-9f8e-1a2b3c4d5e6f",
+            "tools": ["Task", "Bash", "Read", "Edit"],
+            "mcp_servers": [{"name": "docs", "status": "connected"}],
+            "model": "claude-sonnet-4-5-20250929",
+            "permissionMode": "default",
+            "slash_commands": ["compact", "context"],
+            "apiKeySource": "ANTHROPIC_API_KEY",
+            "output_style": "default"
+        });
+        let info = ServerInfo::new(data);
+        assert_eq!(info.session_id.as_deref(), Some("2f1c9a52-7d0e-4c8b-9f8e-1a2b3c4d5e6f"));
+        assert_eq!(info.model.as_deref(), Some("claude-sonnet-4-5-20250929"));
+        assert_eq!(info.tools, vec!["Task", "Bash", "Read", "Edit"]);
+        assert_eq!(info.cwd.as_deref(), Some("/work/project"));
+        assert_eq!(info.api_key_source.as_deref(), Some("ANTHROPIC_API_KEY"));
+        assert_eq!(info.permission_mode.as_deref(), Some("default"));
+        assert_eq!(info.slash_commands, vec!["compact", "context"]);
+        assert!(info.get("mcp_servers").unwrap().is_array());
+        assert_eq!(info.get("model").and_then(|v| v.as_str()), Some("claude-sonnet-4-5-20250929"));
+    }
+
     #[test]
     fn mcp_status_response_serialization_roundtrip() {
         let response = McpStatusResponse {
@@ -1,20 +1,38 @@
 //! Session management for Claude Agent SDK.
 
 use std::collections::HashMap;
+use std::path::Path;
 
+use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
+use crate::types::message::ResultMessage;
+use crate::types::ClaudeAgentError;
+
 /// Session state for a conversation.
-#[derive(Debug, Clone)]
+///
+/// Sessions serialize to JSON with [`Session::to_json`], so they can be saved
+/// and their `cli_session_id` passed back to the CLI via
+/// `ClaudeAgentOptions::resume` after a restart.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Session {
     pub id: String,
     pub is_active: bool,
+    #[serde(default)]
     pub checkpoints: Vec<SessionCheckpoint>,
+    #[serde(default)]
     pub metadata: HashMap<String, serde_json::Value>,
+    /// The last prompt sent in this session.
+    #[serde(default)]
+    pub last_message: Option<String>,
+    /// The session id the CLI reported for this conversation, which
+    /// `ClaudeAgentOptions::resume` expects. `id` is local to this process.
+    #[serde(default)]
+    pub cli_session_id: Option<String>,
 }
 
 /// A checkpoint in a session for file rewinding.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SessionCheckpoint {
     pub user_message_id: String,
     pub timestamp: u64,
@@ -28,12 +46,33 @@ impl Session {
             is_active: true,
             checkpoints: Vec::new(),
             metadata: HashMap::new(),
+            last_message: None,
+            cli_session_id: None,
         }
     }
 
     /// Create a session with a specific ID.
     pub fn with_id(id: impl Into<String>) -> Self {
-        Self { id: id.into(), is_active: true, checkpoints: Vec::new(), metadata: HashMap::new() }
+        Self {
+            id: id.into(),
+            is_active: true,
+            checkpoints: Vec::new(),
+            metadata: HashMap::new(),
+            last_message: None,
+            cli_session_id: None,
+        }
+    }
+
+    /// Serialize the session to JSON.
+    pub fn to_json(&self) -> Result<String, ClaudeAgentError> {
+        serde_json::to_string_pretty(self)
+            .map_err(|e| ClaudeAgentError::JSONDecode(format!("Failed to serialize session: {e}")))
+    }
+
+    /// Restore a session serialized with [`Session::to_json`].
+    pub fn from_json(json: &str) -> Result<Self, ClaudeAgentError> {
+        serde_json::from_str(json)
+            .map_err(|e| ClaudeAgentError::JSONDecode(format!("Invalid session JSON: {e}")))
     }
 
     /// Add a checkpoint.
@@ -65,6 +104,8 @@ impl Session {
             is_active: true,
             checkpoints: self.checkpoints.clone(),
             metadata: self.metadata.clone(),
+            last_message: self.last_message.clone(),
+            cli_session_id: None,
         }
     }
 }
@@ -75,9 +116,45 @@ impl Default for Session {
     }
 }
 
+/// Cumulative statistics aggregated from result messages.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SessionStats {
+    /// Number of result messages seen (one per completed query).
+    pub results: u32,
+    /// Number of results flagged as errors.
+    pub errors: u32,
+    /// Total conversation turns reported by the CLI.
+    pub num_turns: u32,
+    /// Total wall-clock duration in milliseconds.
+    pub duration_ms: u64,
+    /// Total API duration in milliseconds.
+    pub duration_api_ms: u64,
+    /// Total cost in USD, when reported.
+    pub total_cost_usd: f64,
+    /// The most recent result message.
+    pub last_result: Option<ResultMessage>,
+}
+
+impl SessionStats {
+    /// Fold a result message into the running totals.
+    pub fn record_result(&mut self, result: &ResultMessage) {
+        self.results += 1;
+        if result.is_error {
+            self.errors += 1;
+        }
+        self.num_turns += result.num_turns;
+        self.duration_ms += result.duration_ms;
+        self.duration_api_ms += result.duration_api_ms;
+        self.total_cost_usd += result.total_cost_usd.unwrap_or(0.0);
+        self.last_result = Some(result.clone());
+    }
+}
+
 /// Session manager for multiple sessions.
+#[derive(Debug, Serialize, Deserialize)]
 pub struct SessionManager {
     sessions: HashMap<String, Session>,
+    #[serde(default)]
     current_session_id: Option<String>,
 }
 
@@ -95,6 +172,14 @@ impl SessionManager {
         self.sessions.entry(id).or_insert(session)
     }
 
+    /// Create a session with a specific ID and set it as current.
+    pub fn create_session_with_id(&mut self, id: impl Into<String>) -> &Session {
+        let session = Session::with_id(id);
+        let id = session.id.clone();
+        self.current_session_id = Some(id.clone());
+        self.sessions.entry(id).or_insert(session)
+    }
+
     /// Get the current session.
     pub fn current_session(&self) -> Option<&Session> {
         self.current_session_id.as_ref().and_then(|id| self.sessions.get(id))
@@ -111,6 +196,13 @@ impl SessionManager {
         self.sessions.get(id)
     }
 
+    /// List all sessions, ordered by ID.
+    pub fn list_sessions(&self) -> Vec<&Session> {
+        let mut sessions: Vec<&Session> = self.sessions.values().collect();
+        sessions.sort_by(|a, b| a.id.cmp(&b.id));
+        sessions
+    }
+
     /// Resume a session by ID.
     pub fn resume_session(&mut self, id: &str) -> Option<&Session> {
         if self.sessions.contains_key(id) {
@@ -120,6 +212,33 @@ impl SessionManager {
             None
         }
     }
+
+    /// Write every session, and which one is current, to `path` as JSON.
+    pub fn save_to_path(&self, path: impl AsRef<Path>) -> Result<(), ClaudeAgentError> {
+        let path = path.as_ref();
+        let json = serde_json::to_string_pretty(self).map_err(|e| {
+            ClaudeAgentError::JSONDecode(format!("Failed to serialize sessions: {e}"))
+        })?;
+        std::fs::write(path, json).map_err(|e| {
+            ClaudeAgentError::Io(format!("Failed to write session file {}: {e}", path.display()))
+        })
+    }
+
+    /// Load sessions written by [`SessionManager::save_to_path`].
+    ///
+    /// # Errors
+    ///
+    /// Returns `ClaudeAgentError::Io` if the file cannot be read, and
+    /// `ClaudeAgentError::Config` if it is not a valid session file.
+    pub fn load_from_path(path: impl AsRef<Path>) -> Result<Self, ClaudeAgentError> {
+        let path = path.as_ref();
+        let json = std::fs::read_to_string(path).map_err(|e| {
+            ClaudeAgentError::Io(format!("Failed to read session file {}: {e}", path.display()))
+        })?;
+        serde_json::from_str(&json).map_err(|e| {
+            ClaudeAgentError::Config(format!("Invalid session file {}: {e}", path.display()))
+        })
+    }
 }
 
 impl Default for SessionManager {
@@ -1,10 +1,16 @@
 //! Message streaming utilities.
 
+use std::collections::BTreeMap;
+
 use futures::stream::BoxStream;
+use futures::StreamExt;
 use tokio::sync::mpsc;
 
+use crate::types::message::{AssistantMessage, ContentBlock, Delta, TextBlock, ToolUseBlock};
 use crate::types::{ClaudeAgentError, Message};
 
+pub use crate::types::Timestamped;
+
 /// Create a message channel for streaming.
 pub fn message_channel(buffer_size: usize) -> (MessageSender, MessageReceiver) {
     let (tx, rx) = mpsc::channel(buffer_size);
@@ -57,3 +63,191 @@ impl MessageReceiver {
         }))
     }
 }
+
+/// Reassembles partial streaming events into complete assistant messages.
+///
+/// When `include_partial_messages` is enabled the CLI emits `content_block_start`,
+/// `content_block_delta` and `content_block_stop` events (either top-level or wrapped
+/// in a `stream_event`). The accumulator buffers text and input-json deltas per block
+/// index and produces a single `AssistantMessage` once `message_stop` arrives.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// let stream = StreamAccumulator::wrap(agent.query("Hello").await?);
+/// ```
+#[derive(Debug, Default)]
+pub struct StreamAccumulator {
+    model: String,
+    parent_tool_use_id: Option<String>,
+    blocks: BTreeMap<u32, PartialBlock>,
+}
+
+/// A content block that is still receiving deltas.
+#[derive(Debug)]
+enum PartialBlock {
+    Text(String),
+    ToolUse {
+        id: String,
+        name: String,
+        input: serde_json::Value,
+        partial_json: String,
+    },
+    Complete(ContentBlock),
+}
+
+impl StreamAccumulator {
+    /// Create an empty accumulator.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Feed a message into the accumulator.
+    ///
+    /// Returns `None` when the message was a partial event that has been buffered,
+    /// the assembled `Message::Assistant` when `message_stop` completes a message,
+    /// and the original message unchanged for anything that is not a partial event.
+    pub fn push(&mut self, message: Message) -> Option<Result<Message, ClaudeAgentError>> {
+        match message {
+            Message::StreamEvent(event) => {
+                match serde_json::from_value::<Message>(event.event.clone()) {
+                    Ok(inner) if is_partial_event(&inner) => {
+                        if event.parent_tool_use_id.is_some() {
+                            self.parent_tool_use_id = event.parent_tool_use_id;
+                        }
+                        self.apply(inner)
+                    },
+                    _ => Some(Ok(Message::StreamEvent(event))),
+                }
+            },
+            message if is_partial_event(&message) => self.apply(message),
+            message => Some(Ok(message)),
+        }
+    }
+
+    /// Wrap a message stream so that partial events are coalesced into complete messages.
+    pub fn wrap<'a>(
+        stream: BoxStream<'a, Result<Message, ClaudeAgentError>>,
+    ) -> BoxStream<'a, Result<Message, ClaudeAgentError>> {
+        let mut accumulator = Self::new();
+        let mut stream = stream;
+        Box::pin(async_stream::stream! {
+            while let Some(item) = stream.next().await {
+                match item {
+                    Ok(message) => {
+                        if let Some(out) = accumulator.push(message) {
+                            yield out;
+                        }
+                    },
+                    Err(e) => yield Err(e),
+                }
+            }
+        })
+    }
+
+    fn apply(&mut self, message: Message) -> Option<Result<Message, ClaudeAgentError>> {
+        match message {
+            Message::MessageStart(start) => {
+                self.blocks.clear();
+                self.model = start.message.model;
+                None
+            },
+            Message::ContentBlockStart(start) => {
+                let block = match start.content_block {
+                    ContentBlock::Text(text) => PartialBlock::Text(text.text),
+                    ContentBlock::ToolUse(tool) => PartialBlock::ToolUse {
+                        id: tool.id,
+                        name: tool.name,
+                        input: tool.input,
+                        partial_json: String::new(),
+                    },
+                    other => PartialBlock::Complete(other),
+                };
+                self.blocks.insert(start.index, block);
+                None
+            },
+            Message::ContentBlockDelta(delta) => {
+                let block = self.blocks.entry(delta.index).or_insert_with(|| match delta.delta {
+                    Delta::TextDelta { .. } => PartialBlock::Text(String::new()),
+                    _ => PartialBlock::ToolUse {
+                        id: String::new(),
+                        name: String::new(),
+                        input: serde_json::Value::Null,
+                        partial_json: String::new(),
+                    },
+                });
+                match (block, delta.delta) {
+                    (PartialBlock::Text(buf), Delta::TextDelta { text }) => buf.push_str(&text),
+                    (
+                        PartialBlock::ToolUse { partial_json, .. },
+                        Delta::InputJsonDelta { partial_json: chunk },
+                    ) => partial_json.push_str(&chunk),
+                    (
+                        PartialBlock::ToolUse { id, name, input, .. },
+                        Delta::ToolUse { id: new_id, name: new_name, input: new_input },
+                    ) => {
+                        if let Some(new_id) = new_id {
+                            *id = new_id;
+                        }
+                        if let Some(new_name) = new_name {
+                            *name = new_name;
+                        }
+                        if let Some(new_input) = new_input {
+                            *input = new_input;
+                        }
+                    },
+                    _ => {},
+                }
+                None
+            },
+            Message::MessageStop(_) => Some(self.finish().map(Message::Assistant)),
+            // content_block_stop, message_delta and ping carry nothing to assemble
+            _ => None,
+        }
+    }
+
+    /// Build the assembled assistant message and reset the accumulator.
+    fn finish(&mut self) -> Result<AssistantMessage, ClaudeAgentError> {
+        let blocks = std::mem::take(&mut self.blocks);
+        let mut content = Vec::with_capacity(blocks.len());
+        for block in blocks.into_values() {
+            content.push(match block {
+                PartialBlock::Text(text) => ContentBlock::Text(TextBlock { text }),
+                PartialBlock::ToolUse { id, name, input, partial_json } => {
+                    let input = if partial_json.trim().is_empty() {
+                        input
+                    } else {
+                        serde_json::from_str(&partial_json).map_err(|e| {
+                            ClaudeAgentError::MessageParse(format!(
+                                "Failed to parse tool input for {}: {}",
+                                name, e
+                            ))
+                        })?
+                    };
+                    ContentBlock::ToolUse(ToolUseBlock { id, name, input })
+                },
+                PartialBlock::Complete(block) => block,
+            });
+        }
+        Ok(AssistantMessage {
+            content,
+            model: std::mem::take(&mut self.model),
+            parent_tool_use_id: self.parent_tool_use_id.take(),
+            error: None,
+        })
+    }
+}
+
+/// Whether a message is one of the partial streaming events handled by `StreamAccumulator`.
+fn is_partial_event(message: &Message) -> bool {
+    matches!(
+        message,
+        Message::MessageStart(_)
+            | Message::ContentBlockStart(_)
+            | Message::ContentBlockDelta(_)
+            | Message::ContentBlockStop(_)
+            | Message::MessageDelta(_)
+            | Message::MessageStop(_)
+            | Message::Ping(_)
+    )
+}
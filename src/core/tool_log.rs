@@ -0,0 +1,136 @@
+//! JSONL logging of tool calls observed in the message stream.
+
+use std::io::Write;
+use std::path::Path;
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+
+use super::tool_pairing::{ToolCallPairs, DEFAULT_TOOL_RESULT_TIMEOUT};
+use crate::types::message::{
+    ContentBlock, Message, MessageContent, ToolResultBlock, ToolResultContent,
+};
+
+/// One completed tool call, written as a single JSON line.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ToolCallRecord {
+    /// When the tool use was seen, in RFC 3339 format.
+    pub timestamp: String,
+    pub tool_name: String,
+    pub tool_input: serde_json::Value,
+    pub tool_use_id: String,
+    /// The subagent's `Task` tool use when the call was made by a subagent.
+    pub parent_tool_use_id: Option<String>,
+    /// The result content: a string or an array of content blocks.
+    pub tool_output: Option<serde_json::Value>,
+    /// The result's text when the tool reported an error.
+    pub error: Option<String>,
+}
+
+/// Writes a [`ToolCallRecord`] for every tool use once its result arrives.
+///
+/// Attach one to an agent with `ClaudeAgent::set_tool_call_logger`, or feed it
+/// messages yourself with [`ToolCallLogger::observe`]. Tool uses whose result
+/// has not arrived by the end of the turn, or within
+/// `DEFAULT_TOOL_RESULT_TIMEOUT`, are dropped without being logged.
+pub struct ToolCallLogger {
+    writer: Mutex<Box<dyn Write + Send>>,
+    pending: Mutex<ToolCallPairs<ToolCallRecord>>,
+}
+
+impl ToolCallLogger {
+    /// Log to `writer`, one JSON object per line.
+    pub fn new(writer: impl Write + Send + 'static) -> Self {
+        Self {
+            writer: Mutex::new(Box::new(writer)),
+            pending: Mutex::new(ToolCallPairs::new(DEFAULT_TOOL_RESULT_TIMEOUT)),
+        }
+    }
+
+    /// Append to the file at `path`, creating it if needed.
+    pub fn to_file(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        let file = std::fs::OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self::new(file))
+    }
+
+    /// Record tool uses in `message` and write the calls it completes.
+    ///
+    /// # Errors
+    ///
+    /// Returns any error from the writer. The call is dropped in that case.
+    pub fn observe(&self, message: &Message) -> std::io::Result<()> {
+        let Ok(mut pending) = self.pending.lock() else {
+            return Ok(());
+        };
+        pending.expire();
+        let mut completed = Vec::new();
+        match message {
+            Message::Assistant(assistant) => {
+                for block in &assistant.content {
+                    let ContentBlock::ToolUse(tool_use) = block else {
+                        continue;
+                    };
+                    let record = ToolCallRecord {
+                        timestamp: chrono::Utc::now().to_rfc3339(),
+                        tool_name: tool_use.name.clone(),
+                        tool_input: tool_use.input.clone(),
+                        tool_use_id: tool_use.id.clone(),
+                        parent_tool_use_id: assistant.parent_tool_use_id.clone(),
+                        tool_output: None,
+                        error: None,
+                    };
+                    completed.extend(pending.tool_use(tool_use.id.clone(), record));
+                }
+            },
+            Message::User(user) => {
+                let MessageContent::Blocks(blocks) = &user.content else {
+                    return Ok(());
+                };
+                for block in blocks {
+                    if let ContentBlock::ToolResult(result) = block {
+                        completed.extend(pending.tool_result(result.clone()));
+                    }
+                }
+            },
+            // Results arrive before the turn ends, so nothing pending will complete
+            Message::Result(_) => {
+                pending.drain();
+            },
+            _ => {},
+        }
+        drop(pending);
+        for (record, result) in completed {
+            self.write(&with_result(record, &result))?;
+        }
+        Ok(())
+    }
+
+    fn write(&self, record: &ToolCallRecord) -> std::io::Result<()> {
+        let line = serde_json::to_string(record)?;
+        let mut writer = self
+            .writer
+            .lock()
+            .map_err(|_| std::io::Error::other("tool call log writer poisoned"))?;
+        writeln!(writer, "{}", line)?;
+        writer.flush()
+    }
+}
+
+/// Fill in the output of `record` from its tool result.
+fn with_result(mut record: ToolCallRecord, result: &ToolResultBlock) -> ToolCallRecord {
+    record.tool_output = result.content.as_ref().and_then(|c| serde_json::to_value(c).ok());
+    if result.is_error == Some(true) {
+        record.error = Some(result.content.as_ref().map(text_of).unwrap_or_default());
+    }
+    record
+}
+
+/// The text of a tool result, joining text blocks.
+fn text_of(content: &ToolResultContent) -> String {
+    match content {
+        ToolResultContent::Text(text) => text.clone(),
+        ToolResultContent::Blocks(blocks) => {
+            blocks.iter().filter_map(|block| block.get("text").and_then(|t| t.as_str())).collect()
+        },
+    }
+}
@@ -0,0 +1,118 @@
+//! Matching tool results to the tool uses that requested them.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+use crate::types::message::ToolResultBlock;
+
+/// How long `ToolCallCorrelator::default` waits for a result.
+pub const DEFAULT_TOOL_RESULT_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// Tool uses waiting for their results, and results waiting for their tool uses.
+///
+/// Shared by `ToolCallCorrelator` and `ToolCallLogger`, which attach different
+/// data `T` to each tool use. Either side waits at most `timeout` for the other;
+/// [`ToolCallPairs::expire`] hands back the tool uses that timed out and drops
+/// results that never found their tool use, such as late results for tool uses
+/// that already timed out.
+#[derive(Debug)]
+pub(crate) struct ToolCallPairs<T> {
+    timeout: Duration,
+    /// Pending tool uses by id, in arrival order, with their deadlines
+    pending: Vec<(String, T, Instant)>,
+    /// Results whose tool use has not been seen yet, with their deadlines
+    early: HashMap<String, (ToolResultBlock, Instant)>,
+}
+
+impl<T> ToolCallPairs<T> {
+    pub(crate) fn new(timeout: Duration) -> Self {
+        Self { timeout, pending: Vec::new(), early: HashMap::new() }
+    }
+
+    /// Record the tool use `id`, returning it with its result if that came first.
+    pub(crate) fn tool_use(&mut self, id: String, call: T) -> Option<(T, ToolResultBlock)> {
+        match self.early.remove(&id) {
+            Some((result, _)) => Some((call, result)),
+            None => {
+                self.pending.push((id, call, Instant::now() + self.timeout));
+                None
+            },
+        }
+    }
+
+    /// Record a result, returning it with its tool use if that is pending.
+    pub(crate) fn tool_result(&mut self, result: ToolResultBlock) -> Option<(T, ToolResultBlock)> {
+        match self.pending.iter().position(|(id, _, _)| *id == result.tool_use_id) {
+            Some(index) => {
+                let (_, call, _) = self.pending.remove(index);
+                Some((call, result))
+            },
+            None => {
+                let deadline = Instant::now() + self.timeout;
+                self.early.insert(result.tool_use_id.clone(), (result, deadline));
+                None
+            },
+        }
+    }
+
+    /// When the oldest pending tool use times out.
+    pub(crate) fn next_deadline(&self) -> Option<Instant> {
+        self.pending.iter().map(|(_, _, deadline)| *deadline).min()
+    }
+
+    /// Forget everything that waited longer than the timeout, returning the
+    /// tool uses that did.
+    pub(crate) fn expire(&mut self) -> Vec<T> {
+        let now = Instant::now();
+        self.early.retain(|_, (_, deadline)| *deadline > now);
+        let (expired, pending) =
+            std::mem::take(&mut self.pending).into_iter().partition(|(_, _, d)| *d <= now);
+        self.pending = pending;
+        expired.into_iter().map(|(_, call, _)| call).collect()
+    }
+
+    /// Forget everything, returning the tool uses still waiting for results.
+    pub(crate) fn drain(&mut self) -> Vec<T> {
+        self.early.clear();
+        self.pending.drain(..).map(|(_, call, _)| call).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result(id: &str) -> ToolResultBlock {
+        ToolResultBlock { tool_use_id: id.to_string(), content: None, is_error: None }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_late_result_for_timed_out_tool_use_is_dropped() {
+        let mut pairs = ToolCallPairs::new(Duration::from_secs(10));
+        assert!(pairs.tool_use("a".to_string(), "a").is_none());
+
+        tokio::time::advance(Duration::from_secs(11)).await;
+        assert_eq!(pairs.expire(), ["a"]);
+
+        // The late result waits like any early one, then is dropped
+        assert!(pairs.tool_result(result("a")).is_none());
+        tokio::time::advance(Duration::from_secs(11)).await;
+        assert!(pairs.expire().is_empty());
+        assert!(pairs.early.is_empty());
+        assert!(pairs.tool_use("a".to_string(), "a again").is_none());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_early_result_pairs_within_timeout() {
+        let mut pairs = ToolCallPairs::new(Duration::from_secs(10));
+        assert!(pairs.tool_result(result("a")).is_none());
+        tokio::time::advance(Duration::from_secs(5)).await;
+        assert!(pairs.expire().is_empty());
+
+        let (call, result) = pairs.tool_use("a".to_string(), 1).expect("paired");
+        assert_eq!((call, result.tool_use_id.as_str()), (1, "a"));
+        assert!(pairs.drain().is_empty());
+    }
+}
@@ -0,0 +1,302 @@
+//! The message stream of a single query turn.
+//!
+//! A [`TurnStream`] holds the agent's turn lock until its turn is over. If the
+//! stream stops early, because it was dropped, cancelled, hit
+//! `max_messages_per_query`, or yielded a fatal error, the CLI is still
+//! answering the prompt. The turn is then interrupted and its remaining output
+//! read up to the `Result` in the background before the lock is released, so
+//! that output never reaches the next query. Settings overridden for the turn
+//! are restored before the lock is released, however the turn ended.
+
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use futures::future::BoxFuture;
+use futures::stream::BoxStream;
+use futures::{FutureExt, Stream, StreamExt};
+use tokio::sync::{oneshot, OwnedMutexGuard};
+use tokio_util::sync::{CancellationToken, WaitForCancellationFutureOwned};
+
+use super::control::ControlProtocol;
+use crate::types::{ClaudeAgentError, Message, Timestamped};
+
+/// How long the CLI may take to acknowledge an interrupt.
+const INTERRUPT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long an abandoned turn may take to reach its `Result`.
+const TURN_DRAIN_TIMEOUT: Duration = Duration::from_secs(10);
+
+type Stamped = Result<Timestamped<Message>, ClaudeAgentError>;
+
+/// Settings overridden for one turn, with the values to put back afterwards.
+#[derive(Debug, Default)]
+pub(crate) struct Restore {
+    /// The model to switch back to, if the turn changed it.
+    pub(crate) model: Option<Option<String>>,
+    /// The permission mode to switch back to, if the turn changed it.
+    pub(crate) permission_mode: Option<String>,
+}
+
+impl Restore {
+    pub(crate) fn is_empty(&self) -> bool {
+        self.model.is_none() && self.permission_mode.is_none()
+    }
+
+    /// Put the saved settings back on the CLI, logging failures.
+    pub(crate) async fn apply(&self, protocol: &ControlProtocol) {
+        if let Some(model) = &self.model {
+            if let Err(e) = protocol.set_model(model.as_deref()).await {
+                tracing::warn!(error = %e, "Failed to restore model after query");
+            }
+        }
+        if let Some(mode) = &self.permission_mode {
+            if let Err(e) = protocol.set_permission_mode(mode).await {
+                tracing::warn!(error = %e, "Failed to restore permission mode after query");
+            }
+        }
+    }
+}
+
+/// What a turn holds until it is over.
+struct TurnEnd {
+    turn: OwnedMutexGuard<()>,
+    protocol: Option<Arc<ControlProtocol>>,
+    restore: Restore,
+}
+
+impl TurnEnd {
+    /// Restore overridden settings, then release the turn lock.
+    async fn complete(self) {
+        if let Some(protocol) = &self.protocol {
+            self.restore.apply(protocol).await;
+        }
+        drop(self.turn);
+    }
+
+    /// Interrupt the turn, read the rest of it, then complete it.
+    ///
+    /// A turn whose `Result` has already arrived is not interrupted.
+    /// `interrupted` fires once the interrupt was acknowledged or the turn
+    /// has ended, whichever comes first.
+    async fn abandon(
+        self,
+        mut messages: BoxStream<'static, Stamped>,
+        interrupted: oneshot::Sender<()>,
+    ) {
+        let drain = async {
+            while let Some(item) = messages.next().await {
+                if matches!(item, Ok(Timestamped { value: Message::Result(_), .. })) {
+                    break;
+                }
+            }
+        };
+        let finish = async {
+            tokio::pin!(drain);
+            if futures::poll!(drain.as_mut()).is_pending() {
+                let interrupt = self.interrupt();
+                tokio::pin!(interrupt);
+                tokio::select! {
+                    () = &mut drain => {},
+                    () = &mut interrupt => {
+                        let _ = interrupted.send(());
+                        drain.await;
+                    },
+                }
+            }
+        };
+        if tokio::time::timeout(TURN_DRAIN_TIMEOUT, finish).await.is_err() {
+            tracing::warn!("Abandoned turn did not end in time; releasing it anyway");
+        }
+        self.complete().await;
+    }
+
+    async fn interrupt(&self) {
+        let Some(protocol) = &self.protocol else {
+            return;
+        };
+        match tokio::time::timeout(INTERRUPT_TIMEOUT, protocol.interrupt()).await {
+            Ok(Ok(_)) => {},
+            Ok(Err(e)) => tracing::warn!(error = %e, "Failed to interrupt abandoned turn"),
+            Err(_) => tracing::warn!("Interrupt for abandoned turn was not acknowledged"),
+        }
+    }
+}
+
+/// Messages for one turn, ending after its `Result`.
+pub(crate) struct TurnStream {
+    messages: Option<BoxStream<'static, Stamped>>,
+    end: Option<TurnEnd>,
+    /// Work that must finish before the stream yields again: restoring
+    /// settings before the `Result`, or interrupting an abandoned turn.
+    pending: Option<BoxFuture<'static, ()>>,
+    /// The `Result`, held back until `pending` is done.
+    held: Option<Stamped>,
+    cancelled: Option<Pin<Box<WaitForCancellationFutureOwned>>>,
+    error_on_result_failure: bool,
+    max_messages: Option<usize>,
+    count: usize,
+}
+
+impl TurnStream {
+    pub(crate) fn new(
+        messages: BoxStream<'static, Stamped>,
+        turn: OwnedMutexGuard<()>,
+        protocol: Option<Arc<ControlProtocol>>,
+    ) -> Self {
+        Self {
+            messages: Some(messages),
+            end: Some(TurnEnd { turn, protocol, restore: Restore::default() }),
+            pending: None,
+            held: None,
+            cancelled: None,
+            error_on_result_failure: false,
+            max_messages: None,
+            count: 0,
+        }
+    }
+
+    /// Yield an error result as `ClaudeAgentError::Result`.
+    pub(crate) fn error_on_result_failure(mut self, enabled: bool) -> Self {
+        self.error_on_result_failure = enabled;
+        self
+    }
+
+    /// Stop the turn after `max` messages, not counting its `Result`.
+    pub(crate) fn max_messages(mut self, max: Option<usize>) -> Self {
+        self.max_messages = max;
+        self
+    }
+
+    /// Put `restore` back once the turn is over.
+    pub(crate) fn restore(mut self, restore: Restore) -> Self {
+        if let Some(end) = &mut self.end {
+            end.restore = restore;
+        }
+        self
+    }
+
+    /// Stop the turn, ending the stream, when `token` is cancelled.
+    pub(crate) fn cancellation(mut self, token: Option<CancellationToken>) -> Self {
+        self.cancelled = token.map(|token| Box::pin(token.cancelled_owned()));
+        self
+    }
+
+    /// Stop yielding messages and finish the turn in the background.
+    ///
+    /// The stream waits in `pending` until the CLI acknowledges the interrupt.
+    fn abandon(&mut self) {
+        let (Some(messages), Some(end)) = (self.messages.take(), self.end.take()) else {
+            return;
+        };
+        // Without a runtime the turn cannot be drained; the lock is released as is
+        let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+            return;
+        };
+        let (interrupted, acknowledged) = oneshot::channel();
+        runtime.spawn(end.abandon(messages, interrupted));
+        self.pending = Some(acknowledged.map(|_| ()).boxed());
+    }
+
+    /// Interrupt the turn if it is still running, and wait until it is over.
+    pub(crate) async fn stop(mut self) {
+        match (self.messages.take(), self.end.take()) {
+            (Some(messages), Some(end)) => {
+                let (interrupted, _) = oneshot::channel();
+                end.abandon(messages, interrupted).await;
+            },
+            _ => {
+                if let Some(pending) = self.pending.take() {
+                    pending.await;
+                }
+            },
+        }
+    }
+
+    fn map_result(&self, item: Stamped) -> Stamped {
+        match item {
+            Ok(Timestamped { value: Message::Result(result), .. })
+                if self.error_on_result_failure && result.is_error =>
+            {
+                let message = result.result.unwrap_or_else(|| "no details reported".to_string());
+                Err(ClaudeAgentError::Result { subtype: result.subtype, message })
+            },
+            item => item,
+        }
+    }
+}
+
+impl Stream for TurnStream {
+    type Item = Stamped;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+        loop {
+            if let Some(pending) = this.pending.as_mut() {
+                if pending.poll_unpin(cx).is_pending() {
+                    return Poll::Pending;
+                }
+                this.pending = None;
+                if let Some(item) = this.held.take() {
+                    return Poll::Ready(Some(item));
+                }
+            }
+            if this.cancelled.as_mut().is_some_and(|cancelled| cancelled.poll_unpin(cx).is_ready())
+            {
+                this.cancelled = None;
+                this.abandon();
+                continue;
+            }
+            let Some(messages) = this.messages.as_mut() else {
+                return Poll::Ready(None);
+            };
+            let item = match messages.poll_next_unpin(cx) {
+                Poll::Pending => return Poll::Pending,
+                Poll::Ready(Some(item)) => item,
+                Poll::Ready(None) => {
+                    // The transport closed; there is nothing left to drain or restore
+                    this.messages = None;
+                    this.end = None;
+                    return Poll::Ready(None);
+                },
+            };
+            let is_result = matches!(item, Ok(Timestamped { value: Message::Result(_), .. }));
+            // The `Result` always gets through; it ends the turn anyway
+            if let Some(max) = this.max_messages.filter(|max| !is_result && this.count >= *max) {
+                tracing::warn!(limit = max, "Query exceeded max_messages_per_query; ending stream");
+                this.abandon();
+                return Poll::Ready(Some(Err(ClaudeAgentError::MessageLimit(max))));
+            }
+            this.count += 1;
+            if is_result {
+                this.messages = None;
+                let item = this.map_result(item);
+                match this.end.take() {
+                    Some(end) if !end.restore.is_empty() => {
+                        this.held = Some(item);
+                        this.pending = Some(end.complete().boxed());
+                        continue;
+                    },
+                    _ => return Poll::Ready(Some(item)),
+                }
+            }
+            if item.as_ref().is_err_and(ClaudeAgentError::is_fatal) {
+                this.abandon();
+            }
+            return Poll::Ready(Some(this.map_result(item)));
+        }
+    }
+}
+
+impl Drop for TurnStream {
+    fn drop(&mut self) {
+        self.abandon();
+        // Settings still being restored before a held `Result` must finish
+        if let (Some(pending), Some(_)) = (self.pending.take(), self.held.take()) {
+            if let Ok(runtime) = tokio::runtime::Handle::try_current() {
+                runtime.spawn(pending);
+            }
+        }
+    }
+}
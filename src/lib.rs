@@ -11,7 +11,7 @@ pub mod types;
 
 // Convenience re-exports
 pub use api::client::ClaudeAgentClient;
-pub use api::query::query;
+pub use api::query::{ask, query, text_stream};
 pub use api::sessions::{find_claude_cli, SessionInfo};
 pub use core::agent::ClaudeAgent;
 pub use types::config::ClaudeAgentOptions;
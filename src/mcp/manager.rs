@@ -217,6 +217,79 @@ pub trait McpServer: Send + Sync {
         arguments: serde_json::Value,
     ) -> Result<serde_json::Value, ClaudeAgentError>;
 
+    /// Release the server's resources, such as a subprocess.
+    ///
+    /// Called by `McpServerManager::deregister`. The default implementation
+    /// does nothing.
+    async fn shutdown(&self) -> Result<(), ClaudeAgentError> {
+        Ok(())
+    }
+
+    /// Whether this server implements `list_resources` and `read_resource`.
+    ///
+    /// When true, the `initialize` reply advertises the `resources` capability.
+    /// The default implementation returns false.
+    fn supports_resources(&self) -> bool {
+        false
+    }
+
+    /// List resources exposed by this server (`resources/list`).
+    ///
+    /// The default implementation returns `ClaudeAgentError::Mcp` because
+    /// resources are optional in MCP.
+    async fn list_resources(&self) -> Result<Vec<ResourceInfo>, ClaudeAgentError> {
+        Err(ClaudeAgentError::Mcp(format!(
+            "Server {} does not support resources",
+            self.name()
+        )))
+    }
+
+    /// Read the contents of the resource at `uri` (`resources/read`).
+    ///
+    /// A single resource may yield several content entries. The default
+    /// implementation returns `ClaudeAgentError::Mcp`.
+    async fn read_resource(&self, uri: &str) -> Result<Vec<ResourceContents>, ClaudeAgentError> {
+        let _ = uri;
+        Err(ClaudeAgentError::Mcp(format!(
+            "Server {} does not support resources",
+            self.name()
+        )))
+    }
+
+    /// Whether this server implements `list_prompts` and `get_prompt`.
+    ///
+    /// When true, the `initialize` reply advertises the `prompts` capability.
+    /// The default implementation returns false.
+    fn supports_prompts(&self) -> bool {
+        false
+    }
+
+    /// List prompt templates exposed by this server (`prompts/list`).
+    ///
+    /// The default implementation returns `ClaudeAgentError::Mcp` because
+    /// prompts are optional in MCP.
+    async fn list_prompts(&self) -> Result<Vec<PromptInfo>, ClaudeAgentError> {
+        Err(ClaudeAgentError::Mcp(format!(
+            "Server {} does not support prompts",
+            self.name()
+        )))
+    }
+
+    /// Render the prompt `name` with `arguments` (`prompts/get`).
+    ///
+    /// The default implementation returns `ClaudeAgentError::Mcp`.
+    async fn get_prompt(
+        &self,
+        name: &str,
+        arguments: HashMap<String, String>,
+    ) -> Result<RenderedPrompt, ClaudeAgentError> {
+        let _ = (name, arguments);
+        Err(ClaudeAgentError::Mcp(format!(
+            "Server {} does not support prompts",
+            self.name()
+        )))
+    }
+
     /// Handle a raw JSON-RPC message from the client (CLI).
     ///
     /// This method is called when the agent receives a JSON-RPC message
@@ -259,15 +332,28 @@ pub trait McpServer: Send + Sync {
         let method = message.get("method").and_then(|m| m.as_str());
         let id = message.get("id");
         match method {
-            Some("initialize") => Ok(serde_json::json!({
-                "jsonrpc": "2.0",
-                "id": id,
-                "result": {
-                    "protocolVersion": "2024-11-05",
-                    "capabilities": { "tools": {} },
-                    "serverInfo": { "name": self.name(), "version": "1.0.0" }
+            Some("initialize") => {
+                let mut capabilities = serde_json::json!({ "tools": {} });
+                if self.supports_resources() {
+                    capabilities["resources"] = serde_json::json!({});
                 }
-            })),
+                if self.supports_prompts() {
+                    capabilities["prompts"] = serde_json::json!({});
+                }
+                Ok(serde_json::json!({
+                    "jsonrpc": "2.0",
+                    "id": id,
+                    "result": {
+                        "protocolVersion": "2024-11-05",
+                        "capabilities": capabilities,
+                        "serverInfo": { "name": self.name(), "version": "1.0.0" }
+                    }
+                }))
+            },
+            // Notifications such as `notifications/initialized` need no reply
+            Some(method) if method.starts_with("notifications/") => {
+                Ok(serde_json::json!({ "jsonrpc": "2.0", "result": {} }))
+            },
             Some("tools/list") => {
                 let tools = self.list_tools().await?;
                 Ok(serde_json::json!({
@@ -307,6 +393,79 @@ pub trait McpServer: Send + Sync {
                     }))
                 }
             },
+            Some("resources/list") => match self.list_resources().await {
+                Ok(resources) => Ok(serde_json::json!({
+                    "jsonrpc": "2.0",
+                    "id": id,
+                    "result": { "resources": resources }
+                })),
+                Err(e) => Ok(serde_json::json!({
+                    "jsonrpc": "2.0",
+                    "id": id,
+                    "error": { "code": -32000, "message": e.to_string() }
+                })),
+            },
+            Some("resources/read") => {
+                let Some(uri) =
+                    message.get("params").and_then(|p| p.get("uri")).and_then(|u| u.as_str())
+                else {
+                    return Ok(serde_json::json!({
+                        "jsonrpc": "2.0",
+                        "id": id,
+                        "error": { "code": -32602, "message": "Missing resource uri" }
+                    }));
+                };
+                match self.read_resource(uri).await {
+                    Ok(contents) => Ok(serde_json::json!({
+                        "jsonrpc": "2.0",
+                        "id": id,
+                        "result": { "contents": contents }
+                    })),
+                    Err(e) => Ok(serde_json::json!({
+                        "jsonrpc": "2.0",
+                        "id": id,
+                        "error": { "code": -32000, "message": e.to_string() }
+                    })),
+                }
+            },
+            Some("prompts/list") => match self.list_prompts().await {
+                Ok(prompts) => Ok(serde_json::json!({
+                    "jsonrpc": "2.0",
+                    "id": id,
+                    "result": { "prompts": prompts }
+                })),
+                Err(e) => Ok(serde_json::json!({
+                    "jsonrpc": "2.0",
+                    "id": id,
+                    "error": { "code": -32000, "message": e.to_string() }
+                })),
+            },
+            Some("prompts/get") => {
+                let params = message.get("params");
+                let Some(name) = params.and_then(|p| p.get("name")).and_then(|n| n.as_str()) else {
+                    return Ok(serde_json::json!({
+                        "jsonrpc": "2.0",
+                        "id": id,
+                        "error": { "code": -32602, "message": "Missing prompt name" }
+                    }));
+                };
+                let arguments = params
+                    .and_then(|p| p.get("arguments"))
+                    .and_then(|a| serde_json::from_value(a.clone()).ok())
+                    .unwrap_or_default();
+                match self.get_prompt(name, arguments).await {
+                    Ok(prompt) => Ok(serde_json::json!({
+                        "jsonrpc": "2.0",
+                        "id": id,
+                        "result": prompt
+                    })),
+                    Err(e) => Ok(serde_json::json!({
+                        "jsonrpc": "2.0",
+                        "id": id,
+                        "error": { "code": -32000, "message": e.to_string() }
+                    })),
+                }
+            },
             _ => Ok(serde_json::json!({
                 "jsonrpc": "2.0",
                 "id": id,
@@ -326,6 +485,119 @@ pub struct ToolInfo {
     pub input_schema: serde_json::Value,
 }
 
+/// The result of a `tools/call`, as returned in JSON by `McpServer::call_tool`.
+///
+/// A tool that runs but fails reports that in-band with `isError: true`, so an
+/// `Ok` from `call_tool` does not by itself mean the tool succeeded.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ToolCallResult {
+    /// Content blocks produced by the tool, such as `{"type": "text", "text": ...}`.
+    #[serde(default)]
+    pub content: Vec<Value>,
+    #[serde(rename = "isError", default)]
+    pub is_error: bool,
+}
+
+impl ToolCallResult {
+    /// Parse the value returned by `McpServer::call_tool`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ClaudeAgentError::Mcp` if `value` is not a `tools/call` result.
+    pub fn from_value(value: Value) -> Result<Self, ClaudeAgentError> {
+        serde_json::from_value(value)
+            .map_err(|e| ClaudeAgentError::Mcp(format!("Invalid tools/call result: {}", e)))
+    }
+}
+
+/// Whether a `call_tool` result is a tool-reported error (`isError: true`).
+pub fn is_tool_error(result: &Value) -> bool {
+    result.get("isError").and_then(Value::as_bool).unwrap_or(false)
+}
+
+/// Information about an MCP resource, as returned by `resources/list`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ResourceInfo {
+    pub uri: String,
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mime_type: Option<String>,
+}
+
+/// Contents of an MCP resource, as returned by `resources/read`.
+///
+/// Text resources set `text`; binary resources set `blob` (base64).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ResourceContents {
+    pub uri: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mime_type: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub blob: Option<String>,
+}
+
+/// Information about an MCP prompt template, as returned by `prompts/list`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PromptInfo {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub arguments: Vec<PromptArgument>,
+}
+
+/// An argument accepted by an MCP prompt template.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PromptArgument {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub required: bool,
+}
+
+/// A prompt rendered by `prompts/get`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RenderedPrompt {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    pub messages: Vec<PromptMessage>,
+}
+
+/// One message of a rendered prompt.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PromptMessage {
+    /// `"user"` or `"assistant"`.
+    pub role: String,
+    pub content: PromptContent,
+}
+
+/// Content of a rendered prompt message.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PromptContent {
+    Text {
+        text: String,
+    },
+    Image {
+        data: String,
+        #[serde(rename = "mimeType")]
+        mime_type: String,
+    },
+    Resource {
+        resource: ResourceContents,
+    },
+    /// A content type this SDK does not model yet.
+    #[serde(other)]
+    Other,
+}
+
 impl McpServerManager {
     /// Create a new MCP server manager.
     pub fn new() -> Self {
@@ -339,11 +611,29 @@ impl McpServerManager {
         servers.insert(name, Arc::from(server));
     }
 
+    /// Register a server that is already shared, such as one built by
+    /// [`create_mcp_server`](crate::mcp::create_mcp_server).
+    pub async fn register_shared(&self, server: Arc<dyn McpServer>) {
+        let name = server.name().to_string();
+        self.servers.write().await.insert(name, server);
+    }
+
     /// Get a server by name.
     pub async fn get(&self, name: &str) -> Option<Arc<dyn McpServer>> {
         self.servers.read().await.get(name).cloned()
     }
 
+    /// Remove a server and shut it down.
+    ///
+    /// Returns `Ok(false)` if no server is registered under `name`.
+    pub async fn deregister(&self, name: &str) -> Result<bool, ClaudeAgentError> {
+        let removed = self.servers.write().await.remove(name);
+        match removed {
+            Some(server) => server.shutdown().await.map(|()| true),
+            None => Ok(false),
+        }
+    }
+
     /// List all registered servers.
     pub async fn list_servers(&self) -> Vec<String> {
         self.servers.read().await.keys().cloned().collect()
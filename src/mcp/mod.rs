@@ -7,9 +7,12 @@ pub mod server;
 pub mod transport_factory;
 pub mod transports;
 
-pub use manager::{McpServer, McpServerManager, ToolInfo};
+pub use manager::{
+    is_tool_error, McpServer, McpServerManager, PromptArgument, PromptContent, PromptInfo,
+    PromptMessage, RenderedPrompt, ResourceContents, ResourceInfo, ToolCallResult, ToolInfo,
+};
 pub use rate_limiter::{RateLimitConfig, RateLimiter};
-pub use schema::ToolDefinition;
+pub use schema::{validate_against_schema, SchemaViolation, ToolDefinition};
 pub use server::SdkMcpServer;
 pub use transport_factory::create_mcp_server;
-pub use transports::{HttpMcpServer, SseMcpServer, StdioMcpServer};
+pub use transports::{AutoMcpServer, HttpMcpServer, McpNotification, SseMcpServer, StdioMcpServer};
@@ -1,54 +1,82 @@
-//! Rate limiting for MCP tool calls.
+//! Rate limiting for MCP tool calls and outgoing queries.
 //!
 //! This module provides configurable rate limiting to prevent abuse
-//! and ensure fair usage of MCP servers.
+//! and ensure fair usage of MCP servers and the CLI.
 
 use std::num::NonZeroU32;
 use std::sync::Arc;
 use std::time::Duration;
 
 use governor::{
-    clock::DefaultClock,
+    clock::{Clock, DefaultClock},
     middleware::NoOpMiddleware,
     state::{InMemoryState, NotKeyed},
     Quota, RateLimiter as GovernorRateLimiter,
 };
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::types::ClaudeAgentError;
 
 /// Configuration for rate limiting.
-#[derive(Debug, Clone)]
+///
+/// The limiter is a token bucket holding `burst_size` permits that refills at
+/// `requests_per_second`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
 pub struct RateLimitConfig {
     /// Maximum requests per second
     pub requests_per_second: u32,
     /// Burst capacity (max requests in a burst)
     pub burst_size: u32,
+    /// Wait for a permit instead of failing when the limit is exhausted.
+    #[serde(default)]
+    pub wait: bool,
 }
 
 impl Default for RateLimitConfig {
     fn default() -> Self {
-        Self { requests_per_second: 10, burst_size: 20 }
+        Self { requests_per_second: 10, burst_size: 20, wait: false }
     }
 }
 
 impl RateLimitConfig {
     /// Create a new rate limit configuration.
     pub fn new(requests_per_second: u32, burst_size: u32) -> Self {
-        Self { requests_per_second, burst_size }
+        Self { requests_per_second, burst_size, wait: false }
+    }
+
+    /// Allow `requests` per second, spaced evenly with no bursting.
+    pub fn per_second(requests: u32) -> Self {
+        Self::new(requests, 1)
+    }
+
+    /// Token bucket holding `capacity` permits, refilled at `refill_per_second`.
+    pub fn token_bucket(capacity: u32, refill_per_second: u32) -> Self {
+        Self::new(refill_per_second, capacity)
+    }
+
+    /// Wait for a permit instead of returning an error when exhausted.
+    pub fn waiting(mut self) -> Self {
+        self.wait = true;
+        self
     }
 
     /// Create a permissive rate limit (high throughput).
     pub fn permissive() -> Self {
-        Self { requests_per_second: 100, burst_size: 200 }
+        Self::new(100, 200)
     }
 
     /// Create a strict rate limit (low throughput).
     pub fn strict() -> Self {
-        Self { requests_per_second: 5, burst_size: 10 }
+        Self::new(5, 10)
     }
 }
 
 type InnerRateLimiter = GovernorRateLimiter<NotKeyed, InMemoryState, DefaultClock, NoOpMiddleware>;
 
-/// Rate limiter for MCP tool calls.
+/// Rate limiter for MCP tool calls and outgoing queries.
+///
+/// Clones share the same bucket.
 #[derive(Clone)]
 pub struct RateLimiter {
     limiter: Arc<InnerRateLimiter>,
@@ -91,6 +119,26 @@ impl RateLimiter {
         self.limiter.until_ready().await;
     }
 
+    /// Take a permit without waiting.
+    ///
+    /// Returns `ClaudeAgentError::RateLimited` with the time until the next
+    /// permit becomes available when the limit is exhausted.
+    pub fn try_acquire(&self) -> Result<(), ClaudeAgentError> {
+        self.limiter.check().map_err(|not_until| ClaudeAgentError::RateLimited {
+            retry_after: Some(not_until.wait_time_from(DefaultClock::default().now())),
+        })
+    }
+
+    /// Take a permit, waiting or failing according to [`RateLimitConfig::wait`].
+    pub async fn acquire(&self) -> Result<(), ClaudeAgentError> {
+        if self.config.wait {
+            self.wait().await;
+            Ok(())
+        } else {
+            self.try_acquire()
+        }
+    }
+
     /// Wait with a timeout.
     ///
     /// Returns `true` if the request was allowed within the timeout,
@@ -147,6 +195,28 @@ mod tests {
         assert!(!limiter.check());
     }
 
+    #[test]
+    fn test_try_acquire_reports_retry_after() {
+        let limiter = RateLimiter::new(RateLimitConfig::per_second(1));
+        assert!(limiter.try_acquire().is_ok());
+
+        match limiter.try_acquire() {
+            Err(ClaudeAgentError::RateLimited { retry_after: Some(delay) }) => {
+                assert!(delay <= Duration::from_secs(1));
+            },
+            other => panic!("expected RateLimited, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_token_bucket_config() {
+        let config = RateLimitConfig::token_bucket(5, 2);
+        assert_eq!(config.burst_size, 5);
+        assert_eq!(config.requests_per_second, 2);
+        assert!(!config.wait);
+        assert!(config.waiting().wait);
+    }
+
     #[tokio::test]
     async fn test_rate_limiter_wait() {
         let limiter = RateLimiter::new(RateLimitConfig::new(100, 5));
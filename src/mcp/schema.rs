@@ -1,8 +1,11 @@
 //! Tool schema generation for MCP.
 
 use schemars::JsonSchema;
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 
+use crate::types::ClaudeAgentError;
+
 /// Generate JSON schema for a type.
 pub fn generate_schema<T: JsonSchema>() -> serde_json::Value {
     let schema = schemars::schema_for!(T);
@@ -32,17 +35,226 @@ impl ToolDefinition {
     pub fn from_type<T: JsonSchema>(name: impl Into<String>, description: Option<String>) -> Self {
         Self { name: name.into(), description, input_schema: generate_schema::<T>() }
     }
+
+    /// Check `args` against this tool's `input_schema`.
+    ///
+    /// Returns `ClaudeAgentError::Mcp` listing every failing property path.
+    pub fn validate_input(&self, args: &serde_json::Value) -> Result<(), ClaudeAgentError> {
+        validate_against_schema(&self.input_schema, args).map_err(|violations| {
+            let details: Vec<String> = violations.iter().map(ToString::to_string).collect();
+            ClaudeAgentError::Mcp(format!(
+                "Invalid input for tool {}: {}",
+                self.name,
+                details.join("; ")
+            ))
+        })
+    }
+
+    /// Validate `args` and deserialize them into the tool's input type.
+    pub fn parse_input<T: DeserializeOwned>(
+        &self,
+        args: &serde_json::Value,
+    ) -> Result<T, ClaudeAgentError> {
+        self.validate_input(args)?;
+        serde_json::from_value(args.clone()).map_err(|e| {
+            ClaudeAgentError::Mcp(format!("Invalid input for tool {}: {}", self.name, e))
+        })
+    }
+}
+
+/// A single JSON Schema violation, located by a `$.field[0]`-style path.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SchemaViolation {
+    pub path: String,
+    pub message: String,
+}
+
+impl std::fmt::Display for SchemaViolation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.path, self.message)
+    }
+}
+
+/// Validate `value` against a JSON Schema.
+///
+/// Covers the subset used by tool input schemas: `type`, `properties`,
+/// `required`, `additionalProperties`, `items`, `enum`, `const`,
+/// `minimum`/`maximum`, `anyOf`/`oneOf`/`allOf`, and local `$ref`s such as
+/// `#/$defs/Name`. Unknown keywords are ignored.
+pub fn validate_against_schema(
+    schema: &serde_json::Value,
+    value: &serde_json::Value,
+) -> Result<(), Vec<SchemaViolation>> {
+    let mut violations = Vec::new();
+    check_schema(schema, schema, value, "$", &mut violations);
+    if violations.is_empty() {
+        Ok(())
+    } else {
+        Err(violations)
+    }
+}
+
+fn check_schema(
+    root: &serde_json::Value,
+    schema: &serde_json::Value,
+    value: &serde_json::Value,
+    path: &str,
+    out: &mut Vec<SchemaViolation>,
+) {
+    use serde_json::Value;
+
+    let violation = |out: &mut Vec<SchemaViolation>, message: String| {
+        out.push(SchemaViolation { path: path.to_string(), message });
+    };
+
+    let schema = match schema {
+        Value::Bool(true) => return,
+        Value::Bool(false) => return violation(out, "no value is allowed here".to_string()),
+        Value::Object(map) => map,
+        _ => return,
+    };
+
+    if let Some(reference) = schema.get("$ref").and_then(|r| r.as_str()) {
+        match reference.strip_prefix('#').and_then(|pointer| root.pointer(pointer)) {
+            Some(target) => check_schema(root, target, value, path, out),
+            None => violation(out, format!("unresolvable schema reference {}", reference)),
+        }
+    }
+
+    if let Some(expected) = schema.get("type") {
+        let types: Vec<&str> = match expected {
+            Value::String(t) => vec![t.as_str()],
+            Value::Array(ts) => ts.iter().filter_map(|t| t.as_str()).collect(),
+            _ => Vec::new(),
+        };
+        if !types.is_empty() && !types.iter().any(|t| type_matches(t, value)) {
+            return violation(
+                out,
+                format!("expected {}, got {}", types.join(" or "), json_type_name(value)),
+            );
+        }
+    }
+
+    if let Some(allowed) = schema.get("enum").and_then(|e| e.as_array()) {
+        if !allowed.contains(value) {
+            violation(out, format!("{} is not one of {}", value, Value::Array(allowed.clone())));
+        }
+    }
+    if let Some(expected) = schema.get("const") {
+        if expected != value {
+            violation(out, format!("expected {}, got {}", expected, value));
+        }
+    }
+
+    if let Some(number) = value.as_f64() {
+        if let Some(min) = schema.get("minimum").and_then(|m| m.as_f64()) {
+            if number < min {
+                violation(out, format!("{} is less than the minimum {}", value, min));
+            }
+        }
+        if let Some(max) = schema.get("maximum").and_then(|m| m.as_f64()) {
+            if number > max {
+                violation(out, format!("{} is greater than the maximum {}", value, max));
+            }
+        }
+    }
+
+    if let Value::Object(fields) = value {
+        let properties = schema.get("properties").and_then(|p| p.as_object());
+        if let Some(required) = schema.get("required").and_then(|r| r.as_array()) {
+            for name in required.iter().filter_map(|n| n.as_str()) {
+                if !fields.contains_key(name) {
+                    out.push(SchemaViolation {
+                        path: format!("{}.{}", path, name),
+                        message: "missing required property".to_string(),
+                    });
+                }
+            }
+        }
+        for (name, field) in fields {
+            let field_path = format!("{}.{}", path, name);
+            match properties.and_then(|p| p.get(name)) {
+                Some(field_schema) => check_schema(root, field_schema, field, &field_path, out),
+                None => match schema.get("additionalProperties") {
+                    Some(Value::Bool(false)) => out.push(SchemaViolation {
+                        path: field_path,
+                        message: "unexpected property".to_string(),
+                    }),
+                    Some(extra) => check_schema(root, extra, field, &field_path, out),
+                    None => {},
+                },
+            }
+        }
+    }
+
+    if let (Value::Array(elements), Some(items)) = (value, schema.get("items")) {
+        for (index, element) in elements.iter().enumerate() {
+            check_schema(root, items, element, &format!("{}[{}]", path, index), out);
+        }
+    }
+
+    if let Some(branches) = schema.get("allOf").and_then(|b| b.as_array()) {
+        for branch in branches {
+            check_schema(root, branch, value, path, out);
+        }
+    }
+    let matches = |branch: &Value| {
+        let mut scratch = Vec::new();
+        check_schema(root, branch, value, path, &mut scratch);
+        scratch.is_empty()
+    };
+    if let Some(branches) = schema.get("anyOf").and_then(|b| b.as_array()) {
+        if !branches.iter().any(matches) {
+            violation(out, "does not match any schema in anyOf".to_string());
+        }
+    }
+    if let Some(branches) = schema.get("oneOf").and_then(|b| b.as_array()) {
+        match branches.iter().filter(|branch| matches(branch)).count() {
+            1 => {},
+            0 => violation(out, "does not match any schema in oneOf".to_string()),
+            n => violation(out, format!("matches {} schemas in oneOf, expected exactly one", n)),
+        }
+    }
+}
+
+fn type_matches(expected: &str, value: &serde_json::Value) -> bool {
+    match expected {
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        "string" => value.is_string(),
+        "boolean" => value.is_boolean(),
+        "null" => value.is_null(),
+        "number" => value.is_number(),
+        "integer" => {
+            value.is_i64() || value.is_u64() || value.as_f64().is_some_and(|n| n.fract() == 0.0)
+        },
+        _ => true,
+    }
+}
+
+fn json_type_name(value: &serde_json::Value) -> &'static str {
+    match value {
+        serde_json::Value::Null => "null",
+        serde_json::Value::Bool(_) => "boolean",
+        serde_json::Value::Number(n) if n.is_f64() => "number",
+        serde_json::Value::Number(_) => "integer",
+        serde_json::Value::String(_) => "string",
+        serde_json::Value::Array(_) => "array",
+        serde_json::Value::Object(_) => "object",
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use serde_json::json;
 
-    #[derive(JsonSchema)]
-    #[allow(dead_code)]
+    #[derive(JsonSchema, Deserialize, Debug, PartialEq)]
     struct TestInput {
         message: String,
         count: u32,
+        #[serde(default)]
+        tags: Option<Vec<String>>,
     }
 
     #[test]
@@ -58,4 +270,95 @@ mod tests {
         assert_eq!(tool.name, "test_tool");
         assert!(tool.input_schema.get("properties").is_some());
     }
+
+    fn tool_schema() -> serde_json::Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "message": {"type": "string"},
+                "count": {"type": "integer", "minimum": 0},
+                "tags": {"type": "array", "items": {"type": "string"}}
+            },
+            "required": ["message"]
+        })
+    }
+
+    #[test]
+    fn test_validate_accepts_matching_value() {
+        let args = json!({"message": "hi", "count": 2, "tags": ["a"]});
+        assert!(validate_against_schema(&tool_schema(), &args).is_ok());
+    }
+
+    #[test]
+    fn test_validate_reports_missing_required_field() {
+        let errors = validate_against_schema(&tool_schema(), &json!({"count": 1})).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].path, "$.message");
+        assert_eq!(errors[0].message, "missing required property");
+    }
+
+    #[test]
+    fn test_validate_reports_wrong_type_with_path() {
+        let args = json!({"message": "hi", "tags": ["a", 3]});
+        let errors = validate_against_schema(&tool_schema(), &args).unwrap_err();
+        assert_eq!(errors[0].to_string(), "$.tags[1]: expected string, got integer");
+    }
+
+    #[test]
+    fn test_validate_follows_local_refs() {
+        let schema = json!({
+            "type": "object",
+            "properties": {"inner": {"$ref": "#/$defs/Inner"}},
+            "$defs": {"Inner": {"type": "object", "required": ["id"]}}
+        });
+        let errors = validate_against_schema(&schema, &json!({"inner": {}})).unwrap_err();
+        assert_eq!(errors[0].path, "$.inner.id");
+    }
+
+    #[test]
+    fn test_validate_one_of_requires_exactly_one_match() {
+        let schema = json!({"oneOf": [{"type": "integer"}, {"type": "number", "minimum": 10}]});
+        assert!(validate_against_schema(&schema, &json!(3)).is_ok());
+        assert!(validate_against_schema(&schema, &json!(10.5)).is_ok());
+        let errors = validate_against_schema(&schema, &json!(12)).unwrap_err();
+        assert!(errors[0].message.contains("matches 2 schemas in oneOf"));
+        assert!(validate_against_schema(&schema, &json!("x")).is_err());
+
+        // anyOf still accepts several matches
+        let schema = json!({"anyOf": [{"type": "integer"}, {"type": "number", "minimum": 10}]});
+        assert!(validate_against_schema(&schema, &json!(12)).is_ok());
+    }
+
+    #[test]
+    fn test_validate_nullable_type_list() {
+        let schema = json!({"type": ["string", "null"]});
+        assert!(validate_against_schema(&schema, &json!(null)).is_ok());
+        assert!(validate_against_schema(&schema, &json!(1)).is_err());
+    }
+
+    #[test]
+    fn test_validate_input_from_derived_schema() {
+        let tool = ToolDefinition::from_type::<TestInput>("test_tool", None);
+
+        assert!(tool.validate_input(&json!({"message": "hi", "count": 2})).is_ok());
+        assert!(tool.validate_input(&json!({"message": "hi", "count": 2, "tags": null})).is_ok());
+
+        let err = tool.validate_input(&json!({"message": 5, "count": "two"})).unwrap_err();
+        let text = err.to_string();
+        assert!(text.contains("test_tool"));
+        assert!(text.contains("$.message: expected string, got integer"));
+        assert!(text.contains("$.count: expected integer, got string"));
+    }
+
+    #[test]
+    fn test_parse_input_returns_typed_value() {
+        let tool = ToolDefinition::from_type::<TestInput>("test_tool", None);
+        let input: TestInput =
+            tool.parse_input(&json!({"message": "hi", "count": 3, "tags": ["a"]})).unwrap();
+        assert_eq!(
+            input,
+            TestInput { message: "hi".into(), count: 3, tags: Some(vec!["a".into()]) }
+        );
+        assert!(tool.parse_input::<TestInput>(&json!({"count": 3})).is_err());
+    }
 }
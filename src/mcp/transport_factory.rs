@@ -7,53 +7,80 @@ use crate::types::config::{McpServerConfig, McpTransportType};
 use crate::types::ClaudeAgentError;
 
 use crate::mcp::manager::McpServer;
-use crate::mcp::transports::{HttpMcpServer, SseMcpServer, StdioMcpServer};
+use crate::mcp::rate_limiter::RateLimiter;
+use crate::mcp::transports::{AutoMcpServer, HttpMcpServer, SseMcpServer, StdioMcpServer};
 
 /// Creates an MCP server transport based on the provided configuration.
+///
+/// Stdio configs need a `command` and HTTP/SSE configs an `http(s)://` `url`.
+/// `Auto` uses whichever is present; with both it tries HTTP first and falls
+/// back to stdio if the HTTP connection fails.
 pub fn create_mcp_server(
     name: String,
     config: McpServerConfig,
 ) -> Result<Arc<dyn McpServer>, ClaudeAgentError> {
     match config.transport {
-        McpTransportType::Stdio => create_stdio_server(name, config),
-        McpTransportType::Http => create_http_server(name, config),
+        McpTransportType::Stdio => Ok(Arc::new(stdio_server(name, config)?)),
+        McpTransportType::Http => Ok(Arc::new(http_server(name, &config, "HTTP")?)),
         McpTransportType::Sse => create_sse_server(name, config),
         McpTransportType::Auto => create_auto_server(name, config),
     }
 }
 
-fn create_stdio_server(
-    name: String,
-    config: McpServerConfig,
-) -> Result<Arc<dyn McpServer>, ClaudeAgentError> {
-    let command = config.command.ok_or_else(|| {
-        ClaudeAgentError::Config("Stdio transport requires 'command' field".to_string())
-    })?;
-    Ok(Arc::new(StdioMcpServer::new(name, command, config.args)?))
+/// The configured `command`, rejecting missing or blank values.
+fn required_command(config: &McpServerConfig) -> Result<String, ClaudeAgentError> {
+    match config.command.as_deref().map(str::trim) {
+        Some(command) if !command.is_empty() => Ok(command.to_string()),
+        _ => Err(ClaudeAgentError::Config("Stdio transport requires 'command' field".to_string())),
+    }
 }
 
-fn create_http_server(
-    name: String,
-    config: McpServerConfig,
-) -> Result<Arc<dyn McpServer>, ClaudeAgentError> {
-    let url = config.url.ok_or_else(|| {
-        ClaudeAgentError::Config("HTTP transport requires 'url' field".to_string())
-    })?;
-    let server = if let Some(timeout) = config.timeout_secs {
-        HttpMcpServer::with_timeout(name, url, Duration::from_secs(timeout))?
-    } else {
-        HttpMcpServer::new(name, url)?
+/// The configured `url`, rejecting missing values and non-HTTP schemes.
+fn required_url(config: &McpServerConfig, transport: &str) -> Result<String, ClaudeAgentError> {
+    let url = match config.url.as_deref().map(str::trim) {
+        Some(url) if !url.is_empty() => url,
+        _ => {
+            return Err(ClaudeAgentError::Config(format!(
+                "{} transport requires 'url' field",
+                transport
+            )))
+        },
     };
-    Ok(Arc::new(server))
+    if !(url.starts_with("http://") || url.starts_with("https://")) {
+        return Err(ClaudeAgentError::Config(format!(
+            "{} transport requires an http:// or https:// url, got '{}'",
+            transport, url
+        )));
+    }
+    Ok(url.to_string())
+}
+
+fn stdio_server(name: String, config: McpServerConfig) -> Result<StdioMcpServer, ClaudeAgentError> {
+    let command = required_command(&config)?;
+    let mut server = StdioMcpServer::with_env(name, command, config.args, config.env)?;
+    if let Some(rate_limit) = config.rate_limit {
+        server = server.with_rate_limiter(RateLimiter::new(rate_limit));
+    }
+    Ok(server)
+}
+
+fn http_server(
+    name: String,
+    config: &McpServerConfig,
+    transport: &str,
+) -> Result<HttpMcpServer, ClaudeAgentError> {
+    let url = required_url(config, transport)?;
+    match config.timeout_secs {
+        Some(timeout) => HttpMcpServer::with_timeout(name, url, Duration::from_secs(timeout)),
+        None => HttpMcpServer::new(name, url),
+    }
 }
 
 fn create_sse_server(
     name: String,
     config: McpServerConfig,
 ) -> Result<Arc<dyn McpServer>, ClaudeAgentError> {
-    let url = config.url.ok_or_else(|| {
-        ClaudeAgentError::Config("SSE transport requires 'url' field".to_string())
-    })?;
+    let url = required_url(&config, "SSE")?;
     let server = if let Some(timeout) = config.timeout_secs {
         SseMcpServer::with_timeout(name, url, Duration::from_secs(timeout))?
     } else {
@@ -66,14 +93,18 @@ fn create_auto_server(
     name: String,
     config: McpServerConfig,
 ) -> Result<Arc<dyn McpServer>, ClaudeAgentError> {
-    if config.url.is_some() {
-        create_http_server(name, config)
-    } else if config.command.is_some() {
-        create_stdio_server(name, config)
-    } else {
-        Err(ClaudeAgentError::Config(
+    let has_url = config.url.as_deref().is_some_and(|url| !url.trim().is_empty());
+    let has_command = config.command.as_deref().is_some_and(|cmd| !cmd.trim().is_empty());
+    match (has_url, has_command) {
+        (true, true) => {
+            let http = http_server(name.clone(), &config, "HTTP")?;
+            Ok(Arc::new(AutoMcpServer::new(http, stdio_server(name, config)?)))
+        },
+        (true, false) => Ok(Arc::new(http_server(name, &config, "HTTP")?)),
+        (false, true) => Ok(Arc::new(stdio_server(name, config)?)),
+        (false, false) => Err(ClaudeAgentError::Config(
             "Auto transport requires either 'url' (for HTTP) or 'command' (for Stdio)".to_string(),
-        ))
+        )),
     }
 }
 
@@ -152,4 +183,52 @@ mod tests {
         let result = create_mcp_server("test".to_string(), config);
         assert!(result.is_err());
     }
+
+    #[test]
+    fn test_create_auto_server_with_url_and_command() {
+        let config = McpServerConfig {
+            transport: McpTransportType::Auto,
+            url: Some("http://localhost:8080".to_string()),
+            command: Some("node".to_string()),
+            ..Default::default()
+        };
+        let server = create_mcp_server("auto_both".to_string(), config).unwrap();
+        assert_eq!(server.name(), "auto_both");
+    }
+
+    #[test]
+    fn test_auto_server_missing_url_and_command() {
+        let config = McpServerConfig { transport: McpTransportType::Auto, ..Default::default() };
+        let err = create_mcp_server("test".to_string(), config).err().unwrap();
+        assert!(err.to_string().contains("either 'url'"));
+    }
+
+    #[test]
+    fn test_sse_server_missing_url() {
+        let config = McpServerConfig { transport: McpTransportType::Sse, ..Default::default() };
+        let err = create_mcp_server("test".to_string(), config).err().unwrap();
+        assert!(err.to_string().contains("SSE transport requires 'url'"));
+    }
+
+    #[test]
+    fn test_http_server_rejects_non_http_url() {
+        let config = McpServerConfig {
+            transport: McpTransportType::Http,
+            url: Some("ftp://example.com".to_string()),
+            ..Default::default()
+        };
+        let err = create_mcp_server("test".to_string(), config).err().unwrap();
+        assert!(err.to_string().contains("http:// or https://"));
+    }
+
+    #[test]
+    fn test_stdio_server_blank_command() {
+        let config = McpServerConfig {
+            transport: McpTransportType::Stdio,
+            command: Some("  ".to_string()),
+            ..Default::default()
+        };
+        let err = create_mcp_server("test".to_string(), config).err().unwrap();
+        assert!(matches!(err, ClaudeAgentError::Config(_)));
+    }
 }
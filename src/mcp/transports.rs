@@ -5,16 +5,24 @@
 //! - **HttpMcpServer**: HTTP-based JSON-RPC (streamable HTTP)
 //! - **SseMcpServer**: SSE-based JSON-RPC (same transport, kept for API compat)
 
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
 use async_trait::async_trait;
 use serde_json::Value;
-use tokio::sync::OnceCell;
+use tokio::process::Child;
+use tokio::sync::{broadcast, OnceCell};
 
 use rmcp::model::CallToolRequestParams;
-use rmcp::service::{Peer, RunningService, ServiceExt};
-use rmcp::transport::child_process::TokioChildProcess;
-use rmcp::RoleClient;
+use rmcp::service::{NotificationContext, Peer, RunningService, ServiceExt};
+use rmcp::{ClientHandler, RoleClient};
 
-use crate::mcp::manager::{McpServer, ToolInfo};
+use crate::mcp::manager::{
+    McpServer, PromptInfo, RenderedPrompt, ResourceContents, ResourceInfo, ToolInfo,
+};
+use crate::mcp::RateLimiter;
+use crate::transport::process::{self, Stopped};
 use crate::types::ClaudeAgentError;
 
 /// Convert rmcp Tool to our ToolInfo.
@@ -28,40 +36,260 @@ impl From<rmcp::model::Tool> for ToolInfo {
     }
 }
 
+/// Capacity of the per-server notification channel.
+const NOTIFICATION_CAPACITY: usize = 64;
+
+/// A server-initiated MCP notification, such as `notifications/tools/list_changed`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct McpNotification {
+    pub method: String,
+    /// Notification params, or `Null` when the notification has none.
+    pub params: Value,
+}
+
+/// Cached `tools/list` result.
+///
+/// `generation` is bumped on every invalidation so a fetch that started before
+/// an invalidation does not repopulate the cache with stale tools.
+#[derive(Default)]
+struct ToolCache {
+    tools: Option<Vec<ToolInfo>>,
+    generation: u64,
+}
+
+impl ToolCache {
+    fn invalidate(&mut self) {
+        self.tools = None;
+        self.generation = self.generation.wrapping_add(1);
+    }
+}
+
+/// rmcp client handler that forwards server notifications to subscribers.
+#[derive(Clone)]
+struct NotificationForwarder {
+    tx: broadcast::Sender<McpNotification>,
+    tool_cache: Arc<Mutex<ToolCache>>,
+}
+
+impl NotificationForwarder {
+    fn forward(&self, method: &str, params: Value) -> std::future::Ready<()> {
+        // No subscribers is fine; the notification is simply dropped.
+        let _ = self.tx.send(McpNotification { method: method.to_string(), params });
+        std::future::ready(())
+    }
+}
+
+impl ClientHandler for NotificationForwarder {
+    fn on_tool_list_changed(
+        &self,
+        _context: NotificationContext<RoleClient>,
+    ) -> impl std::future::Future<Output = ()> + Send + '_ {
+        if let Ok(mut cache) = self.tool_cache.lock() {
+            cache.invalidate();
+        }
+        self.forward("notifications/tools/list_changed", Value::Null)
+    }
+
+    fn on_resource_list_changed(
+        &self,
+        _context: NotificationContext<RoleClient>,
+    ) -> impl std::future::Future<Output = ()> + Send + '_ {
+        self.forward("notifications/resources/list_changed", Value::Null)
+    }
+
+    fn on_prompt_list_changed(
+        &self,
+        _context: NotificationContext<RoleClient>,
+    ) -> impl std::future::Future<Output = ()> + Send + '_ {
+        self.forward("notifications/prompts/list_changed", Value::Null)
+    }
+
+    fn on_custom_notification(
+        &self,
+        notification: rmcp::model::CustomNotification,
+        _context: NotificationContext<RoleClient>,
+    ) -> impl std::future::Future<Output = ()> + Send + '_ {
+        self.forward(&notification.method, notification.params.clone().unwrap_or(Value::Null))
+    }
+}
+
+/// How long `StdioMcpServer::shutdown` waits for the subprocess before escalating.
+const SHUTDOWN_GRACE: Duration = Duration::from_secs(2);
+
 /// Stdio-based MCP client — connects to a subprocess via rmcp transport.
+///
+/// Call [`McpServer::shutdown`] to stop the subprocess cleanly; dropping the
+/// server without it kills the subprocess.
 pub struct StdioMcpServer {
     name: String,
     command: String,
     args: Vec<String>,
+    env: HashMap<String, String>,
     peer: OnceCell<Peer<RoleClient>>,
+    service: tokio::sync::Mutex<Option<RunningService<RoleClient, NotificationForwarder>>>,
+    child: tokio::sync::Mutex<Option<Child>>,
+    notifications: broadcast::Sender<McpNotification>,
+    tool_cache: Arc<Mutex<ToolCache>>,
+    cache_tools: bool,
+    rate_limiter: Option<RateLimiter>,
+    validate_arguments: bool,
 }
 
 impl StdioMcpServer {
     /// Create a new stdio MCP client.
     pub fn new(name: String, command: String, args: Vec<String>) -> Result<Self, ClaudeAgentError> {
-        Ok(Self { name, command, args, peer: OnceCell::new() })
+        Self::with_env(name, command, args, HashMap::new())
+    }
+
+    /// Create a stdio MCP client whose subprocess also gets `env`.
+    ///
+    /// The variables are added on top of the parent environment, e.g. for
+    /// credentials the server reads at startup.
+    pub fn with_env(
+        name: String,
+        command: String,
+        args: Vec<String>,
+        env: HashMap<String, String>,
+    ) -> Result<Self, ClaudeAgentError> {
+        Ok(Self {
+            name,
+            command,
+            args,
+            env,
+            peer: OnceCell::new(),
+            service: tokio::sync::Mutex::new(None),
+            child: tokio::sync::Mutex::new(None),
+            notifications: broadcast::channel(NOTIFICATION_CAPACITY).0,
+            tool_cache: Arc::new(Mutex::new(ToolCache::default())),
+            cache_tools: false,
+            rate_limiter: None,
+            validate_arguments: false,
+        })
+    }
+
+    /// Cache the `list_tools` result until the server sends
+    /// `notifications/tools/list_changed` or [`StdioMcpServer::refresh_tools`] is called.
+    pub fn with_tool_cache(mut self, enabled: bool) -> Self {
+        self.cache_tools = enabled;
+        self
+    }
+
+    /// Drop any cached tool list and fetch a fresh one from the server.
+    pub async fn refresh_tools(&self) -> Result<Vec<ToolInfo>, ClaudeAgentError> {
+        if let Ok(mut cache) = self.tool_cache.lock() {
+            cache.invalidate();
+        }
+        self.list_tools().await
+    }
+
+    async fn fetch_tools(&self) -> Result<Vec<ToolInfo>, ClaudeAgentError> {
+        let peer = self.ensure_connected().await?;
+        let tools = peer
+            .list_all_tools()
+            .await
+            .map_err(|e| ClaudeAgentError::Mcp(format!("list_tools failed: {:?}", e)))?;
+        Ok(tools.into_iter().map(ToolInfo::from).collect())
+    }
+
+    /// Subscribe to notifications sent by the server.
+    ///
+    /// List-changed notifications for tools, resources, and prompts are
+    /// delivered, as are notifications the SDK does not model. Subscribe before
+    /// the first request to observe notifications sent during startup.
+    pub fn subscribe_notifications(&self) -> broadcast::Receiver<McpNotification> {
+        self.notifications.subscribe()
+    }
+
+    /// Check `call_tool` arguments against the tool's `input_schema` before sending.
+    ///
+    /// Invalid arguments fail with `ClaudeAgentError::Mcp` naming each failing
+    /// property path. Tools the server does not list are passed through unchecked.
+    pub fn with_argument_validation(mut self, enabled: bool) -> Self {
+        self.validate_arguments = enabled;
+        self
+    }
+
+    async fn check_arguments(&self, tool: &str, arguments: &Value) -> Result<(), ClaudeAgentError> {
+        let tools = self.list_tools().await?;
+        let Some(info) = tools.iter().find(|info| info.name == tool) else {
+            return Ok(());
+        };
+        crate::mcp::validate_against_schema(&info.input_schema, arguments).map_err(|violations| {
+            let details: Vec<String> = violations.iter().map(ToString::to_string).collect();
+            ClaudeAgentError::Mcp(format!(
+                "Invalid arguments for tool {}: {}",
+                tool,
+                details.join("; ")
+            ))
+        })
+    }
+
+    /// Limit `call_tool` with `limiter`.
+    ///
+    /// Calls over the limit wait for a permit if the limiter's config has `wait`
+    /// set, and otherwise fail with `ClaudeAgentError::Mcp("rate limited ...")`.
+    pub fn with_rate_limiter(mut self, limiter: RateLimiter) -> Self {
+        self.rate_limiter = Some(limiter);
+        self
+    }
+
+    async fn acquire_permit(&self, tool: &str) -> Result<(), ClaudeAgentError> {
+        let Some(limiter) = &self.rate_limiter else {
+            return Ok(());
+        };
+        if limiter.config().wait {
+            limiter.wait().await;
+        } else if !limiter.check() {
+            return Err(ClaudeAgentError::Mcp(format!(
+                "rate limited: {} on server {}",
+                tool, self.name
+            )));
+        }
+        Ok(())
+    }
+
+    fn build_command(&self) -> tokio::process::Command {
+        let mut cmd = tokio::process::Command::new(&self.command);
+        cmd.args(&self.args).envs(&self.env);
+        cmd
+    }
+
+    /// OS process id of the subprocess, once connected and until shut down.
+    pub async fn pid(&self) -> Option<u32> {
+        self.child.lock().await.as_ref().and_then(Child::id)
     }
 
     async fn ensure_connected(&self) -> Result<&Peer<RoleClient>, ClaudeAgentError> {
         self.peer
             .get_or_try_init(|| async {
-                let mut cmd = tokio::process::Command::new(&self.command);
-                cmd.args(&self.args);
-                let transport = TokioChildProcess::new(cmd).map_err(|e| {
+                let mut cmd = self.build_command();
+                cmd.stdin(std::process::Stdio::piped())
+                    .stdout(std::process::Stdio::piped())
+                    .kill_on_drop(true);
+                let mut child = cmd.spawn().map_err(|e| {
                     ClaudeAgentError::Mcp(format!("Failed to spawn {}: {}", self.name, e))
                 })?;
-                let running: RunningService<RoleClient, ()> =
-                    ().serve(transport).await.map_err(|e| {
+                let (Some(stdout), Some(stdin)) = (child.stdout.take(), child.stdin.take()) else {
+                    return Err(ClaudeAgentError::Mcp(format!(
+                        "Failed to open stdio pipes for {}",
+                        self.name
+                    )));
+                };
+                let handler = NotificationForwarder {
+                    tx: self.notifications.clone(),
+                    tool_cache: self.tool_cache.clone(),
+                };
+                let running: RunningService<RoleClient, NotificationForwarder> =
+                    handler.serve((stdout, stdin)).await.map_err(|e| {
                         ClaudeAgentError::Mcp(format!(
                             "MCP handshake failed for {}: {:?}",
                             self.name, e
                         ))
                     })?;
                 let peer = running.peer().clone();
-                // Detach the background task — it keeps running as long as transport is alive
-                tokio::spawn(async move {
-                    let _ = running;
-                });
+                // Keep the service alive until shutdown; dropping it cancels the peer
+                *self.service.lock().await = Some(running);
+                *self.child.lock().await = Some(child);
                 Ok(peer)
             })
             .await
@@ -75,15 +303,54 @@ impl McpServer for StdioMcpServer {
     }
 
     async fn list_tools(&self) -> Result<Vec<ToolInfo>, ClaudeAgentError> {
-        let peer = self.ensure_connected().await?;
-        let tools = peer
-            .list_all_tools()
+        if !self.cache_tools {
+            return self.fetch_tools().await;
+        }
+        let generation = {
+            let cache = self.tool_cache.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            if let Some(tools) = &cache.tools {
+                return Ok(tools.clone());
+            }
+            cache.generation
+        };
+        let tools = self.fetch_tools().await?;
+        if let Ok(mut cache) = self.tool_cache.lock() {
+            if cache.generation == generation {
+                cache.tools = Some(tools.clone());
+            }
+        }
+        Ok(tools)
+    }
+
+    /// Close the subprocess's stdin, then `SIGTERM` it, then kill it, waiting
+    /// `SHUTDOWN_GRACE` between steps. The subprocess is always reaped.
+    async fn shutdown(&self) -> Result<(), ClaudeAgentError> {
+        // Cancelling the service drops the transport, which closes stdin
+        if let Some(service) = self.service.lock().await.take() {
+            let _ = service.cancel().await;
+        }
+        let Some(mut child) = self.child.lock().await.take() else {
+            return Ok(());
+        };
+        let stopped = process::stop(&mut child, SHUTDOWN_GRACE)
             .await
-            .map_err(|e| ClaudeAgentError::Mcp(format!("list_tools failed: {:?}", e)))?;
-        Ok(tools.into_iter().map(ToolInfo::from).collect())
+            .map_err(|e| ClaudeAgentError::Mcp(format!("Failed to stop {}: {}", self.name, e)))?;
+        if stopped == Stopped::Killed {
+            tracing::warn!(server = %self.name, "MCP server ignored SIGTERM and was killed");
+        }
+        Ok(())
     }
 
+    /// Call `name` on the server and return the `tools/call` result as JSON.
+    ///
+    /// Tool-reported failures come back as `Ok` with `isError: true`; use
+    /// [`ToolCallResult::from_value`](crate::mcp::ToolCallResult::from_value) or
+    /// [`is_tool_error`](crate::mcp::is_tool_error) to tell them apart.
     async fn call_tool(&self, name: &str, arguments: Value) -> Result<Value, ClaudeAgentError> {
+        self.acquire_permit(name).await?;
+        if self.validate_arguments {
+            self.check_arguments(name, &arguments).await?;
+        }
         let peer = self.ensure_connected().await?;
         let params = CallToolRequestParams::new(name.to_string())
             .with_arguments(serde_json::from_value(arguments).unwrap_or_default());
@@ -93,6 +360,69 @@ impl McpServer for StdioMcpServer {
             .map_err(|e| ClaudeAgentError::Mcp(format!("call_tool failed: {:?}", e)))?;
         Ok(serde_json::to_value(result).unwrap_or_default())
     }
+
+    fn supports_resources(&self) -> bool {
+        true
+    }
+
+    async fn list_resources(&self) -> Result<Vec<ResourceInfo>, ClaudeAgentError> {
+        let peer = self.ensure_connected().await?;
+        let resources = peer
+            .list_all_resources()
+            .await
+            .map_err(|e| ClaudeAgentError::Mcp(format!("list_resources failed: {:?}", e)))?;
+        convert_model(resources, "resources/list")
+    }
+
+    async fn read_resource(&self, uri: &str) -> Result<Vec<ResourceContents>, ClaudeAgentError> {
+        let peer = self.ensure_connected().await?;
+        let params = convert_model(serde_json::json!({ "uri": uri }), "resources/read")?;
+        let result = peer
+            .read_resource(params)
+            .await
+            .map_err(|e| ClaudeAgentError::Mcp(format!("read_resource failed: {:?}", e)))?;
+        convert_model(result.contents, "resources/read")
+    }
+
+    fn supports_prompts(&self) -> bool {
+        true
+    }
+
+    async fn list_prompts(&self) -> Result<Vec<PromptInfo>, ClaudeAgentError> {
+        let peer = self.ensure_connected().await?;
+        let prompts = peer
+            .list_all_prompts()
+            .await
+            .map_err(|e| ClaudeAgentError::Mcp(format!("list_prompts failed: {:?}", e)))?;
+        convert_model(prompts, "prompts/list")
+    }
+
+    async fn get_prompt(
+        &self,
+        name: &str,
+        arguments: HashMap<String, String>,
+    ) -> Result<RenderedPrompt, ClaudeAgentError> {
+        let peer = self.ensure_connected().await?;
+        let params = convert_model(
+            serde_json::json!({ "name": name, "arguments": arguments }),
+            "prompts/get",
+        )?;
+        let result = peer
+            .get_prompt(params)
+            .await
+            .map_err(|e| ClaudeAgentError::Mcp(format!("get_prompt failed: {:?}", e)))?;
+        convert_model(result, "prompts/get")
+    }
+}
+
+/// Convert between rmcp model types and ours through their shared wire format.
+fn convert_model<S: serde::Serialize, T: serde::de::DeserializeOwned>(
+    value: S,
+    method: &str,
+) -> Result<T, ClaudeAgentError> {
+    serde_json::to_value(value)
+        .and_then(serde_json::from_value)
+        .map_err(|e| ClaudeAgentError::Mcp(format!("Unexpected {} payload: {}", method, e)))
 }
 
 /// HTTP-based MCP client using rmcp's streamable HTTP transport.
@@ -131,7 +461,7 @@ impl HttpMcpServer {
                     })?;
                 let peer = running.peer().clone();
                 tokio::spawn(async move {
-                    let _ = running;
+                    let _ = running.waiting().await;
                 });
                 Ok(peer)
             })
@@ -166,6 +496,97 @@ impl McpServer for HttpMcpServer {
     }
 }
 
+/// MCP client that prefers HTTP and falls back to stdio.
+///
+/// Built by `create_mcp_server` for `McpTransportType::Auto` configs that have
+/// both a `url` and a `command`. The first operation tries to connect over
+/// HTTP; if that fails, this and every later operation use the stdio server.
+pub struct AutoMcpServer {
+    http: HttpMcpServer,
+    stdio: StdioMcpServer,
+    use_stdio: OnceCell<bool>,
+}
+
+impl AutoMcpServer {
+    /// Create a client that tries `http` before falling back to `stdio`.
+    pub fn new(http: HttpMcpServer, stdio: StdioMcpServer) -> Self {
+        Self { http, stdio, use_stdio: OnceCell::new() }
+    }
+
+    async fn selected(&self) -> &dyn McpServer {
+        let use_stdio = *self
+            .use_stdio
+            .get_or_init(|| async {
+                match self.http.ensure_connected().await {
+                    Ok(_) => false,
+                    Err(e) => {
+                        tracing::warn!(
+                            server = %self.stdio.name,
+                            error = %e,
+                            "HTTP MCP connection failed, falling back to stdio"
+                        );
+                        true
+                    },
+                }
+            })
+            .await;
+        if use_stdio {
+            &self.stdio
+        } else {
+            &self.http
+        }
+    }
+}
+
+#[async_trait]
+impl McpServer for AutoMcpServer {
+    fn name(&self) -> &str {
+        &self.stdio.name
+    }
+
+    async fn list_tools(&self) -> Result<Vec<ToolInfo>, ClaudeAgentError> {
+        self.selected().await.list_tools().await
+    }
+
+    async fn call_tool(&self, name: &str, arguments: Value) -> Result<Value, ClaudeAgentError> {
+        self.selected().await.call_tool(name, arguments).await
+    }
+
+    /// True because the stdio fallback forwards resource requests.
+    fn supports_resources(&self) -> bool {
+        self.stdio.supports_resources()
+    }
+
+    async fn list_resources(&self) -> Result<Vec<ResourceInfo>, ClaudeAgentError> {
+        self.selected().await.list_resources().await
+    }
+
+    async fn read_resource(&self, uri: &str) -> Result<Vec<ResourceContents>, ClaudeAgentError> {
+        self.selected().await.read_resource(uri).await
+    }
+
+    /// True because the stdio fallback forwards prompt requests.
+    fn supports_prompts(&self) -> bool {
+        self.stdio.supports_prompts()
+    }
+
+    async fn list_prompts(&self) -> Result<Vec<PromptInfo>, ClaudeAgentError> {
+        self.selected().await.list_prompts().await
+    }
+
+    async fn get_prompt(
+        &self,
+        name: &str,
+        arguments: HashMap<String, String>,
+    ) -> Result<RenderedPrompt, ClaudeAgentError> {
+        self.selected().await.get_prompt(name, arguments).await
+    }
+
+    async fn shutdown(&self) -> Result<(), ClaudeAgentError> {
+        self.stdio.shutdown().await
+    }
+}
+
 /// SSE-based MCP client (uses same HTTP transport; kept for API compat).
 pub struct SseMcpServer {
     inner: HttpMcpServer,
@@ -201,3 +622,37 @@ impl McpServer for SseMcpServer {
         self.inner.call_tool(name, arguments).await
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stdio_command_includes_env() {
+        let env = HashMap::from([("API_TOKEN".to_string(), "secret".to_string())]);
+        let server = StdioMcpServer::with_env(
+            "env".to_string(),
+            "mcp-server".to_string(),
+            vec!["--verbose".to_string()],
+            env,
+        )
+        .unwrap();
+
+        let cmd = server.build_command();
+        let cmd = cmd.as_std();
+        assert_eq!(cmd.get_program(), "mcp-server");
+        assert_eq!(cmd.get_args().collect::<Vec<_>>(), vec!["--verbose"]);
+        let envs: Vec<_> = cmd.get_envs().collect();
+        assert_eq!(
+            envs,
+            vec![(std::ffi::OsStr::new("API_TOKEN"), Some(std::ffi::OsStr::new("secret")))]
+        );
+    }
+
+    #[test]
+    fn test_stdio_new_sets_no_env() {
+        let server =
+            StdioMcpServer::new("plain".to_string(), "mcp-server".to_string(), vec![]).unwrap();
+        assert_eq!(server.build_command().as_std().get_envs().count(), 0);
+    }
+}
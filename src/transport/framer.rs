@@ -0,0 +1,341 @@
+//! Splitting buffered CLI output into complete JSON values.
+//!
+//! `MessageReader` pushes raw bytes into a [`Framer`] and asks it for the next
+//! message. The framer finds value boundaries without re-reading input it has
+//! already scanned, and reports input that is not valid UTF-8 or JSON.
+
+use serde_json::Value;
+
+use crate::types::ClaudeAgentError;
+
+/// Accumulated input plus how far the structural scan has progressed through it.
+///
+/// Complete lines are parsed directly, since the CLI emits one object per
+/// line. Anything else (objects split across lines, several objects on one
+/// line) is scanned byte by byte, remembering nesting depth and
+/// string state between reads, so appended data is examined once and
+/// `serde_json` only runs when a value is structurally complete. Consumed
+/// messages advance `start` instead of shifting the buffer, which is compacted
+/// only once the consumed prefix outgrows the remainder.
+#[derive(Debug, Default)]
+pub(crate) struct Framer {
+    /// Raw bytes, kept undecoded so multi-byte characters may span reads.
+    buffer: Vec<u8>,
+    /// Offset of the first byte not yet handed out.
+    start: usize,
+    /// Offset up to which the current value has been scanned.
+    pos: usize,
+    /// Offset up to which the current value has been syntax-checked.
+    checked: usize,
+    /// Nesting depth of the value being scanned; 0 between values.
+    depth: usize,
+    in_string: bool,
+    escaped: bool,
+    /// Discarding input until the end of an oversized line.
+    skipping: bool,
+    /// Bytes examined so far by the scan and by `serde_json` combined.
+    pub(crate) work: usize,
+}
+
+impl Framer {
+    /// Append freshly read bytes, minus the rest of a line being skipped.
+    pub(crate) fn push(&mut self, chunk: &[u8]) {
+        if !self.skipping {
+            self.buffer.extend_from_slice(chunk);
+        } else if let Some(end) = chunk.iter().position(|&b| b == b'\n') {
+            self.skipping = false;
+            self.buffer.extend_from_slice(&chunk[end + 1..]);
+        }
+    }
+
+    /// Drop the message being buffered through the end of its line.
+    ///
+    /// If the line has not ended yet, the rest of it is discarded as it arrives.
+    pub(crate) fn skip_line(&mut self) {
+        self.reset_scan();
+        match self.buffer[self.start..].iter().position(|&b| b == b'\n') {
+            Some(end) => self.consume(end + 1),
+            None => {
+                self.consume(self.len());
+                self.skipping = true;
+            },
+        }
+    }
+
+    /// Bytes buffered but not yet handed out.
+    pub(crate) fn len(&self) -> usize {
+        self.buffer.len() - self.start
+    }
+
+    /// Extract the next complete message, or `None` if more input is needed.
+    pub(crate) fn next_message(&mut self) -> Option<Result<Value, ClaudeAgentError>> {
+        if self.depth == 0 {
+            let blank =
+                self.buffer[self.start..].iter().take_while(|b| b.is_ascii_whitespace()).count();
+            self.consume(blank);
+            if let Some(message) = self.parse_line() {
+                return Some(message);
+            }
+            match self.buffer.get(self.start) {
+                None => return None,
+                Some(b'{') | Some(b'[') => {
+                    self.depth = 1;
+                    self.pos = self.start + 1;
+                    self.checked = self.start;
+                },
+                // Scalars and garbage are rare enough to hand straight to serde
+                Some(_) => return self.parse_prefix(),
+            }
+        }
+
+        while self.pos < self.buffer.len() {
+            let byte = self.buffer[self.pos];
+            self.pos += 1;
+            self.work += 1;
+            if self.in_string {
+                if self.escaped {
+                    self.escaped = false;
+                } else if byte == b'\\' {
+                    self.escaped = true;
+                } else if byte == b'"' {
+                    self.in_string = false;
+                }
+                continue;
+            }
+            match byte {
+                b'"' => self.in_string = true,
+                b'{' | b'[' => self.depth += 1,
+                b'}' | b']' => {
+                    self.depth -= 1;
+                    if self.depth == 0 {
+                        return Some(self.finish_value());
+                    }
+                },
+                b'\n' => {
+                    // A line break inside an unfinished value: make sure the
+                    // lines so far are valid JSON so garbage is reported now
+                    // instead of after the next closing bracket.
+                    if let Some(err) = self.check_partial() {
+                        return Some(Err(err));
+                    }
+                },
+                _ => {},
+            }
+        }
+        None
+    }
+
+    /// NDJSON fast path: parse the next complete line directly.
+    ///
+    /// Returns `None` if no full line is buffered or the line is not exactly
+    /// one JSON value (split objects, several objects, garbage); those go
+    /// through the scan instead. A line that is not UTF-8 is reported and dropped.
+    fn parse_line(&mut self) -> Option<Result<Value, ClaudeAgentError>> {
+        let end = self.buffer[self.start..].iter().position(|&b| b == b'\n')?;
+        let line = &self.buffer[self.start..self.start + end];
+        self.work += line.len();
+        let text = match std::str::from_utf8(line) {
+            Ok(text) => text,
+            Err(e) => return Some(Err(self.syntax_error(format!("invalid UTF-8: {}", e)))),
+        };
+        let value = serde_json::from_str(text).ok()?;
+        self.consume(end + 1);
+        Some(Ok(value))
+    }
+
+    /// Parse the structurally complete value ending at `pos`.
+    fn finish_value(&mut self) -> Result<Value, ClaudeAgentError> {
+        let end = self.pos;
+        self.reset_scan();
+        self.work += end - self.start;
+        let parsed = match std::str::from_utf8(&self.buffer[self.start..end]) {
+            Ok(text) => serde_json::from_str(text).map_err(|e| e.to_string()),
+            Err(e) => Err(format!("invalid UTF-8: {}", e)),
+        };
+        match parsed {
+            Ok(value) => {
+                self.consume(end - self.start);
+                Ok(value)
+            },
+            Err(reason) => Err(self.syntax_error(reason)),
+        }
+    }
+
+    /// Check the scanned part of an unfinished value for syntax errors.
+    ///
+    /// Each check re-reads the value from its start, so checking at every line
+    /// would cost quadratic time for a value spread over many lines. Checking
+    /// again only once the value has doubled since the last check keeps the
+    /// total linear.
+    fn check_partial(&mut self) -> Option<ClaudeAgentError> {
+        if self.pos - self.start < 2 * self.checked.saturating_sub(self.start) {
+            return None;
+        }
+        self.checked = self.pos;
+        let scanned = &self.buffer[self.start..self.pos];
+        self.work += scanned.len();
+        let reason = match std::str::from_utf8(scanned) {
+            Ok(text) => {
+                let mut stream = serde_json::Deserializer::from_str(text).into_iter::<Value>();
+                match stream.next() {
+                    Some(Err(e)) if !e.is_eof() => e.to_string(),
+                    _ => return None,
+                }
+            },
+            Err(e) => format!("invalid UTF-8: {}", e),
+        };
+        self.reset_scan();
+        Some(self.syntax_error(reason))
+    }
+
+    /// Parse a value that does not start with a bracket using the streaming deserializer.
+    fn parse_prefix(&mut self) -> Option<Result<Value, ClaudeAgentError>> {
+        let bytes = &self.buffer[self.start..];
+        let text = match std::str::from_utf8(bytes) {
+            Ok(text) => text,
+            // The last character is still arriving; parse what precedes it
+            Err(e) if e.error_len().is_none() => {
+                std::str::from_utf8(&bytes[..e.valid_up_to()]).unwrap_or_default()
+            },
+            Err(e) => return Some(Err(self.syntax_error(format!("invalid UTF-8: {}", e)))),
+        };
+        let available = text.len();
+        self.work += available;
+        let mut stream = serde_json::Deserializer::from_str(text).into_iter::<Value>();
+        match stream.next() {
+            Some(Ok(value)) => {
+                let offset = stream.byte_offset();
+                self.consume(offset);
+                Some(Ok(value))
+            },
+            // Incomplete JSON, need more data.
+            Some(Err(ref e)) if e.is_eof() => None,
+            Some(Err(e)) => Some(Err(self.syntax_error(e))),
+            None => {
+                self.consume(available);
+                None
+            },
+        }
+    }
+
+    /// Report invalid input and drop the offending line so the next call
+    /// resumes with the following message.
+    fn syntax_error(&mut self, reason: impl std::fmt::Display) -> ClaudeAgentError {
+        let rest = &self.buffer[self.start..];
+        let preview = String::from_utf8_lossy(&rest[..rest.len().min(400)])
+            .chars()
+            .take(100)
+            .collect::<String>();
+        let blank = rest.iter().take_while(|b| b.is_ascii_whitespace()).count();
+        let line = match rest[blank..].iter().position(|&b| b == b'\n') {
+            Some(end) => blank + end + 1,
+            None => rest.len(),
+        };
+        self.consume(line);
+        ClaudeAgentError::JSONDecode(format!(
+            "Parse error: {}. Buffer preview: {}",
+            reason, preview
+        ))
+    }
+
+    /// Parse whatever is left once the source is exhausted.
+    pub(crate) fn finish_eof(&mut self) -> Option<Result<Value, ClaudeAgentError>> {
+        self.reset_scan();
+        let parsed = match std::str::from_utf8(&self.buffer[self.start..]) {
+            Ok(text) if text.trim().is_empty() => None,
+            Ok(text) => Some(serde_json::from_str(text).map_err(|e| {
+                ClaudeAgentError::JSONDecode(format!("EOF with invalid json: {}", e))
+            })),
+            Err(e) => {
+                Some(Err(ClaudeAgentError::JSONDecode(format!("EOF with invalid UTF-8: {}", e))))
+            },
+        };
+        self.consume(self.len());
+        parsed
+    }
+
+    /// Mark `n` bytes as handed out, compacting once most of the buffer is consumed.
+    fn consume(&mut self, n: usize) {
+        self.start += n;
+        if self.start == self.buffer.len() {
+            self.buffer.clear();
+            self.start = 0;
+        } else if self.start > self.buffer.len() / 2 {
+            self.buffer.drain(..self.start);
+            self.start = 0;
+        }
+        self.pos = self.start;
+        self.checked = self.start;
+    }
+
+    fn reset_scan(&mut self) {
+        self.pos = self.start;
+        self.checked = self.start;
+        self.depth = 0;
+        self.in_string = false;
+        self.escaped = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Feed `input` and collect every message the framer hands out, then the EOF remainder.
+    fn frame_all(input: &[u8]) -> (Framer, Vec<Result<Value, ClaudeAgentError>>) {
+        let mut framer = Framer::default();
+        framer.push(input);
+        let mut messages = Vec::new();
+        while let Some(message) = framer.next_message() {
+            messages.push(message);
+        }
+        messages.extend(framer.finish_eof());
+        (framer, messages)
+    }
+
+    #[test]
+    fn test_value_over_many_lines_is_checked_in_linear_time() {
+        let object = serde_json::json!({
+            "items": (0..2000).map(|i| serde_json::json!({"id": i})).collect::<Vec<_>>()
+        });
+        let data = format!("{}\n", serde_json::to_string_pretty(&object).unwrap());
+        let size = data.len();
+
+        let (framer, messages) = frame_all(data.as_bytes());
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].as_ref().unwrap(), &object);
+        // One scan, one parse, and partial checks that at most double the scan
+        assert!(framer.work <= 4 * size, "work {} for {} bytes", framer.work, size);
+    }
+
+    #[test]
+    fn test_invalid_utf8_line_is_reported_and_skipped() {
+        let (_, messages) = frame_all(b"{\"id\":1}\n{\"text\":\"\xff\"}\n{\"id\":2}\n");
+        assert_eq!(messages.len(), 3);
+        assert_eq!(messages[0].as_ref().unwrap()["id"], 1);
+        assert!(
+            matches!(&messages[1], Err(ClaudeAgentError::JSONDecode(msg)) if msg.contains("invalid UTF-8"))
+        );
+        assert_eq!(messages[2].as_ref().unwrap()["id"], 2);
+    }
+
+    #[test]
+    fn test_invalid_utf8_inside_split_value_is_reported() {
+        let (_, messages) = frame_all(b"{\"a\":\n\"\xc3\x28\"}\n{\"id\":2}\n");
+        assert!(
+            matches!(&messages[0], Err(ClaudeAgentError::JSONDecode(msg)) if msg.contains("invalid UTF-8"))
+        );
+        assert_eq!(messages.last().unwrap().as_ref().unwrap()["id"], 2);
+    }
+
+    #[test]
+    fn test_character_split_across_reads_is_kept() {
+        let data = "{\"text\":\"caf\u{e9}\"}\n".as_bytes();
+        let split = data.iter().position(|&b| b == 0xc3).unwrap() + 1;
+        let mut framer = Framer::default();
+        framer.push(&data[..split]);
+        assert!(framer.next_message().is_none());
+        framer.push(&data[split..]);
+        assert_eq!(framer.next_message().unwrap().unwrap()["text"], "caf\u{e9}");
+    }
+}
@@ -0,0 +1,304 @@
+//! In-memory transport for offline tests.
+//!
+//! `MockTransport` replays scripted CLI output whenever a user message is
+//! written, so agents and clients can be exercised without spawning the CLI.
+//! `ControlMockTransport` also answers control requests.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use futures::stream::BoxStream;
+use tokio::sync::Notify;
+
+use super::{Subscription, Transport};
+use crate::types::{ClaudeAgentError, Timestamped};
+
+#[derive(Default)]
+struct MockState {
+    /// Every message emitted so far, in order.
+    log: Vec<serde_json::Value>,
+    /// Index in `log` where the current turn's output starts.
+    turn_start: usize,
+    /// Scripted output, one entry per user message.
+    turns: Vec<Vec<serde_json::Value>>,
+    /// Index of the next turn to replay.
+    next_turn: usize,
+    /// Replay the first turn for every user message.
+    repeat: bool,
+    closed: bool,
+}
+
+/// A transport that replays scripted responses for each user message.
+///
+/// Readers subscribed before a turn (such as the control loop) see every
+/// message; readers subscribed after writing a prompt see that turn's output.
+/// [`Transport::subscribe`] behaves like a live CLI instead and only sees
+/// messages emitted after it was called. Streams stay open until the
+/// transport is closed.
+#[derive(Clone)]
+pub struct MockTransport {
+    state: Arc<Mutex<MockState>>,
+    notify: Arc<Notify>,
+    sent: Arc<Mutex<Vec<String>>>,
+}
+
+impl MockTransport {
+    /// Replay the same `responses` after every user message.
+    pub fn new(responses: Vec<serde_json::Value>) -> Self {
+        let mock = Self::with_turns(vec![responses]);
+        if let Ok(mut state) = mock.state.lock() {
+            state.repeat = true;
+        }
+        mock
+    }
+
+    /// Replay `turns[i]` after the i-th user message; later messages get no output.
+    pub fn with_turns(turns: Vec<Vec<serde_json::Value>>) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(MockState { turns, ..Default::default() })),
+            notify: Arc::new(Notify::new()),
+            sent: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    /// Everything written to the transport so far.
+    pub fn sent_messages(&self) -> Vec<String> {
+        self.sent.lock().map(|sent| sent.clone()).unwrap_or_default()
+    }
+
+    /// Emit a message to all readers immediately.
+    pub fn push_incoming(&self, message: serde_json::Value) {
+        if let Ok(mut state) = self.state.lock() {
+            state.log.push(message);
+        }
+        self.notify.notify_waiters();
+    }
+
+    fn start_turn(&self) {
+        if let Ok(mut state) = self.state.lock() {
+            let index = if state.repeat { 0 } else { state.next_turn };
+            state.next_turn += 1;
+            let turn = state.turns.get(index).cloned();
+            state.turn_start = state.log.len();
+            state.log.extend(turn.unwrap_or_default());
+        }
+        self.notify.notify_waiters();
+    }
+}
+
+#[async_trait]
+impl Transport for MockTransport {
+    async fn connect(&mut self) -> Result<(), ClaudeAgentError> {
+        Ok(())
+    }
+
+    async fn write(&self, data: &str) -> Result<(), ClaudeAgentError> {
+        if let Ok(mut sent) = self.sent.lock() {
+            sent.push(data.to_string());
+        }
+        let is_user = serde_json::from_str::<serde_json::Value>(data)
+            .map(|v| v.get("type").and_then(|t| t.as_str()) == Some("user"))
+            .unwrap_or(false);
+        if is_user {
+            self.start_turn();
+        }
+        Ok(())
+    }
+
+    async fn read_messages(&self) -> BoxStream<'_, Result<serde_json::Value, ClaudeAgentError>> {
+        let turn_start = self.state.lock().map(|state| state.turn_start).unwrap_or_default();
+        self.replay_from(turn_start)
+    }
+
+    fn subscribe(&self) -> Option<Subscription> {
+        use futures::StreamExt;
+
+        let end = self.state.lock().map(|state| state.log.len()).unwrap_or_default();
+        Some(Box::pin(self.replay_from(end).map(|item| item.map(Timestamped::now))))
+    }
+
+    fn is_connected(&self) -> bool {
+        self.state.lock().map(|state| !state.closed).unwrap_or(false)
+    }
+
+    async fn close(&mut self) -> Result<(), ClaudeAgentError> {
+        if let Ok(mut state) = self.state.lock() {
+            state.closed = true;
+        }
+        self.notify.notify_waiters();
+        Ok(())
+    }
+}
+
+impl MockTransport {
+    /// Messages from `log[position]` on, until the transport closes.
+    fn replay_from(
+        &self,
+        mut position: usize,
+    ) -> BoxStream<'static, Result<serde_json::Value, ClaudeAgentError>> {
+        let mock = self.clone();
+        let stream = async_stream::stream! {
+            loop {
+                let notified = mock.notify.notified();
+                let (next, closed) = match mock.state.lock() {
+                    Ok(state) => (state.log.get(position).cloned(), state.closed),
+                    Err(_) => break,
+                };
+                match next {
+                    Some(message) => {
+                        position += 1;
+                        yield Ok(message);
+                    },
+                    None if closed => break,
+                    None => notified.await,
+                }
+            }
+        };
+        Box::pin(stream)
+    }
+}
+
+/// How [`ControlMockTransport`] answers a control request.
+#[derive(Debug, Clone)]
+pub enum ControlReply {
+    /// A `success` response carrying this payload.
+    Success(serde_json::Value),
+    /// An `error` response with this message.
+    Error(String),
+}
+
+/// A [`MockTransport`] that also answers control requests, the way the CLI does.
+///
+/// Every `control_request` written gets a `control_response` in the CLI's
+/// wire format. Requests succeed with an empty payload unless a reply was
+/// configured for their subtype with [`respond_with`](Self::respond_with) or
+/// [`fail_with`](Self::fail_with).
+///
+/// # Example
+///
+/// ```rust
+/// use claude_agent::transport::{ControlMockTransport, MockTransport};
+/// use claude_agent::ClaudeAgent;
+///
+/// # async fn example() -> Result<(), claude_agent::ClaudeAgentError> {
+/// let transport = ControlMockTransport::new(MockTransport::new(vec![]))
+///     .fail_with("interrupt", "no turn in progress");
+/// let mut agent = ClaudeAgent::new(Default::default());
+/// agent.set_transport(Box::new(transport.clone()));
+/// agent.connect(None).await?;
+///
+/// let response = agent.interrupt().await?;
+/// assert!(!response.success);
+/// assert_eq!(transport.control_requests()[1]["request"]["subtype"], "interrupt");
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct ControlMockTransport {
+    inner: MockTransport,
+    replies: Arc<Mutex<HashMap<String, ControlReply>>>,
+    requests: Arc<Mutex<Vec<serde_json::Value>>>,
+}
+
+impl ControlMockTransport {
+    /// Wrap `inner`, which still replays its scripted output for user messages.
+    pub fn new(inner: MockTransport) -> Self {
+        Self {
+            inner,
+            replies: Arc::new(Mutex::new(HashMap::new())),
+            requests: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    /// Answer requests of `subtype` with a success carrying `payload`.
+    pub fn respond_with(self, subtype: &str, payload: serde_json::Value) -> Self {
+        self.set_reply(subtype, ControlReply::Success(payload));
+        self
+    }
+
+    /// Answer requests of `subtype` with an error carrying `message`.
+    pub fn fail_with(self, subtype: &str, message: impl Into<String>) -> Self {
+        self.set_reply(subtype, ControlReply::Error(message.into()));
+        self
+    }
+
+    /// Change how requests of `subtype` are answered from now on.
+    pub fn set_reply(&self, subtype: &str, reply: ControlReply) {
+        if let Ok(mut replies) = self.replies.lock() {
+            replies.insert(subtype.to_string(), reply);
+        }
+    }
+
+    /// Every control request written so far, in order.
+    pub fn control_requests(&self) -> Vec<serde_json::Value> {
+        self.requests.lock().map(|requests| requests.clone()).unwrap_or_default()
+    }
+
+    /// The wrapped transport, e.g. to push messages or inspect all writes.
+    pub fn mock(&self) -> &MockTransport {
+        &self.inner
+    }
+
+    fn reply_to(&self, request: &serde_json::Value) {
+        let request_id = request.get("request_id").and_then(|id| id.as_str()).unwrap_or_default();
+        let subtype = request["request"]["subtype"].as_str().unwrap_or_default();
+        let reply = self
+            .replies
+            .lock()
+            .ok()
+            .and_then(|replies| replies.get(subtype).cloned())
+            .unwrap_or(ControlReply::Success(serde_json::json!({})));
+        let response = match reply {
+            ControlReply::Success(payload) => serde_json::json!({
+                "subtype": "success",
+                "request_id": request_id,
+                "response": payload,
+            }),
+            ControlReply::Error(message) => serde_json::json!({
+                "subtype": "error",
+                "request_id": request_id,
+                "error": message,
+            }),
+        };
+        self.inner
+            .push_incoming(serde_json::json!({"type": "control_response", "response": response}));
+    }
+}
+
+#[async_trait]
+impl Transport for ControlMockTransport {
+    async fn connect(&mut self) -> Result<(), ClaudeAgentError> {
+        self.inner.connect().await
+    }
+
+    async fn write(&self, data: &str) -> Result<(), ClaudeAgentError> {
+        self.inner.write(data).await?;
+        let request = serde_json::from_str::<serde_json::Value>(data)
+            .ok()
+            .filter(|value| value.get("type").and_then(|t| t.as_str()) == Some("control_request"));
+        if let Some(request) = request {
+            if let Ok(mut requests) = self.requests.lock() {
+                requests.push(request.clone());
+            }
+            self.reply_to(&request);
+        }
+        Ok(())
+    }
+
+    async fn read_messages(&self) -> BoxStream<'_, Result<serde_json::Value, ClaudeAgentError>> {
+        self.inner.read_messages().await
+    }
+
+    fn subscribe(&self) -> Option<Subscription> {
+        self.inner.subscribe()
+    }
+
+    fn is_connected(&self) -> bool {
+        self.inner.is_connected()
+    }
+
+    async fn close(&mut self) -> Result<(), ClaudeAgentError> {
+        self.inner.close().await
+    }
+}
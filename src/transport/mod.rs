@@ -1,20 +1,75 @@
 //! Transport layer for Claude Agent SDK.
 
+mod framer;
+pub mod mock;
 pub mod parser;
+pub(crate) mod process;
 pub mod reader;
 pub mod subprocess;
+pub mod version;
 
-use crate::types::ClaudeAgentError;
+use crate::types::{ClaudeAgentError, Timestamped};
 use async_trait::async_trait;
 use futures::stream::BoxStream;
 
+pub use mock::{ControlMockTransport, ControlReply, MockTransport};
 pub use subprocess::SubprocessTransport;
+pub use version::CliVersion;
+
+/// Incoming messages, each stamped with the time the transport received it.
+pub type Subscription =
+    BoxStream<'static, Result<Timestamped<serde_json::Value>, ClaudeAgentError>>;
 
 /// Transport trait for communication with Claude Code.
 #[async_trait]
 pub trait Transport: Send + Sync {
     async fn connect(&mut self) -> Result<(), ClaudeAgentError>;
     async fn write(&self, data: &str) -> Result<(), ClaudeAgentError>;
+
+    /// Write a raw payload as one newline-framed message.
+    ///
+    /// The default implementation requires UTF-8 and delegates to `write`;
+    /// transports that can write bytes directly should override it.
+    async fn write_bytes(&self, data: &[u8]) -> Result<(), ClaudeAgentError> {
+        let text = std::str::from_utf8(data)
+            .map_err(|e| ClaudeAgentError::Transport(format!("Payload is not UTF-8: {}", e)))?;
+        self.write(text).await
+    }
     async fn read_messages(&self) -> BoxStream<'_, Result<serde_json::Value, ClaudeAgentError>>;
+
+    /// Subscribe to incoming messages now, as an owned stream.
+    ///
+    /// Unlike `read_messages`, whose stream may only start listening when first
+    /// polled, nothing arriving after this returns is missed, and the stream does
+    /// not borrow the transport. Each message carries the time it was received,
+    /// not the time it is polled. Returns `None` by default; callers then fall
+    /// back to `read_messages`.
+    fn subscribe(&self) -> Option<Subscription> {
+        None
+    }
     async fn close(&mut self) -> Result<(), ClaudeAgentError>;
+
+    /// Stop immediately, without the graceful shutdown `close` attempts.
+    ///
+    /// The default implementation delegates to `close`.
+    async fn kill(&mut self) -> Result<(), ClaudeAgentError> {
+        self.close().await
+    }
+
+    /// The process id of the CLI, while it is running. Defaults to `None`.
+    ///
+    /// Lets callers kill the CLI without locking the transport.
+    fn process_id(&self) -> Option<u32> {
+        None
+    }
+
+    /// The CLI version detected when connecting, if the transport knows it.
+    fn detected_cli_version(&self) -> Option<CliVersion> {
+        None
+    }
+
+    /// Whether the transport is still usable. Defaults to `true`.
+    fn is_connected(&self) -> bool {
+        true
+    }
 }
@@ -0,0 +1,111 @@
+//! Stopping child processes: the CLI and stdio MCP servers.
+
+use std::time::Duration;
+
+use tokio::process::Child;
+
+/// How a child process ended when stopped with [`stop`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Stopped {
+    /// It exited within the grace period after its stdin was closed.
+    Exited,
+    /// It exited within the grace period after `SIGTERM`.
+    Terminated,
+    /// It ignored `SIGTERM` and was killed.
+    Killed,
+}
+
+/// Stop `child`, whose stdin the caller has already closed.
+///
+/// Waits `grace` for it to exit, then sends `SIGTERM` and waits `grace`
+/// again, then kills it. The child is always reaped.
+pub(crate) async fn stop(child: &mut Child, grace: Duration) -> std::io::Result<Stopped> {
+    if wait_for_exit(child, grace).await? {
+        return Ok(Stopped::Exited);
+    }
+    terminate(child);
+    if wait_for_exit(child, grace).await? {
+        return Ok(Stopped::Terminated);
+    }
+    child.kill().await?;
+    Ok(Stopped::Killed)
+}
+
+/// Wait up to `grace` for `child` to exit; `Ok(false)` if it is still running.
+async fn wait_for_exit(child: &mut Child, grace: Duration) -> std::io::Result<bool> {
+    match tokio::time::timeout(grace, child.wait()).await {
+        Ok(status) => status.map(|_| true),
+        Err(_) => Ok(false),
+    }
+}
+
+/// Ask `child` to terminate with `SIGTERM`.
+///
+/// Windows has no `SIGTERM`; there [`stop`] goes straight on to killing it.
+fn terminate(child: &Child) {
+    if let Some(pid) = child.id() {
+        signal(pid, Signal::Terminate);
+    }
+}
+
+/// Kill the process `pid` with `SIGKILL`, without waiting for it.
+///
+/// `pid` must be a child that has not been reaped yet. Windows has no
+/// signals; there the caller must kill the child through its handle.
+pub(crate) fn kill(pid: u32) {
+    signal(pid, Signal::Kill);
+}
+
+/// Signals [`signal`] can send.
+#[derive(Debug, Clone, Copy)]
+enum Signal {
+    Terminate,
+    Kill,
+}
+
+#[cfg(unix)]
+fn signal(pid: u32, signal: Signal) {
+    let signal = match signal {
+        Signal::Terminate => libc::SIGTERM,
+        Signal::Kill => libc::SIGKILL,
+    };
+    // SAFETY: `kill` has no memory-safety preconditions, and the pid belongs
+    // to a child that has not been reaped yet.
+    unsafe {
+        libc::kill(pid as libc::pid_t, signal);
+    }
+}
+
+#[cfg(not(unix))]
+fn signal(_pid: u32, _signal: Signal) {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_stop_escalates_to_kill_when_sigterm_is_ignored() {
+        let mut child = tokio::process::Command::new("sh")
+            .arg("-c")
+            .arg("trap '' TERM; while :; do sleep 1; done")
+            .spawn()
+            .unwrap();
+        // Give the shell time to install its trap
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        let stopped = stop(&mut child, Duration::from_millis(100)).await.unwrap();
+        assert_eq!(stopped, Stopped::Killed);
+        assert!(child.try_wait().unwrap().is_some());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_stop_terminates_a_child_that_ignores_eof() {
+        let mut child =
+            tokio::process::Command::new("sh").arg("-c").arg("sleep 30").spawn().unwrap();
+
+        let stopped = stop(&mut child, Duration::from_millis(100)).await.unwrap();
+        assert_eq!(stopped, Stopped::Terminated);
+    }
+}
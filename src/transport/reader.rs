@@ -5,8 +5,11 @@
 //!
 //! # Features
 //!
-//! - **Streaming Parser**: Uses `serde_json::Deserializer::from_str` with `into_iter`
-//!   for efficient streaming without loading entire input into memory
+//! - **NDJSON Fast Path**: Complete lines are parsed directly, since the CLI
+//!   emits one object per line
+//! - **Incremental Framing**: Scans objects and arrays once as bytes arrive and
+//!   hands each complete value to `serde_json`, so large messages are not
+//!   re-parsed on every read
 //! - **Split Packet Handling**: Correctly handles JSON objects split across
 //!   multiple read operations
 //! - **Multiple Messages Per Line**: Supports multiple JSON objects on a single line
@@ -16,9 +19,11 @@
 //! # Architecture
 //!
 //! The parser maintains an internal buffer that accumulates data from the underlying
-//! reader. It attempts to parse complete JSON objects using a streaming deserializer.
-//! If parsing fails due to incomplete data, it continues reading. If parsing
-//! fails due to invalid JSON, it returns an error with a buffer preview.
+//! reader, remembering how far it has scanned and how deeply nested the current
+//! value is. Once the closing bracket arrives, the value is parsed in one pass.
+//! If the value is still incomplete, it continues reading. If parsing
+//! fails due to invalid JSON, it returns an error with a buffer preview and
+//! skips the rest of that line, so later messages are still delivered.
 //!
 //! # Example
 //!
@@ -29,8 +34,11 @@
 //! The parser will return an error if the internal buffer exceeds `max_buffer_size`.
 //! This prevents memory exhaustion from malformed input or unbounded data streams.
 //! The default buffer size is 64KB, which can be customized using
-//! `MessageReader::with_capacity()`.
+//! `MessageReader::with_capacity()`. With `BufferOverflowPolicy::SkipToNewline`
+//! the oversized message is dropped instead and reading resumes at the next line.
 
+use super::framer::Framer;
+use crate::types::config::BufferOverflowPolicy;
 use crate::types::ClaudeAgentError;
 use futures::Stream;
 use pin_project_lite::pin_project;
@@ -42,6 +50,9 @@ use tokio::io::{AsyncRead, BufReader};
 // Default buffer size 64KB
 const DEFAULT_BUFFER_SIZE: usize = 64 * 1024;
 
+// Default size of each read from the underlying source 16KB
+const DEFAULT_CHUNK_SIZE: usize = 16 * 1024;
+
 pin_project! {
     /// A stream reader that parses JSON messages from an AsyncRead source.
     ///
@@ -57,14 +68,18 @@ pin_project! {
     ///
     /// The reader maintains an internal buffer that grows as data is read.
     /// If the buffer exceeds `max_buffer_size`, a `Transport` error is returned
-    /// to prevent memory exhaustion.
+    /// to prevent memory exhaustion. Data is pulled from the source in chunks
+    /// of `chunk_size` bytes (16KB by default, see `with_chunk_size()`), and
+    /// each byte is scanned once no matter how many reads a message spans.
     ///
     /// # Error Handling
     ///
     /// - **EOF**: Returns `Poll::Ready(None)` when the underlying reader is exhausted
     /// - **Incomplete JSON**: Continues reading if JSON is incomplete (EOF error)
-    /// - **Invalid JSON**: Returns error with buffer preview (first 100 chars) for debugging
-    /// - **Buffer Overflow**: Returns error if buffer size limit is exceeded
+    /// - **Invalid JSON**: Returns error with buffer preview (first 100 chars) for debugging,
+    ///   then skips to the next line
+    /// - **Buffer Overflow**: Returns error if buffer size limit is exceeded; see
+    ///   `with_overflow_policy()` to skip the oversized message instead
     ///
     /// # Example
     ///
@@ -87,8 +102,10 @@ pin_project! {
     pub struct MessageReader<R> {
         #[pin]
         reader: BufReader<R>,
-        buffer: String,
+        framer: Framer,
+        chunk: Vec<u8>,
         max_buffer_size: usize,
+        overflow_policy: BufferOverflowPolicy,
     }
 }
 
@@ -114,11 +131,7 @@ impl<R: AsyncRead> MessageReader<R> {
     /// let reader = MessageReader::new(stdout);
     /// ```
     pub fn new(inner: R) -> Self {
-        Self {
-            reader: BufReader::new(inner),
-            buffer: String::new(),
-            max_buffer_size: DEFAULT_BUFFER_SIZE,
-        }
+        Self::with_capacity(inner, DEFAULT_BUFFER_SIZE)
     }
 
     /// Create a new message reader with custom buffer size.
@@ -145,7 +158,48 @@ impl<R: AsyncRead> MessageReader<R> {
     /// let reader = MessageReader::with_capacity(stdout, 1024 * 1024);
     /// ```
     pub fn with_capacity(inner: R, max_size: usize) -> Self {
-        Self { reader: BufReader::new(inner), buffer: String::new(), max_buffer_size: max_size }
+        Self {
+            reader: BufReader::new(inner),
+            framer: Framer::default(),
+            chunk: vec![0; DEFAULT_CHUNK_SIZE],
+            max_buffer_size: max_size,
+            overflow_policy: BufferOverflowPolicy::default(),
+        }
+    }
+
+    /// Read from the source in chunks of `chunk_size` bytes (default 16KB).
+    ///
+    /// Larger chunks mean fewer polls for big messages; a size of 0 is treated as 1.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// use crate::transport::reader::MessageReader;
+    ///
+    /// let reader = MessageReader::with_capacity(stdout, 8 * 1024 * 1024).with_chunk_size(64 * 1024);
+    /// ```
+    pub fn with_chunk_size(mut self, chunk_size: usize) -> Self {
+        self.chunk = vec![0; chunk_size.max(1)];
+        self
+    }
+
+    /// Choose what happens when a message outgrows the buffer (default: fail).
+    ///
+    /// With `BufferOverflowPolicy::SkipToNewline` the partial message is dropped,
+    /// input is discarded up to the next newline, and a non-fatal
+    /// `MessageTooLarge` error is yielded before reading resumes.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// use crate::transport::reader::MessageReader;
+    /// use crate::types::config::BufferOverflowPolicy;
+    ///
+    /// let reader = MessageReader::new(stdout).with_overflow_policy(BufferOverflowPolicy::SkipToNewline);
+    /// ```
+    pub fn with_overflow_policy(mut self, policy: BufferOverflowPolicy) -> Self {
+        self.overflow_policy = policy;
+        self
     }
 }
 
@@ -156,84 +210,42 @@ impl<R: AsyncRead + Unpin> Stream for MessageReader<R> {
         let mut this = self.project();
 
         loop {
-            // 1. Check if we have a complete message in buffer using streaming deserializer
-            // We use from_str which allows us to peek without consuming if we clone mechanism,
-            // but effectively we just try to parse the first object.
-
-            // We need a specific block to limit borrow scope if needed
-            {
-                let mut stream =
-                    serde_json::Deserializer::from_str(this.buffer).into_iter::<Value>();
-                match stream.next() {
-                    Some(Ok(val)) => {
-                        let offset = stream.byte_offset();
-                        // Consumed one object
-                        this.buffer.drain(..offset);
-                        return Poll::Ready(Some(Ok(val)));
-                    },
-                    Some(Err(ref e)) if e.is_eof() => {
-                        // Incomplete JSON, need more data.
-                        // Break out of match to read more.
-                    },
-                    Some(Err(e)) => {
-                        // Syntax error or other error
-                        // If it's just "trailing characters", that shouldn't happen with into_iter (it handles stream).
-                        // If it's truly invalid syntax, we should return error.
-                        // But wait: if buffer is "{" (eof), it hits is_eof().
-                        // If buffer is "invalid", it hits here.
-                        let preview = this.buffer.chars().take(100).collect::<String>();
-                        return Poll::Ready(Some(Err(ClaudeAgentError::JSONDecode(format!(
-                            "Parse error: {}. Buffer preview: {}",
-                            e, preview
-                        )))));
-                    },
-                    None => {
-                        // Buffer might be empty or just whitespace
-                        if this.buffer.trim().is_empty() {
-                            this.buffer.clear();
-                            // If buffer empty, we need read.
-                        } else {
-                            // Should theoretically not happen if trim is not empty and next() is None?
-                            // Actually it means only whitespace.
-                            this.buffer.clear();
-                        }
-                    },
-                }
+            // 1. Hand out a complete message if the buffer holds one
+            if let Some(message) = this.framer.next_message() {
+                return Poll::Ready(Some(message));
             }
 
             // 2. Read more data
-            let mut buf = [0u8; 1024];
-            let mut read_buf = tokio::io::ReadBuf::new(&mut buf);
+            let mut read_buf = tokio::io::ReadBuf::new(this.chunk);
 
             match this.reader.as_mut().poll_read(cx, &mut read_buf) {
                 Poll::Ready(Ok(())) => {
                     let n = read_buf.filled().len();
                     if n == 0 {
                         // EOF
-                        if !this.buffer.trim().is_empty() {
-                            // Try parse remaining
-                            match serde_json::from_str(this.buffer) {
-                                Ok(val) => {
-                                    this.buffer.clear();
-                                    return Poll::Ready(Some(Ok(val)));
-                                },
-                                Err(e) => {
-                                    return Poll::Ready(Some(Err(ClaudeAgentError::JSONDecode(
-                                        format!("EOF with invalid json: {}", e),
-                                    ))));
-                                },
-                            }
-                        }
-                        return Poll::Ready(None);
+                        return Poll::Ready(this.framer.finish_eof());
                     }
 
-                    let chunk = String::from_utf8_lossy(read_buf.filled());
-                    this.buffer.push_str(&chunk);
+                    this.framer.push(read_buf.filled());
 
-                    if this.buffer.len() > *this.max_buffer_size {
-                        return Poll::Ready(Some(Err(ClaudeAgentError::Transport(
-                            "Buffer overflow".to_string(),
-                        ))));
+                    if this.framer.len() > *this.max_buffer_size {
+                        match this.overflow_policy {
+                            BufferOverflowPolicy::Error => {
+                                return Poll::Ready(Some(Err(ClaudeAgentError::Transport(
+                                    "Buffer overflow".to_string(),
+                                ))));
+                            },
+                            // Messages completed by this read are still handed out first
+                            BufferOverflowPolicy::SkipToNewline => {
+                                if let Some(message) = this.framer.next_message() {
+                                    return Poll::Ready(Some(message));
+                                }
+                                this.framer.skip_line();
+                                return Poll::Ready(Some(Err(ClaudeAgentError::MessageTooLarge(
+                                    *this.max_buffer_size,
+                                ))));
+                            },
+                        }
                     }
                     // Loop back to try parsing
                 },
@@ -245,3 +257,84 @@ impl<R: AsyncRead + Unpin> Stream for MessageReader<R> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::StreamExt;
+    use std::io::Cursor;
+
+    #[tokio::test]
+    async fn test_large_object_in_small_chunks_is_scanned_once() {
+        let object = serde_json::json!({"type": "assistant", "text": "y".repeat(500 * 1024)});
+        let data = format!("{}\n", object).into_bytes();
+        let size = data.len();
+
+        let mut reader =
+            MessageReader::with_capacity(Cursor::new(data), 1024 * 1024).with_chunk_size(64);
+        let parsed = reader.next().await.unwrap().unwrap();
+        assert_eq!(parsed, object);
+
+        // One scan plus one parse; re-parsing per chunk would be ~4000x the size
+        assert!(reader.framer.work <= 2 * size, "work {} for {} bytes", reader.framer.work, size);
+    }
+
+    #[tokio::test]
+    async fn test_ndjson_lines_skip_the_scan() {
+        let lines: Vec<String> = (0..100).map(|i| format!("{{\"id\":{}}}", i)).collect();
+        let data = lines.join("\n") + "\n";
+        let payload: usize = lines.iter().map(String::len).sum();
+
+        let mut reader = MessageReader::new(Cursor::new(data.into_bytes()));
+        for i in 0..100 {
+            assert_eq!(reader.next().await.unwrap().unwrap()["id"], i);
+        }
+        assert!(reader.next().await.is_none());
+        assert_eq!(reader.framer.work, payload);
+    }
+
+    #[tokio::test]
+    async fn test_many_small_messages_leave_buffer_empty() {
+        let data: String = (0..1000).map(|i| format!("{{\"id\":{}}}\n", i)).collect();
+        let mut reader = MessageReader::new(Cursor::new(data.into_bytes()));
+
+        for i in 0..1000 {
+            assert_eq!(reader.next().await.unwrap().unwrap()["id"], i);
+        }
+        assert!(reader.next().await.is_none());
+        assert_eq!(reader.framer.len(), 0);
+    }
+
+    /// Small messages around one that is too large for a 64-byte buffer.
+    fn oversized_input() -> Vec<u8> {
+        let big = serde_json::json!({"big": "x".repeat(200)});
+        format!("{{\"id\":1}}\n{}\n{{\"id\":2}}\n", big).into_bytes()
+    }
+
+    #[tokio::test]
+    async fn test_overflow_error_policy_fails_fatally() {
+        let mut reader = MessageReader::with_capacity(Cursor::new(oversized_input()), 64)
+            .with_chunk_size(16)
+            .with_overflow_policy(BufferOverflowPolicy::Error);
+
+        assert_eq!(reader.next().await.unwrap().unwrap()["id"], 1);
+        let err = reader.next().await.unwrap().unwrap_err();
+        assert!(matches!(err, ClaudeAgentError::Transport(ref msg) if msg == "Buffer overflow"));
+        assert!(err.is_fatal());
+    }
+
+    #[tokio::test]
+    async fn test_overflow_skip_policy_resyncs_on_next_line() {
+        let mut reader = MessageReader::with_capacity(Cursor::new(oversized_input()), 64)
+            .with_chunk_size(16)
+            .with_overflow_policy(BufferOverflowPolicy::SkipToNewline);
+
+        assert_eq!(reader.next().await.unwrap().unwrap()["id"], 1);
+        let err = reader.next().await.unwrap().unwrap_err();
+        assert!(matches!(err, ClaudeAgentError::MessageTooLarge(64)));
+        assert!(!err.is_fatal());
+        assert_eq!(reader.next().await.unwrap().unwrap()["id"], 2);
+        assert!(reader.next().await.is_none());
+        assert_eq!(reader.framer.len(), 0);
+    }
+}
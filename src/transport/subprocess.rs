@@ -16,6 +16,7 @@
 //! - **Timeout Handling**: Prevents indefinite hangs during connection
 //! - **Resource Cleanup**: Properly aborts background tasks on close
 //! - **Broadcast Channel**: Distributes messages to multiple subscribers
+//! - **Buffered Reads**: Optional bounded queue that applies backpressure instead
 //!
 //! # Example
 //!
@@ -55,18 +56,121 @@
 
 use std::path::PathBuf;
 use std::process::Stdio;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 
 use async_trait::async_trait;
 use futures::stream::{self, BoxStream};
-use tokio::io::AsyncWriteExt;
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt, BufWriter};
 use tokio::process::{Child, Command};
 
 use tokio::sync::Mutex;
 
-use crate::types::{ClaudeAgentError, ClaudeAgentOptions};
+use crate::types::config::{mask, mask_json, CLEAR_ENV_DEFAULTS};
+use crate::types::{ClaudeAgentError, ClaudeAgentOptions, Timestamped};
 
-use crate::transport::Transport;
+use crate::transport::process::{self, Stopped};
+use crate::transport::{CliVersion, Subscription, Transport};
+
+/// Item carried from the reader task to subscribers, stamped when it was parsed.
+type Inbound = Result<Timestamped<serde_json::Value>, ClaudeAgentError>;
+
+/// Where the reader task delivers parsed messages.
+enum Sink {
+    Broadcast(tokio::sync::broadcast::Sender<Inbound>),
+    Queue(tokio::sync::mpsc::Sender<Inbound>),
+}
+
+/// Default capacity of the broadcast channel distributing CLI output to readers.
+const DEFAULT_BROADCAST_CAPACITY: usize = 1000;
+
+/// Write half of the CLI connection.
+type CliWriter = Box<dyn AsyncWrite + Send + Unpin>;
+
+/// Read half of the CLI connection.
+type CliReader = Box<dyn AsyncRead + Send + Unpin>;
+
+/// Default base delay between spawn retries.
+const DEFAULT_RETRY_DELAY_MS: u64 = 100;
+
+/// Default for `ClaudeAgentOptions::close_grace_period_ms`.
+const DEFAULT_CLOSE_GRACE_MS: u64 = 5000;
+
+/// Oldest CLI the SDK supports; `connect` refuses older ones.
+///
+/// Every flag `build_command` emits, including `--verbose` and
+/// `--input-format`, is accepted from this version on, so none is gated
+/// separately.
+const MINIMUM_CLI_VERSION: CliVersion = CliVersion::new(2, 0, 0);
+
+/// How long `claude --version` may take before the probe is abandoned.
+const VERSION_PROBE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(2);
+
+/// Identifies a CLI binary for the version cache: its path and modification time.
+type ProbeCacheKey = (PathBuf, std::time::SystemTime);
+
+/// CLI versions probed so far in this process, so each binary is run once.
+static CLI_VERSION_CACHE: std::sync::OnceLock<
+    std::sync::Mutex<std::collections::HashMap<ProbeCacheKey, CliVersion>>,
+> = std::sync::OnceLock::new();
+
+/// The cache key for `cli_path`, or `None` if its modification time is unknown.
+fn probe_cache_key(cli_path: &std::path::Path) -> Option<ProbeCacheKey> {
+    let modified = std::fs::metadata(cli_path).and_then(|metadata| metadata.modified()).ok()?;
+    Some((cli_path.to_path_buf(), modified))
+}
+
+fn cached_cli_version(key: &ProbeCacheKey) -> Option<CliVersion> {
+    let cache = CLI_VERSION_CACHE.get()?.lock().unwrap_or_else(|e| e.into_inner());
+    cache.get(key).copied()
+}
+
+fn cache_cli_version(key: ProbeCacheKey, version: CliVersion) {
+    let cache = CLI_VERSION_CACHE.get_or_init(Default::default);
+    cache.lock().unwrap_or_else(|e| e.into_inner()).insert(key, version);
+}
+
+/// Whether a spawn error is likely to succeed on retry (e.g. `ETXTBSY` right after install).
+fn is_transient_spawn_error(error: &std::io::Error) -> bool {
+    use std::io::ErrorKind;
+    matches!(
+        error.kind(),
+        ErrorKind::ExecutableFileBusy
+            | ErrorKind::ResourceBusy
+            | ErrorKind::Interrupted
+            | ErrorKind::WouldBlock
+    )
+}
+
+/// Run `spawn`, retrying transient failures up to `retries` times with exponential backoff.
+async fn spawn_with_retry<T>(
+    mut spawn: impl FnMut() -> std::io::Result<T>,
+    retries: u32,
+    base_delay: std::time::Duration,
+) -> std::io::Result<T> {
+    let mut backoff = crate::types::Backoff::new(base_delay, base_delay.saturating_mul(32))
+        .with_jitter(0.2)
+        .with_max_attempts(retries);
+    loop {
+        match spawn() {
+            Ok(value) => return Ok(value),
+            Err(e) if is_transient_spawn_error(&e) => match backoff.next_delay() {
+                Some(delay) => {
+                    tracing::warn!(
+                        attempt = backoff.attempt(),
+                        max_retries = retries,
+                        ?delay,
+                        error = %e,
+                        "Transient CLI spawn failure, retrying"
+                    );
+                    tokio::time::sleep(delay).await;
+                },
+                None => return Err(e),
+            },
+            Err(e) => return Err(e),
+        }
+    }
+}
 
 /// Subprocess transport using Claude Code CLI.
 ///
@@ -93,11 +197,16 @@ use crate::transport::Transport;
 /// without blocking each other. If there are no subscribers, messages are
 /// silently dropped (SendError is ignored).
 ///
+/// Build with [`SubprocessTransport::with_buffered_reads`] to use a bounded
+/// queue instead: the reader stops pulling from stdout while the queue is
+/// full, so nothing is dropped for a slow consumer.
+///
 /// # Timeout
 ///
 /// The `connect()` method has a 30-second timeout to prevent indefinite hangs
 /// if the CLI process fails to start. This can be customized by modifying
-/// the `CONNECT_TIMEOUT_SECS` constant.
+/// the `CONNECT_TIMEOUT_SECS` constant. A timed-out connect fails with
+/// `ClaudeAgentError::ConnectTimeout`.
 ///
 /// # Example
 ///
@@ -125,19 +234,263 @@ pub struct SubprocessTransport {
     process: Option<Child>,
 
     /// Shared stdin handle for writing to the process.
-    stdin: Option<Arc<Mutex<tokio::process::ChildStdin>>>,
+    stdin: Option<Arc<Mutex<CliWriter>>>,
+
+    /// Handles supplied by `from_handles`, wired up on `connect` instead of spawning.
+    /// Held in a mutex only so the transport stays `Sync`.
+    attached: Option<std::sync::Mutex<(CliWriter, CliReader)>>,
+
+    /// Set when coalesced writes are waiting for the next periodic flush.
+    unflushed: Arc<AtomicBool>,
+
+    /// Abort handle for the periodic flush task when writes are coalesced.
+    flusher_abort_handle: Option<tokio::task::AbortHandle>,
+
+    /// Error that stopped the periodic flush task, returned by the next write.
+    flush_error: Arc<std::sync::Mutex<Option<ClaudeAgentError>>>,
 
     /// Broadcast channel for distributing messages to multiple subscribers (turns).
-    inbox: Option<tokio::sync::broadcast::Sender<Result<serde_json::Value, ClaudeAgentError>>>,
+    inbox: Option<tokio::sync::broadcast::Sender<Inbound>>,
+
+    /// Capacity of the bounded queue set by `with_buffered_reads`.
+    buffered_read_capacity: Option<usize>,
+
+    /// Bounded queue used instead of `inbox` when buffered reads are enabled.
+    queue: Option<Arc<Mutex<tokio::sync::mpsc::Receiver<Inbound>>>>,
 
     /// Abort handle for the background reader task.
     reader_abort_handle: Option<tokio::task::AbortHandle>,
+
+    /// Version reported by `claude --version`, once probed.
+    cli_version: std::sync::OnceLock<CliVersion>,
 }
 
 impl SubprocessTransport {
     /// Create a new subprocess transport.
     pub fn new(prompt: Option<String>, options: ClaudeAgentOptions) -> Self {
-        Self { options, prompt, process: None, stdin: None, inbox: None, reader_abort_handle: None }
+        Self {
+            options,
+            prompt,
+            process: None,
+            stdin: None,
+            attached: None,
+            unflushed: Arc::new(AtomicBool::new(false)),
+            flusher_abort_handle: None,
+            flush_error: Arc::new(std::sync::Mutex::new(None)),
+            inbox: None,
+            buffered_read_capacity: None,
+            queue: None,
+            reader_abort_handle: None,
+            cli_version: std::sync::OnceLock::new(),
+        }
+    }
+
+    /// Deliver CLI output through a bounded queue of `capacity` messages instead
+    /// of the broadcast channel.
+    ///
+    /// The reader stops pulling from stdout while the queue is full, so a slow
+    /// consumer applies backpressure rather than lagging. The queue has a single
+    /// reader at a time; see [`SubprocessTransport::read_messages_buffered`].
+    /// Do not hand such a transport to `ClaudeAgent`: its control loop would
+    /// take that reader for good.
+    pub fn with_buffered_reads(mut self, capacity: usize) -> Self {
+        self.buffered_read_capacity = Some(capacity);
+        self
+    }
+
+    /// Run `claude --version` and parse the result, caching it for later calls.
+    ///
+    /// The probe gets the same environment as the CLI itself and is abandoned
+    /// after two seconds. Results are shared across transports in this process,
+    /// so a CLI binary is only probed again once it changes on disk.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ClaudeAgentError::CLINotFound` if there is no CLI, and
+    /// `ClaudeAgentError::Process` if it fails, times out, or prints no version.
+    pub async fn cli_version(&self) -> Result<CliVersion, ClaudeAgentError> {
+        if let Some(version) = self.cli_version.get() {
+            return Ok(*version);
+        }
+        let cli_path = self.find_cli()?;
+        let key = probe_cache_key(&cli_path);
+        if let Some(version) = key.as_ref().and_then(cached_cli_version) {
+            return Ok(*self.cli_version.get_or_init(|| version));
+        }
+        let mut cmd = Command::new(&cli_path);
+        self.apply_env(&mut cmd);
+        cmd.arg("--version")
+            .stdin(std::process::Stdio::null())
+            .stdout(std::process::Stdio::piped())
+            .stderr(std::process::Stdio::null())
+            .kill_on_drop(true);
+        let output = tokio::time::timeout(VERSION_PROBE_TIMEOUT, cmd.output())
+            .await
+            .map_err(|_| ClaudeAgentError::Process("claude --version timed out".to_string()))?
+            .map_err(|e| {
+                ClaudeAgentError::Process(format!("Failed to run claude --version: {}", e))
+            })?;
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let version = CliVersion::parse(&stdout).ok_or_else(|| {
+            ClaudeAgentError::Process(format!(
+                "Could not parse CLI version from {:?}",
+                stdout.trim()
+            ))
+        })?;
+        if let Some(key) = key {
+            cache_cli_version(key, version);
+        }
+        Ok(*self.cli_version.get_or_init(|| version))
+    }
+
+    /// Attach to an already-running CLI through its stdin and stdout handles.
+    ///
+    /// `connect` skips spawning and starts reading from `stdout` directly;
+    /// `close` drops both handles but leaves the process itself alone.
+    pub fn from_handles<W, R>(stdin: W, stdout: R, options: ClaudeAgentOptions) -> Self
+    where
+        W: AsyncWrite + Send + Unpin + 'static,
+        R: AsyncRead + Send + Unpin + 'static,
+    {
+        let mut transport = Self::new(None, options);
+        transport.attached = Some(std::sync::Mutex::new((Box::new(stdin), Box::new(stdout))));
+        transport
+    }
+
+    /// Install the CLI's stdin, buffering it behind a periodic flush if configured.
+    fn attach_writer(&mut self, writer: CliWriter) {
+        let Some(interval_ms) = self.options.write_flush_interval_ms else {
+            self.stdin = Some(Arc::new(Mutex::new(writer)));
+            return;
+        };
+
+        let writer: CliWriter = Box::new(BufWriter::new(writer));
+        let stdin = Arc::new(Mutex::new(writer));
+        let target = stdin.clone();
+        let unflushed = self.unflushed.clone();
+        self.flush_error = Arc::new(std::sync::Mutex::new(None));
+        let flush_error = self.flush_error.clone();
+        let period = std::time::Duration::from_millis(interval_ms.max(1));
+
+        let abort_handle = tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(period);
+            loop {
+                ticker.tick().await;
+                if unflushed.swap(false, Ordering::SeqCst) {
+                    if let Err(e) = target.lock().await.flush().await {
+                        tracing::warn!(error = %e, "Coalesced flush to CLI stdin failed");
+                        if let Ok(mut slot) = flush_error.lock() {
+                            *slot =
+                                Some(ClaudeAgentError::Transport(format!("Flush failed: {}", e)));
+                        }
+                        break;
+                    }
+                }
+            }
+        })
+        .abort_handle();
+
+        self.flusher_abort_handle = Some(abort_handle);
+        self.stdin = Some(stdin);
+    }
+
+    /// The error that stopped coalesced writes from being flushed, if any.
+    fn flush_error(&self) -> Option<ClaudeAgentError> {
+        self.flush_error.lock().ok().and_then(|slot| slot.clone())
+    }
+
+    /// Start the background task that delivers parsed messages from `stdout`.
+    fn start_reader(&mut self, stdout: CliReader) {
+        let mut sink = match self.buffered_read_capacity {
+            Some(capacity) => {
+                let (tx, rx) = tokio::sync::mpsc::channel(capacity.max(1));
+                self.queue = Some(Arc::new(Mutex::new(rx)));
+                self.inbox = None;
+                Sink::Queue(tx)
+            },
+            None => {
+                let capacity =
+                    self.options.broadcast_capacity.unwrap_or(DEFAULT_BROADCAST_CAPACITY).max(1);
+                let (tx, _) = tokio::sync::broadcast::channel(capacity);
+                self.inbox = Some(tx.clone());
+                self.queue = None;
+                Sink::Broadcast(tx)
+            },
+        };
+        let max_buffer_size = self.options.max_buffer_size;
+        let overflow_policy = self.options.buffer_overflow_policy;
+
+        let abort_handle = tokio::spawn(async move {
+            use crate::transport::reader::MessageReader;
+            use futures::StreamExt;
+
+            let reader = match max_buffer_size {
+                Some(max_size) => MessageReader::with_capacity(stdout, max_size),
+                None => MessageReader::new(stdout),
+            }
+            .with_overflow_policy(overflow_policy);
+            let mut stream = Box::pin(reader);
+
+            while let Some(msg_res) = stream.next().await {
+                let msg_res = msg_res.map(Timestamped::now);
+                // Keep reading past malformed messages; stop after a fatal read error
+                let fatal = msg_res.as_ref().is_err_and(ClaudeAgentError::is_fatal);
+
+                match &mut sink {
+                    Sink::Broadcast(tx) => {
+                        // No subscribers left, but we should keep reading to drain stdout?
+                        // Or maybe just exit.
+                        // Ideally we keep reading because a new subscriber might appear (Next Turn).
+                        // But broadcast channel returns error only if there are NO receivers?
+                        // "SendError if there are no active receivers"
+                        // In our case, Agent drops stream between turns.
+                        // So there might be moments with 0 receivers.
+                        // We should ignore SendError and continue.
+                        let _ = tx.send(msg_res);
+                    },
+                    Sink::Queue(tx) => {
+                        // Waiting here is the backpressure: stdout is not read while the queue is full
+                        if tx.send(msg_res).await.is_err() {
+                            break;
+                        }
+                    },
+                }
+                if fatal {
+                    break;
+                }
+            }
+        })
+        .abort_handle();
+
+        self.reader_abort_handle = Some(abort_handle);
+    }
+
+    /// Read CLI output through the bounded queue enabled by `with_buffered_reads`.
+    ///
+    /// Unlike the broadcast channel, the queue never drops messages: when the
+    /// consumer falls behind, the reader task waits and stops pulling from the
+    /// CLI's stdout until there is room. The queue has a single consumer, so a
+    /// second stream waits until the first is dropped and then continues where
+    /// it left off. This makes buffered mode unsuitable for `ClaudeAgent`,
+    /// whose control loop reads alongside every turn.
+    ///
+    /// `read_messages` returns this stream when buffered mode is enabled.
+    pub async fn read_messages_buffered(
+        &self,
+    ) -> BoxStream<'_, Result<serde_json::Value, ClaudeAgentError>> {
+        let Some(queue) = self.queue.clone() else {
+            return Box::pin(stream::once(async {
+                Err(ClaudeAgentError::Transport(
+                    "Buffered reads are not enabled; use with_buffered_reads".to_string(),
+                ))
+            }));
+        };
+        Box::pin(async_stream::stream! {
+            let mut rx = queue.lock_owned().await;
+            while let Some(item) = rx.recv().await {
+                yield item.map(|message| message.value);
+            }
+        })
     }
 
     /// Find the Claude Code CLI binary.
@@ -216,8 +569,130 @@ impl SubprocessTransport {
         ))
     }
 
+    /// Build the `--settings` value, merging enabled sandbox settings into the user's settings.
+    ///
+    /// Without an enabled sandbox the user's value is passed through untouched. Otherwise
+    /// the user's settings (inline JSON or a file path) are loaded as a JSON object and
+    /// the sandbox config is added under the `sandbox` key, so only one `--settings` is sent.
+    /// A relative file path is read from `cwd` when set, like the CLI would.
+    fn build_settings_value(&self) -> Result<Option<String>, ClaudeAgentError> {
+        let sandbox = self.options.sandbox.as_ref().filter(|sandbox| sandbox.enabled);
+        let Some(sandbox) = sandbox else {
+            return Ok(self.options.settings.clone());
+        };
+
+        let mut settings = match self.options.settings.as_deref().map(str::trim) {
+            Some(inline) if inline.starts_with('{') && inline.ends_with('}') => {
+                serde_json::from_str::<serde_json::Map<String, serde_json::Value>>(inline).map_err(
+                    |e| ClaudeAgentError::Config(format!("Invalid settings JSON: {}", e)),
+                )?
+            },
+            Some(path) => {
+                let path = match self.options.cwd {
+                    Some(ref cwd) => cwd.join(path),
+                    None => PathBuf::from(path),
+                };
+                let contents = std::fs::read_to_string(&path).map_err(|e| {
+                    ClaudeAgentError::Config(format!(
+                        "Failed to read settings file {}: {}",
+                        path.display(),
+                        e
+                    ))
+                })?;
+                serde_json::from_str(&contents).map_err(|e| {
+                    ClaudeAgentError::Config(format!(
+                        "Invalid settings file {}: {}",
+                        path.display(),
+                        e
+                    ))
+                })?
+            },
+            None => serde_json::Map::new(),
+        };
+
+        let sandbox_json = serde_json::to_value(sandbox).map_err(|e| {
+            ClaudeAgentError::CLIConnection(format!("Failed to serialize sandbox settings: {}", e))
+        })?;
+        settings.insert("sandbox".to_string(), sandbox_json);
+        Ok(Some(serde_json::Value::Object(settings).to_string()))
+    }
+
+    /// Set the CLI's environment variables, optionally on top of a clean slate.
+    fn apply_env(&self, cmd: &mut Command) {
+        if self.options.clear_env {
+            cmd.env_clear();
+            let defaults: &[&str] =
+                if self.options.clear_env_strict { &[] } else { CLEAR_ENV_DEFAULTS };
+            let kept = defaults
+                .iter()
+                .copied()
+                .chain(self.options.env_passthrough.iter().map(String::as_str));
+            for key in kept {
+                if let Some(value) = std::env::var_os(key) {
+                    cmd.env(key, value);
+                }
+            }
+        }
+        for (key, value) in &self.options.env {
+            cmd.env(key, value);
+        }
+        if let Some(ref api_key) = self.options.api_key {
+            cmd.env("ANTHROPIC_AUTH_TOKEN", api_key.expose());
+        }
+
+        // SDK entrypoint marker
+        cmd.env("CLAUDE_CODE_ENTRYPOINT", "sdk-rs");
+    }
+
+    /// The argv the CLI would be spawned with: the binary followed by its arguments.
+    ///
+    /// Nothing is spawned, so this is safe for debugging and dry runs. The
+    /// environment is not included, and the CLI version is not checked. Credentials
+    /// are masked as `"***"` like in `ClaudeAgentOptions`' `Debug` output: values
+    /// of `extra_args` flags named like a credential, and credential-like `env`
+    /// and `headers` entries in `--mcp-config`. The SDK's own flags, such as
+    /// `--max-thinking-tokens`, are shown as they are.
+    ///
+    /// # Errors
+    ///
+    /// Fails like connecting would, e.g. if the CLI cannot be found or the
+    /// options are invalid.
+    pub fn command_preview(&self) -> Result<Vec<String>, ClaudeAgentError> {
+        let cmd = self.build_command()?;
+        let cmd = cmd.as_std();
+        let extra_flags: Vec<String> = self
+            .options
+            .extra_args
+            .keys()
+            .map(|flag| if flag.starts_with("--") { flag.clone() } else { format!("--{}", flag) })
+            .collect();
+        let mut preview = vec![cmd.get_program().to_string_lossy().into_owned()];
+        let mut flag: Option<String> = None;
+        for arg in cmd.get_args() {
+            let arg = arg.to_string_lossy().into_owned();
+            let masked = match flag.as_deref() {
+                Some("--mcp-config") => match serde_json::from_str(&arg) {
+                    Ok(mut config) => {
+                        mask_json(&mut config);
+                        config.to_string()
+                    },
+                    Err(_) => arg.clone(),
+                },
+                Some(flag) if extra_flags.iter().any(|extra| extra == flag) => {
+                    mask(flag, &arg).to_string()
+                },
+                _ => arg.clone(),
+            };
+            flag = arg.starts_with("--").then_some(arg);
+            preview.push(masked);
+        }
+        Ok(preview)
+    }
+
     /// Build the CLI command with arguments.
     fn build_command(&self) -> Result<Command, ClaudeAgentError> {
+        self.options.validate_mcp_servers()?;
+        self.options.validate_tool_lists()?;
         let cli_path = self.find_cli()?;
         let mut cmd = Command::new(&cli_path);
 
@@ -226,20 +701,17 @@ impl SubprocessTransport {
             cmd.current_dir(cwd);
         }
 
-        // Add environment variables
-        for (key, value) in &self.options.env {
-            cmd.env(key, value);
-        }
-
-        // SDK entrypoint marker
-        cmd.env("CLAUDE_CODE_ENTRYPOINT", "sdk-rs");
+        self.apply_env(&mut cmd);
 
         // Basic output configuration
         cmd.arg("--output-format");
         cmd.arg("stream-json");
         cmd.arg("--input-format");
         cmd.arg("stream-json");
-        cmd.arg("--verbose"); // Required for stream-json in newer CLI versions
+        // Required for stream-json in newer CLI versions
+        if self.options.verbose.unwrap_or(true) {
+            cmd.arg("--verbose");
+        }
 
         // Add prompt if provided
         if let Some(ref prompt) = self.prompt {
@@ -261,6 +733,12 @@ impl SubprocessTransport {
                         cmd.arg(append_text);
                     }
                 },
+                SystemPromptConfig::TextWithAppend { text, append } => {
+                    cmd.arg("--system-prompt");
+                    cmd.arg(text);
+                    cmd.arg("--append-system-prompt");
+                    cmd.arg(append);
+                },
             }
         }
 
@@ -269,16 +747,14 @@ impl SubprocessTransport {
             use crate::types::config::ToolsConfig;
             match tools {
                 ToolsConfig::List(list) => {
-                    if !list.is_empty() {
-                        cmd.arg("--tools");
-                        cmd.arg(list.join(","));
-                    }
+                    // An empty value tells the CLI to disable every built-in tool
+                    cmd.arg("--tools");
+                    cmd.arg(list.join(","));
                 },
-                ToolsConfig::Preset(preset) => {
+                ToolsConfig::Preset(crate::types::config::ToolsPreset::Preset { preset }) => {
+                    // The CLI calls the full Claude Code tool set `default`
                     cmd.arg("--tools");
-                    cmd.arg(match preset {
-                        crate::types::config::ToolsPreset::Preset { ref preset } => preset.as_str(),
-                    });
+                    cmd.arg(if preset == "claude_code" { "default" } else { preset.as_str() });
                 },
             }
         }
@@ -309,10 +785,14 @@ impl SubprocessTransport {
             cmd.arg(mode.to_string());
         }
 
-        // Permission prompt tool name
+        // Permission prompt tool name. Permission rules are answered by the SDK,
+        // so the CLI must route its permission prompts over stdio.
         if let Some(ref tool_name) = self.options.permission_prompt_tool_name {
             cmd.arg("--permission-prompt-tool");
             cmd.arg(tool_name);
+        } else if !self.options.permission_rules.is_empty() {
+            cmd.arg("--permission-prompt-tool");
+            cmd.arg("stdio");
         }
 
         // Limits
@@ -390,10 +870,19 @@ impl SubprocessTransport {
             cmd.arg("--include-partial-messages");
         }
 
-        // Output format override (overrides default stream-json if set)
+        // Structured output — the wire format stays stream-json; the schema goes via --json-schema
         if let Some(ref format) = self.options.output_format {
-            cmd.arg("--output-format");
-            cmd.arg(format.to_string());
+            let schema = format
+                .get("schema")
+                .filter(|_| format.get("type").and_then(|t| t.as_str()) == Some("json_schema"))
+                .ok_or_else(|| {
+                    ClaudeAgentError::Config(format!(
+                        "Unsupported output_format {}: expected type \"json_schema\" with a schema",
+                        format
+                    ))
+                })?;
+            cmd.arg("--json-schema");
+            cmd.arg(schema.to_string());
         }
 
         // Session ID
@@ -407,18 +896,6 @@ impl SubprocessTransport {
             cmd.arg("--strict-mcp-config");
         }
 
-        // Sandbox settings — merge into --settings JSON
-        if let Some(ref sandbox) = self.options.sandbox {
-            let sandbox_json = serde_json::to_value(sandbox).map_err(|e| {
-                ClaudeAgentError::CLIConnection(format!(
-                    "Failed to serialize sandbox settings: {}",
-                    e
-                ))
-            })?;
-            cmd.arg("--settings");
-            cmd.arg(serde_json::json!({ "sandbox": sandbox_json }).to_string());
-        }
-
         // Plugins — repeat --plugin-dir for each plugin
         for plugin in &self.options.plugins {
             match plugin {
@@ -430,16 +907,11 @@ impl SubprocessTransport {
         }
 
         // Agents — serialize the HashMap to JSON
-        if let Some(ref agents) = self.options.agents {
-            if !agents.is_empty() {
-                cmd.arg("--agents");
-                cmd.arg(serde_json::to_string(agents).map_err(|e| {
-                    ClaudeAgentError::CLIConnection(format!(
-                        "Failed to serialize agents config: {}",
-                        e
-                    ))
-                })?);
-            }
+        if let Some(agents) = self.options.agents.as_ref().filter(|agents| !agents.is_empty()) {
+            cmd.arg("--agents");
+            cmd.arg(serde_json::to_string(agents).map_err(|e| {
+                ClaudeAgentError::CLIConnection(format!("Failed to serialize agents config: {}", e))
+            })?);
         }
 
         // File checkpointing env var
@@ -453,13 +925,13 @@ impl SubprocessTransport {
             cmd.arg(dir.to_string_lossy().to_string());
         }
 
-        // Session continuation
+        // Session continuation — `--resume` stands on its own and does not need `--continue`
         if self.options.continue_conversation {
             cmd.arg("--continue");
-            if let Some(ref id) = self.options.resume {
-                cmd.arg("--resume");
-                cmd.arg(id);
-            }
+        }
+        if let Some(ref id) = self.options.resume {
+            cmd.arg("--resume");
+            cmd.arg(id);
         }
 
         // MCP Config
@@ -471,8 +943,8 @@ impl SubprocessTransport {
             cmd.arg(config.to_string());
         }
 
-        // Settings (user-provided settings file or JSON)
-        if let Some(ref settings) = self.options.settings {
+        // Settings (user-provided settings file or JSON, with sandbox merged in)
+        if let Some(settings) = self.build_settings_value()? {
             cmd.arg("--settings");
             cmd.arg(settings);
         }
@@ -497,60 +969,57 @@ impl SubprocessTransport {
 #[async_trait]
 impl Transport for SubprocessTransport {
     async fn connect(&mut self) -> Result<(), ClaudeAgentError> {
+        if let Some(handles) = self.attached.take() {
+            let (stdin, stdout) =
+                handles.into_inner().unwrap_or_else(std::sync::PoisonError::into_inner);
+            self.attach_writer(stdin);
+            self.start_reader(stdout);
+            return Ok(());
+        }
+
+        if !self.options.skip_dir_validation {
+            self.options.validate_directories()?;
+        }
+
+        if !self.options.skip_version_check {
+            match self.cli_version().await {
+                Ok(version) if version < MINIMUM_CLI_VERSION => {
+                    return Err(ClaudeAgentError::Config(format!(
+                        "Claude Code CLI {} is older than {}, the oldest version this SDK \
+                         supports; update it or set skip_version_check",
+                        version, MINIMUM_CLI_VERSION
+                    )));
+                },
+                Ok(version) => tracing::debug!(%version, "Detected CLI version"),
+                Err(e) => tracing::debug!(error = %e, "Could not detect CLI version"),
+            }
+        }
+
         // Add timeout to prevent hanging indefinitely
         const CONNECT_TIMEOUT_SECS: u64 = 30;
         tokio::time::timeout(tokio::time::Duration::from_secs(CONNECT_TIMEOUT_SECS), async {
             let mut cmd = self.build_command()?;
-            let mut child = cmd.spawn().map_err(|e| {
-                ClaudeAgentError::CLIConnection(format!("Failed to spawn CLI process: {}", e))
-            })?;
+            let retries = self.options.connect_retries.unwrap_or(0);
+            let base_delay = std::time::Duration::from_millis(
+                self.options.connect_retry_delay_ms.unwrap_or(DEFAULT_RETRY_DELAY_MS),
+            );
+            let mut child =
+                spawn_with_retry(|| cmd.spawn(), retries, base_delay).await.map_err(|e| {
+                    ClaudeAgentError::CLIConnection(format!("Failed to spawn CLI process: {}", e))
+                })?;
 
             // Take ownership of stdin
             let stdin = child.stdin.take().ok_or_else(|| {
                 ClaudeAgentError::CLIConnection("Failed to get stdin handle".to_string())
             })?;
-            self.stdin = Some(Arc::new(Mutex::new(stdin)));
+            self.attach_writer(Box::new(stdin));
 
             // Take ownership of stdout and spawn reader task
             let stdout = child.stdout.take().ok_or_else(|| {
                 ClaudeAgentError::CLIConnection("Failed to get stdout handle".to_string())
             })?;
 
-            // precise capacity
-            const BROADCAST_CHANNEL_CAPACITY: usize = 1000;
-            let (tx, _) = tokio::sync::broadcast::channel(BROADCAST_CHANNEL_CAPACITY);
-            self.inbox = Some(tx.clone());
-
-            let abort_handle = tokio::spawn(async move {
-                use crate::transport::reader::MessageReader;
-                use futures::StreamExt;
-
-                let reader = MessageReader::new(stdout);
-                let mut stream = Box::pin(reader);
-
-                while let Some(msg_res) = stream.next().await {
-                    // Determine if we should stop?
-                    // If everyone disconnected?
-                    // For now, keep reading until EOF or error.
-
-                    // We map parse errors or logic errors from reader
-                    // reader returns Result<Value, ClaudeAgentError>
-
-                    if tx.send(msg_res).is_err() {
-                        // No subscribers left, but we should keep reading to drain stdout?
-                        // Or maybe just exit.
-                        // Ideally we keep reading because a new subscriber might appear (Next Turn).
-                        // But broadcast channel returns error only if there are NO receivers?
-                        // "SendError if there are no active receivers"
-                        // In our case, Agent drops stream between turns.
-                        // So there might be moments with 0 receivers.
-                        // We should ignore SendError and continue.
-                    }
-                }
-            })
-            .abort_handle();
-
-            self.reader_abort_handle = Some(abort_handle);
+            self.start_reader(Box::new(stdout));
 
             self.process = Some(child);
 
@@ -558,28 +1027,37 @@ impl Transport for SubprocessTransport {
         })
         .await
         .map_err(|_| {
-            ClaudeAgentError::CLIConnection(format!(
-                "Connection timeout after {} seconds",
-                CONNECT_TIMEOUT_SECS
-            ))
+            ClaudeAgentError::ConnectTimeout(std::time::Duration::from_secs(CONNECT_TIMEOUT_SECS))
         })?
     }
 
     async fn write(&self, data: &str) -> Result<(), ClaudeAgentError> {
+        self.write_bytes(data.as_bytes()).await
+    }
+
+    async fn write_bytes(&self, data: &[u8]) -> Result<(), ClaudeAgentError> {
         let stdin = self
             .stdin
             .as_ref()
             .ok_or_else(|| ClaudeAgentError::Transport("Transport not connected".to_string()))?;
+        if let Some(error) = self.flush_error() {
+            return Err(error);
+        }
 
         let mut guard = stdin.lock().await;
         guard
-            .write_all(data.as_bytes())
+            .write_all(data)
             .await
             .map_err(|e| ClaudeAgentError::Transport(format!("Write failed: {}", e)))?;
         guard
             .write_all(b"\n")
             .await
             .map_err(|e| ClaudeAgentError::Transport(format!("Write newline failed: {}", e)))?;
+        if self.flusher_abort_handle.is_some() {
+            // The periodic flush task picks this up
+            self.unflushed.store(true, Ordering::SeqCst);
+            return Ok(());
+        }
         guard
             .flush()
             .await
@@ -589,22 +1067,13 @@ impl Transport for SubprocessTransport {
     }
 
     async fn read_messages(&self) -> BoxStream<'_, Result<serde_json::Value, ClaudeAgentError>> {
-        use futures::StreamExt;
-        use tokio_stream::wrappers::BroadcastStream;
-
-        match &self.inbox {
-            Some(tx) => {
-                let rx = tx.subscribe();
-                let stream = BroadcastStream::new(rx);
-                // BroadcastStream yields Result<Result<Value, Error>, RecvError>
-                // We need to flatten and map RecvError
-
-                Box::pin(stream.map(|item| match item {
-                    Ok(payload) => payload,
-                    Err(e) => {
-                        Err(ClaudeAgentError::Transport(format!("Broadcast receive error: {}", e)))
-                    },
-                }))
+        if self.queue.is_some() {
+            return self.read_messages_buffered().await;
+        }
+        match self.subscribe() {
+            Some(stream) => {
+                use futures::StreamExt;
+                Box::pin(stream.map(|item| item.map(|message| message.value)))
             },
             None => Box::pin(stream::once(async {
                 Err(ClaudeAgentError::Transport("Transport not connected".to_string()))
@@ -612,27 +1081,105 @@ impl Transport for SubprocessTransport {
         }
     }
 
+    /// Subscribe to the broadcast of CLI output; `None` before connecting or
+    /// when `with_buffered_reads` routes output through the queue instead.
+    fn subscribe(&self) -> Option<Subscription> {
+        use futures::StreamExt;
+        use tokio_stream::wrappers::errors::BroadcastStreamRecvError;
+        use tokio_stream::wrappers::BroadcastStream;
+
+        let rx = self.inbox.as_ref()?.subscribe();
+        // BroadcastStream yields Result<Result<Value, Error>, RecvError>; flatten it
+        Some(Box::pin(BroadcastStream::new(rx).map(|item| match item {
+            Ok(payload) => payload,
+            Err(BroadcastStreamRecvError::Lagged(skipped)) => {
+                Err(ClaudeAgentError::Lagged(skipped))
+            },
+        })))
+    }
+
+    fn process_id(&self) -> Option<u32> {
+        self.process.as_ref().and_then(Child::id)
+    }
+
+    fn detected_cli_version(&self) -> Option<CliVersion> {
+        self.cli_version.get().copied()
+    }
+
+    fn is_connected(&self) -> bool {
+        self.stdin.is_some()
+            && self.flush_error().is_none()
+            && self.reader_abort_handle.as_ref().is_some_and(|handle| !handle.is_finished())
+    }
+
     async fn close(&mut self) -> Result<(), ClaudeAgentError> {
         // Abort reader task
         if let Some(abort_handle) = self.reader_abort_handle.take() {
             abort_handle.abort();
         }
 
+        if let Some(abort_handle) = self.flusher_abort_handle.take() {
+            abort_handle.abort();
+            // Deliver anything still sitting in the coalescing buffer
+            if let Some(stdin) = &self.stdin {
+                let _ = stdin.lock().await.flush().await;
+            }
+            self.unflushed.store(false, Ordering::SeqCst);
+        }
+
         // Drop stdin to signal EOF
         self.stdin = None;
 
-        // Wait for process to exit
-        if let Some(ref mut process) = self.process {
-            process.wait().await.map_err(|e| {
-                ClaudeAgentError::Process(format!("Failed to wait for process exit: {}", e))
-            })?;
+        // Give the CLI a grace period to exit on EOF, then on SIGTERM, then kill it
+        let Some(mut process) = self.process.take() else {
+            return Ok(());
+        };
+        let grace = std::time::Duration::from_millis(
+            self.options.close_grace_period_ms.unwrap_or(DEFAULT_CLOSE_GRACE_MS),
+        );
+        let stopped = process::stop(&mut process, grace)
+            .await
+            .map_err(|e| ClaudeAgentError::Process(format!("Failed to stop CLI process: {}", e)))?;
+        if stopped == Stopped::Killed {
+            tracing::warn!("CLI ignored SIGTERM and was killed");
+        }
+        Ok(())
+    }
+
+    async fn kill(&mut self) -> Result<(), ClaudeAgentError> {
+        if let Some(abort_handle) = self.reader_abort_handle.take() {
+            abort_handle.abort();
         }
-        self.process = None;
+        if let Some(abort_handle) = self.flusher_abort_handle.take() {
+            abort_handle.abort();
+        }
+        self.unflushed.store(false, Ordering::SeqCst);
+        self.stdin = None;
 
+        if let Some(mut process) = self.process.take() {
+            process.kill().await.map_err(|e| {
+                ClaudeAgentError::Process(format!("Failed to kill CLI process: {}", e))
+            })?;
+        }
         Ok(())
     }
 }
 
+impl Drop for SubprocessTransport {
+    /// Stop the background tasks and kill a CLI that was never closed.
+    fn drop(&mut self) {
+        if let Some(abort_handle) = self.reader_abort_handle.take() {
+            abort_handle.abort();
+        }
+        if let Some(abort_handle) = self.flusher_abort_handle.take() {
+            abort_handle.abort();
+        }
+        if let Some(process) = self.process.as_mut() {
+            let _ = process.start_kill();
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -641,10 +1188,12 @@ mod tests {
         SystemPromptConfig, SystemPromptPreset, TaskBudget, ThinkingConfig, ToolsConfig,
         ToolsPreset,
     };
+    use crate::types::security::ApiKey;
     use serde_json::json;
     use std::collections::HashMap;
     use std::fs::{self, File};
     use std::io::Write;
+    use std::path::Path;
 
     fn dummy_cli_path() -> &'static std::path::PathBuf {
         static PATH: std::sync::OnceLock<std::path::PathBuf> = std::sync::OnceLock::new();
@@ -671,53 +1220,699 @@ mod tests {
         })
     }
 
-    fn make_options() -> ClaudeAgentOptions {
-        let mut options = ClaudeAgentOptions { ..Default::default() };
-        options.cli_path = Some(dummy_cli_path().clone());
-        options
+    /// Write an executable `sh` script running `body` into `dir` and return its path.
+    #[cfg(unix)]
+    fn script_cli(dir: &Path, body: &str) -> PathBuf {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = dir.join("claude");
+        fs::write(&path, format!("#!/bin/sh\n{}", body)).unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+        path
     }
 
-    #[test]
-    fn test_build_command_basic() {
-        let transport = SubprocessTransport::new(Some("Hello".to_string()), make_options());
+    /// Collect the built command's arguments for exact assertions.
+    fn command_args(transport: &SubprocessTransport) -> Vec<String> {
         let cmd = transport.build_command().expect("Failed to build command");
-        let cmd_str = format!("{:?}", cmd);
-
-        assert!(cmd_str.contains("--output-format"));
-        assert!(cmd_str.contains("stream-json"));
+        cmd.as_std().get_args().map(|a| a.to_string_lossy().to_string()).collect()
     }
 
     #[test]
-    fn test_build_command_with_system_prompt_string() {
+    fn test_command_preview_lists_binary_and_flags() {
         let mut options = make_options();
-        options.system_prompt = Some(SystemPromptConfig::Text("Be helpful".to_string()));
-
-        let transport = SubprocessTransport::new(Some("test".to_string()), options);
-        let cmd = transport.build_command().expect("Failed to build command");
-        let cmd_str = format!("{:?}", cmd);
-
-        assert!(cmd_str.contains("--system-prompt"));
-        assert!(cmd_str.contains("Be helpful"));
+        options.model = Some("claude-sonnet-4-5".to_string());
+        options.max_turns = Some(3);
+        options.max_thinking_tokens = Some(8000);
+        let transport = SubprocessTransport::new(None, options);
+
+        let preview = transport.command_preview().unwrap();
+
+        assert_eq!(preview[0], dummy_cli_path().to_string_lossy());
+        assert_eq!(&preview[1..], command_args(&transport).as_slice());
+        let joined = preview.join(" ");
+        assert!(joined.contains("--output-format stream-json"), "{joined}");
+        assert!(joined.contains("--model claude-sonnet-4-5"), "{joined}");
+        assert!(joined.contains("--max-turns 3"), "{joined}");
+        assert!(joined.contains("--max-thinking-tokens 8000"), "{joined}");
     }
 
     #[test]
-    fn test_build_command_with_system_prompt_preset() {
+    fn test_command_preview_masks_credentials() {
         let mut options = make_options();
-        options.system_prompt = Some(SystemPromptConfig::Preset(SystemPromptPreset::Preset {
-            preset: "claude_code".to_string(),
-            append: None,
-        }));
+        options.mcp_servers.insert(
+            "remote".to_string(),
+            serde_json::json!({
+                "type": "http",
+                "url": "https://mcp.example.com",
+                "headers": {"Authorization": "Bearer tok-123", "Accept": "application/json"}
+            }),
+        );
+        options.mcp_servers.insert(
+            "local".to_string(),
+            serde_json::json!({"command": "server", "env": {"GITHUB_TOKEN": "gh-456"}}),
+        );
+        options.extra_args.insert("api-key".to_string(), Some("sk-789".to_string()));
+        options.extra_args.insert("debug-to".to_string(), Some("stderr".to_string()));
+        let transport = SubprocessTransport::new(None, options);
 
-        let transport = SubprocessTransport::new(Some("test".to_string()), options);
-        let cmd = transport.build_command().expect("Failed to build command");
-        let cmd_str = format!("{:?}", cmd);
+        let joined = transport.command_preview().unwrap().join(" ");
 
-        assert!(!cmd_str.contains("--system-prompt"));
-        assert!(!cmd_str.contains("--append-system-prompt"));
+        for secret in ["tok-123", "gh-456", "sk-789"] {
+            assert!(!joined.contains(secret), "{secret} leaked: {joined}");
+        }
+        assert!(joined.contains("--api-key ***"), "{joined}");
+        assert!(joined.contains("--debug-to stderr"), "{joined}");
+        assert!(joined.contains(r#""Accept":"application/json""#), "{joined}");
+        assert!(joined.contains("https://mcp.example.com"), "{joined}");
     }
 
-    #[test]
-    fn test_build_command_with_system_prompt_preset_and_append() {
+    fn make_options() -> ClaudeAgentOptions {
+        let mut options = ClaudeAgentOptions { ..Default::default() };
+        options.cli_path = Some(dummy_cli_path().clone());
+        options
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_write_bytes_reaches_stdin_unchanged() {
+        let dir = tempfile::tempdir().unwrap();
+        let out_path = dir.path().join("stdin.bin");
+        let script_path = script_cli(dir.path(), &format!("cat > '{}'\n", out_path.display()));
+
+        let options = ClaudeAgentOptions { cli_path: Some(script_path), ..Default::default() };
+        let mut transport = SubprocessTransport::new(None, options);
+        transport.connect().await.unwrap();
+
+        let payload: &[u8] = &[b'{', 0xff, 0xfe, 0x00, b'}'];
+        transport.write_bytes(payload).await.unwrap();
+        transport.write("text").await.unwrap();
+        transport.close().await.unwrap();
+
+        let written = fs::read(&out_path).unwrap();
+        assert_eq!(written, [payload, b"\n", b"text\n"].concat());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_small_broadcast_capacity_reports_lag_count() {
+        use futures::StreamExt;
+
+        // Wait for one line on stdin, then emit 20 messages at once
+        let dir = tempfile::tempdir().unwrap();
+        let script_path = script_cli(
+            dir.path(),
+            "read line\nfor i in $(seq 1 20); do echo '{\"type\":\"tick\"}'; done\ncat > /dev/null\n",
+        );
+
+        let options = ClaudeAgentOptions {
+            cli_path: Some(script_path),
+            broadcast_capacity: Some(4),
+            ..Default::default()
+        };
+        let mut transport = SubprocessTransport::new(None, options);
+        transport.connect().await.unwrap();
+
+        {
+            let mut stream = transport.read_messages().await;
+            transport.write("go").await.unwrap();
+            tokio::time::sleep(std::time::Duration::from_millis(300)).await;
+
+            let first = stream.next().await.unwrap();
+            assert!(matches!(first, Err(ClaudeAgentError::Lagged(16))), "got {:?}", first);
+            let remaining: Vec<_> = stream.take(4).collect().await;
+            assert!(remaining.iter().all(|item| item.is_ok()));
+        }
+
+        transport.close().await.unwrap();
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_buffered_reads_keep_every_message_for_slow_consumer() {
+        use futures::StreamExt;
+
+        // Same burst as the lag test, but larger than the queue by far
+        let dir = tempfile::tempdir().unwrap();
+        let script_path = script_cli(
+            dir.path(),
+            "read line\nfor i in $(seq 1 200); do echo \"{\\\"n\\\":$i}\"; done\ncat > /dev/null\n",
+        );
+
+        let options = ClaudeAgentOptions { cli_path: Some(script_path), ..Default::default() };
+        let mut transport = SubprocessTransport::new(None, options).with_buffered_reads(4);
+        transport.connect().await.unwrap();
+
+        {
+            let stream = transport.read_messages().await;
+            transport.write("go").await.unwrap();
+            let received: Vec<u64> = stream
+                .take(200)
+                .then(|item| async move {
+                    tokio::time::sleep(std::time::Duration::from_millis(1)).await;
+                    item.unwrap()["n"].as_u64().unwrap()
+                })
+                .collect()
+                .await;
+            assert_eq!(received, (1..=200).collect::<Vec<u64>>());
+        }
+
+        transport.close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_buffered_read_requires_with_buffered_reads() {
+        use futures::StreamExt;
+
+        let transport = SubprocessTransport::new(None, ClaudeAgentOptions::default());
+        let first = transport.read_messages_buffered().await.next().await.unwrap();
+        assert!(
+            matches!(first, Err(ClaudeAgentError::Transport(msg)) if msg.contains("with_buffered_reads"))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_spawn_retry_succeeds_after_transient_failures() {
+        use std::sync::atomic::{AtomicU32, Ordering};
+
+        let attempts = AtomicU32::new(0);
+        let result = spawn_with_retry(
+            || {
+                if attempts.fetch_add(1, Ordering::SeqCst) < 2 {
+                    Err(std::io::Error::from(std::io::ErrorKind::ExecutableFileBusy))
+                } else {
+                    Ok("spawned")
+                }
+            },
+            3,
+            std::time::Duration::from_millis(1),
+        )
+        .await;
+
+        assert_eq!(result.unwrap(), "spawned");
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_spawn_retry_gives_up_after_limit() {
+        use std::sync::atomic::{AtomicU32, Ordering};
+
+        let attempts = AtomicU32::new(0);
+        let result: std::io::Result<()> = spawn_with_retry(
+            || {
+                attempts.fetch_add(1, Ordering::SeqCst);
+                Err(std::io::Error::from(std::io::ErrorKind::ResourceBusy))
+            },
+            2,
+            std::time::Duration::from_millis(1),
+        )
+        .await;
+
+        assert!(result.is_err());
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_spawn_retry_does_not_retry_permanent_errors() {
+        use std::sync::atomic::{AtomicU32, Ordering};
+
+        let attempts = AtomicU32::new(0);
+        let result: std::io::Result<()> = spawn_with_retry(
+            || {
+                attempts.fetch_add(1, Ordering::SeqCst);
+                Err(std::io::Error::from(std::io::ErrorKind::NotFound))
+            },
+            5,
+            std::time::Duration::from_millis(1),
+        )
+        .await;
+
+        assert!(result.is_err());
+        assert_eq!(attempts.load(Ordering::SeqCst), 1);
+    }
+
+    /// Stdin stand-in that records written bytes and counts flushes.
+    #[derive(Clone, Default)]
+    struct CountingWriter {
+        written: Arc<std::sync::Mutex<Vec<u8>>>,
+        flushes: Arc<std::sync::atomic::AtomicUsize>,
+        fail_flush: bool,
+    }
+
+    impl AsyncWrite for CountingWriter {
+        fn poll_write(
+            self: std::pin::Pin<&mut Self>,
+            _cx: &mut std::task::Context<'_>,
+            buf: &[u8],
+        ) -> std::task::Poll<std::io::Result<usize>> {
+            self.written.lock().unwrap().extend_from_slice(buf);
+            std::task::Poll::Ready(Ok(buf.len()))
+        }
+
+        fn poll_flush(
+            self: std::pin::Pin<&mut Self>,
+            _cx: &mut std::task::Context<'_>,
+        ) -> std::task::Poll<std::io::Result<()>> {
+            self.flushes.fetch_add(1, Ordering::SeqCst);
+            if self.fail_flush {
+                return std::task::Poll::Ready(Err(std::io::ErrorKind::BrokenPipe.into()));
+            }
+            std::task::Poll::Ready(Ok(()))
+        }
+
+        fn poll_shutdown(
+            self: std::pin::Pin<&mut Self>,
+            _cx: &mut std::task::Context<'_>,
+        ) -> std::task::Poll<std::io::Result<()>> {
+            std::task::Poll::Ready(Ok(()))
+        }
+    }
+
+    /// Connect a transport writing to `writer`, letting a flush task take its first tick.
+    async fn connected_over(
+        writer: CountingWriter,
+        options: ClaudeAgentOptions,
+    ) -> (SubprocessTransport, tokio::io::DuplexStream) {
+        let (cli_stdout, transport_stdout) = tokio::io::duplex(64);
+        let mut transport = SubprocessTransport::from_handles(writer, transport_stdout, options);
+        transport.connect().await.unwrap();
+        tokio::task::yield_now().await;
+        (transport, cli_stdout)
+    }
+
+    /// Write `count` messages through `transport`, returning the expected bytes.
+    async fn write_burst(transport: &SubprocessTransport, count: usize) -> Vec<u8> {
+        let mut expected = Vec::new();
+        for i in 0..count {
+            let message = format!(r#"{{"type":"control_request","request_id":"{}"}}"#, i);
+            transport.write(&message).await.unwrap();
+            expected.extend_from_slice(message.as_bytes());
+            expected.push(b'\n');
+        }
+        expected
+    }
+
+    #[tokio::test]
+    async fn test_write_flush_per_message_by_default() {
+        let writer = CountingWriter::default();
+        let (mut transport, _cli_stdout) =
+            connected_over(writer.clone(), ClaudeAgentOptions::default()).await;
+        let expected = write_burst(&transport, 50).await;
+        transport.close().await.unwrap();
+
+        assert_eq!(*writer.written.lock().unwrap(), expected);
+        assert_eq!(writer.flushes.load(Ordering::SeqCst), 50);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_write_coalescing_batches_flushes() {
+        let writer = CountingWriter::default();
+        let options =
+            ClaudeAgentOptions { write_flush_interval_ms: Some(10), ..Default::default() };
+        let (mut transport, _cli_stdout) = connected_over(writer.clone(), options).await;
+
+        // The clock is paused, so the burst waits for the next tick
+        let expected = write_burst(&transport, 50).await;
+        assert_eq!(writer.flushes.load(Ordering::SeqCst), 0);
+
+        tokio::time::sleep(std::time::Duration::from_millis(15)).await;
+        assert_eq!(writer.flushes.load(Ordering::SeqCst), 1);
+        assert_eq!(*writer.written.lock().unwrap(), expected);
+
+        // Idle ticks do not flush
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        assert_eq!(writer.flushes.load(Ordering::SeqCst), 1);
+        transport.close().await.unwrap();
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_write_coalescing_reports_flush_errors() {
+        let writer = CountingWriter { fail_flush: true, ..Default::default() };
+        let options =
+            ClaudeAgentOptions { write_flush_interval_ms: Some(10), ..Default::default() };
+        let (transport, _cli_stdout) = connected_over(writer, options).await;
+
+        transport.write("one").await.unwrap();
+        assert!(transport.is_connected());
+        tokio::time::sleep(std::time::Duration::from_millis(15)).await;
+
+        let err = transport.write("two").await.expect_err("the failed flush should be reported");
+        assert!(
+            matches!(err, ClaudeAgentError::Transport(ref msg) if msg.contains("Flush failed"))
+        );
+        assert!(!transport.is_connected());
+    }
+
+    #[tokio::test]
+    async fn test_write_coalescing_flushes_on_interval() {
+        let (cli_stdin, transport_stdin) = tokio::io::duplex(1024);
+        let (_cli_stdout, transport_stdout) = tokio::io::duplex(64);
+        let options =
+            ClaudeAgentOptions { write_flush_interval_ms: Some(10), ..Default::default() };
+        let mut transport =
+            SubprocessTransport::from_handles(transport_stdin, transport_stdout, options);
+        transport.connect().await.unwrap();
+
+        transport.write("one").await.unwrap();
+        transport.write("two").await.unwrap();
+
+        use tokio::io::AsyncReadExt;
+        let mut buf = vec![0u8; 8];
+        let mut cli_stdin = cli_stdin;
+        tokio::time::timeout(std::time::Duration::from_secs(2), cli_stdin.read_exact(&mut buf))
+            .await
+            .expect("buffered writes should be flushed by the timer")
+            .unwrap();
+        assert_eq!(buf, b"one\ntwo\n");
+
+        transport.close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_from_handles_uses_supplied_pipes() {
+        use futures::StreamExt;
+        use tokio::io::{AsyncBufReadExt, BufReader};
+
+        // Each pipe pair stands in for one direction of the CLI's stdio
+        let (cli_stdin, transport_stdin) = tokio::io::duplex(1024);
+        let (transport_stdout, mut cli_stdout) = tokio::io::duplex(1024);
+
+        let mut transport = SubprocessTransport::from_handles(
+            transport_stdin,
+            transport_stdout,
+            ClaudeAgentOptions::default(),
+        );
+        assert!(!transport.is_connected());
+        transport.connect().await.unwrap();
+        assert!(transport.is_connected());
+
+        let mut stream = transport.read_messages().await;
+        transport.write(r#"{"type":"user"}"#).await.unwrap();
+
+        let mut line = String::new();
+        BufReader::new(cli_stdin).read_line(&mut line).await.unwrap();
+        assert_eq!(line, "{\"type\":\"user\"}\n");
+
+        cli_stdout.write_all(b"{\"type\":\"result\"}\n").await.unwrap();
+        let message = stream.next().await.unwrap().unwrap();
+        assert_eq!(message, json!({"type": "result"}));
+
+        drop(stream);
+        transport.close().await.unwrap();
+        assert!(!transport.is_connected());
+    }
+
+    #[tokio::test]
+    async fn test_subscribe_stamps_messages_when_read_from_cli() {
+        use futures::StreamExt;
+
+        let (_cli_stdin, transport_stdin) = tokio::io::duplex(1024);
+        let (transport_stdout, mut cli_stdout) = tokio::io::duplex(1024);
+        let mut transport = SubprocessTransport::from_handles(
+            transport_stdin,
+            transport_stdout,
+            ClaudeAgentOptions::default(),
+        );
+        transport.connect().await.unwrap();
+
+        let mut stream = transport.subscribe().expect("connected transport subscribes");
+        cli_stdout.write_all(b"{\"type\":\"result\"}\n").await.unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+
+        let polled_at = std::time::Instant::now();
+        let message = stream.next().await.unwrap().unwrap();
+        assert_eq!(message.value, json!({"type": "result"}));
+        assert!(message.received_at + std::time::Duration::from_millis(150) <= polled_at);
+
+        drop(stream);
+        transport.close().await.unwrap();
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_is_connected_flips_after_close() {
+        let dir = tempfile::tempdir().unwrap();
+        let script_path = script_cli(dir.path(), "cat > /dev/null\n");
+
+        let options = ClaudeAgentOptions { cli_path: Some(script_path), ..Default::default() };
+        let mut transport = SubprocessTransport::new(None, options);
+        assert!(!transport.is_connected());
+
+        transport.connect().await.unwrap();
+        assert!(transport.is_connected());
+
+        transport.close().await.unwrap();
+        assert!(!transport.is_connected());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_close_terminates_cli_ignoring_stdin_eof() {
+        let dir = tempfile::tempdir().unwrap();
+        let script_path = script_cli(dir.path(), "exec sleep 30\n");
+
+        let options = ClaudeAgentOptions {
+            cli_path: Some(script_path),
+            close_grace_period_ms: Some(100),
+            ..Default::default()
+        };
+        let mut transport = SubprocessTransport::new(None, options);
+        transport.connect().await.unwrap();
+
+        let started = std::time::Instant::now();
+        tokio::time::timeout(std::time::Duration::from_secs(5), transport.close())
+            .await
+            .expect("close should not hang")
+            .unwrap();
+        assert!(started.elapsed() < std::time::Duration::from_secs(2));
+        assert!(!transport.is_connected());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_kill_stops_running_cli_without_grace_period() {
+        let dir = tempfile::tempdir().unwrap();
+        let script_path = script_cli(dir.path(), "exec sleep 30\n");
+
+        let options = ClaudeAgentOptions {
+            cli_path: Some(script_path),
+            close_grace_period_ms: Some(10_000),
+            ..Default::default()
+        };
+        let mut transport = SubprocessTransport::new(None, options);
+        transport.connect().await.unwrap();
+
+        let started = std::time::Instant::now();
+        transport.kill().await.unwrap();
+        assert!(started.elapsed() < std::time::Duration::from_secs(2));
+        assert!(!transport.is_connected());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_close_kills_cli_ignoring_sigterm() {
+        let dir = tempfile::tempdir().unwrap();
+        let script_path = script_cli(dir.path(), "trap '' TERM\nwhile :; do sleep 0.05; done\n");
+
+        let options = ClaudeAgentOptions {
+            cli_path: Some(script_path),
+            close_grace_period_ms: Some(100),
+            ..Default::default()
+        };
+        let mut transport = SubprocessTransport::new(None, options);
+        transport.connect().await.unwrap();
+
+        tokio::time::timeout(std::time::Duration::from_secs(5), transport.close())
+            .await
+            .expect("close should escalate to SIGKILL")
+            .unwrap();
+        assert!(!transport.is_connected());
+    }
+
+    #[tokio::test]
+    async fn test_connect_rejects_missing_cwd() {
+        let dir = tempfile::tempdir().unwrap();
+        let missing = dir.path().join("no-such-dir");
+        let mut options = make_options();
+        options.cwd = Some(missing.clone());
+
+        let err = SubprocessTransport::new(None, options).connect().await.unwrap_err();
+        assert!(matches!(err, ClaudeAgentError::Config(_)), "got {err:?}");
+        assert!(err.to_string().contains("cwd"));
+        assert!(err.to_string().contains(&missing.display().to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_connect_rejects_missing_add_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        let missing = dir.path().join("no-such-dir");
+        let mut options = make_options();
+        options.add_dirs = vec![dir.path().to_path_buf(), missing.clone()];
+
+        let err = SubprocessTransport::new(None, options).connect().await.unwrap_err();
+        assert!(matches!(err, ClaudeAgentError::Config(_)), "got {err:?}");
+        assert!(err.to_string().contains("add_dirs"));
+        assert!(err.to_string().contains(&missing.display().to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_skip_dir_validation_allows_missing_dirs() {
+        let mut options = make_options();
+        options.cwd = Some(PathBuf::from("/path/that/does/not/exist"));
+        assert!(options.validate_directories().is_err());
+
+        options.skip_dir_validation = true;
+        let mut transport = SubprocessTransport::new(None, options);
+        // Validation is skipped, so connecting gets as far as spawning
+        let err = transport.connect().await.unwrap_err();
+        assert!(!matches!(err, ClaudeAgentError::Config(_)), "got {err:?}");
+    }
+
+    #[test]
+    fn test_build_command_basic() {
+        let transport = SubprocessTransport::new(Some("Hello".to_string()), make_options());
+        let cmd = transport.build_command().expect("Failed to build command");
+        let cmd_str = format!("{:?}", cmd);
+
+        assert!(cmd_str.contains("--output-format"));
+        assert!(cmd_str.contains("stream-json"));
+        assert!(cmd_str.contains("\"--verbose\""));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_cli_version_is_parsed_and_cached() {
+        let dir = tempfile::tempdir().unwrap();
+        let calls_path = dir.path().join("calls");
+        let script_path = script_cli(
+            dir.path(),
+            &format!("echo called >> '{}'\necho '2.1.7 (Claude Code)'\n", calls_path.display()),
+        );
+
+        let options = ClaudeAgentOptions { cli_path: Some(script_path), ..Default::default() };
+        let transport = SubprocessTransport::new(None, options);
+        assert_eq!(transport.detected_cli_version(), None);
+
+        assert_eq!(transport.cli_version().await.unwrap(), CliVersion::new(2, 1, 7));
+        assert_eq!(transport.cli_version().await.unwrap(), CliVersion::new(2, 1, 7));
+        assert_eq!(transport.detected_cli_version(), Some(CliVersion::new(2, 1, 7)));
+        assert_eq!(fs::read_to_string(&calls_path).unwrap().lines().count(), 1);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_cli_version_probe_is_shared_until_the_cli_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let calls_path = dir.path().join("calls");
+        let script_path = script_cli(
+            dir.path(),
+            &format!("echo called >> '{}'\necho '2.1.7 (Claude Code)'\n", calls_path.display()),
+        );
+        let probe = || async {
+            let options =
+                ClaudeAgentOptions { cli_path: Some(script_path.clone()), ..Default::default() };
+            SubprocessTransport::new(None, options).cli_version().await.unwrap()
+        };
+        let calls = || fs::read_to_string(&calls_path).unwrap().lines().count();
+
+        assert_eq!(probe().await, CliVersion::new(2, 1, 7));
+        assert_eq!(probe().await, CliVersion::new(2, 1, 7));
+        assert_eq!(calls(), 1);
+
+        // An updated binary is probed again
+        let later = fs::metadata(&script_path).unwrap().modified().unwrap()
+            + std::time::Duration::from_secs(60);
+        fs::File::options().write(true).open(&script_path).unwrap().set_modified(later).unwrap();
+        assert_eq!(probe().await, CliVersion::new(2, 1, 7));
+        assert_eq!(calls(), 2);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_connect_rejects_cli_older_than_minimum() {
+        let dir = tempfile::tempdir().unwrap();
+        let script_path = script_cli(
+            dir.path(),
+            "if [ \"$1\" = --version ]; then echo '1.0.128 (Claude Code)'; exit 0; fi\ncat > /dev/null\n",
+        );
+
+        let options =
+            ClaudeAgentOptions { cli_path: Some(script_path.clone()), ..Default::default() };
+        let err = SubprocessTransport::new(None, options).connect().await.unwrap_err();
+        assert!(
+            matches!(err, ClaudeAgentError::Config(ref msg) if msg.contains("1.0.128")),
+            "got {err:?}"
+        );
+
+        // Skipping the probe lets an old CLI through
+        let options = ClaudeAgentOptions {
+            cli_path: Some(script_path),
+            skip_version_check: true,
+            ..Default::default()
+        };
+        let mut transport = SubprocessTransport::new(None, options);
+        transport.connect().await.unwrap();
+        transport.close().await.unwrap();
+    }
+
+    #[test]
+    fn test_build_command_omits_verbose_when_disabled() {
+        let mut options = make_options();
+        options.verbose = Some(false);
+        let transport = SubprocessTransport::new(None, options);
+        let cmd_str = format!("{:?}", transport.build_command().expect("Failed to build command"));
+
+        assert!(!cmd_str.contains("--verbose"));
+    }
+
+    #[test]
+    fn test_build_command_with_system_prompt_string() {
+        let mut options = make_options();
+        options.system_prompt = Some(SystemPromptConfig::Text("Be helpful".to_string()));
+
+        let transport = SubprocessTransport::new(Some("test".to_string()), options);
+        let cmd = transport.build_command().expect("Failed to build command");
+        let cmd_str = format!("{:?}", cmd);
+
+        assert!(cmd_str.contains("--system-prompt"));
+        assert!(cmd_str.contains("Be helpful"));
+    }
+
+    #[test]
+    fn test_build_command_with_system_prompt_text_and_append() {
+        let mut options = make_options();
+        options.system_prompt = Some(SystemPromptConfig::TextWithAppend {
+            text: "Be helpful".to_string(),
+            append: "Be concise.".to_string(),
+        });
+
+        let transport = SubprocessTransport::new(Some("test".to_string()), options);
+        let cmd = transport.build_command().expect("Failed to build command");
+        let args: Vec<_> = cmd.as_std().get_args().map(|arg| arg.to_string_lossy()).collect();
+
+        let system = args.iter().position(|arg| arg == "--system-prompt").unwrap();
+        assert_eq!(args[system + 1], "Be helpful");
+        let append = args.iter().position(|arg| arg == "--append-system-prompt").unwrap();
+        assert_eq!(args[append + 1], "Be concise.");
+    }
+
+    #[test]
+    fn test_build_command_with_system_prompt_preset() {
+        let mut options = make_options();
+        options.system_prompt = Some(SystemPromptConfig::Preset(SystemPromptPreset::Preset {
+            preset: "claude_code".to_string(),
+            append: None,
+        }));
+
+        let transport = SubprocessTransport::new(Some("test".to_string()), options);
+        let cmd = transport.build_command().expect("Failed to build command");
+        let cmd_str = format!("{:?}", cmd);
+
+        assert!(!cmd_str.contains("--system-prompt"));
+        assert!(!cmd_str.contains("--append-system-prompt"));
+    }
+
+    #[test]
+    fn test_build_command_with_system_prompt_preset_and_append() {
         let mut options = make_options();
         options.system_prompt = Some(SystemPromptConfig::Preset(SystemPromptPreset::Preset {
             preset: "claude_code".to_string(),
@@ -816,6 +2011,40 @@ mod tests {
         assert!(cmd_str.contains("session-123"));
     }
 
+    #[test]
+    fn test_resume_without_continue() {
+        let mut options = make_options();
+        options.resume = Some("session-456".to_string());
+
+        let transport = SubprocessTransport::new(None, options);
+        let args = command_args(&transport);
+
+        let pos = args.iter().position(|a| a == "--resume").expect("--resume present");
+        assert_eq!(args[pos + 1], "session-456");
+        assert!(!args.contains(&"--continue".to_string()));
+    }
+
+    #[test]
+    fn test_resume_session_loaded_from_file() {
+        use crate::core::SessionManager;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("sessions.json");
+        let mut manager = SessionManager::new();
+        manager.create_session_with_id("local-session");
+        manager.current_session_mut().unwrap().cli_session_id = Some("saved-session".to_string());
+        manager.save_to_path(&path).unwrap();
+
+        let restored = SessionManager::load_from_path(&path).unwrap();
+        let mut options = make_options();
+        options.resume =
+            restored.current_session().and_then(|session| session.cli_session_id.clone());
+
+        let args = command_args(&SubprocessTransport::new(None, options));
+        let pos = args.iter().position(|a| a == "--resume").expect("--resume present");
+        assert_eq!(args[pos + 1], "saved-session");
+    }
+
     #[test]
     fn test_build_command_with_settings_file() {
         let mut options = make_options();
@@ -829,6 +2058,22 @@ mod tests {
         assert!(cmd_str.contains("/path/to/settings.json"));
     }
 
+    #[test]
+    fn test_build_command_with_api_key() {
+        let mut options = make_options();
+        options.env.insert("ANTHROPIC_AUTH_TOKEN".to_string(), "from-env".to_string());
+        options.api_key = Some(ApiKey::new("sk-ant-test-123"));
+        let transport = SubprocessTransport::new(None, options);
+
+        let cmd = transport.build_command().expect("Failed to build command");
+        let token = cmd
+            .as_std()
+            .get_envs()
+            .find(|(key, _)| *key == "ANTHROPIC_AUTH_TOKEN")
+            .and_then(|(_, value)| value);
+        assert_eq!(token, Some(std::ffi::OsStr::new("sk-ant-test-123")));
+    }
+
     #[test]
     fn test_build_command_with_extra_args() {
         let mut options = make_options();
@@ -869,6 +2114,168 @@ mod tests {
         assert!(cmd_str.contains("test-cmd"));
     }
 
+    /// Run a stand-in CLI with `options` and return the environment it saw.
+    #[cfg(unix)]
+    async fn cli_env(mut options: ClaudeAgentOptions) -> HashMap<String, String> {
+        let dir = tempfile::tempdir().unwrap();
+        let env_path = dir.path().join("env.txt");
+        options.cli_path = Some(script_cli(
+            dir.path(),
+            &format!(
+                "env > '{0}.tmp' && mv '{0}.tmp' '{0}'\ncat > /dev/null\n",
+                env_path.display()
+            ),
+        ));
+        options.skip_version_check = true;
+        let mut transport = SubprocessTransport::new(None, options);
+        transport.connect().await.unwrap();
+        let env = tokio::time::timeout(std::time::Duration::from_secs(5), async {
+            loop {
+                let env = fs::read_to_string(&env_path).unwrap_or_default();
+                if !env.is_empty() {
+                    return env;
+                }
+                tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("CLI should dump its environment");
+        transport.close().await.unwrap();
+        env.lines()
+            .filter_map(|line| line.split_once('='))
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_clear_env_drops_inherited_variables() {
+        // Reuse variables this process already has instead of mutating its environment
+        let parent: HashMap<String, String> = std::env::vars()
+            .filter(|(key, _)| !CLEAR_ENV_DEFAULTS.contains(&key.as_str()))
+            .filter(|(key, _)| !key.starts_with("CLAUDE") && !key.starts_with("ANTHROPIC"))
+            .collect();
+        let mut names = parent.keys().cloned().collect::<Vec<_>>();
+        names.sort();
+        let [passthrough, inherited] = [&names[0], &names[1]];
+
+        let options = ClaudeAgentOptions {
+            clear_env: true,
+            env_passthrough: vec![passthrough.clone()],
+            env: HashMap::from([("CLAUDE_AGENT_TEST_EXPLICIT".to_string(), "child".to_string())]),
+            ..Default::default()
+        };
+        let env = cli_env(options).await;
+
+        assert_eq!(env.get("CLAUDE_AGENT_TEST_EXPLICIT").map(String::as_str), Some("child"));
+        assert_eq!(env.get(passthrough), parent.get(passthrough));
+        assert!(!env.contains_key(inherited), "env: {env:?}");
+        for key in CLEAR_ENV_DEFAULTS {
+            assert_eq!(env.get(*key).cloned(), std::env::var(key).ok(), "{key}");
+        }
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_clear_env_strict_drops_the_defaults_too() {
+        let options = ClaudeAgentOptions {
+            clear_env: true,
+            clear_env_strict: true,
+            env: HashMap::from([("CLAUDE_AGENT_TEST_EXPLICIT".to_string(), "child".to_string())]),
+            ..Default::default()
+        };
+        let env = cli_env(options).await;
+
+        assert_eq!(env.get("CLAUDE_AGENT_TEST_EXPLICIT").map(String::as_str), Some("child"));
+        for key in ["HOME", "USER", "TMPDIR"] {
+            assert!(!env.contains_key(key), "{key} leaked: {env:?}");
+        }
+    }
+
+    #[test]
+    fn test_build_command_rejects_tool_in_both_lists() {
+        let mut options = make_options();
+        options.allowed_tools = vec!["Read".to_string(), "Bash".to_string()];
+        options.disallowed_tools = vec!["Bash".to_string(), "WebFetch".to_string()];
+
+        let err = SubprocessTransport::new(None, options).build_command().unwrap_err();
+        assert!(
+            matches!(&err, ClaudeAgentError::Config(msg) if msg == "Tools both allowed and disallowed: Bash"),
+            "got {err}"
+        );
+    }
+
+    #[test]
+    fn test_allow_and_deny_tools_keep_lists_disjoint() {
+        let mut options = make_options();
+        options.allow_tools(["Read", "Bash"]).deny_tools(["Bash", "WebFetch"]);
+        assert_eq!(options.allowed_tools, vec!["Read"]);
+        assert_eq!(options.disallowed_tools, vec!["Bash", "WebFetch"]);
+
+        options.allow_tools(["WebFetch"]);
+        assert_eq!(options.allowed_tools, vec!["Read", "WebFetch"]);
+        assert_eq!(options.disallowed_tools, vec!["Bash"]);
+
+        let args = command_args(&SubprocessTransport::new(None, options));
+        let allowed = args.iter().position(|a| a == "--allowedTools").unwrap();
+        assert_eq!(args[allowed + 1], "Read,WebFetch");
+        let denied = args.iter().position(|a| a == "--disallowedTools").unwrap();
+        assert_eq!(args[denied + 1], "Bash");
+    }
+
+    fn build_with_server(server: serde_json::Value) -> Result<Command, ClaudeAgentError> {
+        let mut options = make_options();
+        options.mcp_servers.insert("broken".to_string(), server);
+        SubprocessTransport::new(None, options).build_command()
+    }
+
+    #[test]
+    fn test_build_command_accepts_each_mcp_server_type() {
+        let mut options = make_options();
+        options.mcp_servers = HashMap::from([
+            (
+                "local".to_string(),
+                json!({"type": "stdio", "command": "srv", "env": {"A": "1"}}),
+            ),
+            ("remote".to_string(), json!({"type": "http", "url": "https://mcp.example.com"})),
+            (
+                "events".to_string(),
+                json!({"type": "sse", "url": "https://sse.example.com", "headers": {"X-Key": "k"}}),
+            ),
+            ("inproc".to_string(), json!({"type": "sdk", "name": "inproc"})),
+            // Fields the SDK does not know yet are passed through with a warning
+            ("newer".to_string(), json!({"command": "srv", "timeout": 30})),
+        ]);
+        assert!(options.validate_mcp_servers().is_ok());
+        assert!(SubprocessTransport::new(None, options).build_command().is_ok());
+    }
+
+    #[test]
+    fn test_build_command_rejects_malformed_mcp_servers() {
+        let cases = [
+            (json!(["srv"]), "expected a JSON object"),
+            (json!({"type": "stdio"}), "stdio servers need a non-empty string `command`"),
+            (json!({"args": ["x"]}), "stdio servers need a non-empty string `command`"),
+            (json!({"type": "http"}), "http servers need a non-empty string `url`"),
+            (json!({"type": "websocket", "url": "ws://x"}), "unknown type `websocket`"),
+            (
+                json!({"command": "srv", "args": "--flag"}),
+                "`args` must be an array of strings",
+            ),
+            (
+                json!({"type": "sse", "url": "u", "headers": {"n": 1}}),
+                "`headers` must be an object of strings",
+            ),
+        ];
+        for (server, reason) in cases {
+            let err = build_with_server(server.clone()).expect_err("malformed server accepted");
+            assert!(
+                matches!(&err, ClaudeAgentError::Config(msg) if msg.contains("Invalid MCP server 'broken'") && msg.contains(reason)),
+                "{server} gave {err}"
+            );
+        }
+    }
+
     // --- New tests for Part B: unwired CLI flags ---
 
     #[test]
@@ -910,6 +2317,35 @@ mod tests {
         assert!(cmd_str.contains("custom-tool"));
     }
 
+    #[test]
+    fn test_build_command_omits_permission_prompt_tool_by_default() {
+        let args = command_args(&SubprocessTransport::new(None, make_options()));
+        assert!(!args.iter().any(|a| a == "--permission-prompt-tool"));
+    }
+
+    #[test]
+    fn test_build_command_routes_permission_prompts_to_stdio_for_rules() {
+        let mut options = make_options();
+        options.permission_rules = vec![crate::types::hooks::ToolPermissionRule::deny_outside(
+            "Write",
+            "file_path",
+            "/work",
+        )];
+
+        let args = command_args(&SubprocessTransport::new(None, options.clone()));
+        let pos = args
+            .iter()
+            .position(|a| a == "--permission-prompt-tool")
+            .expect("--permission-prompt-tool present");
+        assert_eq!(args[pos + 1], "stdio");
+
+        // An explicit tool name wins
+        options.permission_prompt_tool_name = Some("mcp__approvals__prompt".to_string());
+        let args = command_args(&SubprocessTransport::new(None, options));
+        let pos = args.iter().position(|a| a == "--permission-prompt-tool").unwrap();
+        assert_eq!(args[pos + 1], "mcp__approvals__prompt");
+    }
+
     #[test]
     fn test_build_command_with_setting_sources() {
         let mut options = make_options();
@@ -924,6 +2360,32 @@ mod tests {
         assert!(cmd_str.contains("user,project,local"));
     }
 
+    #[test]
+    fn test_build_command_setting_sources_and_betas_values() {
+        let mut options = make_options();
+        options.setting_sources = Some(vec![SettingSource::User, SettingSource::Local]);
+        options.betas = vec!["context-1m-2025-08-07".to_string(), "beta-two".to_string()];
+
+        let args = command_args(&SubprocessTransport::new(None, options));
+
+        let sources = args.iter().position(|a| a == "--setting-sources").unwrap();
+        assert_eq!(args[sources + 1], "user,local");
+        let betas = args.iter().position(|a| a == "--betas").unwrap();
+        assert_eq!(args[betas + 1], "context-1m-2025-08-07,beta-two");
+    }
+
+    #[test]
+    fn test_build_command_empty_setting_sources_loads_none() {
+        let mut options = make_options();
+        options.setting_sources = Some(vec![]);
+
+        let args = command_args(&SubprocessTransport::new(None, options));
+
+        let sources = args.iter().position(|a| a == "--setting-sources").unwrap();
+        assert_eq!(args[sources + 1], "");
+        assert!(!args.contains(&"--betas".to_string()));
+    }
+
     #[test]
     fn test_build_command_with_fork_session() {
         let mut options = make_options();
@@ -936,6 +2398,20 @@ mod tests {
         assert!(cmd_str.contains("--fork-session"));
     }
 
+    #[test]
+    fn test_build_command_fork_session_with_resume() {
+        let mut options = make_options();
+        options.fork_session = true;
+        options.resume = Some("session-789".to_string());
+
+        let transport = SubprocessTransport::new(None, options);
+        let args = command_args(&transport);
+
+        assert!(args.contains(&"--fork-session".to_string()));
+        let pos = args.iter().position(|a| a == "--resume").expect("--resume present");
+        assert_eq!(args[pos + 1], "session-789");
+    }
+
     #[test]
     fn test_build_command_without_fork_session() {
         let options = make_options();
@@ -973,40 +2449,77 @@ mod tests {
     }
 
     #[test]
-    fn test_build_command_with_tools_preset() {
+    fn test_build_command_with_empty_tools_list_disables_tools() {
+        let mut options = make_options();
+        options.tools = Some(ToolsConfig::List(vec![]));
+
+        let args = command_args(&SubprocessTransport::new(Some("test".to_string()), options));
+
+        let pos = args.iter().position(|a| a == "--tools").expect("--tools present");
+        assert_eq!(args[pos + 1], "");
+    }
+
+    #[test]
+    fn test_build_command_omits_tools_by_default() {
+        let args = command_args(&SubprocessTransport::new(None, make_options()));
+        assert!(!args.iter().any(|a| a == "--tools"));
+    }
+
+    #[test]
+    fn test_build_command_maps_claude_code_tools_preset_to_default() {
         let mut options = make_options();
         options.tools =
             Some(ToolsConfig::Preset(ToolsPreset::Preset { preset: "claude_code".to_string() }));
 
-        let transport = SubprocessTransport::new(Some("test".to_string()), options);
-        let cmd = transport.build_command().expect("Failed to build command");
-        let cmd_str = format!("{:?}", cmd);
+        let args = command_args(&SubprocessTransport::new(Some("test".to_string()), options));
 
-        assert!(cmd_str.contains("--tools"));
-        assert!(cmd_str.contains("claude_code"));
+        let pos = args.iter().position(|a| a == "--tools").expect("--tools present");
+        assert_eq!(args[pos + 1], "default");
+        assert_eq!(args.iter().filter(|a| *a == "--tools").count(), 1);
     }
 
     #[test]
-    fn test_build_command_with_output_format_override() {
+    fn test_build_command_with_output_format_schema() {
         let mut options = make_options();
-        options.output_format = Some(json!("json"));
-
-        let transport = SubprocessTransport::new(Some("test".to_string()), options);
-        let cmd = transport.build_command().expect("Failed to build command");
-        let cmd_str = format!("{:?}", cmd);
+        let schema = json!({
+            "type": "object",
+            "properties": {"answer": {"type": "integer"}},
+            "required": ["answer"]
+        });
+        options.output_format = Some(json!({"type": "json_schema", "schema": schema}));
+
+        let args = command_args(&SubprocessTransport::new(None, options));
+
+        let pos = args.iter().position(|a| a == "--json-schema").expect("--json-schema present");
+        let emitted: serde_json::Value = serde_json::from_str(&args[pos + 1]).unwrap();
+        assert_eq!(emitted, schema);
+        // The wire format is not overridden
+        assert_eq!(args.iter().filter(|a| *a == "--output-format").count(), 1);
+        let format = args.iter().position(|a| a == "--output-format").unwrap();
+        assert_eq!(args[format + 1], "stream-json");
+    }
 
-        // output-format should appear twice: once for default stream-json, once for override
-        // The override should come after and contain "json"
-        assert!(cmd_str.contains("json"));
+    #[test]
+    fn test_build_command_rejects_non_schema_output_format() {
+        for format in [json!("json"), json!({"type": "json_schema"}), json!({"type": "text"})] {
+            let mut options = make_options();
+            options.output_format = Some(format.clone());
+
+            let err = SubprocessTransport::new(None, options)
+                .build_command()
+                .expect_err("unsupported output_format should be rejected");
+            assert!(
+                matches!(err, ClaudeAgentError::Config(ref msg) if msg.contains("output_format")),
+                "{format}: got {err:?}"
+            );
+        }
     }
 
     #[test]
     fn test_build_command_with_sandbox_settings() {
         use crate::types::config::SandboxSettings;
         let mut options = make_options();
-        let mut sandbox = SandboxSettings::default();
-        sandbox.enabled = true;
-        options.sandbox = Some(sandbox);
+        options.sandbox = Some(SandboxSettings { enabled: true, ..Default::default() });
 
         let transport = SubprocessTransport::new(Some("test".to_string()), options);
         let cmd = transport.build_command().expect("Failed to build command");
@@ -1016,6 +2529,93 @@ mod tests {
         assert!(cmd_str.contains("sandbox"));
     }
 
+    #[test]
+    fn test_build_command_sandbox_reaches_settings_json() {
+        use crate::types::config::{SandboxNetworkConfig, SandboxSettings};
+        let mut options = make_options();
+        options.sandbox = Some(SandboxSettings {
+            enabled: true,
+            excluded_commands: vec!["docker".to_string(), "git".to_string()],
+            network: Some(SandboxNetworkConfig {
+                http_proxy_port: Some(8080),
+                ..Default::default()
+            }),
+            ..Default::default()
+        });
+
+        let args = command_args(&SubprocessTransport::new(None, options));
+
+        let pos = args.iter().position(|a| a == "--settings").expect("--settings present");
+        let settings: serde_json::Value = serde_json::from_str(&args[pos + 1]).unwrap();
+        assert_eq!(settings["sandbox"]["enabled"], true);
+        assert_eq!(settings["sandbox"]["excludedCommands"], serde_json::json!(["docker", "git"]));
+        assert_eq!(settings["sandbox"]["network"]["httpProxyPort"], 8080);
+    }
+
+    #[test]
+    fn test_build_command_sandbox_merges_with_inline_settings() {
+        use crate::types::config::SandboxSettings;
+        let mut options = make_options();
+        options.settings = Some(r#"{"model": "claude-sonnet-4-5"}"#.to_string());
+        options.sandbox = Some(SandboxSettings { enabled: true, ..Default::default() });
+
+        let args = command_args(&SubprocessTransport::new(None, options));
+
+        assert_eq!(args.iter().filter(|a| *a == "--settings").count(), 1);
+        let pos = args.iter().position(|a| a == "--settings").unwrap();
+        let settings: serde_json::Value = serde_json::from_str(&args[pos + 1]).unwrap();
+        assert_eq!(settings["model"], "claude-sonnet-4-5");
+        assert_eq!(settings["sandbox"]["enabled"], true);
+    }
+
+    #[test]
+    fn test_build_command_sandbox_merges_with_settings_file() {
+        use crate::types::config::SandboxSettings;
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        std::io::Write::write_all(&mut file, br#"{"permissions": {"allow": ["Read"]}}"#).unwrap();
+
+        let mut options = make_options();
+        options.settings = Some(file.path().to_string_lossy().to_string());
+        options.sandbox = Some(SandboxSettings { enabled: true, ..Default::default() });
+
+        let args = command_args(&SubprocessTransport::new(None, options));
+
+        let pos = args.iter().position(|a| a == "--settings").unwrap();
+        let settings: serde_json::Value = serde_json::from_str(&args[pos + 1]).unwrap();
+        assert_eq!(settings["permissions"]["allow"], serde_json::json!(["Read"]));
+        assert_eq!(settings["sandbox"]["enabled"], true);
+    }
+
+    #[test]
+    fn test_build_command_sandbox_reads_relative_settings_file_from_cwd() {
+        use crate::types::config::SandboxSettings;
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("settings.json"), r#"{"model": "from-cwd"}"#).unwrap();
+
+        let mut options = make_options();
+        options.cwd = Some(dir.path().to_path_buf());
+        options.settings = Some("settings.json".to_string());
+        options.sandbox = Some(SandboxSettings { enabled: true, ..Default::default() });
+
+        let args = command_args(&SubprocessTransport::new(None, options));
+
+        let pos = args.iter().position(|a| a == "--settings").unwrap();
+        let settings: serde_json::Value = serde_json::from_str(&args[pos + 1]).unwrap();
+        assert_eq!(settings["model"], "from-cwd");
+        assert_eq!(settings["sandbox"]["enabled"], true);
+    }
+
+    #[test]
+    fn test_build_command_disabled_sandbox_is_omitted() {
+        use crate::types::config::SandboxSettings;
+        let mut options = make_options();
+        options.sandbox = Some(SandboxSettings::default());
+
+        let args = command_args(&SubprocessTransport::new(None, options));
+
+        assert!(!args.contains(&"--settings".to_string()));
+    }
+
     #[test]
     fn test_build_command_with_plugins() {
         let mut options = make_options();
@@ -1033,6 +2633,24 @@ mod tests {
         assert!(cmd_str.contains("/path/to/plugin2"));
     }
 
+    #[test]
+    fn test_build_command_pairs_each_plugin_with_its_flag() {
+        let mut options = make_options();
+        options.plugins = vec![
+            PluginConfig::Local { path: PathBuf::from("/plugins/one") },
+            PluginConfig::Local { path: PathBuf::from("/plugins/two") },
+        ];
+
+        let args = command_args(&SubprocessTransport::new(None, options));
+
+        let plugin_dirs: Vec<&str> = args
+            .windows(2)
+            .filter(|pair| pair[0] == "--plugin-dir")
+            .map(|pair| pair[1].as_str())
+            .collect();
+        assert_eq!(plugin_dirs, vec!["/plugins/one", "/plugins/two"]);
+    }
+
     #[test]
     fn test_build_command_with_agents() {
         let mut options = make_options();
@@ -1078,6 +2696,25 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_build_command_file_checkpointing_env_value() {
+        let mut options = make_options();
+        options.enable_file_checkpointing = true;
+        let cmd = SubprocessTransport::new(None, options).build_command().unwrap();
+        let value = cmd
+            .as_std()
+            .get_envs()
+            .find(|(key, _)| *key == "CLAUDE_CODE_ENABLE_SDK_FILE_CHECKPOINTING")
+            .and_then(|(_, value)| value);
+        assert_eq!(value, Some(std::ffi::OsStr::new("1")));
+
+        let cmd = SubprocessTransport::new(None, make_options()).build_command().unwrap();
+        assert!(!cmd
+            .as_std()
+            .get_envs()
+            .any(|(key, _)| key == "CLAUDE_CODE_ENABLE_SDK_FILE_CHECKPOINTING"));
+    }
+
     #[test]
     fn test_build_command_with_effort() {
         let mut options = make_options();
@@ -0,0 +1,60 @@
+//! Claude Code CLI version numbers.
+
+use std::fmt;
+
+/// A CLI version as reported by `claude --version`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct CliVersion {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+}
+
+impl CliVersion {
+    pub const fn new(major: u32, minor: u32, patch: u32) -> Self {
+        Self { major, minor, patch }
+    }
+
+    /// Find the first `major.minor.patch` token in `output`, such as
+    /// `"2.0.14 (Claude Code)"`. A pre-release or build suffix is ignored.
+    pub fn parse(output: &str) -> Option<Self> {
+        output.split_whitespace().find_map(|token| {
+            let core = token.trim_start_matches('v').split(['-', '+']).next()?;
+            let mut parts = core.split('.').map(|part| part.parse::<u32>().ok());
+            let version = Self::new(parts.next()??, parts.next()??, parts.next()??);
+            parts.next().is_none().then_some(version)
+        })
+    }
+}
+
+impl fmt::Display for CliVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_version_output() {
+        assert_eq!(CliVersion::parse("2.0.14 (Claude Code)"), Some(CliVersion::new(2, 0, 14)));
+        assert_eq!(CliVersion::parse("claude v1.2.3-beta.1"), Some(CliVersion::new(1, 2, 3)));
+        assert_eq!(CliVersion::parse("version 10.20.30\n"), Some(CliVersion::new(10, 20, 30)));
+    }
+
+    #[test]
+    fn test_parse_rejects_output_without_version() {
+        assert_eq!(CliVersion::parse(""), None);
+        assert_eq!(CliVersion::parse("Claude Code 2.0"), None);
+        assert_eq!(CliVersion::parse("1.2.3.4"), None);
+    }
+
+    #[test]
+    fn test_versions_order_numerically() {
+        assert!(CliVersion::new(2, 0, 10) > CliVersion::new(2, 0, 9));
+        assert!(CliVersion::new(1, 99, 99) < CliVersion::new(2, 0, 0));
+        assert_eq!(CliVersion::new(2, 1, 0).to_string(), "2.1.0");
+    }
+}
@@ -0,0 +1,158 @@
+//! Exponential backoff for retrying transient failures.
+//!
+//! Shared by reconnect, spawn retry, and MCP restart logic so every retry
+//! loop uses the same delay progression.
+
+use std::time::Duration;
+
+/// Exponential backoff with optional jitter and attempt limit.
+///
+/// Each call to [`Backoff::next_delay`] doubles the delay, starting at `base`
+/// and capped at `max`. With `jitter` set, the returned delay is reduced by a
+/// random fraction of up to `jitter`, so it always lies in
+/// `[delay * (1 - jitter), delay]`.
+#[derive(Debug, Clone)]
+pub struct Backoff {
+    /// Delay before the first retry.
+    pub base: Duration,
+    /// Upper bound for any single delay.
+    pub max: Duration,
+    /// Jitter fraction in `0.0..=1.0`.
+    pub jitter: f64,
+    /// Maximum number of delays to hand out (`None` for unlimited).
+    pub max_attempts: Option<u32>,
+    attempt: u32,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self {
+            base: Duration::from_millis(100),
+            max: Duration::from_secs(10),
+            jitter: 0.2,
+            max_attempts: Some(5),
+            attempt: 0,
+        }
+    }
+}
+
+impl Backoff {
+    /// Create a backoff without jitter or attempt limit.
+    pub fn new(base: Duration, max: Duration) -> Self {
+        Self { base, max, jitter: 0.0, max_attempts: None, attempt: 0 }
+    }
+
+    /// Set the jitter fraction, clamped to `0.0..=1.0`.
+    pub fn with_jitter(mut self, jitter: f64) -> Self {
+        self.jitter = jitter.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Limit the number of delays returned before giving up.
+    pub fn with_max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = Some(max_attempts);
+        self
+    }
+
+    /// Number of delays handed out since creation or the last reset.
+    pub fn attempt(&self) -> u32 {
+        self.attempt
+    }
+
+    /// Return the delay before the next retry, or `None` once attempts are exhausted.
+    pub fn next_delay(&mut self) -> Option<Duration> {
+        if self.max_attempts.is_some_and(|max| self.attempt >= max) {
+            return None;
+        }
+
+        let factor = 2u32.saturating_pow(self.attempt);
+        let delay = self.base.saturating_mul(factor).min(self.max);
+        self.attempt = self.attempt.saturating_add(1);
+
+        if self.jitter <= 0.0 {
+            return Some(delay);
+        }
+        let reduction = self.jitter.clamp(0.0, 1.0) * random_unit();
+        Some(delay.mul_f64(1.0 - reduction))
+    }
+
+    /// Start the progression over from `base`.
+    pub fn reset(&mut self) {
+        self.attempt = 0;
+    }
+}
+
+/// Uniform random value in `[0.0, 1.0)`, drawn from the OS RNG via uuid v4.
+///
+/// Uses the low 53 bits, which sit below the fixed version and variant bits.
+fn random_unit() -> f64 {
+    let bits = uuid::Uuid::new_v4().as_u128() as u64 & ((1u64 << 53) - 1);
+    bits as f64 / (1u64 << 53) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn delays_double_until_capped() {
+        let mut backoff = Backoff::new(Duration::from_millis(100), Duration::from_millis(500));
+        let delays: Vec<_> = (0..5).map(|_| backoff.next_delay().unwrap()).collect();
+        assert_eq!(
+            delays,
+            vec![
+                Duration::from_millis(100),
+                Duration::from_millis(200),
+                Duration::from_millis(400),
+                Duration::from_millis(500),
+                Duration::from_millis(500),
+            ]
+        );
+    }
+
+    #[test]
+    fn stops_after_max_attempts() {
+        let mut backoff =
+            Backoff::new(Duration::from_millis(10), Duration::from_secs(1)).with_max_attempts(2);
+        assert!(backoff.next_delay().is_some());
+        assert!(backoff.next_delay().is_some());
+        assert!(backoff.next_delay().is_none());
+        assert_eq!(backoff.attempt(), 2);
+    }
+
+    #[test]
+    fn reset_restarts_progression() {
+        let mut backoff =
+            Backoff::new(Duration::from_millis(10), Duration::from_secs(1)).with_max_attempts(1);
+        backoff.next_delay();
+        assert!(backoff.next_delay().is_none());
+        backoff.reset();
+        assert_eq!(backoff.next_delay(), Some(Duration::from_millis(10)));
+    }
+
+    #[test]
+    fn jitter_stays_within_bounds() {
+        let base = Duration::from_millis(1000);
+        let mut backoff = Backoff::new(base, base).with_jitter(0.25);
+        for _ in 0..200 {
+            let delay = backoff.next_delay().unwrap();
+            assert!(delay <= base);
+            assert!(delay >= Duration::from_millis(750));
+        }
+    }
+
+    #[test]
+    fn jitter_is_clamped() {
+        let backoff =
+            Backoff::new(Duration::from_millis(1), Duration::from_millis(1)).with_jitter(3.0);
+        assert_eq!(backoff.jitter, 1.0);
+    }
+
+    #[test]
+    fn large_attempt_counts_do_not_overflow() {
+        let mut backoff = Backoff::new(Duration::from_secs(1), Duration::from_secs(30));
+        for _ in 0..100 {
+            assert!(backoff.next_delay().unwrap() <= Duration::from_secs(30));
+        }
+    }
+}
@@ -1,7 +1,11 @@
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use std::path::PathBuf;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use tokio_util::sync::CancellationToken;
+
+use super::error::ClaudeAgentError;
+use super::security::ApiKey;
 // Hook types are handled via callbacks in Rust
 
 /// Permission mode controlling how Claude interacts with tools.
@@ -58,6 +62,29 @@ impl std::fmt::Display for McpTransportType {
     }
 }
 
+impl McpServerConfig {
+    /// The entry the CLI expects for this server under `--mcp-config`'s `mcpServers`.
+    ///
+    /// `Auto` is declared as HTTP when a `url` is set and as stdio otherwise.
+    pub(crate) fn cli_config(&self) -> serde_json::Value {
+        let remote = match self.transport {
+            McpTransportType::Http => Some("http"),
+            McpTransportType::Sse => Some("sse"),
+            McpTransportType::Auto if self.url.is_some() => Some("http"),
+            McpTransportType::Stdio | McpTransportType::Auto => None,
+        };
+        match remote {
+            Some(kind) => serde_json::json!({"type": kind, "url": self.url}),
+            None => serde_json::json!({
+                "type": "stdio",
+                "command": self.command,
+                "args": self.args,
+                "env": self.env,
+            }),
+        }
+    }
+}
+
 /// Level of effort to use for Claude's responses.
 #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
 #[serde(rename_all = "camelCase")]
@@ -105,6 +132,9 @@ pub struct McpServerConfig {
     /// Environment variables for subprocess
     #[serde(default)]
     pub env: HashMap<String, String>,
+    /// Rate limit for tool calls (for stdio transport)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rate_limit: Option<crate::mcp::RateLimitConfig>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
@@ -115,6 +145,87 @@ pub enum SettingSource {
     Local,
 }
 
+impl SettingSource {
+    /// Where the CLI reads this layer's settings file.
+    ///
+    /// User settings live in `~/.claude/settings.json`, project settings in
+    /// `.claude/settings.json` and local settings in `.claude/settings.local.json`
+    /// under the project directory.
+    pub fn settings_path(&self, home: &Path, project_dir: &Path) -> PathBuf {
+        match self {
+            SettingSource::User => home.join(".claude/settings.json"),
+            SettingSource::Project => project_dir.join(".claude/settings.json"),
+            SettingSource::Local => project_dir.join(".claude/settings.local.json"),
+        }
+    }
+
+    /// Rank in the merge; higher layers override lower ones.
+    fn precedence(&self) -> u8 {
+        match self {
+            SettingSource::User => 0,
+            SettingSource::Project => 1,
+            SettingSource::Local => 2,
+        }
+    }
+}
+
+/// Merge the settings files of `sources`, with Local overriding Project overriding User.
+///
+/// Precedence does not depend on the order of `sources`. Objects are merged key by
+/// key at every depth; any other value replaces the lower layer's. Missing files
+/// are skipped.
+///
+/// # Errors
+///
+/// Returns `ClaudeAgentError::Config` if a settings file cannot be read or is not
+/// a JSON object.
+pub fn merge_setting_sources(
+    sources: &[SettingSource],
+    home: &Path,
+    project_dir: &Path,
+) -> Result<serde_json::Map<String, serde_json::Value>, ClaudeAgentError> {
+    let mut layers: Vec<&SettingSource> = sources.iter().collect();
+    layers.sort_by_key(|source| source.precedence());
+    let mut merged = serde_json::Map::new();
+    for source in layers {
+        let path = source.settings_path(home, project_dir);
+        let contents = match std::fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => {
+                return Err(ClaudeAgentError::Config(format!(
+                    "Failed to read settings file {}: {}",
+                    path.display(),
+                    e
+                )))
+            },
+        };
+        let layer: serde_json::Map<String, serde_json::Value> = serde_json::from_str(&contents)
+            .map_err(|e| {
+                ClaudeAgentError::Config(format!("Invalid settings file {}: {}", path.display(), e))
+            })?;
+        merge_settings_object(&mut merged, layer);
+    }
+    Ok(merged)
+}
+
+/// Merge `layer` into `base`, recursing into objects present in both.
+fn merge_settings_object(
+    base: &mut serde_json::Map<String, serde_json::Value>,
+    layer: serde_json::Map<String, serde_json::Value>,
+) {
+    for (key, value) in layer {
+        match (base.get_mut(&key), value) {
+            (Some(serde_json::Value::Object(base)), serde_json::Value::Object(layer)) => {
+                merge_settings_object(base, layer)
+            },
+            (_, value) => {
+                base.insert(key, value);
+            },
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 #[serde(tag = "type")]
 pub enum SystemPromptPreset {
@@ -135,6 +246,18 @@ pub enum ToolsPreset {
     },
 }
 
+/// What the reader does when one CLI message outgrows `max_buffer_size`.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, JsonSchema, PartialEq, Eq, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum BufferOverflowPolicy {
+    /// Fail with a fatal `Transport` error, ending the stream.
+    #[default]
+    Error,
+    /// Drop the oversized message through the end of its line, report a
+    /// recoverable `MessageTooLarge` error and resume with the next line.
+    SkipToNewline,
+}
+
 /// Scope for memory storage across sessions.
 #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
 pub enum MemoryScope {
@@ -213,8 +336,10 @@ pub struct AgentDefinition {
     pub max_turns: Option<u32>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[derive(Clone, Serialize, Deserialize, Default)]
 pub struct ClaudeAgentOptions {
+    /// Built-in tools available to Claude (`--tools`). An empty list disables
+    /// them all; the `claude_code` preset selects the CLI's default set.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub tools: Option<ToolsConfig>,
     #[serde(default)]
@@ -225,8 +350,10 @@ pub struct ClaudeAgentOptions {
     pub mcp_servers: HashMap<String, serde_json::Value>, // Simplified for now
     #[serde(skip_serializing_if = "Option::is_none")]
     pub permission_mode: Option<PermissionMode>,
+    /// Continue the most recent conversation (`--continue`).
     #[serde(default)]
     pub continue_conversation: bool,
+    /// Resume a specific session by id (`--resume`). Independent of `continue_conversation`.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub resume: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -239,6 +366,7 @@ pub struct ClaudeAgentOptions {
     pub model: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub fallback_model: Option<String>,
+    /// Beta features to enable, passed comma-joined as `--betas`.
     #[serde(default)]
     pub betas: Vec<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -251,28 +379,103 @@ pub struct ClaudeAgentOptions {
     pub settings: Option<String>,
     #[serde(default)]
     pub add_dirs: Vec<PathBuf>,
+    /// Skip checking that `cwd` and `add_dirs` exist before spawning, for
+    /// directories created after the options are built.
+    #[serde(default)]
+    pub skip_dir_validation: bool,
     #[serde(default)]
     pub env: HashMap<String, String>,
+    /// Start the CLI with an empty environment instead of inheriting this process's.
+    ///
+    /// Only the variables in [`CLEAR_ENV_DEFAULTS`], those named in
+    /// `env_passthrough`, and `env` itself reach the CLI. The defaults are what
+    /// the CLI needs to find its binaries, settings and credentials; set
+    /// `clear_env_strict` to drop them too.
+    #[serde(default)]
+    pub clear_env: bool,
+    /// With `clear_env`, leave out [`CLEAR_ENV_DEFAULTS`] as well, so only
+    /// `env_passthrough` and `env` reach the CLI.
+    #[serde(default)]
+    pub clear_env_strict: bool,
+    /// Parent variables kept alongside [`CLEAR_ENV_DEFAULTS`] when `clear_env` is set.
+    #[serde(default)]
+    pub env_passthrough: Vec<String>,
+    /// Credential passed to the CLI as `ANTHROPIC_AUTH_TOKEN`, overriding `env`.
+    ///
+    /// Held as an [`ApiKey`], so it is redacted from `Debug` output, never
+    /// serialized, and zeroized when dropped.
+    #[serde(default, skip_serializing)]
+    pub api_key: Option<ApiKey>,
     #[serde(default)]
     pub extra_args: HashMap<String, Option<String>>,
+    /// Maximum bytes buffered while reading one CLI message (default 64KB).
     #[serde(skip_serializing_if = "Option::is_none")]
     pub max_buffer_size: Option<usize>,
+    /// How to handle a message larger than `max_buffer_size` (default: fail).
+    #[serde(default)]
+    pub buffer_overflow_policy: BufferOverflowPolicy,
+    /// Extra attempts to spawn the CLI after transient failures such as `ETXTBSY`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub connect_retries: Option<u32>,
+    /// Base delay in milliseconds for spawn retry backoff (default 100).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub connect_retry_delay_ms: Option<u64>,
+    /// How long `close()` waits for the CLI after closing stdin, and again after
+    /// `SIGTERM`, before killing it, in milliseconds (default 5000).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub close_grace_period_ms: Option<u64>,
+    /// Skip running `claude --version` before spawning the CLI.
+    ///
+    /// With the probe, connecting to a CLI older than the oldest version the
+    /// SDK supports (2.0.0) fails with `ClaudeAgentError::Config`. Without it,
+    /// the CLI is spawned whatever its version.
+    #[serde(default)]
+    pub skip_version_check: bool,
+    /// Capacity of the channel fanning CLI output out to readers (default 1000).
+    ///
+    /// Readers that fall further behind than this receive `ClaudeAgentError::Lagged`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub broadcast_capacity: Option<usize>,
+    /// Coalesce writes to the CLI and flush them together every this many milliseconds.
+    ///
+    /// `None` (the default) flushes after every message. If a periodic flush
+    /// fails, later writes return that error and the transport reports itself
+    /// disconnected.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub write_flush_interval_ms: Option<u64>,
     #[serde(default)]
     pub include_partial_messages: bool,
+    /// Pass `--verbose` to the CLI. `None` (the default) passes it, since
+    /// current CLIs require it with `stream-json` output; set `Some(false)` for
+    /// CLIs or modes that do not.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub verbose: Option<bool>,
+    /// Fork to a new session id when resuming (`--fork-session`); pair with `resume`.
     #[serde(default)]
     pub fork_session: bool,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub agents: Option<HashMap<String, AgentDefinition>>,
+    /// Settings layers to load (`--setting-sources`). `Some(vec![])` loads none.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub setting_sources: Option<Vec<SettingSource>>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub sandbox: Option<SandboxSettings>,
+    /// Plugins to load; each local plugin is passed as its own `--plugin-dir`.
     #[serde(default)]
     pub plugins: Vec<PluginConfig>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub max_thinking_tokens: Option<u32>,
+    /// Structured output format, e.g. `{"type": "json_schema", "schema": {...}}`.
+    ///
+    /// The schema is passed as `--json-schema`; the validated result arrives in
+    /// `ResultMessage::structured_output`. Any other shape fails the connect with
+    /// `ClaudeAgentError::Config`.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub output_format: Option<serde_json::Value>,
+    /// Record file checkpoints so `rewind_files` can restore them.
+    ///
+    /// The CLI reads this from the `CLAUDE_CODE_ENABLE_SDK_FILE_CHECKPOINTING`
+    /// environment variable rather than a command-line flag.
     #[serde(default)]
     pub enable_file_checkpointing: bool,
     /// Effort level for Claude's responses.
@@ -290,9 +493,346 @@ pub struct ClaudeAgentOptions {
     /// Whether to use strict MCP configuration (no defaults).
     #[serde(default)]
     pub strict_mcp_config: bool,
+    /// Throttle outgoing queries; each prompt takes one permit before it is written.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub query_rate_limit: Option<crate::mcp::RateLimitConfig>,
+    /// Tool permission rules evaluated by the SDK before the permission callback.
+    ///
+    /// Unless `permission_prompt_tool_name` is set, a non-empty list makes the
+    /// CLI send its permission prompts to the SDK (`--permission-prompt-tool stdio`).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub permission_rules: Vec<crate::types::hooks::ToolPermissionRule>,
+    /// Yield `ClaudeAgentError::Result` in place of a `Result` message with `is_error` set.
+    #[serde(default)]
+    pub error_on_result_failure: bool,
+    /// End a query's stream with `ClaudeAgentError::MessageLimit` when a turn
+    /// sends more than this many messages before its `Result`. The turn is
+    /// then interrupted, and its remaining output is discarded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_messages_per_query: Option<usize>,
     // Note: can_use_tool and hooks are handled differently in Rust (callbacks)
 }
 
+/// Parent environment variables the CLI keeps under `ClaudeAgentOptions::clear_env`,
+/// unless `clear_env_strict` is set.
+pub const CLEAR_ENV_DEFAULTS: &[&str] = &["HOME", "PATH", "USER", "TMPDIR"];
+
+/// Parent environment variables copied by [`ClaudeAgentOptions::inherit_anthropic_env`].
+pub const ANTHROPIC_ENV_VARS: &[&str] = &[
+    "ANTHROPIC_AUTH_TOKEN",
+    "ANTHROPIC_BASE_URL",
+    "ANTHROPIC_MODEL",
+    "ANTHROPIC_DEFAULT_HAIKU_MODEL",
+    "ANTHROPIC_DEFAULT_SONNET_MODEL",
+    "ANTHROPIC_DEFAULT_OPUS_MODEL",
+];
+
+impl ClaudeAgentOptions {
+    /// Copy the Anthropic credentials, endpoint and model variables listed in
+    /// [`ANTHROPIC_ENV_VARS`] from this process into `env`.
+    ///
+    /// Unset variables are skipped and entries already in `env` are kept. This
+    /// matters mostly with `clear_env`, which otherwise hides them from the CLI.
+    pub fn inherit_anthropic_env(&mut self) -> &mut Self {
+        for var in ANTHROPIC_ENV_VARS {
+            if let Ok(value) = std::env::var(var) {
+                self.env.entry(var.to_string()).or_insert(value);
+            }
+        }
+        self
+    }
+
+    /// Allow `tools`, removing them from `disallowed_tools`.
+    ///
+    /// Together with [`ClaudeAgentOptions::deny_tools`] this keeps the two lists
+    /// disjoint, with the latest call winning for a given tool.
+    pub fn allow_tools<I, S>(&mut self, tools: I) -> &mut Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        for tool in tools.into_iter().map(Into::into) {
+            self.disallowed_tools.retain(|denied| *denied != tool);
+            if !self.allowed_tools.contains(&tool) {
+                self.allowed_tools.push(tool);
+            }
+        }
+        self
+    }
+
+    /// Disallow `tools`, removing them from `allowed_tools`.
+    pub fn deny_tools<I, S>(&mut self, tools: I) -> &mut Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        for tool in tools.into_iter().map(Into::into) {
+            self.allowed_tools.retain(|allowed| *allowed != tool);
+            if !self.disallowed_tools.contains(&tool) {
+                self.disallowed_tools.push(tool);
+            }
+        }
+        self
+    }
+
+    /// Check that no tool is both allowed and disallowed.
+    ///
+    /// The CLI's behavior for a tool in both `--allowedTools` and
+    /// `--disallowedTools` is undefined, so this is rejected before spawning.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ClaudeAgentError::Config` listing the conflicting tools.
+    pub fn validate_tool_lists(&self) -> Result<(), ClaudeAgentError> {
+        let conflicts: Vec<&str> = self
+            .allowed_tools
+            .iter()
+            .filter(|tool| self.disallowed_tools.contains(tool))
+            .map(String::as_str)
+            .collect();
+        if conflicts.is_empty() {
+            return Ok(());
+        }
+        Err(ClaudeAgentError::Config(format!(
+            "Tools both allowed and disallowed: {}",
+            conflicts.join(", ")
+        )))
+    }
+
+    /// Check that `cwd` and every `add_dirs` entry are existing directories.
+    ///
+    /// Called before the CLI is spawned unless `skip_dir_validation` is set.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ClaudeAgentError::Config` naming the first missing directory.
+    pub fn validate_directories(&self) -> Result<(), ClaudeAgentError> {
+        let cwd = self.cwd.iter().map(|dir| ("cwd", dir));
+        let add_dirs = self.add_dirs.iter().map(|dir| ("add_dirs", dir));
+        for (option, dir) in cwd.chain(add_dirs) {
+            if !dir.is_dir() {
+                return Err(ClaudeAgentError::Config(format!(
+                    "{} directory does not exist: {}",
+                    option,
+                    dir.display()
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Check that every `mcp_servers` entry has the shape the CLI expects.
+    ///
+    /// Each entry must be an object whose `type` (default `"stdio"`) is one of
+    /// `stdio`, `http`, `sse` or `sdk`, carrying that transport's required key
+    /// (`command`, `url`, `url` or `name`). Keys the SDK does not know are
+    /// logged as warnings and passed through, since newer CLIs may accept them.
+    /// Called before the CLI is spawned, so mistakes surface as a descriptive
+    /// error rather than a CLI failure.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ClaudeAgentError::Config` naming the first invalid server.
+    pub fn validate_mcp_servers(&self) -> Result<(), ClaudeAgentError> {
+        let mut names: Vec<&String> = self.mcp_servers.keys().collect();
+        names.sort();
+        for name in names {
+            validate_mcp_server(name, &self.mcp_servers[name]).map_err(|reason| {
+                ClaudeAgentError::Config(format!("Invalid MCP server '{}': {}", name, reason))
+            })?;
+        }
+        Ok(())
+    }
+
+    /// The settings the CLI will load from `setting_sources`, merged by precedence.
+    ///
+    /// The CLI does the layering itself from `--setting-sources`; this resolves the
+    /// same files so callers can inspect the result. Without `setting_sources` all
+    /// three layers are read, as the CLI does. The project directory is `cwd`, or
+    /// the current directory when unset. The `settings` option is not included.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ClaudeAgentError::Config` if the home directory cannot be found or a
+    /// settings file is unreadable. See [`merge_setting_sources`].
+    pub fn resolve_settings(
+        &self,
+    ) -> Result<serde_json::Map<String, serde_json::Value>, ClaudeAgentError> {
+        let home = dirs::home_dir().ok_or_else(|| {
+            ClaudeAgentError::Config("Could not determine the home directory".to_string())
+        })?;
+        let project_dir = match &self.cwd {
+            Some(cwd) => cwd.clone(),
+            None => std::env::current_dir().map_err(|e| {
+                ClaudeAgentError::Config(format!(
+                    "Could not determine the current directory: {}",
+                    e
+                ))
+            })?,
+        };
+        let all = [SettingSource::User, SettingSource::Project, SettingSource::Local];
+        let sources = self.setting_sources.as_deref().unwrap_or(&all);
+        merge_setting_sources(sources, &home, &project_dir)
+    }
+}
+
+/// Check one `mcp_servers` entry, describing the first problem found.
+fn validate_mcp_server(name: &str, entry: &serde_json::Value) -> Result<(), String> {
+    let Some(fields) = entry.as_object() else {
+        return Err("expected a JSON object".to_string());
+    };
+    let kind = match fields.get("type") {
+        None => "stdio",
+        Some(serde_json::Value::String(kind)) => kind.as_str(),
+        Some(_) => return Err("`type` must be a string".to_string()),
+    };
+    let (required, known): (&str, &[&str]) = match kind {
+        "stdio" => ("command", &["type", "command", "args", "env"]),
+        "http" | "sse" => ("url", &["type", "url", "headers"]),
+        "sdk" => ("name", &["type", "name"]),
+        other => return Err(format!("unknown type `{}`, expected stdio, http, sse or sdk", other)),
+    };
+
+    match fields.get(required).and_then(|value| value.as_str()) {
+        Some(value) if !value.is_empty() => {},
+        _ => return Err(format!("{} servers need a non-empty string `{}`", kind, required)),
+    }
+    for unknown in fields.keys().filter(|key| !known.contains(&key.as_str())) {
+        tracing::warn!(server = name, field = %unknown, "Unknown field for {} MCP servers", kind);
+    }
+    if let Some(args) = fields.get("args") {
+        if !args.as_array().is_some_and(|args| args.iter().all(|arg| arg.is_string())) {
+            return Err("`args` must be an array of strings".to_string());
+        }
+    }
+    for key in ["env", "headers"] {
+        if let Some(map) = fields.get(key) {
+            if !map.as_object().is_some_and(|map| map.values().all(|value| value.is_string())) {
+                return Err(format!("`{}` must be an object of strings", key));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Hide `value` if its key, such as an `env` or `extra_args` name, looks like it
+/// names a credential.
+pub(crate) fn mask<'a>(key: &str, value: &'a str) -> &'a str {
+    let key = key.to_ascii_uppercase();
+    if ["TOKEN", "KEY", "SECRET", "AUTHORIZATION", "PASSWORD"]
+        .iter()
+        .any(|marker| key.contains(marker))
+    {
+        "***"
+    } else {
+        value
+    }
+}
+
+/// Apply [`mask`] to every string in `value` held under a key, at any depth,
+/// such as the `env` and `headers` of MCP server configs.
+pub(crate) fn mask_json(value: &mut serde_json::Value) {
+    match value {
+        serde_json::Value::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                match value {
+                    serde_json::Value::String(text) if mask(key, text) != text.as_str() => {
+                        *text = "***".to_string();
+                    },
+                    _ => mask_json(value),
+                }
+            }
+        },
+        serde_json::Value::Array(items) => items.iter_mut().for_each(mask_json),
+        _ => {},
+    }
+}
+
+/// Masks values of sensitive `env`, `extra_args` and `mcp_servers` entries as `"***"`.
+impl std::fmt::Debug for ClaudeAgentOptions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let env: BTreeMap<&str, &str> =
+            self.env.iter().map(|(key, value)| (key.as_str(), mask(key, value))).collect();
+        let extra_args: BTreeMap<&str, Option<&str>> = self
+            .extra_args
+            .iter()
+            .map(|(key, value)| (key.as_str(), value.as_deref().map(|v| mask(key, v))))
+            .collect();
+        let mut mcp_servers = serde_json::to_value(&self.mcp_servers).unwrap_or_default();
+        mask_json(&mut mcp_servers);
+
+        f.debug_struct("ClaudeAgentOptions")
+            .field("tools", &self.tools)
+            .field("allowed_tools", &self.allowed_tools)
+            .field("system_prompt", &self.system_prompt)
+            .field("mcp_servers", &mcp_servers)
+            .field("permission_mode", &self.permission_mode)
+            .field("continue_conversation", &self.continue_conversation)
+            .field("resume", &self.resume)
+            .field("max_turns", &self.max_turns)
+            .field("max_budget_usd", &self.max_budget_usd)
+            .field("disallowed_tools", &self.disallowed_tools)
+            .field("model", &self.model)
+            .field("fallback_model", &self.fallback_model)
+            .field("betas", &self.betas)
+            .field("permission_prompt_tool_name", &self.permission_prompt_tool_name)
+            .field("cwd", &self.cwd)
+            .field("cli_path", &self.cli_path)
+            .field("settings", &self.settings)
+            .field("add_dirs", &self.add_dirs)
+            .field("skip_dir_validation", &self.skip_dir_validation)
+            .field("env", &env)
+            .field("clear_env", &self.clear_env)
+            .field("clear_env_strict", &self.clear_env_strict)
+            .field("env_passthrough", &self.env_passthrough)
+            .field("api_key", &self.api_key)
+            .field("extra_args", &extra_args)
+            .field("max_buffer_size", &self.max_buffer_size)
+            .field("buffer_overflow_policy", &self.buffer_overflow_policy)
+            .field("connect_retries", &self.connect_retries)
+            .field("connect_retry_delay_ms", &self.connect_retry_delay_ms)
+            .field("close_grace_period_ms", &self.close_grace_period_ms)
+            .field("skip_version_check", &self.skip_version_check)
+            .field("broadcast_capacity", &self.broadcast_capacity)
+            .field("write_flush_interval_ms", &self.write_flush_interval_ms)
+            .field("include_partial_messages", &self.include_partial_messages)
+            .field("verbose", &self.verbose)
+            .field("fork_session", &self.fork_session)
+            .field("agents", &self.agents)
+            .field("setting_sources", &self.setting_sources)
+            .field("sandbox", &self.sandbox)
+            .field("plugins", &self.plugins)
+            .field("max_thinking_tokens", &self.max_thinking_tokens)
+            .field("output_format", &self.output_format)
+            .field("enable_file_checkpointing", &self.enable_file_checkpointing)
+            .field("effort", &self.effort)
+            .field("thinking", &self.thinking)
+            .field("task_budget", &self.task_budget)
+            .field("session_id", &self.session_id)
+            .field("strict_mcp_config", &self.strict_mcp_config)
+            .field("query_rate_limit", &self.query_rate_limit)
+            .field("permission_rules", &self.permission_rules)
+            .field("error_on_result_failure", &self.error_on_result_failure)
+            .field("max_messages_per_query", &self.max_messages_per_query)
+            .finish()
+    }
+}
+
+/// Options overridden for a single query; see `ClaudeAgent::query_with`.
+///
+/// `model` and `permission_mode` are changed on the running CLI with control
+/// requests and restored when the turn ends. Options the CLI only reads at
+/// startup, such as `max_turns`, belong in [`ClaudeAgentOptions`].
+#[derive(Debug, Clone, Default)]
+pub struct QueryOverrides {
+    /// Model to use for this turn.
+    pub model: Option<String>,
+    /// Permission mode for this turn.
+    pub permission_mode: Option<PermissionMode>,
+    /// Cancelling this token interrupts the turn and ends its stream.
+    pub cancellation: Option<CancellationToken>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 #[serde(tag = "type")]
 pub enum PluginConfig {
@@ -341,4 +881,10 @@ pub enum ToolsConfig {
 pub enum SystemPromptConfig {
     Text(String),
     Preset(SystemPromptPreset),
+    /// A custom prompt plus text appended to it, passed as both
+    /// `--system-prompt` and `--append-system-prompt`.
+    TextWithAppend {
+        text: String,
+        append: String,
+    },
 }
@@ -1,3 +1,5 @@
+use std::time::Duration;
+
 use thiserror::Error;
 
 #[derive(Debug, Error, Clone)]
@@ -20,6 +22,47 @@ pub enum ClaudeAgentError {
     #[error("Transport error: {0}")]
     Transport(String),
 
+    #[error("Message stream lagged: {0} messages skipped")]
+    Lagged(u64),
+
+    /// A rate limit was hit; `retry_after` is the suggested wait when known.
+    #[error(
+        "Rate limited{}",
+        .retry_after.map(|d| format!(" (retry after {:?})", d)).unwrap_or_default()
+    )]
+    RateLimited { retry_after: Option<Duration> },
+
+    /// The CLI could not authenticate with the API.
+    #[error(
+        "Authentication failed: {0}. Set ANTHROPIC_API_KEY or ANTHROPIC_AUTH_TOKEN in the \
+         environment (or via `ClaudeAgentOptions::env`), or run `claude /login`"
+    )]
+    Authentication(String),
+
+    /// The turn ended with an error result, e.g. `error_max_turns`.
+    ///
+    /// Only yielded when `ClaudeAgentOptions::error_on_result_failure` is set.
+    #[error("Query failed ({subtype}): {message}")]
+    Result { subtype: String, message: String },
+
+    /// No message arrived within the allowed idle time.
+    #[error("Timed out after {0:?} without a message")]
+    Timeout(Duration),
+
+    /// The CLI did not start within the connect timeout.
+    #[error("CLI connection error: Connection timeout after {0:?}")]
+    ConnectTimeout(Duration),
+
+    /// A CLI message outgrew the reader's buffer limit and was skipped.
+    ///
+    /// Only yielded with `BufferOverflowPolicy::SkipToNewline`.
+    #[error("Skipped a message larger than the {0}-byte buffer limit")]
+    MessageTooLarge(usize),
+
+    /// A query emitted more messages than `ClaudeAgentOptions::max_messages_per_query`.
+    #[error("Query exceeded the limit of {0} messages")]
+    MessageLimit(usize),
+
     #[error("Control protocol error: {0}")]
     ControlProtocol(String),
 
@@ -29,9 +72,74 @@ pub enum ClaudeAgentError {
     #[error("Configuration error: {0}")]
     Config(String),
 
+    /// Reading or writing a file failed.
+    #[error("I/O error: {0}")]
+    Io(String),
+
     #[error("Initialization error: {0}")]
     Initialization(String),
 
     #[error("Unknown error: {0}")]
     Unknown(String),
 }
+
+impl ClaudeAgentError {
+    /// Whether a message stream yielding this error has ended.
+    ///
+    /// Query streams keep going after non-fatal errors such as a malformed
+    /// message (`JSONDecode`, `MessageParse`), skipped messages (`Lagged`,
+    /// `MessageTooLarge`), or a rate-limit report, and end after yielding a
+    /// fatal one, such as a `Transport` or `Process` failure.
+    pub fn is_fatal(&self) -> bool {
+        match self {
+            Self::JSONDecode(_)
+            | Self::MessageParse(_)
+            | Self::Lagged(_)
+            | Self::MessageTooLarge(_)
+            | Self::RateLimited { .. }
+            | Self::ControlProtocol(_)
+            | Self::Mcp(_) => false,
+            Self::CLINotFound(_)
+            | Self::CLIConnection(_)
+            | Self::Process(_)
+            | Self::Transport(_)
+            | Self::Authentication(_)
+            | Self::Result { .. }
+            | Self::Timeout(_)
+            | Self::ConnectTimeout(_)
+            | Self::MessageLimit(_)
+            | Self::Config(_)
+            | Self::Io(_)
+            | Self::Initialization(_)
+            | Self::Unknown(_) => true,
+        }
+    }
+
+    /// Whether the Claude Code CLI could not be found.
+    pub fn is_cli_not_found(&self) -> bool {
+        matches!(self, Self::CLINotFound(_))
+    }
+
+    /// Whether connecting to or talking with the CLI failed: a `CLIConnection`
+    /// or `ConnectTimeout` error, or a `Transport` error such as a failed
+    /// write to its stdin.
+    pub fn is_connection(&self) -> bool {
+        matches!(self, Self::CLIConnection(_) | Self::ConnectTimeout(_) | Self::Transport(_))
+    }
+
+    /// Whether a line from the CLI was not valid JSON.
+    pub fn is_json_decode(&self) -> bool {
+        matches!(self, Self::JSONDecode(_))
+    }
+
+    /// Whether an MCP server or request failed.
+    pub fn is_mcp(&self) -> bool {
+        matches!(self, Self::Mcp(_))
+    }
+
+    /// Whether something took too long: no message arrived within the allowed
+    /// idle time, or the CLI did not start within the connect timeout.
+    pub fn is_timeout(&self) -> bool {
+        matches!(self, Self::Timeout(_) | Self::ConnectTimeout(_))
+    }
+}
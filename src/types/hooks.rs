@@ -1,5 +1,6 @@
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
 
 #[derive(Debug, Clone, Serialize, Deserialize, Hash, PartialEq, Eq)]
 pub enum HookEvent {
@@ -64,6 +65,138 @@ pub enum PermissionBehavior {
     Ask,
 }
 
+/// A permission rule evaluated in the SDK for `can_use_tool` requests.
+///
+/// Rules are checked in order before the permission callback; the first rule
+/// whose tool name and condition match decides the request. An `Ask` rule stops
+/// rule evaluation and defers to the callback.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ToolPermissionRule {
+    /// Tool name the rule applies to, or `*` for any tool.
+    pub tool_name: String,
+    /// Decision applied when the rule matches.
+    pub behavior: PermissionBehavior,
+    /// Condition on the tool input.
+    #[serde(default)]
+    pub condition: ToolRuleCondition,
+}
+
+/// Input condition for a [`ToolPermissionRule`].
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ToolRuleCondition {
+    /// Matches every input.
+    #[default]
+    Always,
+    /// A string input field matches a glob pattern where `*` matches any run of
+    /// characters that does not chain or substitute a shell command (`;`, `&`,
+    /// `|`, a newline, a backtick or `$(`). So `git *` matches `git status` but
+    /// not `git status && curl … | sh`; write such characters literally in the
+    /// pattern to match them.
+    InputMatches { field: String, pattern: String },
+    /// A path input field resolves outside `root`. Relative paths are resolved against `root`.
+    PathOutside { field: String, root: PathBuf },
+}
+
+impl ToolPermissionRule {
+    /// Allow `tool_name` when the string input `field` matches `pattern`.
+    ///
+    /// See [`ToolRuleCondition::InputMatches`] for the pattern syntax; chained
+    /// shell commands fall through to later rules and the callback.
+    pub fn allow_matching(
+        tool_name: impl Into<String>,
+        field: impl Into<String>,
+        pattern: impl Into<String>,
+    ) -> Self {
+        Self {
+            tool_name: tool_name.into(),
+            behavior: PermissionBehavior::Allow,
+            condition: ToolRuleCondition::InputMatches {
+                field: field.into(),
+                pattern: pattern.into(),
+            },
+        }
+    }
+
+    /// Deny `tool_name` when the path input `field` points outside `root`.
+    pub fn deny_outside(
+        tool_name: impl Into<String>,
+        field: impl Into<String>,
+        root: impl Into<PathBuf>,
+    ) -> Self {
+        Self {
+            tool_name: tool_name.into(),
+            behavior: PermissionBehavior::Deny,
+            condition: ToolRuleCondition::PathOutside { field: field.into(), root: root.into() },
+        }
+    }
+
+    /// Check whether this rule applies to the given tool call.
+    pub fn matches(&self, tool_name: &str, input: &serde_json::Value) -> bool {
+        if self.tool_name != "*" && self.tool_name != tool_name {
+            return false;
+        }
+        match &self.condition {
+            ToolRuleCondition::Always => true,
+            ToolRuleCondition::InputMatches { field, pattern } => input
+                .get(field)
+                .and_then(|v| v.as_str())
+                .is_some_and(|value| glob_match(pattern, value)),
+            ToolRuleCondition::PathOutside { field, root } => {
+                input.get(field).and_then(|v| v.as_str()).is_some_and(|value| {
+                    let root = normalize_path(root);
+                    !normalize_path(&root.join(value)).starts_with(&root)
+                })
+            },
+        }
+    }
+}
+
+/// Match `value` against a pattern where `*` matches any (possibly empty) run of
+/// characters without a shell control operator or command substitution.
+fn glob_match(pattern: &str, value: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = value.strip_prefix(first) else {
+        return false;
+    };
+    let parts: Vec<&str> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else {
+        return rest.is_empty();
+    };
+    // Taking the earliest occurrence of each part keeps every wildcard run as
+    // short as possible, so no later occurrence could avoid a control operator.
+    for part in middle {
+        match rest.find(part) {
+            Some(idx) if !chains_command(&rest[..idx]) => rest = &rest[idx + part.len()..],
+            _ => return false,
+        }
+    }
+    rest.len() >= last.len()
+        && rest.ends_with(last)
+        && !chains_command(&rest[..rest.len() - last.len()])
+}
+
+/// Whether `text` could end one shell command and start another.
+fn chains_command(text: &str) -> bool {
+    text.contains([';', '&', '|', '\n', '\r', '`']) || text.contains("$(")
+}
+
+/// Lexically resolve `.` and `..` components without touching the filesystem.
+fn normalize_path(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {},
+            Component::ParentDir => {
+                normalized.pop();
+            },
+            other => normalized.push(other.as_os_str()),
+        }
+    }
+    normalized
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub enum PermissionUpdateDestination {
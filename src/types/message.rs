@@ -12,6 +12,22 @@ pub enum ContentBlock {
     ToolUse(ToolUseBlock),
     #[serde(rename = "tool_result")]
     ToolResult(ToolResultBlock),
+    #[serde(rename = "image")]
+    Image(ImageBlock),
+    /// A block the SDK does not model (e.g. `document` or `redacted_thinking`),
+    /// or a known block type with an unexpected shape, kept as raw JSON.
+    #[serde(untagged)]
+    Unknown(serde_json::Value),
+}
+
+impl ContentBlock {
+    /// The block's text, if it is a text block.
+    pub fn as_text(&self) -> Option<&str> {
+        match self {
+            Self::Text(block) => Some(&block.text),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -22,6 +38,8 @@ pub struct TextBlock {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ThinkingBlock {
     pub thinking: String,
+    /// Empty when the CLI omits it, e.g. for partial thinking blocks.
+    #[serde(default)]
     pub signature: String,
 }
 
@@ -42,6 +60,104 @@ pub struct ToolResultBlock {
     pub is_error: Option<bool>,
 }
 
+impl ToolResultBlock {
+    /// Parse the result as a structured Edit tool result, if it has that shape.
+    ///
+    /// Accepts the payload either as JSON text or as a JSON block, with
+    /// snake_case or camelCase keys (`file_path`/`filePath`, etc.). MultiEdit
+    /// results carry several edits and return `None` here; use
+    /// [`ToolResultBlock::as_edits`] to handle both tools.
+    pub fn as_edit(&self) -> Option<EditResult> {
+        self.parse_payload()
+    }
+
+    /// Parse the result as the edits made by an Edit or MultiEdit tool call.
+    ///
+    /// An Edit result yields one edit, a MultiEdit result one per entry in its
+    /// `edits` list, each carrying the shared file path.
+    pub fn as_edits(&self) -> Option<Vec<EditResult>> {
+        if let Some(edit) = self.as_edit() {
+            return Some(vec![edit]);
+        }
+        let multi: MultiEditPayload = self.parse_payload()?;
+        Some(
+            multi
+                .edits
+                .into_iter()
+                .map(|edit| EditResult {
+                    file_path: multi.file_path.clone(),
+                    old_string: edit.old_string,
+                    new_string: edit.new_string,
+                    replace_all: edit.replace_all,
+                })
+                .collect(),
+        )
+    }
+
+    /// Deserialize the first text or JSON block that has the shape of `T`.
+    fn parse_payload<T: serde::de::DeserializeOwned>(&self) -> Option<T> {
+        let parse_text = |text: &str| serde_json::from_str::<T>(text).ok();
+        match self.content.as_ref()? {
+            ToolResultContent::Text(text) => parse_text(text),
+            ToolResultContent::Blocks(blocks) => {
+                blocks.iter().find_map(|block| match block.get("text").and_then(|t| t.as_str()) {
+                    Some(text) if block.get("type").and_then(|t| t.as_str()) == Some("text") => {
+                        parse_text(text)
+                    },
+                    _ => serde_json::from_value(block.clone()).ok(),
+                })
+            },
+        }
+    }
+}
+
+/// Structured result of an Edit tool call, suitable for rendering a diff.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EditResult {
+    #[serde(alias = "filePath")]
+    pub file_path: String,
+    #[serde(alias = "oldString")]
+    pub old_string: String,
+    #[serde(alias = "newString")]
+    pub new_string: String,
+    #[serde(default, alias = "replaceAll")]
+    pub replace_all: bool,
+}
+
+/// Result payload of a MultiEdit tool call.
+#[derive(Deserialize)]
+struct MultiEditPayload {
+    #[serde(alias = "filePath")]
+    file_path: String,
+    edits: Vec<MultiEditEntry>,
+}
+
+/// One edit in a MultiEdit payload; the file path is shared.
+#[derive(Deserialize)]
+struct MultiEditEntry {
+    #[serde(alias = "oldString")]
+    old_string: String,
+    #[serde(alias = "newString")]
+    new_string: String,
+    #[serde(default, alias = "replaceAll")]
+    replace_all: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImageBlock {
+    pub source: ImageSource,
+}
+
+/// Image source in the Anthropic wire format.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ImageSource {
+    /// Inline base64-encoded image data.
+    Base64 { media_type: String, data: String },
+    /// Image referenced by URL.
+    Url { url: String },
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(untagged)]
 pub enum ToolResultContent {
@@ -49,8 +165,13 @@ pub enum ToolResultContent {
     Blocks(Vec<serde_json::Value>),
 }
 
+/// A message from the CLI's `stream-json` output.
+///
+/// Messages whose `type` the SDK does not know deserialize to
+/// [`Message::Unknown`], so newer CLIs do not break older SDKs. A known `type`
+/// with an unexpected shape is still an error.
 #[derive(Debug, Clone, Serialize, Deserialize)]
-#[serde(tag = "type", rename_all = "camelCase")]
+#[serde(remote = "Self", tag = "type", rename_all = "camelCase")]
 pub enum Message {
     #[serde(rename = "user")]
     User(UserMessage),
@@ -80,12 +201,108 @@ pub enum Message {
     Ping(Ping),
     #[serde(rename = "error")]
     Error(ErrorEvent),
+
+    /// A message with a `type` this SDK does not recognize, kept as raw JSON.
+    #[serde(skip)]
+    Unknown(serde_json::Value),
+}
+
+impl Message {
+    /// Wire `type` tags of the variants above, other than `Unknown`.
+    const KNOWN_TYPES: &'static [&'static str] = &[
+        "user",
+        "assistant",
+        "system",
+        "result",
+        "stream_event",
+        "message_start",
+        "content_block_start",
+        "content_block_delta",
+        "content_block_stop",
+        "message_delta",
+        "message_stop",
+        "ping",
+        "error",
+    ];
+}
+
+impl<'de> Deserialize<'de> for Message {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = serde_json::Value::deserialize(deserializer)?;
+        let known = value
+            .get("type")
+            .and_then(|t| t.as_str())
+            .is_some_and(|t| Self::KNOWN_TYPES.contains(&t));
+        if !known {
+            return Ok(Self::Unknown(value));
+        }
+        Message::deserialize(value).map_err(serde::de::Error::custom)
+    }
+}
+
+impl Serialize for Message {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Self::Unknown(value) => value.serialize(serializer),
+            message => Message::serialize(message, serializer),
+        }
+    }
+}
+
+impl Message {
+    /// Concatenated text of an assistant message's text blocks.
+    ///
+    /// Returns `None` for other variants and for assistant messages without
+    /// text, such as ones that only call tools.
+    pub fn text(&self) -> Option<String> {
+        let Self::Assistant(message) = self else {
+            return None;
+        };
+        let mut texts = message.content.iter().filter_map(ContentBlock::as_text).peekable();
+        texts.peek()?;
+        Some(texts.collect())
+    }
+
+    /// The tool use that spawned the subagent this message came from.
+    ///
+    /// `None` for messages from the main agent and for variants that do not
+    /// carry the field.
+    pub fn parent_tool_use_id(&self) -> Option<&str> {
+        match self {
+            Self::User(message) => message.parent_tool_use_id.as_deref(),
+            Self::Assistant(message) => message.parent_tool_use_id.as_deref(),
+            Self::StreamEvent(event) => event.parent_tool_use_id.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// Concatenated thinking of an assistant message's thinking blocks.
+    ///
+    /// Returns `None` for other variants and for assistant messages without
+    /// thinking blocks.
+    pub fn thinking(&self) -> Option<String> {
+        let Self::Assistant(message) = self else {
+            return None;
+        };
+        let mut thoughts = message
+            .content
+            .iter()
+            .filter_map(|block| match block {
+                ContentBlock::Thinking(thinking) => Some(thinking.thinking.as_str()),
+                _ => None,
+            })
+            .peekable();
+        thoughts.peek()?;
+        Some(thoughts.collect())
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(from = "WireUserMessage", into = "WireUserMessage")]
 pub struct UserMessage {
     pub content: MessageContent,
+    /// Message identifier assigned by the CLI. Kept as a loose string because the
+    /// CLI does not guarantee RFC 4122 format.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub uuid: Option<String>,
     #[serde(rename = "parent_tool_use_id", skip_serializing_if = "Option::is_none")]
@@ -204,11 +421,95 @@ pub enum AssistantMessageError {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SystemMessage {
     pub subtype: String,
-    #[serde(default)]
+    /// Subtype-specific payload. Absent on bare system messages, in which case it is `Null`.
+    #[serde(default, skip_serializing_if = "serde_json::Value::is_null")]
     pub data: serde_json::Value,
+    /// Top-level fields not explicitly modeled (e.g. `session_id`, `tools`, `model` on init).
+    #[serde(flatten)]
+    pub extra: HashMap<String, serde_json::Value>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+impl SystemMessage {
+    /// The typed payload of an `init` system message; `None` for other subtypes.
+    pub fn init(&self) -> Option<SystemInit> {
+        if self.subtype != "init" {
+            return None;
+        }
+        let payload = if self.data.is_object() {
+            self.data.clone()
+        } else {
+            serde_json::to_value(&self.extra).ok()?
+        };
+        Some(SystemInit::from_payload(payload))
+    }
+}
+
+/// Session details the CLI reports in its `system`/`init` message.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SystemInit {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    /// Working directory of the CLI process.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cwd: Option<String>,
+    /// Names of the tools available to the model.
+    #[serde(default)]
+    pub tools: Vec<String>,
+    #[serde(default)]
+    pub mcp_servers: Vec<SystemInitMcpServer>,
+    #[serde(default, rename = "permissionMode", skip_serializing_if = "Option::is_none")]
+    pub permission_mode: Option<String>,
+    #[serde(default, rename = "apiKeySource", skip_serializing_if = "Option::is_none")]
+    pub api_key_source: Option<String>,
+    #[serde(default)]
+    pub slash_commands: Vec<String>,
+    /// Fields not modeled above, such as `claude_code_version` or `agents`.
+    #[serde(flatten)]
+    pub extra: HashMap<String, serde_json::Value>,
+}
+
+impl SystemInit {
+    /// Parse a raw CLI message if it is a `system`/`init` message.
+    ///
+    /// Accepts both the current layout, with fields at the top level, and the
+    /// older one that nests them under `data`. If a modeled field has an
+    /// unexpected shape, the whole payload is kept in `extra` instead.
+    pub fn from_message(value: &serde_json::Value) -> Option<Self> {
+        if value.get("type").and_then(|t| t.as_str()) != Some("system")
+            || value.get("subtype").and_then(|t| t.as_str()) != Some("init")
+        {
+            return None;
+        }
+        let mut payload = value.get("data").cloned().unwrap_or_else(|| value.clone());
+        if let Some(fields) = payload.as_object_mut() {
+            fields.remove("type");
+            fields.remove("subtype");
+        }
+        Some(Self::from_payload(payload))
+    }
+
+    fn from_payload(payload: serde_json::Value) -> Self {
+        serde_json::from_value(payload.clone()).unwrap_or_else(|_| Self {
+            extra: payload
+                .as_object()
+                .map(|fields| fields.clone().into_iter().collect())
+                .unwrap_or_default(),
+            ..Default::default()
+        })
+    }
+}
+
+/// An MCP server as listed in [`SystemInit::mcp_servers`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SystemInitMcpServer {
+    pub name: String,
+    /// Connection status, e.g. `"connected"` or `"failed"`.
+    pub status: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ResultMessage {
     pub subtype: String,
     pub duration_ms: u64,
@@ -224,10 +525,28 @@ pub struct ResultMessage {
     pub result: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub structured_output: Option<serde_json::Value>,
+    /// Tool calls blocked by permissions during the query.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub permission_denials: Option<Vec<PermissionDenial>>,
+}
+
+/// A tool call the CLI refused to run because permission was denied.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PermissionDenial {
+    #[serde(alias = "toolName")]
+    pub tool_name: String,
+    #[serde(default, alias = "toolUseId", skip_serializing_if = "Option::is_none")]
+    pub tool_use_id: Option<String>,
+    #[serde(default, alias = "toolInput")]
+    pub tool_input: serde_json::Value,
+    /// Why the call was denied, when the CLI reports it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct StreamEvent {
+    /// Event identifier; a loose string like `UserMessage::uuid`.
     pub uuid: String,
     pub session_id: String,
     pub event: serde_json::Value,
@@ -306,8 +625,16 @@ pub struct ErrorBody {
     pub message: String,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct Usage {
     pub input_tokens: Option<u32>,
+    /// Output tokens; partial deltas may omit it, in which case it is `0`.
+    #[serde(default)]
     pub output_tokens: u32,
+    /// Tokens written to the prompt cache.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_creation_input_tokens: Option<u32>,
+    /// Tokens served from the prompt cache.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_read_input_tokens: Option<u32>,
 }
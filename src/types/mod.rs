@@ -1,16 +1,21 @@
 //! Type definitions for Claude Agent SDK.
 
+pub mod backoff;
 pub mod config;
 pub mod error;
 pub mod hooks;
 pub mod message;
 pub mod security;
+pub mod timestamped;
 
+pub use backoff::Backoff;
 pub use config::ClaudeAgentOptions;
 pub use config::EffortLevel;
 pub use config::MemoryScope;
+pub use config::QueryOverrides;
 pub use config::TaskBudget;
 pub use config::ThinkingConfig;
 pub use error::ClaudeAgentError;
 pub use message::{Message, MessageContent};
 pub use security::{constant_time_eq, constant_time_str_eq, ApiKey};
+pub use timestamped::Timestamped;
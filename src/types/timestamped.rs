@@ -0,0 +1,29 @@
+//! Values stamped with their receive time.
+
+use std::time::{Instant, SystemTime};
+
+/// A value paired with the time the SDK received it.
+#[derive(Debug, Clone)]
+pub struct Timestamped<T> {
+    pub value: T,
+    /// Monotonic receive time, for measuring latency between messages.
+    pub received_at: Instant,
+    /// Wall-clock receive time, for correlating with logs.
+    pub received_at_system: SystemTime,
+}
+
+impl<T> Timestamped<T> {
+    /// Stamp `value` with the current time.
+    pub fn now(value: T) -> Self {
+        Self { value, received_at: Instant::now(), received_at_system: SystemTime::now() }
+    }
+
+    /// Transform the value, keeping its timestamps.
+    pub fn map<U>(self, f: impl FnOnce(T) -> U) -> Timestamped<U> {
+        Timestamped {
+            value: f(self.value),
+            received_at: self.received_at,
+            received_at_system: self.received_at_system,
+        }
+    }
+}
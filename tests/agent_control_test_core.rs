@@ -1,6 +1,8 @@
 //! Integration tests for agent control methods using MockTransport.
 
 use claude_agent::core::ClaudeAgent;
+use claude_agent::types::hooks::ToolPermissionRule;
+use claude_agent::types::ClaudeAgentError;
 use claude_agent::ClaudeAgentOptions;
 use serde_json::json;
 
@@ -102,3 +104,114 @@ async fn test_agent_rewind_files() {
         Some("rewind_files")
     );
 }
+
+#[tokio::test]
+async fn test_agent_answers_can_use_tool_from_rules() {
+    let options = ClaudeAgentOptions {
+        permission_rules: vec![ToolPermissionRule::deny_outside("Write", "file_path", "/work")],
+        ..Default::default()
+    };
+    let mut agent = ClaudeAgent::new(options);
+    let transport = MockTransport::new();
+    agent.set_transport(Box::new(transport.clone()));
+    agent.connect(None).await.expect("Connect should succeed");
+    tokio::time::sleep(tokio::time::Duration::from_millis(50)).await;
+
+    transport
+        .push_incoming(json!({
+            "type": "control_request",
+            "request_id": "perm-1",
+            "request": {
+                "subtype": "can_use_tool",
+                "tool_name": "Write",
+                "input": {"file_path": "/etc/hosts"}
+            }
+        }))
+        .await;
+    tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+
+    let msgs = transport.sent_messages.lock().unwrap();
+    let parsed: serde_json::Value = serde_json::from_str(msgs.last().unwrap()).unwrap();
+    assert_eq!(parsed["response"]["request_id"], "perm-1");
+    assert_eq!(parsed["response"]["response"]["behavior"], "deny");
+}
+
+#[tokio::test]
+async fn test_agent_ping_succeeds_when_cli_responds() {
+    let (agent, transport) = connected_agent().await;
+    let handle = spawn_responder(transport.clone());
+    agent.ping().await.expect("ping should succeed");
+    handle.await.unwrap();
+    let msgs = transport.sent_messages.lock().unwrap();
+    let parsed: serde_json::Value = serde_json::from_str(msgs.last().unwrap()).unwrap();
+    assert_eq!(parsed["request"]["subtype"], "mcp_status");
+}
+
+#[tokio::test]
+async fn test_agent_ping_times_out_when_cli_is_silent() {
+    let (agent, _transport) = connected_agent().await;
+    let err = agent
+        .ping_within(std::time::Duration::from_millis(50))
+        .await
+        .expect_err("ping should time out");
+    assert!(matches!(err, ClaudeAgentError::Transport(ref msg) if msg.contains("no response")));
+}
+
+#[tokio::test]
+async fn test_agent_ping_without_connect_is_transport_error() {
+    let agent = ClaudeAgent::new(ClaudeAgentOptions::default());
+    // Fails at once instead of waiting out the ping timeout
+    let err = tokio::time::timeout(std::time::Duration::from_millis(500), agent.ping())
+        .await
+        .expect("ping should not wait for a reply")
+        .expect_err("ping should fail");
+    assert!(matches!(err, ClaudeAgentError::Transport(ref msg) if msg.contains("not connected")));
+}
+
+async fn agent_with_control_mock(
+    transport: claude_agent::transport::ControlMockTransport,
+) -> ClaudeAgent {
+    let mut agent = ClaudeAgent::new(ClaudeAgentOptions::default());
+    agent.set_transport(Box::new(transport));
+    agent.connect(None).await.expect("Connect should succeed");
+    agent
+}
+
+#[tokio::test]
+async fn test_interrupt_round_trip_succeeds_through_control_mock() {
+    use claude_agent::transport::{ControlMockTransport, MockTransport};
+
+    let transport = ControlMockTransport::new(MockTransport::new(vec![]))
+        .respond_with("interrupt", json!({"interrupted": true}));
+    let agent = agent_with_control_mock(transport.clone()).await;
+
+    let response = tokio::time::timeout(std::time::Duration::from_secs(2), agent.interrupt())
+        .await
+        .expect("interrupt should be answered")
+        .expect("interrupt should succeed");
+    assert!(response.success);
+    assert_eq!(response.error, None);
+    assert_eq!(response.response.unwrap()["response"]["response"], json!({"interrupted": true}));
+
+    let requests = transport.control_requests();
+    let interrupt = requests.last().expect("interrupt request");
+    assert_eq!(interrupt["request"]["subtype"], "interrupt");
+    assert_eq!(response.request_id, interrupt["request_id"].as_str().unwrap());
+}
+
+#[tokio::test]
+async fn test_interrupt_round_trip_reports_failure_through_control_mock() {
+    use claude_agent::transport::{ControlMockTransport, MockTransport};
+
+    let transport = ControlMockTransport::new(MockTransport::new(vec![]))
+        .fail_with("interrupt", "No query in progress");
+    let agent = agent_with_control_mock(transport.clone()).await;
+
+    let response = tokio::time::timeout(std::time::Duration::from_secs(2), agent.interrupt())
+        .await
+        .expect("interrupt should be answered")
+        .expect("the round trip itself should succeed");
+    assert!(!response.success);
+    assert_eq!(response.error.as_deref(), Some("No query in progress"));
+    assert_eq!(transport.control_requests().last().unwrap()["request"]["subtype"], "interrupt");
+}
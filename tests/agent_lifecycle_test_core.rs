@@ -1,8 +1,13 @@
 //! Integration tests for agent lifecycle: connect, query, disconnect.
 
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
 use claude_agent::core::ClaudeAgent;
-use claude_agent::types::Message;
+use claude_agent::transport::Transport;
+use claude_agent::types::{ClaudeAgentError, Message};
 use claude_agent::ClaudeAgentOptions;
+use futures::stream::BoxStream;
 use futures::StreamExt;
 use serde_json::json;
 use tokio::time::{timeout, Duration};
@@ -180,3 +185,544 @@ async fn test_agent_get_server_info_after_init_message() {
     let server_info = info.unwrap();
     assert_eq!(server_info.get("output_style").and_then(|v| v.as_str()), Some("concise"));
 }
+
+#[tokio::test]
+async fn test_agent_get_server_info_from_top_level_init() {
+    let (agent, transport) = connected_agent().await;
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    transport
+        .push_incoming(json!({
+            "type": "system",
+            "subtype": "init",
+            "cwd": "/work/project",
+            "session_id": "sess-init",
+            "tools": ["Bash", "Read"],
+            "model": "claude-sonnet-4-5",
+            "apiKeySource": "none"
+        }))
+        .await;
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let info = agent.get_server_info().await.expect("server info after init");
+    assert_eq!(info.session_id.as_deref(), Some("sess-init"));
+    assert_eq!(info.model.as_deref(), Some("claude-sonnet-4-5"));
+    assert_eq!(info.tools, vec!["Bash", "Read"]);
+    assert_eq!(info.cwd.as_deref(), Some("/work/project"));
+    assert_eq!(info.api_key_source.as_deref(), Some("none"));
+
+    let raw = agent.get_server_info_raw().await.unwrap();
+    assert_eq!(raw["subtype"], "init");
+
+    let init = agent.system_init().expect("typed init");
+    assert_eq!(init.session_id.as_deref(), Some("sess-init"));
+    assert_eq!(init.tools, vec!["Bash", "Read"]);
+}
+
+/// Transport whose control-response writes are slow, recording write and close ordering.
+struct SlowWriteTransport {
+    inner: MockTransport,
+    events: Arc<Mutex<Vec<&'static str>>>,
+}
+
+#[async_trait]
+impl Transport for SlowWriteTransport {
+    async fn connect(&mut self) -> Result<(), ClaudeAgentError> {
+        Ok(())
+    }
+
+    async fn write(&self, message: &str) -> Result<(), ClaudeAgentError> {
+        if message.contains("control_response") {
+            self.events.lock().unwrap().push("write_start");
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            self.events.lock().unwrap().push("write_done");
+        }
+        self.inner.write(message).await
+    }
+
+    async fn read_messages(&self) -> BoxStream<'_, Result<serde_json::Value, ClaudeAgentError>> {
+        self.inner.read_messages().await
+    }
+
+    async fn close(&mut self) -> Result<(), ClaudeAgentError> {
+        self.events.lock().unwrap().push("close");
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn test_agent_disconnect_waits_for_in_flight_control_response() {
+    let inner = MockTransport::new();
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let transport = SlowWriteTransport { inner: inner.clone(), events: events.clone() };
+
+    let mut agent = ClaudeAgent::new(ClaudeAgentOptions::default());
+    agent.set_transport(Box::new(transport));
+    agent.connect(None).await.expect("Connect should succeed");
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    inner
+        .push_incoming(json!({
+            "type": "control_request",
+            "request_id": "req-slow",
+            "request": {"subtype": "unknown_subtype"}
+        }))
+        .await;
+
+    // Wait until the control loop is mid-write, then disconnect.
+    timeout(Duration::from_secs(2), async {
+        while !events.lock().unwrap().contains(&"write_start") {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+    })
+    .await
+    .expect("control response write should start");
+
+    agent.disconnect().await.expect("Disconnect should succeed");
+
+    assert_eq!(*events.lock().unwrap(), vec!["write_start", "write_done", "close"]);
+    let sent = inner.sent_messages.lock().unwrap();
+    assert!(sent.iter().any(|m| m.contains("req-slow")));
+}
+
+#[tokio::test]
+async fn test_query_handle_detach_still_records_session_stats() {
+    let (mut agent, transport) = connected_agent().await;
+    let mut handle = agent.query_handle("Hello").await.expect("Query should succeed");
+
+    let push_transport = transport.clone();
+    tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        push_transport
+            .push_incoming(json!({
+                "type": "assistant",
+                "message": {"model": "claude-sonnet-4-5", "content": [{"type": "text", "text": "Hi"}]}
+            }))
+            .await;
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        push_transport
+            .push_incoming(json!({
+                "type": "result",
+                "subtype": "success",
+                "duration_ms": 120,
+                "duration_api_ms": 80,
+                "is_error": false,
+                "num_turns": 1,
+                "session_id": "sess-1",
+                "total_cost_usd": 0.25
+            }))
+            .await;
+    });
+
+    let first = timeout(Duration::from_secs(2), handle.next()).await.unwrap().unwrap().unwrap();
+    assert!(matches!(first, Message::Assistant(_)));
+    assert_eq!(agent.session_stats().results, 0);
+
+    timeout(Duration::from_secs(2), handle.detach())
+        .await
+        .expect("detached drain should finish at the result")
+        .unwrap();
+
+    let stats = agent.session_stats();
+    assert_eq!(stats.results, 1);
+    assert_eq!(stats.num_turns, 1);
+    assert_eq!(stats.duration_ms, 120);
+    assert_eq!(stats.total_cost_usd, 0.25);
+}
+
+#[tokio::test]
+async fn test_query_handle_ends_after_result() {
+    let (mut agent, transport) = connected_agent().await;
+    let handle = agent.query_handle("Hello").await.expect("Query should succeed");
+
+    let push_transport = transport.clone();
+    tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        for id in ["sess-1", "sess-2"] {
+            push_transport
+                .push_incoming(json!({
+                    "type": "result",
+                    "subtype": "success",
+                    "duration_ms": 1,
+                    "duration_api_ms": 1,
+                    "is_error": false,
+                    "num_turns": 1,
+                    "session_id": id
+                }))
+                .await;
+        }
+    });
+
+    let messages: Vec<_> = timeout(Duration::from_secs(2), handle.collect()).await.unwrap();
+    assert_eq!(messages.len(), 1);
+}
+
+fn turn_output(text: &str, session_id: &str) -> Vec<serde_json::Value> {
+    vec![
+        json!({
+            "type": "assistant",
+            "message": {"model": "claude-sonnet-4-5", "content": [{"type": "text", "text": text}]}
+        }),
+        json!({
+            "type": "result",
+            "subtype": "success",
+            "duration_ms": 1,
+            "duration_api_ms": 1,
+            "is_error": false,
+            "num_turns": 1,
+            "session_id": session_id
+        }),
+    ]
+}
+
+fn texts_and_sessions(messages: Vec<Result<Message, ClaudeAgentError>>) -> Vec<String> {
+    messages
+        .into_iter()
+        .map(|message| match message.expect("message should parse") {
+            Message::Result(result) => format!("result:{}", result.session_id),
+            other => other.text().unwrap_or_default(),
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn test_overlapping_queries_each_see_only_their_turn() {
+    let transport = claude_agent::transport::MockTransport::with_turns(vec![
+        turn_output("first answer", "turn-1"),
+        turn_output("second answer", "turn-2"),
+    ]);
+    let mut agent = ClaudeAgent::new(ClaudeAgentOptions::default());
+    agent.set_transport(Box::new(transport.clone()));
+    agent.connect(None).await.expect("Connect should succeed");
+
+    let first = agent.query_handle("first").await.expect("first query should start");
+
+    // The second query queues behind the open first turn
+    assert!(timeout(Duration::from_millis(100), agent.query_handle("second")).await.is_err());
+    let prompts =
+        transport.sent_messages().iter().filter(|m| m.contains(r#""type":"user""#)).count();
+    assert_eq!(prompts, 1, "second prompt must wait for the first turn");
+
+    let first_turn = tokio::spawn(first.collect::<Vec<_>>());
+    let second = timeout(Duration::from_secs(2), agent.query_handle("second"))
+        .await
+        .expect("second query should start once the first turn ends")
+        .expect("second query should start");
+    let second_messages = timeout(Duration::from_secs(2), second.collect::<Vec<_>>())
+        .await
+        .expect("second turn should end at its result");
+    let first_messages = first_turn.await.unwrap();
+
+    assert_eq!(texts_and_sessions(first_messages), ["first answer", "result:turn-1"]);
+    assert_eq!(texts_and_sessions(second_messages), ["second answer", "result:turn-2"]);
+}
+
+#[tokio::test]
+async fn test_dropping_a_query_stream_mid_turn_drains_it_before_the_next_query() {
+    use claude_agent::transport::{ControlMockTransport, MockTransport};
+
+    // The first turn's result only arrives after its stream was dropped
+    let mut first_turn = turn_output("partial", "abandoned");
+    let late_result = first_turn.pop().unwrap();
+    let transport = ControlMockTransport::new(MockTransport::with_turns(vec![
+        first_turn,
+        turn_output("ok", "s"),
+    ]));
+    let mut agent = ClaudeAgent::new(ClaudeAgentOptions::default());
+    agent.set_transport(Box::new(transport.clone()));
+    agent.connect(None).await.expect("Connect should succeed");
+
+    let mut abandoned = agent.query_handle("abandoned").await.expect("query should start");
+    let first = abandoned.next().await.unwrap().unwrap();
+    assert_eq!(first.text().as_deref(), Some("partial"));
+    drop(abandoned);
+
+    let pusher = transport.clone();
+    tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        pusher.mock().push_incoming(late_result);
+    });
+    let handle = timeout(Duration::from_secs(2), agent.query_handle("next"))
+        .await
+        .expect("dropped stream should release the turn once drained")
+        .expect("query should start");
+    let messages = timeout(Duration::from_secs(2), handle.collect::<Vec<_>>()).await.unwrap();
+    assert_eq!(texts_and_sessions(messages), ["ok", "result:s"]);
+
+    let subtypes: Vec<_> = transport
+        .control_requests()
+        .iter()
+        .map(|request| request["request"]["subtype"].as_str().unwrap_or_default().to_string())
+        .collect();
+    assert!(subtypes.contains(&"interrupt".to_string()), "got {subtypes:?}");
+}
+
+#[tokio::test]
+async fn test_cancelled_query_with_restores_model_and_drains_its_turn() {
+    use claude_agent::transport::{ControlMockTransport, MockTransport};
+    use claude_agent::types::QueryOverrides;
+    use tokio_util::sync::CancellationToken;
+
+    let mut first_turn = turn_output("working", "cancelled");
+    let late_result = first_turn.pop().unwrap();
+    let transport = ControlMockTransport::new(MockTransport::with_turns(vec![
+        first_turn,
+        turn_output("ok", "s"),
+    ]));
+    let mut agent = ClaudeAgent::new(ClaudeAgentOptions {
+        model: Some("base-model".to_string()),
+        ..Default::default()
+    });
+    agent.set_transport(Box::new(transport.clone()));
+    agent.connect(None).await.expect("Connect should succeed");
+
+    let token = CancellationToken::new();
+    let overrides = QueryOverrides {
+        model: Some("turn-model".to_string()),
+        cancellation: Some(token.clone()),
+        ..Default::default()
+    };
+    let mut stream = agent.query_with("long task", overrides).await.expect("query should start");
+    assert_eq!(stream.next().await.unwrap().unwrap().text().as_deref(), Some("working"));
+    token.cancel();
+    assert!(timeout(Duration::from_secs(2), stream.next()).await.unwrap().is_none());
+    drop(stream);
+
+    let pusher = transport.clone();
+    tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        pusher.mock().push_incoming(late_result);
+    });
+    let handle = timeout(Duration::from_secs(2), agent.query_handle("next"))
+        .await
+        .expect("cancelled turn should be released once drained")
+        .expect("query should start");
+    let messages = timeout(Duration::from_secs(2), handle.collect::<Vec<_>>()).await.unwrap();
+    assert_eq!(texts_and_sessions(messages), ["ok", "result:s"]);
+
+    let requests: Vec<_> = transport
+        .control_requests()
+        .iter()
+        .map(|request| {
+            let request = &request["request"];
+            match request["subtype"].as_str().unwrap_or_default() {
+                "set_model" => format!("set_model:{}", request["model"].as_str().unwrap_or("")),
+                other => other.to_string(),
+            }
+        })
+        .collect();
+    assert_eq!(requests, ["set_model:turn-model", "interrupt", "set_model:base-model"]);
+}
+
+#[tokio::test]
+async fn test_double_connect_is_rejected() {
+    let (mut agent, _transport) = connected_agent().await;
+
+    let err = agent.connect(None).await.expect_err("second connect should fail");
+    assert!(
+        matches!(err, ClaudeAgentError::CLIConnection(ref msg) if msg.contains("Already connected"))
+    );
+    assert!(agent.is_connected().await, "first connection should be untouched");
+
+    agent.disconnect().await.expect("Disconnect should succeed");
+    agent.set_transport(Box::new(MockTransport::new()));
+    agent.connect(None).await.expect("reconnect after disconnect should succeed");
+    agent.disconnect().await.expect("Disconnect should succeed");
+}
+
+#[tokio::test]
+async fn test_double_disconnect_is_ok() {
+    let (mut agent, _transport) = connected_agent().await;
+    agent.disconnect().await.expect("first disconnect should succeed");
+    agent.disconnect().await.expect("second disconnect should succeed");
+    assert!(!agent.is_connected().await);
+
+    let mut never_connected = ClaudeAgent::new(ClaudeAgentOptions::default());
+    never_connected.disconnect().await.expect("disconnect without connect should succeed");
+}
+
+/// Emits `error` forever, or once followed by silence when `once` is set.
+struct ErroringTransport {
+    error: ClaudeAgentError,
+    once: bool,
+    reads: Arc<Mutex<usize>>,
+}
+
+#[async_trait]
+impl Transport for ErroringTransport {
+    async fn connect(&mut self) -> Result<(), ClaudeAgentError> {
+        Ok(())
+    }
+
+    async fn write(&self, _data: &str) -> Result<(), ClaudeAgentError> {
+        Ok(())
+    }
+
+    async fn read_messages(&self) -> BoxStream<'_, Result<serde_json::Value, ClaudeAgentError>> {
+        let errors = futures::stream::repeat_with(move || {
+            *self.reads.lock().unwrap() += 1;
+            Err(self.error.clone())
+        });
+        if self.once {
+            Box::pin(errors.take(1).chain(futures::stream::pending()))
+        } else {
+            Box::pin(errors)
+        }
+    }
+
+    async fn close(&mut self) -> Result<(), ClaudeAgentError> {
+        Ok(())
+    }
+}
+
+async fn wait_for_loop_exit(agent: &ClaudeAgent) -> ClaudeAgentError {
+    timeout(Duration::from_secs(2), async {
+        loop {
+            if let Some(reason) = agent.control_loop_error() {
+                return reason;
+            }
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+    })
+    .await
+    .expect("control loop should stop")
+}
+
+#[tokio::test]
+async fn test_control_loop_stops_after_repeated_read_errors() {
+    let reads = Arc::new(Mutex::new(0));
+    let mut agent = ClaudeAgent::new(ClaudeAgentOptions::default());
+    agent.set_transport(Box::new(ErroringTransport {
+        error: ClaudeAgentError::JSONDecode("garbage".to_string()),
+        once: false,
+        reads: reads.clone(),
+    }));
+    agent.connect(None).await.unwrap();
+
+    let reason = wait_for_loop_exit(&agent).await;
+    assert!(
+        matches!(&reason, ClaudeAgentError::Transport(msg) if msg.contains("16 consecutive read errors") && msg.contains("garbage")),
+        "got {reason}"
+    );
+
+    // The loop is gone, so the stream is no longer being polled
+    let settled = *reads.lock().unwrap();
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert_eq!(*reads.lock().unwrap(), settled);
+    assert_eq!(settled, 16);
+    agent.disconnect().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_control_loop_stops_on_fatal_read_error() {
+    let mut agent = ClaudeAgent::new(ClaudeAgentOptions::default());
+    agent.set_transport(Box::new(ErroringTransport {
+        error: ClaudeAgentError::Transport("stdout closed".to_string()),
+        once: true,
+        reads: Arc::new(Mutex::new(0)),
+    }));
+    agent.connect(None).await.unwrap();
+
+    let reason = wait_for_loop_exit(&agent).await;
+    assert!(matches!(&reason, ClaudeAgentError::Transport(msg) if msg == "stdout closed"));
+    agent.disconnect().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_control_loop_error_is_none_while_running() {
+    let (mut agent, _transport) = connected_agent().await;
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    assert!(agent.control_loop_error().is_none());
+    agent.disconnect().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_control_request_written_promptly_during_incoming_flood() {
+    let transport = claude_agent::transport::MockTransport::new(vec![]);
+    let mut agent = ClaudeAgent::new(ClaudeAgentOptions::default());
+    agent.set_transport(Box::new(transport.clone()));
+    agent.connect(None).await.unwrap();
+
+    // Keep the control loop busy with a steady stream of incoming messages
+    let flooding = Arc::new(std::sync::atomic::AtomicBool::new(true));
+    let flood = {
+        let transport = transport.clone();
+        let flooding = flooding.clone();
+        tokio::spawn(async move {
+            while flooding.load(std::sync::atomic::Ordering::SeqCst) {
+                for _ in 0..100 {
+                    transport.push_incoming(json!({"type": "system", "subtype": "status"}));
+                }
+                tokio::task::yield_now().await;
+            }
+        })
+    };
+    tokio::time::sleep(Duration::from_millis(20)).await;
+
+    // The CLI never answers, so only the write matters here
+    let _ = timeout(Duration::from_millis(300), agent.interrupt()).await;
+    flooding.store(false, std::sync::atomic::Ordering::SeqCst);
+    flood.await.unwrap();
+
+    let interrupt_written = transport.sent_messages().iter().any(|sent| {
+        serde_json::from_str::<serde_json::Value>(sent)
+            .is_ok_and(|value| value["request"]["subtype"] == "interrupt")
+    });
+    assert!(interrupt_written, "interrupt was not written while messages were flowing");
+    agent.disconnect().await.unwrap();
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_dropping_connected_agent_kills_the_cli() {
+    use std::os::unix::fs::PermissionsExt;
+
+    // A stand-in CLI that records its pid and ignores stdin
+    let dir = tempfile::tempdir().unwrap();
+    let pid_path = dir.path().join("pid");
+    let cli_path = dir.path().join("fake_cli");
+    let script = format!(
+        "#!/bin/sh\necho $$ > '{0}.tmp' && mv '{0}.tmp' '{0}'\nexec sleep 30\n",
+        pid_path.display()
+    );
+    std::fs::write(&cli_path, script).unwrap();
+    std::fs::set_permissions(&cli_path, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+    let mut agent = ClaudeAgent::new(ClaudeAgentOptions {
+        cli_path: Some(cli_path),
+        skip_version_check: true,
+        ..Default::default()
+    });
+    agent.connect(None).await.expect("Connect should succeed");
+    let pid = timeout(Duration::from_secs(5), async {
+        loop {
+            if let Ok(pid) = std::fs::read_to_string(&pid_path) {
+                return pid.trim().to_string();
+            }
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+    })
+    .await
+    .expect("fake CLI should start");
+
+    drop(agent);
+
+    // The killed CLI may linger as a zombie until the runtime reaps it
+    let running = || {
+        std::process::Command::new("ps")
+            .args(["-o", "stat=", "-p", &pid])
+            .output()
+            .map(|out| {
+                let stat = String::from_utf8_lossy(&out.stdout);
+                !stat.trim().is_empty() && !stat.trim().starts_with('Z')
+            })
+            .unwrap_or(false)
+    };
+    timeout(Duration::from_secs(2), async {
+        while running() {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+    })
+    .await
+    .expect("dropping a connected agent should kill its CLI");
+}
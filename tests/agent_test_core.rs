@@ -126,3 +126,28 @@ async fn test_agent_connects_and_queries() {
         "Should receive the assistant response"
     );
 }
+
+#[tokio::test]
+async fn test_responses_listens_before_first_poll() {
+    let mut agent = ClaudeAgent::new(ClaudeAgentOptions::default());
+    let transport = MockTransport::new();
+    let feeder = transport.clone();
+    agent.set_transport(Box::new(transport));
+    agent.connect(None).await.expect("Connect failed");
+
+    let responses = agent.responses().await.expect("responses failed");
+    agent.send("Hello").await.expect("send failed");
+    feeder
+        .push_incoming(json!({
+            "type": "assistant",
+            "message": {"content": [{"type": "text", "text": "early reply"}], "role": "assistant", "model": "m"}
+        }))
+        .await;
+
+    let first = tokio::time::timeout(std::time::Duration::from_secs(2), responses.take(1).next())
+        .await
+        .expect("reply pushed before the first poll was lost")
+        .expect("stream ended")
+        .expect("message error");
+    assert_eq!(first.text().as_deref(), Some("early reply"));
+}
@@ -86,3 +86,106 @@ async fn test_split_packet() {
     // But logically, if `poll_read` returns partial, `MessageReader` buffers it?
     // Yes.
 }
+
+#[tokio::test]
+async fn test_garbage_line_is_skipped() {
+    let data = format!("{}\nnot json at all\n{}\n", json!({"id": 1}), json!({"id": 2}));
+    let mut stream = MessageReader::new(Cursor::new(data.into_bytes()));
+
+    assert_eq!(stream.next().await.unwrap().unwrap()["id"], 1);
+    let err = stream.next().await.unwrap().unwrap_err();
+    assert!(!err.is_fatal(), "parse errors should be recoverable: {err}");
+    assert_eq!(stream.next().await.unwrap().unwrap()["id"], 2);
+    assert!(stream.next().await.is_none());
+}
+
+#[tokio::test]
+async fn test_multi_megabyte_message_parses_in_linear_time() {
+    use tokio::io::AsyncWriteExt;
+
+    let payload = "x".repeat(4 * 1024 * 1024);
+    let line =
+        format!("{}\n{}\n", json!({"type": "tool_result", "data": payload}), json!({"id": 2}));
+
+    // A small pipe forces the message to arrive over hundreds of reads
+    let (mut writer, reader) = tokio::io::duplex(16 * 1024);
+    let feeder = tokio::spawn(async move {
+        writer.write_all(line.as_bytes()).await.unwrap();
+    });
+
+    let started = std::time::Instant::now();
+    let mut stream = MessageReader::with_capacity(reader, 8 * 1024 * 1024);
+    let first = stream.next().await.unwrap().unwrap();
+    assert_eq!(first["data"].as_str().map(str::len), Some(4 * 1024 * 1024));
+    assert_eq!(stream.next().await.unwrap().unwrap()["id"], 2);
+    assert!(stream.next().await.is_none());
+    feeder.await.unwrap();
+
+    // Re-parsing the growing buffer on each read takes minutes at this size
+    assert!(
+        started.elapsed() < std::time::Duration::from_secs(10),
+        "took {:?}",
+        started.elapsed()
+    );
+}
+
+#[tokio::test]
+async fn test_custom_chunk_size_handles_tiny_reads() {
+    let data =
+        format!("{}\n{}", json!({"text": "a \"quoted\" } brace"}), json!([1, [2, {"k": "]"}]]));
+    let mut stream = MessageReader::new(Cursor::new(data.into_bytes())).with_chunk_size(3);
+
+    assert_eq!(stream.next().await.unwrap().unwrap()["text"], "a \"quoted\" } brace");
+    assert_eq!(stream.next().await.unwrap().unwrap(), json!([1, [2, {"k": "]"}]]));
+    assert!(stream.next().await.is_none());
+}
+
+#[tokio::test]
+async fn test_invalid_trailing_data_at_eof_is_reported_once() {
+    let data = format!("{}\n{{\"id\": ", json!({"id": 1}));
+    let mut stream = MessageReader::new(Cursor::new(data.into_bytes()));
+
+    assert_eq!(stream.next().await.unwrap().unwrap()["id"], 1);
+    assert!(stream.next().await.unwrap().is_err());
+    assert!(stream.next().await.is_none());
+}
+
+#[tokio::test]
+async fn test_multibyte_characters_split_across_reads() {
+    let text = "héllo wörld 😀 日本語";
+    let data = format!("{}\n", json!({ "text": text }));
+    let mut stream = MessageReader::new(Cursor::new(data.into_bytes())).with_chunk_size(1);
+
+    assert_eq!(stream.next().await.unwrap().unwrap()["text"], text);
+    assert!(stream.next().await.is_none());
+}
+
+#[tokio::test]
+async fn test_pure_ndjson_stream() {
+    let data: String = (0..50).map(|i| format!("{}\n", json!({"type": "tick", "n": i}))).collect();
+    let mut stream = MessageReader::new(Cursor::new(data.into_bytes()));
+
+    for i in 0..50 {
+        let message = stream.next().await.unwrap().unwrap();
+        assert_eq!(message, json!({"type": "tick", "n": i}));
+    }
+    assert!(stream.next().await.is_none());
+}
+
+#[tokio::test]
+async fn test_object_spanning_two_lines_between_ndjson_lines() {
+    let data = format!(
+        "{}\n{{\"type\": \"assistant\",\n\"text\": \"split\"}}\n{}\n",
+        json!({"id": 1}),
+        json!({"id": 2})
+    );
+    let mut stream = MessageReader::new(Cursor::new(data.into_bytes()));
+
+    assert_eq!(stream.next().await.unwrap().unwrap()["id"], 1);
+    assert_eq!(
+        stream.next().await.unwrap().unwrap(),
+        json!({"type": "assistant", "text": "split"})
+    );
+    assert_eq!(stream.next().await.unwrap().unwrap()["id"], 2);
+    assert!(stream.next().await.is_none());
+}
@@ -42,6 +42,10 @@ impl Transport for MockTransport {
                     "response": {}
                 });
                 let _ = self.tx.send(Ok(resp));
+                // Like the CLI, end the interrupted turn with a result
+                if val["request"]["subtype"] == "interrupt" {
+                    let _ = self.tx.send(Ok(interrupted_result()));
+                }
             }
         }
         Ok(())
@@ -167,3 +171,1315 @@ async fn test_client_control_methods() {
         assert_eq!(json["request"]["mode"], "plan");
     }
 }
+
+#[tokio::test]
+async fn test_client_for_testing_runs_offline_query() {
+    let mut client = ClaudeAgentClient::for_testing(vec![
+        json!({
+            "type": "assistant",
+            "message": {"model": "claude-sonnet-4-5", "content": [{"type": "text", "text": "4"}]}
+        }),
+        json!({
+            "type": "result",
+            "subtype": "success",
+            "duration_ms": 10,
+            "duration_api_ms": 5,
+            "is_error": false,
+            "num_turns": 1,
+            "session_id": "test-session"
+        }),
+    ]);
+    client.connect().await.unwrap();
+
+    let mut messages = Vec::new();
+    {
+        let mut stream = client.query("What is 2+2?").await.unwrap();
+        while let Some(message) =
+            tokio::time::timeout(std::time::Duration::from_secs(2), stream.next()).await.unwrap()
+        {
+            let message = message.unwrap();
+            let done = matches!(message, Message::Result(_));
+            messages.push(message);
+            if done {
+                break;
+            }
+        }
+    }
+
+    assert_eq!(messages.len(), 2);
+    match &messages[0] {
+        Message::Assistant(assistant) => match &assistant.content[0] {
+            ContentBlock::Text(text) => assert_eq!(text.text, "4"),
+            other => panic!("expected text block, got {:?}", other),
+        },
+        other => panic!("expected assistant message, got {:?}", other),
+    }
+    // The control loop records the CLI's session id alongside the query stream
+    tokio::time::timeout(std::time::Duration::from_secs(2), async {
+        while client.session_id().is_none() {
+            tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+        }
+    })
+    .await
+    .expect("session id from the result message");
+    assert_eq!(client.session_id().as_deref(), Some("test-session"));
+    client.disconnect().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_client_session_id_from_init_message() {
+    let transport = claude_agent::transport::MockTransport::new(vec![]);
+    let mut client = ClaudeAgentClient::new(None);
+    client.set_transport(Box::new(transport.clone()));
+    client.connect().await.unwrap();
+    assert!(client.session_id().is_none(), "the local session id is not reported");
+
+    tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    transport.push_incoming(json!({
+        "type": "system",
+        "subtype": "init",
+        "session_id": "cli-session-42",
+        "model": "claude-sonnet-4-5"
+    }));
+    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+
+    assert_eq!(client.session_id().as_deref(), Some("cli-session-42"));
+    client.disconnect().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_client_is_connected_tracks_lifecycle() {
+    let mut client = ClaudeAgentClient::for_testing(vec![]);
+    assert!(!client.is_connected().await);
+
+    client.connect().await.unwrap();
+    assert!(client.is_connected().await);
+
+    client.disconnect().await.unwrap();
+    assert!(!client.is_connected().await);
+}
+
+#[tokio::test]
+async fn test_client_rate_limiter_rejects_rapid_queries() {
+    use claude_agent::mcp::{RateLimitConfig, RateLimiter};
+
+    let mut client = ClaudeAgentClient::for_testing(vec![success_result()])
+        .with_rate_limiter(RateLimiter::new(RateLimitConfig::per_second(1)));
+    client.connect().await.unwrap();
+
+    drop(client.query("first").await.unwrap());
+    let error = client.query("second").await.err().expect("second query should be rate limited");
+    assert!(matches!(error, ClaudeAgentError::RateLimited { retry_after: Some(_) }));
+}
+
+#[tokio::test]
+async fn test_client_rate_limiter_waits_for_permit() {
+    use claude_agent::mcp::RateLimitConfig;
+
+    let options = ClaudeAgentOptions {
+        query_rate_limit: Some(RateLimitConfig::per_second(20).waiting()),
+        ..Default::default()
+    };
+    let mut client = ClaudeAgentClient::new(Some(options));
+    client.set_transport(Box::new(claude_agent::transport::MockTransport::new(vec![])));
+    client.connect().await.unwrap();
+
+    let start = std::time::Instant::now();
+    for prompt in ["one", "two", "three"] {
+        drop(client.query(prompt).await.unwrap());
+    }
+    // The first permit is immediate; the next two are spaced 50ms apart.
+    assert!(start.elapsed() >= std::time::Duration::from_millis(90));
+}
+
+fn control_subtypes_and_prompts(sent: &[String]) -> Vec<String> {
+    sent.iter()
+        .filter_map(|data| serde_json::from_str::<serde_json::Value>(data).ok())
+        .filter_map(|value| match value["type"].as_str() {
+            Some("control_request") => value["request"]["subtype"].as_str().map(str::to_string),
+            Some("user") => Some("prompt".to_string()),
+            _ => None,
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn test_client_query_with_model_override_sets_model_first() {
+    use claude_agent::types::QueryOverrides;
+
+    let result = json!({
+        "type": "result",
+        "subtype": "success",
+        "duration_ms": 10,
+        "duration_api_ms": 5,
+        "is_error": false,
+        "num_turns": 1,
+        "session_id": "s1"
+    });
+    let mock_transport = MockTransport::new(vec![result]);
+    let sent_data = mock_transport.sent_data.clone();
+    let mut client = ClaudeAgentClient::new(Some(ClaudeAgentOptions {
+        model: Some("claude-base".to_string()),
+        ..Default::default()
+    }));
+    client.set_transport(Box::new(mock_transport));
+
+    let overrides =
+        QueryOverrides { model: Some("claude-override".to_string()), ..Default::default() };
+    let mut stream = client.query_with("hi", overrides).await.expect("query_with failed");
+    while let Some(message) = stream.next().await {
+        if matches!(message, Ok(Message::Result(_))) {
+            break;
+        }
+    }
+    drop(stream);
+
+    let sent = sent_data.lock().unwrap().clone();
+    assert_eq!(control_subtypes_and_prompts(&sent), vec!["set_model", "prompt", "set_model"]);
+    let models: Vec<serde_json::Value> = sent
+        .iter()
+        .filter_map(|data| serde_json::from_str::<serde_json::Value>(data).ok())
+        .filter(|value| value["request"]["subtype"] == "set_model")
+        .map(|value| value["request"]["model"].clone())
+        .collect();
+    assert_eq!(models, vec![json!("claude-override"), json!("claude-base")]);
+}
+
+#[tokio::test]
+async fn test_client_query_with_permission_mode_override() {
+    use claude_agent::types::config::PermissionMode;
+    use claude_agent::types::QueryOverrides;
+
+    let mock_transport = MockTransport::new(vec![]);
+    let sent_data = mock_transport.sent_data.clone();
+    let mut client = ClaudeAgentClient::new(None);
+    client.set_transport(Box::new(mock_transport));
+    client.connect().await.unwrap();
+
+    let overrides =
+        QueryOverrides { permission_mode: Some(PermissionMode::Plan), ..Default::default() };
+    drop(client.query_with("plan it", overrides).await.expect("query_with failed"));
+
+    let sent = sent_data.lock().unwrap().clone();
+    let subtypes = control_subtypes_and_prompts(&sent);
+    assert_eq!(subtypes[subtypes.len() - 2..], ["set_permission_mode", "prompt"]);
+    assert!(sent.iter().any(|data| data.contains(r#""mode":"plan""#)));
+}
+
+#[tokio::test]
+async fn test_client_query_with_reverts_model_when_a_later_override_fails() {
+    use claude_agent::transport::{ControlMockTransport, ControlReply, MockTransport};
+    use claude_agent::types::config::PermissionMode;
+    use claude_agent::types::QueryOverrides;
+
+    let transport = ControlMockTransport::new(MockTransport::new(vec![success_result()]));
+    transport.set_reply("set_permission_mode", ControlReply::Error("not allowed".to_string()));
+    let mut client = ClaudeAgentClient::new(Some(ClaudeAgentOptions {
+        model: Some("claude-base".to_string()),
+        ..Default::default()
+    }));
+    client.set_transport(Box::new(transport.clone()));
+    client.connect().await.unwrap();
+
+    let overrides = QueryOverrides {
+        model: Some("claude-override".to_string()),
+        permission_mode: Some(PermissionMode::Plan),
+        ..Default::default()
+    };
+    assert!(client.query_with("hi", overrides).await.is_err());
+
+    let models: Vec<_> = transport
+        .control_requests()
+        .iter()
+        .filter(|request| request["request"]["subtype"] == "set_model")
+        .map(|request| request["request"]["model"].clone())
+        .collect();
+    assert_eq!(models, [json!("claude-override"), json!("claude-base")]);
+    assert!(transport.mock().sent_messages().iter().all(|m| !m.contains(r#""type":"user""#)));
+}
+
+fn assistant_text_message(text: &str) -> serde_json::Value {
+    json!({
+        "type": "assistant",
+        "message": {"content": [{"type": "text", "text": text}], "role": "assistant", "model": "m"}
+    })
+}
+
+fn interrupted_result() -> serde_json::Value {
+    json!({
+        "type": "result",
+        "subtype": "error_during_execution",
+        "duration_ms": 1,
+        "duration_api_ms": 1,
+        "is_error": true,
+        "num_turns": 1,
+        "session_id": "s1"
+    })
+}
+
+fn success_result() -> serde_json::Value {
+    json!({
+        "type": "result",
+        "subtype": "success",
+        "duration_ms": 10,
+        "duration_api_ms": 5,
+        "is_error": false,
+        "num_turns": 1,
+        "session_id": "s1"
+    })
+}
+
+#[tokio::test]
+async fn test_client_text_stream_yields_text_until_result() {
+    let tool_only = json!({
+        "type": "assistant",
+        "message": {
+            "content": [{"type": "tool_use", "id": "t1", "name": "Read", "input": {}}],
+            "role": "assistant",
+            "model": "m"
+        }
+    });
+    let mock_transport = MockTransport::new(vec![
+        assistant_text_message("first"),
+        tool_only,
+        assistant_text_message("second"),
+        success_result(),
+        assistant_text_message("after result"),
+    ]);
+    let mut client = ClaudeAgentClient::new(None);
+    client.set_transport(Box::new(mock_transport));
+    client.connect().await.unwrap();
+
+    let texts: Vec<String> = client
+        .text_stream("hi")
+        .await
+        .expect("text_stream failed")
+        .map(|text| text.expect("text chunk"))
+        .collect()
+        .await;
+    assert_eq!(texts, vec!["first", "second"]);
+}
+
+async fn client_replaying(responses: Vec<serde_json::Value>) -> ClaudeAgentClient {
+    let mut client = ClaudeAgentClient::new(None);
+    client.set_transport(Box::new(MockTransport::new(responses)));
+    client.connect().await.unwrap();
+    client
+}
+
+#[tokio::test]
+async fn test_client_collect_text_success() {
+    let mut client =
+        client_replaying(vec![assistant_text_message("Hello"), success_result()]).await;
+    assert_eq!(client.collect_text("hi").await.unwrap(), "Hello");
+}
+
+#[tokio::test]
+async fn test_client_collect_text_concatenates_blocks_and_messages() {
+    let multi_block = json!({
+        "type": "assistant",
+        "message": {
+            "content": [{"type": "text", "text": "one "}, {"type": "text", "text": "two "}],
+            "role": "assistant",
+            "model": "m"
+        }
+    });
+    let mut client =
+        client_replaying(vec![multi_block, assistant_text_message("three"), success_result()])
+            .await;
+    assert_eq!(client.collect_text("count").await.unwrap(), "one two three");
+}
+
+#[tokio::test]
+async fn test_client_collect_text_error_event_mid_stream() {
+    let error = json!({
+        "type": "error",
+        "error": {"type": "overloaded_error", "message": "Overloaded"}
+    });
+    let mut client = client_replaying(vec![
+        assistant_text_message("partial"),
+        error,
+        assistant_text_message("ignored"),
+        success_result(),
+    ])
+    .await;
+    let err = client.collect_text("hi").await.unwrap_err();
+    assert!(matches!(err, ClaudeAgentError::Process(ref msg) if msg.contains("Overloaded")));
+}
+
+#[tokio::test]
+async fn test_client_collect_text_interrupts_a_failed_turn() {
+    let error = json!({
+        "type": "error",
+        "error": {"type": "overloaded_error", "message": "Overloaded"}
+    });
+    let mock_transport = MockTransport::new(vec![assistant_text_message("partial"), error]);
+    let sent_data = mock_transport.sent_data.clone();
+    let mut client = ClaudeAgentClient::new(None);
+    client.set_transport(Box::new(mock_transport));
+    client.connect().await.unwrap();
+
+    assert!(client.collect_text("hi").await.is_err());
+    let subtypes = control_subtypes_and_prompts(&sent_data.lock().unwrap());
+    assert_eq!(subtypes[subtypes.len() - 2..], ["prompt", "interrupt"]);
+}
+
+#[tokio::test]
+async fn test_client_collect_text_error_result() {
+    let mut result = success_result();
+    result["subtype"] = json!("error_max_turns");
+    result["is_error"] = json!(true);
+    let mut client = client_replaying(vec![assistant_text_message("partial"), result]).await;
+    let err = client.collect_text("hi").await.unwrap_err();
+    assert!(matches!(err, ClaudeAgentError::Process(ref msg) if msg.contains("error_max_turns")));
+}
+
+#[tokio::test]
+async fn test_client_query_maps_rate_limit_error_event() {
+    let rate_limited = json!({
+        "type": "error",
+        "error": {"type": "rate_limit_error", "message": "Too many requests", "retry_after": 30}
+    });
+    let mut client = client_replaying(vec![rate_limited]).await;
+    let mut stream = client.query("hi").await.unwrap();
+    let err = stream.next().await.expect("an item").unwrap_err();
+    assert!(matches!(
+        err,
+        ClaudeAgentError::RateLimited { retry_after: Some(d) } if d == std::time::Duration::from_secs(30)
+    ));
+}
+
+#[tokio::test]
+async fn test_client_query_maps_rate_limited_assistant_message() {
+    let rate_limited = json!({
+        "type": "assistant",
+        "message": {
+            "content": [{"type": "text", "text": "API Error: 429"}],
+            "role": "assistant",
+            "model": "m"
+        },
+        "error": "rate_limit",
+        "retry_after_ms": 1500
+    });
+    let mut client = client_replaying(vec![rate_limited, success_result()]).await;
+    let err = client.collect_text("hi").await.unwrap_err();
+    assert!(matches!(
+        err,
+        ClaudeAgentError::RateLimited { retry_after: Some(d) } if d == std::time::Duration::from_millis(1500)
+    ));
+}
+
+#[tokio::test]
+async fn test_client_query_rate_limit_without_retry_after() {
+    let rate_limited = json!({
+        "type": "error",
+        "error": {"type": "rate_limit_error", "message": "Too many requests"}
+    });
+    let mut client = client_replaying(vec![rate_limited]).await;
+    let mut stream = client.query("hi").await.unwrap();
+    let err = stream.next().await.expect("an item").unwrap_err();
+    assert!(matches!(err, ClaudeAgentError::RateLimited { retry_after: None }));
+}
+
+#[tokio::test]
+async fn test_client_query_maps_authentication_failed_assistant_message() {
+    let auth_failed = json!({
+        "type": "assistant",
+        "message": {
+            "content": [{"type": "text", "text": "Invalid API key · Please run /login"}],
+            "role": "assistant",
+            "model": "<synthetic>"
+        },
+        "error": "authentication_failed"
+    });
+    let mut client = client_replaying(vec![auth_failed, success_result()]).await;
+    let mut stream = client.query("hi").await.unwrap();
+
+    let err = stream.next().await.expect("an item").unwrap_err();
+    assert!(
+        matches!(err, ClaudeAgentError::Authentication(ref detail) if detail.contains("Invalid API key"))
+    );
+    assert!(stream.next().await.is_none(), "authentication failures end the stream");
+}
+
+#[tokio::test]
+async fn test_client_query_maps_authentication_error_event() {
+    let auth_error = json!({
+        "type": "error",
+        "error": {"type": "authentication_error", "message": "invalid x-api-key"}
+    });
+    let mut client = client_replaying(vec![auth_error]).await;
+    let err = client.collect_text("hi").await.unwrap_err();
+    assert!(
+        matches!(err, ClaudeAgentError::Authentication(ref detail) if detail == "invalid x-api-key")
+    );
+}
+
+#[tokio::test]
+async fn test_client_query_with_cancellation_interrupts_and_ends_stream() {
+    use claude_agent::types::QueryOverrides;
+    use tokio_util::sync::CancellationToken;
+
+    let mock_transport = MockTransport::new(vec![assistant_text_message("working on it")]);
+    let sent_data = mock_transport.sent_data.clone();
+    let mut client = ClaudeAgentClient::new(None);
+    client.set_transport(Box::new(mock_transport));
+    client.connect().await.unwrap();
+
+    let token = CancellationToken::new();
+    let overrides = QueryOverrides { cancellation: Some(token.clone()), ..Default::default() };
+    let mut stream = client.query_with("long task", overrides).await.unwrap();
+
+    let first = stream.next().await.unwrap().unwrap();
+    assert_eq!(first.text().as_deref(), Some("working on it"));
+
+    token.cancel();
+    let ended = tokio::time::timeout(std::time::Duration::from_secs(2), stream.next()).await;
+    assert!(matches!(ended, Ok(None)), "stream should end after cancellation");
+    drop(stream);
+
+    let sent = sent_data.lock().unwrap().clone();
+    let subtypes = control_subtypes_and_prompts(&sent);
+    assert_eq!(subtypes[subtypes.len() - 2..], ["prompt", "interrupt"]);
+}
+
+fn max_turns_result() -> serde_json::Value {
+    let mut result = success_result();
+    result["subtype"] = json!("error_max_turns");
+    result["is_error"] = json!(true);
+    result["result"] = json!("Reached maximum number of turns (1)");
+    result
+}
+
+#[tokio::test]
+async fn test_client_error_result_is_a_message_by_default() {
+    let mut client = client_replaying(vec![max_turns_result()]).await;
+    let mut stream = client.query("hi").await.unwrap();
+    match stream.next().await {
+        Some(Ok(Message::Result(result))) => {
+            assert!(result.is_error);
+            assert_eq!(result.subtype, "error_max_turns");
+        },
+        other => panic!("expected an error result message, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn test_client_error_on_result_failure_yields_result_error() {
+    let mut client = ClaudeAgentClient::new(Some(ClaudeAgentOptions {
+        error_on_result_failure: true,
+        ..Default::default()
+    }));
+    client.set_transport(Box::new(MockTransport::new(vec![
+        assistant_text_message("partial"),
+        max_turns_result(),
+    ])));
+    client.connect().await.unwrap();
+
+    let mut stream = client.query("hi").await.unwrap();
+    assert!(stream.next().await.unwrap().is_ok());
+    let err = stream.next().await.unwrap().unwrap_err();
+    assert!(matches!(
+        err,
+        ClaudeAgentError::Result { ref subtype, ref message }
+            if subtype == "error_max_turns" && message.contains("maximum number of turns")
+    ));
+    assert!(stream.next().await.is_none());
+}
+
+#[tokio::test]
+async fn test_client_error_on_result_failure_keeps_successful_results() {
+    let mut client = ClaudeAgentClient::new(Some(ClaudeAgentOptions {
+        error_on_result_failure: true,
+        ..Default::default()
+    }));
+    client.set_transport(Box::new(MockTransport::new(vec![success_result()])));
+    client.connect().await.unwrap();
+
+    let mut stream = client.query("hi").await.unwrap();
+    assert!(matches!(stream.next().await, Some(Ok(Message::Result(_)))));
+}
+
+#[tokio::test]
+async fn test_client_query_with_timeout_fast_stream_completes() {
+    let mut client =
+        client_replaying(vec![assistant_text_message("quick"), success_result()]).await;
+    let mut stream =
+        client.query_with_timeout("hi", std::time::Duration::from_secs(5)).await.unwrap();
+
+    let first = stream.next().await.unwrap().unwrap();
+    assert_eq!(first.text().as_deref(), Some("quick"));
+    assert!(matches!(stream.next().await, Some(Ok(Message::Result(_)))));
+}
+
+#[tokio::test]
+async fn test_client_query_with_timeout_stalled_stream_errors() {
+    let mut client = client_replaying(vec![assistant_text_message("then silence")]).await;
+    let idle = std::time::Duration::from_millis(50);
+    let mut stream = client.query_with_timeout("hi", idle).await.unwrap();
+
+    assert!(stream.next().await.unwrap().is_ok());
+    let err = stream.next().await.unwrap().unwrap_err();
+    assert!(matches!(err, ClaudeAgentError::Timeout(d) if d == idle));
+    assert!(stream.next().await.is_none(), "stream should end after the timeout");
+}
+
+#[tokio::test]
+async fn test_client_query_with_timeout_interrupts_before_reporting() {
+    let mock_transport = MockTransport::new(vec![assistant_text_message("then silence")]);
+    let sent_data = mock_transport.sent_data.clone();
+    let mut client = ClaudeAgentClient::new(None);
+    client.set_transport(Box::new(mock_transport));
+    client.connect().await.unwrap();
+
+    let idle = std::time::Duration::from_millis(50);
+    let mut stream = client.query_with_timeout("hi", idle).await.unwrap();
+    assert!(stream.next().await.unwrap().is_ok());
+    assert!(matches!(stream.next().await, Some(Err(ClaudeAgentError::Timeout(_)))));
+
+    let subtypes = control_subtypes_and_prompts(&sent_data.lock().unwrap());
+    assert_eq!(subtypes[subtypes.len() - 2..], ["prompt", "interrupt"]);
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_client_reconnect_resumes_session_after_eof() {
+    // A stand-in CLI that records its arguments, answers one prompt and exits
+    let dir = tempfile::tempdir().unwrap();
+    let args_path = dir.path().join("args.log");
+    let result_line = success_result().to_string();
+    let script = format!(
+        "echo \"$@\" >> '{}'\nwhile IFS= read -r line; do\n  case \"$line\" in *'\"type\":\"user\"'*) printf '%s\\n' '{}'; exit 0 ;; esac\ndone\n",
+        args_path.display(),
+        result_line
+    );
+    let cli_path = script_cli(dir.path(), &script);
+
+    let mut client = ClaudeAgentClient::new(Some(ClaudeAgentOptions {
+        cli_path: Some(cli_path),
+        skip_version_check: true,
+        ..Default::default()
+    }));
+    client.connect().await.unwrap();
+    let first: Vec<_> = client.query("first").await.unwrap().collect().await;
+    assert!(first.iter().all(|item| item.is_ok()));
+    // The control loop records the CLI's session id, then sees the CLI exit
+    tokio::time::timeout(std::time::Duration::from_secs(5), async {
+        while client.session_id().as_deref() != Some("s1") || client.is_connected().await {
+            tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+        }
+    })
+    .await
+    .expect("session id from the result message, then EOF");
+
+    client.reconnect().await.unwrap();
+    assert!(client.is_connected().await);
+    let second: Vec<_> = client.query("second").await.unwrap().collect().await;
+    assert!(matches!(second.last(), Some(Ok(Message::Result(_)))), "got {:?}", second);
+
+    let args = std::fs::read_to_string(&args_path).unwrap();
+    let relaunch = args.lines().last().unwrap();
+    assert!(relaunch.contains("--resume s1"), "args: {relaunch}");
+    assert!(!relaunch.contains("--continue"), "args: {relaunch}");
+    client.disconnect().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_client_reconnect_rejects_custom_transport() {
+    let mut client = ClaudeAgentClient::new(None);
+    client.set_transport(Box::new(claude_agent::transport::MockTransport::new(vec![])));
+    client.connect().await.unwrap();
+
+    let err = client.reconnect().await.unwrap_err();
+    assert!(matches!(err, ClaudeAgentError::Config(ref msg) if msg.contains("set_transport")));
+    assert!(client.is_connected().await);
+    client.disconnect().await.unwrap();
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_client_add_mcp_server_reaches_manager_and_cli_config() {
+    use claude_agent::mcp::SdkMcpServer;
+    use claude_agent::types::config::{McpServerConfig, McpTransportType};
+    // A stand-in CLI that records its arguments, one per line
+    let dir = tempfile::tempdir().unwrap();
+    let args_path = dir.path().join("args.log");
+    let script = format!(
+        "for arg in \"$@\"; do printf '%s\\n' \"$arg\" >> '{0}.tmp'; done\nmv '{0}.tmp' '{0}'\ncat > /dev/null\n",
+        args_path.display()
+    );
+    let cli_path = script_cli(dir.path(), &script);
+
+    let mut client = ClaudeAgentClient::new(Some(ClaudeAgentOptions {
+        cli_path: Some(cli_path),
+        ..Default::default()
+    }));
+
+    let mut calc = SdkMcpServer::new("calc");
+    calc.register_tool(
+        "add",
+        Some("Add two numbers".to_string()),
+        json!({"type": "object"}),
+        |_| Box::pin(async { Ok(json!({"content": []})) }),
+    );
+    client.add_mcp_server(Box::new(calc)).await.unwrap();
+
+    let tools = client.mcp_manager().list_all_tools().await.unwrap();
+    assert!(tools.iter().any(|(server, tool)| server == "calc" && tool.name == "add"));
+
+    let files = McpServerConfig {
+        transport: McpTransportType::Stdio,
+        command: Some("files-server".to_string()),
+        args: vec!["--root".to_string(), "/tmp".to_string()],
+        ..Default::default()
+    };
+    client.add_mcp_server_from_config("files", files).await.unwrap();
+    assert!(client.mcp_manager().get("files").await.is_some());
+
+    client.connect().await.unwrap();
+    let args = tokio::time::timeout(std::time::Duration::from_secs(5), async {
+        loop {
+            let args = std::fs::read_to_string(&args_path).unwrap_or_default();
+            if args.contains("--mcp-config") {
+                return args;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("CLI should be spawned with --mcp-config");
+    client.disconnect().await.unwrap();
+
+    let lines: Vec<&str> = args.lines().collect();
+    let position = lines.iter().position(|arg| *arg == "--mcp-config").unwrap();
+    let config: serde_json::Value = serde_json::from_str(lines[position + 1]).unwrap();
+    assert_eq!(config["mcpServers"]["calc"], json!({"type": "sdk", "name": "calc"}));
+    assert_eq!(config["mcpServers"]["files"]["type"], "stdio");
+    assert_eq!(config["mcpServers"]["files"]["command"], "files-server");
+    assert_eq!(config["mcpServers"]["files"]["args"], json!(["--root", "/tmp"]));
+}
+
+#[tokio::test]
+async fn test_client_query_blocks_writes_block_array() {
+    use claude_agent::types::message::{
+        ImageBlock, ImageSource, TextBlock, ToolResultBlock, ToolResultContent,
+    };
+
+    let mock_transport = MockTransport::new(vec![success_result()]);
+    let sent_data = mock_transport.sent_data.clone();
+    let mut client = ClaudeAgentClient::new(None);
+    client.set_transport(Box::new(mock_transport));
+    client.connect().await.unwrap();
+
+    let blocks = vec![
+        ContentBlock::Text(TextBlock { text: "What is in this image?".to_string() }),
+        ContentBlock::Image(ImageBlock {
+            source: ImageSource::Base64 {
+                media_type: "image/png".to_string(),
+                data: "iVBORw0KGgo=".to_string(),
+            },
+        }),
+        ContentBlock::ToolResult(ToolResultBlock {
+            tool_use_id: "toolu_1".to_string(),
+            content: Some(ToolResultContent::Text("42".to_string())),
+            is_error: None,
+        }),
+    ];
+    let messages: Vec<_> = client.query_blocks(blocks).await.unwrap().collect().await;
+    assert!(matches!(messages.last(), Some(Ok(Message::Result(_)))));
+
+    let sent = sent_data.lock().unwrap();
+    let user: serde_json::Value = sent
+        .iter()
+        .filter_map(|data| serde_json::from_str::<serde_json::Value>(data).ok())
+        .find(|value| value["type"] == "user")
+        .expect("a user message is written");
+    assert_eq!(user["message"]["role"], "user");
+    assert_eq!(
+        user["message"]["content"],
+        json!([
+            {"type": "text", "text": "What is in this image?"},
+            {"type": "image", "source": {"type": "base64", "media_type": "image/png", "data": "iVBORw0KGgo="}},
+            {"type": "tool_result", "tool_use_id": "toolu_1", "content": "42"}
+        ])
+    );
+}
+
+#[tokio::test]
+async fn test_client_submit_tool_result_wire_shape() {
+    use claude_agent::types::message::ToolResultContent;
+
+    let mock_transport = MockTransport::new(vec![]);
+    let sent_data = mock_transport.sent_data.clone();
+    let mut client = ClaudeAgentClient::new(Some(ClaudeAgentOptions {
+        session_id: Some("sess-9".to_string()),
+        ..Default::default()
+    }));
+    client.set_transport(Box::new(mock_transport));
+    client.connect().await.unwrap();
+
+    client
+        .submit_tool_result("toolu_42", ToolResultContent::Text("file not found".to_string()), true)
+        .await
+        .unwrap();
+
+    let sent = sent_data.lock().unwrap();
+    let written: serde_json::Value = serde_json::from_str(sent.last().unwrap()).unwrap();
+    assert_eq!(
+        written,
+        json!({
+            "type": "user",
+            "message": {
+                "role": "user",
+                "content": [{
+                    "type": "tool_result",
+                    "tool_use_id": "toolu_42",
+                    "content": "file not found",
+                    "is_error": true
+                }]
+            },
+            "session_id": "sess-9"
+        })
+    );
+}
+
+#[tokio::test]
+async fn test_client_submit_tool_result_mid_turn() {
+    use claude_agent::types::message::ToolResultContent;
+
+    let tool_use = json!({
+        "type": "assistant",
+        "message": {"model": "claude-sonnet-4-5", "content": [
+            {"type": "tool_use", "id": "toolu_7", "name": "lookup", "input": {"key": "answer"}}
+        ]}
+    });
+    let reply = json!({
+        "type": "assistant",
+        "message": {"model": "claude-sonnet-4-5", "content": [{"type": "text", "text": "It is 42"}]}
+    });
+    // The prompt yields the tool call; the tool result yields the rest of the turn
+    let mock = claude_agent::transport::MockTransport::with_turns(vec![
+        vec![tool_use],
+        vec![reply, success_result()],
+    ]);
+    let mut client = ClaudeAgentClient::new(None);
+    client.set_transport(Box::new(mock.clone()));
+    client.connect().await.unwrap();
+
+    let mut turn = client.query_handle("look it up").await.unwrap();
+    let first = tokio::time::timeout(std::time::Duration::from_secs(2), turn.next())
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap();
+    let tool_use_id = match &first {
+        Message::Assistant(assistant) => match &assistant.content[0] {
+            ContentBlock::ToolUse(tool_use) => tool_use.id.clone(),
+            other => panic!("expected tool use, got {:?}", other),
+        },
+        other => panic!("expected assistant message, got {:?}", other),
+    };
+
+    client
+        .submit_tool_result(&tool_use_id, ToolResultContent::Text("42".to_string()), false)
+        .await
+        .unwrap();
+
+    let rest: Vec<Message> =
+        tokio::time::timeout(std::time::Duration::from_secs(2), turn.map(|m| m.unwrap()).collect())
+            .await
+            .unwrap();
+    assert_eq!(rest.len(), 2);
+    match &rest[0] {
+        Message::Assistant(assistant) => match &assistant.content[0] {
+            ContentBlock::Text(text) => assert_eq!(text.text, "It is 42"),
+            other => panic!("expected text block, got {:?}", other),
+        },
+        other => panic!("expected assistant message, got {:?}", other),
+    }
+    assert!(matches!(rest[1], Message::Result(_)));
+    assert!(mock.sent_messages().iter().any(|sent| sent.contains("\"tool_use_id\":\"toolu_7\"")));
+    client.disconnect().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_client_submit_tool_result_requires_connection() {
+    use claude_agent::types::message::ToolResultContent;
+
+    let client = ClaudeAgentClient::new(None);
+    let err = client
+        .submit_tool_result("toolu_1", ToolResultContent::Text("ok".to_string()), false)
+        .await
+        .unwrap_err();
+    assert!(matches!(err, ClaudeAgentError::Transport(_)));
+}
+
+#[tokio::test]
+async fn test_client_last_stop_reason_from_message_delta() {
+    let message_delta = json!({
+        "type": "message_delta",
+        "delta": {"stop_reason": "tool_use", "stop_sequence": null},
+        "usage": {"input_tokens": 10, "output_tokens": 25}
+    });
+    let mut client = client_replaying(vec![
+        assistant_text_message("Let me check."),
+        message_delta,
+        success_result(),
+    ])
+    .await;
+    assert!(client.last_stop_reason().is_none());
+
+    let messages: Vec<_> = client.query("list files").await.unwrap().collect().await;
+    assert!(messages.iter().all(|item| item.is_ok()), "got {:?}", messages);
+    assert_eq!(client.last_stop_reason().as_deref(), Some("tool_use"));
+}
+
+#[tokio::test]
+async fn test_client_last_stop_reason_from_partial_stream_event() {
+    let stream_event = json!({
+        "type": "stream_event",
+        "uuid": "evt-1",
+        "session_id": "s1",
+        "event": {"type": "message_delta", "delta": {"stop_reason": "max_tokens"}}
+    });
+    let mut client = client_replaying(vec![stream_event, success_result()]).await;
+    let _: Vec<_> = client.query("write an essay").await.unwrap().collect().await;
+    assert_eq!(client.last_stop_reason().as_deref(), Some("max_tokens"));
+}
+
+#[tokio::test]
+async fn test_raw_messages_include_control_traffic_filtered_from_query() {
+    let mock_transport = MockTransport::new(vec![
+        json!({"type": "control_response", "response": {"subtype": "success", "request_id": "cli-1"}}),
+        json!({"type": "control_request", "request_id": "cli-2", "request": {"subtype": "unknown_subtype"}}),
+        json!({"type": "assistant", "message": {"content": [{"type": "text", "text": "hi"}], "model": "m"}}),
+        success_result(),
+    ]);
+    let mut client = ClaudeAgentClient::new(None);
+    client.set_transport(Box::new(mock_transport));
+    client.connect().await.expect("Connect failed");
+
+    let raw: Vec<_> = tokio::time::timeout(
+        std::time::Duration::from_secs(2),
+        client.raw_messages().take(4).collect::<Vec<_>>(),
+    )
+    .await
+    .expect("raw stream should yield the scripted values");
+    let types: Vec<_> = raw
+        .iter()
+        .map(|value| value.as_ref().expect("raw value")["type"].as_str().unwrap().to_string())
+        .collect();
+    assert_eq!(types, ["control_response", "control_request", "assistant", "result"]);
+
+    let messages: Vec<_> = client.query("hello").await.expect("Query failed").collect().await;
+    assert_eq!(messages.len(), 2);
+    assert!(matches!(messages[0], Ok(Message::Assistant(_))));
+    assert!(matches!(messages[1], Ok(Message::Result(_))));
+}
+
+/// A `Write` sink whose contents the test can inspect.
+#[derive(Clone, Default)]
+struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+impl std::io::Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn test_tool_call_logger_writes_correlated_record() {
+    use claude_agent::core::{ToolCallLogger, ToolCallRecord};
+
+    let buffer = SharedBuffer::default();
+    let mut client = client_replaying(vec![
+        json!({
+            "type": "assistant",
+            "parent_tool_use_id": "task-1",
+            "message": {
+                "model": "m",
+                "content": [{"type": "tool_use", "id": "tu-1", "name": "Read", "input": {"file_path": "a.txt"}}]
+            }
+        }),
+        json!({
+            "type": "user",
+            "message": {"content": [{"type": "tool_result", "tool_use_id": "tu-1", "content": "hello"}]}
+        }),
+        success_result(),
+    ])
+    .await
+    .with_tool_call_logger(Arc::new(ToolCallLogger::new(buffer.clone())));
+
+    let _: Vec<_> = client.query("read a.txt").await.expect("Query failed").collect().await;
+
+    let log = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+    let records: Vec<ToolCallRecord> =
+        log.lines().map(|line| serde_json::from_str(line).expect("record line")).collect();
+    assert_eq!(records.len(), 1);
+    let record = &records[0];
+    assert_eq!(record.tool_name, "Read");
+    assert_eq!(record.tool_use_id, "tu-1");
+    assert_eq!(record.tool_input, json!({"file_path": "a.txt"}));
+    assert_eq!(record.parent_tool_use_id.as_deref(), Some("task-1"));
+    assert_eq!(record.tool_output, Some(json!("hello")));
+    assert_eq!(record.error, None);
+    assert!(!record.timestamp.is_empty());
+}
+
+#[test]
+fn test_tool_call_logger_drops_tool_uses_unanswered_by_the_end_of_the_turn() {
+    use claude_agent::core::ToolCallLogger;
+
+    let message = |value: serde_json::Value| -> Message { serde_json::from_value(value).unwrap() };
+    let buffer = SharedBuffer::default();
+    let logger = ToolCallLogger::new(buffer.clone());
+    let tool_use = message(json!({
+        "type": "assistant",
+        "message": {
+            "model": "m",
+            "content": [{"type": "tool_use", "id": "tu-1", "name": "Read", "input": {}}]
+        }
+    }));
+    logger.observe(&tool_use).unwrap();
+    logger.observe(&message(success_result())).unwrap();
+
+    // The turn is over, so a stray result no longer completes the call
+    logger
+        .observe(&message(json!({
+            "type": "user",
+            "message": {"content": [{"type": "tool_result", "tool_use_id": "tu-1", "content": "late"}]}
+        })))
+        .unwrap();
+    assert!(buffer.0.lock().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_max_messages_per_query_truncates_stream() {
+    let mut responses: Vec<_> =
+        (0..4).map(|i| assistant_text_message(&format!("chunk {i}"))).collect();
+    responses.push(success_result());
+    let mut client = ClaudeAgentClient::new(Some(ClaudeAgentOptions {
+        max_messages_per_query: Some(2),
+        ..Default::default()
+    }));
+    client.set_transport(Box::new(MockTransport::new(responses)));
+    client.connect().await.expect("Connect failed");
+
+    let items: Vec<_> = client.query("flood").await.expect("Query failed").collect().await;
+    assert_eq!(items.len(), 3);
+    assert!(items[..2].iter().all(|item| matches!(item, Ok(Message::Assistant(_)))));
+    assert!(matches!(items[2], Err(ClaudeAgentError::MessageLimit(2))));
+}
+
+#[tokio::test]
+async fn test_max_messages_per_query_lets_result_through_at_the_limit() {
+    let responses =
+        vec![assistant_text_message("one"), assistant_text_message("two"), success_result()];
+    let mut client = ClaudeAgentClient::new(Some(ClaudeAgentOptions {
+        max_messages_per_query: Some(2),
+        ..Default::default()
+    }));
+    client.set_transport(Box::new(MockTransport::new(responses)));
+    client.connect().await.expect("Connect failed");
+
+    let items: Vec<_> = client.query("exact").await.expect("Query failed").collect().await;
+    assert_eq!(items.len(), 3);
+    assert!(matches!(items[2], Ok(Message::Result(_))), "got {:?}", items[2]);
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_client_kill_terminates_running_cli_promptly() {
+    // A stand-in CLI that records its pid and ignores stdin
+    let dir = tempfile::tempdir().unwrap();
+    let pid_path = dir.path().join("pid");
+    let script =
+        format!("echo $$ > '{0}.tmp' && mv '{0}.tmp' '{0}'\nexec sleep 30\n", pid_path.display());
+    let cli_path = script_cli(dir.path(), &script);
+
+    // Skip the version probe, which would run the script and record its own pid
+    let mut client = ClaudeAgentClient::new(Some(ClaudeAgentOptions {
+        cli_path: Some(cli_path),
+        skip_version_check: true,
+        ..Default::default()
+    }));
+    client.connect().await.unwrap();
+    let pid = tokio::time::timeout(std::time::Duration::from_secs(5), async {
+        loop {
+            if let Ok(pid) = std::fs::read_to_string(&pid_path) {
+                return pid.trim().to_string();
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+        }
+    })
+    .await
+    .expect("fake CLI should start");
+
+    let started = std::time::Instant::now();
+    client.kill().await.unwrap();
+    assert!(started.elapsed() < std::time::Duration::from_secs(2));
+
+    let alive = std::process::Command::new("kill")
+        .args(["-0", &pid])
+        .stderr(std::process::Stdio::null())
+        .status()
+        .map(|status| status.success())
+        .unwrap_or(false);
+    assert!(!alive, "CLI {pid} should be gone");
+    assert!(!client.is_connected().await);
+}
+
+#[tokio::test]
+async fn test_client_kill_does_not_wait_for_a_live_stream() {
+    // This transport has no `subscribe`, so a polled stream holds its lock
+    let mut client = client_replaying(vec![]).await;
+    let mut stream = client.responses().await.unwrap();
+    assert!(futures::poll!(stream.next()).is_pending());
+
+    tokio::time::timeout(std::time::Duration::from_secs(1), client.kill())
+        .await
+        .expect("kill should not wait for the stream")
+        .unwrap();
+    assert!(!client.is_connected().await);
+}
+
+#[tokio::test]
+async fn test_last_result_reflects_most_recent_query() {
+    let result = |num_turns: u32, duration_ms: u64| {
+        json!({
+            "type": "result",
+            "subtype": "success",
+            "duration_ms": duration_ms,
+            "duration_api_ms": duration_ms / 2,
+            "is_error": false,
+            "num_turns": num_turns,
+            "session_id": "s1"
+        })
+    };
+    let mut client = ClaudeAgentClient::new(None);
+    client.set_transport(Box::new(claude_agent::transport::MockTransport::with_turns(vec![
+        vec![result(1, 100)],
+        vec![result(3, 400)],
+    ])));
+    client.connect().await.unwrap();
+    assert!(client.last_result().is_none());
+
+    let _: Vec<_> = client.query("first").await.unwrap().collect().await;
+    assert_eq!(client.last_result().map(|r| r.num_turns), Some(1));
+
+    let _: Vec<_> = client.query("second").await.unwrap().collect().await;
+    let last = client.last_result().expect("result after query");
+    assert_eq!((last.num_turns, last.duration_ms, last.duration_api_ms), (3, 400, 200));
+
+    let stats = client.session_stats();
+    assert_eq!((stats.results, stats.num_turns, stats.duration_ms), (2, 4, 500));
+    assert_eq!(stats.last_result, Some(last));
+}
+
+#[tokio::test]
+async fn test_send_pipelines_prompts_before_reading() {
+    let mock_transport = MockTransport::new(vec![
+        assistant_text_message("first answer"),
+        success_result(),
+        assistant_text_message("second answer"),
+        success_result(),
+    ]);
+    let sent_data = mock_transport.sent_data.clone();
+    let mut client = ClaudeAgentClient::new(None);
+    client.set_transport(Box::new(mock_transport));
+    client.connect().await.expect("Connect failed");
+
+    let responses = client.responses().await.unwrap();
+    client.send("first").await.unwrap();
+    client.send("second").await.unwrap();
+
+    let prompts: Vec<String> = sent_data
+        .lock()
+        .unwrap()
+        .iter()
+        .filter_map(|line| serde_json::from_str::<serde_json::Value>(line).ok())
+        .filter(|value| value["type"] == "user")
+        .map(|value| value["message"]["content"][0]["text"].as_str().unwrap().to_string())
+        .collect();
+    assert_eq!(prompts, ["first", "second"]);
+
+    let messages: Vec<_> = responses.take(4).collect().await;
+    let texts: Vec<_> =
+        messages.iter().filter_map(|item| item.as_ref().ok().and_then(Message::text)).collect();
+    assert_eq!(texts, ["first answer", "second answer"]);
+    assert_eq!(messages.iter().filter(|item| matches!(item, Ok(Message::Result(_)))).count(), 2);
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_client_reports_cli_version_after_connect() {
+    use claude_agent::transport::CliVersion;
+    let dir = tempfile::tempdir().unwrap();
+    let script =
+        "if [ \"$1\" = --version ]; then echo '2.3.4 (Claude Code)'; exit 0; fi\ncat > /dev/null\n";
+    let cli_path = script_cli(dir.path(), script);
+
+    let mut client = ClaudeAgentClient::new(Some(ClaudeAgentOptions {
+        cli_path: Some(cli_path),
+        ..Default::default()
+    }));
+    assert_eq!(client.cli_version().await, None);
+    client.connect().await.unwrap();
+    assert_eq!(client.cli_version().await, Some(CliVersion::new(2, 3, 4)));
+    client.disconnect().await.unwrap();
+}
+
+/// Write an executable `sh` script running `body` into `dir` and return its path.
+#[cfg(unix)]
+fn script_cli(dir: &std::path::Path, body: &str) -> std::path::PathBuf {
+    use std::os::unix::fs::PermissionsExt;
+
+    let path = dir.join("fake_cli");
+    std::fs::write(&path, format!("#!/bin/sh\n{}", body)).unwrap();
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+    path
+}
+
+/// Options for a stand-in CLI that prints `responses` for each prompt.
+#[cfg(unix)]
+fn fake_cli_answering(
+    dir: &std::path::Path,
+    responses: &[serde_json::Value],
+) -> ClaudeAgentOptions {
+    let responses_path = dir.join("responses.jsonl");
+    let lines: Vec<String> = responses.iter().map(|r| r.to_string()).collect();
+    std::fs::write(&responses_path, lines.join("\n") + "\n").unwrap();
+    let script = format!(
+        "while IFS= read -r line; do\n  case \"$line\" in *'\"type\":\"user\"'*) cat '{}' ;; esac\ndone\n",
+        responses_path.display()
+    );
+    let cli_path = script_cli(dir, &script);
+    ClaudeAgentOptions { cli_path: Some(cli_path), skip_version_check: true, ..Default::default() }
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_query_connects_a_fresh_client() {
+    let dir = tempfile::tempdir().unwrap();
+    let options = fake_cli_answering(dir.path(), &[assistant_text_message("4"), success_result()]);
+
+    // No transport has been set or connected yet
+    let mut client = ClaudeAgentClient::new(Some(options.clone()));
+    let messages: Vec<_> = client.query("What is 2+2?").await.unwrap().collect().await;
+    assert!(matches!(messages.last(), Some(Ok(Message::Result(_)))), "got {:?}", messages);
+    assert!(client.is_connected().await);
+    client.disconnect().await.unwrap();
+
+    let mut client = ClaudeAgentClient::new(Some(options));
+    let messages: Vec<_> = client.query_handle("What is 2+2?").await.unwrap().collect().await;
+    assert!(matches!(messages.last(), Some(Ok(Message::Result(_)))), "got {:?}", messages);
+    client.disconnect().await.unwrap();
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_ask_returns_plain_answer() {
+    let dir = tempfile::tempdir().unwrap();
+    let options = fake_cli_answering(dir.path(), &[assistant_text_message("4"), success_result()]);
+
+    let answer = claude_agent::ask("What is 2+2?", Some(options)).await.unwrap();
+    assert_eq!(answer, "4");
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_ask_joins_text_across_blocks_and_messages() {
+    let dir = tempfile::tempdir().unwrap();
+    let two_blocks = json!({
+        "type": "assistant",
+        "message": {
+            "content": [
+                {"type": "text", "text": "Hello, "},
+                {"type": "tool_use", "id": "t1", "name": "Read", "input": {}},
+                {"type": "text", "text": "world"}
+            ],
+            "role": "assistant",
+            "model": "m"
+        }
+    });
+    let options = fake_cli_answering(
+        dir.path(),
+        &[two_blocks, assistant_text_message("!"), success_result()],
+    );
+
+    let answer = claude_agent::ask("Greet me", Some(options)).await.unwrap();
+    assert_eq!(answer, "Hello, world!");
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_ask_fails_on_error_result() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut error_result = success_result();
+    error_result["subtype"] = json!("error_max_turns");
+    error_result["is_error"] = json!(true);
+    error_result["result"] = json!("Reached the turn limit");
+    let options =
+        fake_cli_answering(dir.path(), &[assistant_text_message("partial"), error_result]);
+
+    let err = claude_agent::ask("Do a lot", Some(options)).await.unwrap_err();
+    let message = err.to_string();
+    assert!(message.contains("error_max_turns"), "got {message}");
+    assert!(message.contains("Reached the turn limit"), "got {message}");
+}
+
+#[cfg(unix)]
+#[test]
+fn test_client_command_preview_shows_cli_invocation_without_spawning() {
+    // A stand-in CLI that leaves a marker if it is ever run
+    let dir = tempfile::tempdir().unwrap();
+    let marker = dir.path().join("spawned");
+    let cli_path = script_cli(dir.path(), &format!("touch '{}'\n", marker.display()));
+    let client = ClaudeAgentClient::new(Some(ClaudeAgentOptions {
+        cli_path: Some(cli_path.clone()),
+        allowed_tools: vec!["Read".to_string(), "Grep".to_string()],
+        max_turns: Some(2),
+        ..Default::default()
+    }));
+
+    let preview = client.command_preview().unwrap();
+
+    assert_eq!(preview[0], cli_path.to_string_lossy());
+    let joined = preview.join(" ");
+    assert!(joined.contains("--output-format stream-json"), "{joined}");
+    assert!(joined.contains("--allowedTools Read,Grep"), "{joined}");
+    assert!(joined.contains("--max-turns 2"), "{joined}");
+    assert!(!marker.exists(), "previewing must not spawn the CLI");
+}
+
+#[tokio::test]
+async fn test_query_timestamped_stamps_messages_in_receive_order() {
+    let mut client = client_replaying(vec![
+        assistant_text_message("one"),
+        assistant_text_message("two"),
+        assistant_text_message("three"),
+        success_result(),
+    ])
+    .await;
+
+    let before = std::time::Instant::now();
+    let items: Vec<_> = client.query_timestamped("count").await.unwrap().collect().await;
+    let after = std::time::Instant::now();
+
+    let stamped: Vec<_> = items.into_iter().map(|item| item.unwrap()).collect();
+    assert_eq!(stamped.len(), 4);
+    assert!(matches!(stamped[3].value, Message::Result(_)));
+    assert_eq!(stamped[0].value.text().as_deref(), Some("one"));
+    for pair in stamped.windows(2) {
+        assert!(pair[0].received_at <= pair[1].received_at);
+    }
+    assert!(before <= stamped[0].received_at && stamped[3].received_at <= after);
+    // Wall-clock stamps can step backwards, so only check they are recent
+    let age = std::time::SystemTime::now().duration_since(stamped[0].received_at_system);
+    assert!(age.is_ok_and(|age| age < std::time::Duration::from_secs(60)));
+}
@@ -95,16 +95,26 @@ async fn client_with_explicit_options() {
 #[tokio::test]
 async fn connect_and_disconnect() {
     let (mut client, _) = connected_client_async(vec![]).await;
-    assert!(client.session_id().is_some());
+    assert!(client.session_id().is_none());
     assert!(client.disconnect().await.is_ok());
 }
 
 #[tokio::test]
 async fn connect_sets_session_id() {
-    let (client, _) = connected_client_async(vec![]).await;
-    let session = client.session_id();
-    assert!(session.is_some());
-    assert!(!session.unwrap().is_empty());
+    let (client, _) = connected_client_async(vec![serde_json::json!({
+        "type": "system",
+        "subtype": "init",
+        "session_id": "cli-session-1"
+    })])
+    .await;
+    tokio::time::timeout(std::time::Duration::from_secs(2), async {
+        while client.session_id().is_none() {
+            tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+        }
+    })
+    .await
+    .expect("session id from the init message");
+    assert_eq!(client.session_id().as_deref(), Some("cli-session-1"));
 }
 
 // --- Message type parsing ---
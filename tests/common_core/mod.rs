@@ -1,6 +1,6 @@
 use async_trait::async_trait;
-use claude_agent::transport::Transport;
-use claude_agent::types::ClaudeAgentError;
+use claude_agent::transport::{Subscription, Transport};
+use claude_agent::types::{ClaudeAgentError, Timestamped};
 use futures::stream::BoxStream;
 use std::sync::{Arc, Mutex};
 use tokio::sync::broadcast;
@@ -34,17 +34,22 @@ impl Transport for MockTransport {
     }
 
     async fn read_messages(&self) -> BoxStream<'_, Result<serde_json::Value, ClaudeAgentError>> {
+        use futures::StreamExt;
+        Box::pin(self.subscribe().unwrap().map(|item| item.map(|message| message.value)))
+    }
+
+    fn subscribe(&self) -> Option<Subscription> {
         let mut rx = self.tx.subscribe();
         let s = async_stream::stream! {
             loop {
                 match rx.recv().await {
-                    Ok(val) => yield val,
+                    Ok(val) => yield val.map(Timestamped::now),
                     Err(broadcast::error::RecvError::Closed) => break,
                     Err(_) => continue,
                 }
             }
         };
-        Box::pin(s)
+        Some(Box::pin(s))
     }
 
     async fn close(&mut self) -> Result<(), ClaudeAgentError> {
@@ -0,0 +1,79 @@
+//! Shared helpers for MCP integration tests.
+//!
+//! Each integration test binary compiles its own copy of this module,
+//! so individual items may appear unused when viewed from a single test.
+#![allow(dead_code)]
+
+use std::path::{Path, PathBuf};
+
+/// A scripted stdio MCP server plus the log of JSON-RPC methods it received.
+pub struct MockMcpServer {
+    pub script: PathBuf,
+    pub log: PathBuf,
+}
+
+impl MockMcpServer {
+    /// Methods received so far, one per request or notification.
+    pub fn received(&self) -> Vec<String> {
+        std::fs::read_to_string(&self.log).unwrap_or_default().lines().map(str::to_string).collect()
+    }
+
+    /// Number of times `method` was received.
+    pub fn count(&self, method: &str) -> usize {
+        self.received().iter().filter(|m| m.as_str() == method).count()
+    }
+}
+
+/// Write a `/bin/sh` MCP server into `dir` that answers `initialize` and each
+/// `(method, result_json)` pair in `handlers`, and logs every method it sees.
+///
+/// Result JSON is embedded in single quotes, so it must not contain `'`.
+pub fn mock_mcp_server(dir: &Path, handlers: &[(&str, String)]) -> MockMcpServer {
+    mock_mcp_server_with_notifications(dir, handlers, &[])
+}
+
+/// Like [`mock_mcp_server`], but for each `(trigger_method, notification)` pair
+/// also sends the JSON-RPC `notification` whenever `trigger_method` arrives,
+/// before responding to it.
+pub fn mock_mcp_server_with_notifications(
+    dir: &Path,
+    handlers: &[(&str, String)],
+    notifications: &[(&str, String)],
+) -> MockMcpServer {
+    use std::os::unix::fs::PermissionsExt;
+
+    let log = dir.join("methods.log");
+    let mut arms = String::new();
+    for (method, result) in handlers {
+        arms.push_str(&format!(
+            "    {method}) printf '{{\"jsonrpc\":\"2.0\",\"id\":%s,\"result\":%s}}\\n' \"$id\" '{result}' ;;\n"
+        ));
+    }
+    let mut triggers = String::new();
+    for (trigger, notification) in notifications {
+        triggers.push_str(&format!(
+            "  [ \"$method\" = \"{trigger}\" ] && printf '%s\\n' '{notification}'\n"
+        ));
+    }
+    let script = format!(
+        r#"#!/bin/sh
+while IFS= read -r line; do
+  id=$(printf '%s\n' "$line" | sed -n 's/.*"id":\([0-9][0-9]*\).*/\1/p')
+  method=$(printf '%s\n' "$line" | sed -n 's/.*"method":"\([^"]*\)".*/\1/p')
+  echo "$method" >> '{log}'
+{triggers}  [ -z "$id" ] && continue
+  case "$method" in
+    initialize) printf '{{"jsonrpc":"2.0","id":%s,"result":{{"protocolVersion":"2024-11-05","capabilities":{{"tools":{{}},"resources":{{}},"prompts":{{}}}},"serverInfo":{{"name":"mock","version":"0.1.0"}}}}}}\n' "$id" ;;
+{arms}    *) printf '{{"jsonrpc":"2.0","id":%s,"error":{{"code":-32601,"message":"method not found"}}}}\n' "$id" ;;
+  esac
+done
+"#,
+        log = log.display(),
+        arms = arms,
+        triggers = triggers,
+    );
+    let path = dir.join("mock_mcp_server.sh");
+    std::fs::write(&path, script).unwrap();
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+    MockMcpServer { script: path, log }
+}
@@ -1,5 +1,5 @@
 use claude_agent::types::config::*;
-use claude_agent::types::ClaudeAgentOptions;
+use claude_agent::types::{ApiKey, ClaudeAgentOptions};
 use std::collections::HashMap;
 use std::path::PathBuf;
 
@@ -132,6 +132,7 @@ fn mcp_server_config_full_serde_roundtrip() {
         url: None,
         timeout_secs: Some(30),
         env,
+        rate_limit: Some(claude_agent::mcp::RateLimitConfig::per_second(5)),
     };
     let json = serde_json::to_string(&cfg).unwrap();
     let back: McpServerConfig = serde_json::from_str(&json).unwrap();
@@ -139,6 +140,7 @@ fn mcp_server_config_full_serde_roundtrip() {
     assert_eq!(back.args, vec!["-y", "@anthropic/mcp"]);
     assert_eq!(back.timeout_secs, Some(30));
     assert_eq!(back.env.get("KEY").unwrap(), "VALUE");
+    assert_eq!(back.rate_limit, Some(claude_agent::mcp::RateLimitConfig::per_second(5)));
 }
 
 #[test]
@@ -150,6 +152,7 @@ fn mcp_server_config_stdio_with_args() {
         url: None,
         timeout_secs: None,
         env: HashMap::new(),
+        rate_limit: None,
     };
     let json = serde_json::to_string(&cfg).unwrap();
     let back: McpServerConfig = serde_json::from_str(&json).unwrap();
@@ -282,10 +285,23 @@ fn claude_agent_options_full_serde_roundtrip() {
         cli_path: Some(PathBuf::from("/usr/local/bin/claude")),
         settings: Some("settings.json".to_string()),
         add_dirs: vec![PathBuf::from("/extra")],
+        skip_dir_validation: false,
         env,
+        clear_env: true,
+        clear_env_strict: false,
+        env_passthrough: vec!["HOME".to_string()],
+        api_key: Some(ApiKey::new("sk-ant-test")),
         extra_args,
         max_buffer_size: Some(1024),
+        buffer_overflow_policy: BufferOverflowPolicy::SkipToNewline,
+        broadcast_capacity: Some(64),
+        write_flush_interval_ms: Some(5),
+        connect_retries: Some(2),
+        connect_retry_delay_ms: Some(50),
+        close_grace_period_ms: Some(1000),
+        skip_version_check: false,
         include_partial_messages: true,
+        verbose: Some(true),
         fork_session: true,
         agents: Some(agents),
         setting_sources: Some(vec![SettingSource::User, SettingSource::Project]),
@@ -307,6 +323,10 @@ fn claude_agent_options_full_serde_roundtrip() {
         task_budget: None,
         session_id: None,
         strict_mcp_config: false,
+        query_rate_limit: None,
+        permission_rules: vec![],
+        error_on_result_failure: true,
+        max_messages_per_query: None,
     };
 
     let json = serde_json::to_string(&opts).unwrap();
@@ -354,6 +374,60 @@ fn claude_agent_options_with_env() {
     assert_eq!(back.env.get("API_KEY").unwrap(), "secret");
 }
 
+#[test]
+fn claude_agent_options_api_key_is_redacted_and_not_serialized() {
+    let opts =
+        ClaudeAgentOptions { api_key: Some(ApiKey::new("sk-ant-secret-42")), ..Default::default() };
+
+    let debug = format!("{:?}", opts);
+    assert!(!debug.contains("sk-ant-secret-42"));
+    assert!(debug.contains("REDACTED"));
+
+    let json = serde_json::to_string(&opts).unwrap();
+    assert!(!json.contains("sk-ant-secret-42"));
+    assert!(!json.contains("api_key"));
+}
+
+#[test]
+fn claude_agent_options_debug_masks_secrets() {
+    let mut env = HashMap::new();
+    env.insert("ANTHROPIC_AUTH_TOKEN".to_string(), "tok-123".to_string());
+    env.insert("my_secret".to_string(), "hunter2".to_string());
+    env.insert("RUST_LOG".to_string(), "debug".to_string());
+    let mut extra_args = HashMap::new();
+    extra_args.insert("api-key".to_string(), Some("sk-789".to_string()));
+    extra_args.insert("verbose".to_string(), None);
+    let opts = ClaudeAgentOptions {
+        model: Some("claude-sonnet-4".to_string()),
+        env,
+        extra_args,
+        ..Default::default()
+    };
+
+    let debug = format!("{:?}", opts);
+    for secret in ["tok-123", "hunter2", "sk-789"] {
+        assert!(!debug.contains(secret), "{secret} leaked: {debug}");
+    }
+    assert!(debug.contains(r#""ANTHROPIC_AUTH_TOKEN": "***""#));
+    assert!(debug.contains(r#""RUST_LOG": "debug""#));
+    assert!(debug.contains(r#""verbose": None"#));
+    assert!(debug.contains(r#"model: Some("claude-sonnet-4")"#));
+}
+
+#[test]
+fn claude_agent_options_debug_masks_mcp_server_secrets() {
+    let mut mcp = HashMap::new();
+    mcp.insert(
+        "remote".to_string(),
+        serde_json::json!({"url": "https://mcp.example.com", "headers": {"Authorization": "Bearer tok-1"}}),
+    );
+    let opts = ClaudeAgentOptions { mcp_servers: mcp, ..Default::default() };
+
+    let debug = format!("{:?}", opts);
+    assert!(!debug.contains("tok-1"), "secret leaked: {debug}");
+    assert!(debug.contains("https://mcp.example.com"), "{debug}");
+}
+
 #[test]
 fn claude_agent_options_with_mcp_servers() {
     let mut mcp = HashMap::new();
@@ -407,8 +481,24 @@ fn system_prompt_config_text_serde_roundtrip() {
     let back: SystemPromptConfig = serde_json::from_str(&json).unwrap();
     match back {
         SystemPromptConfig::Text(s) => assert_eq!(s, "You are a helpful assistant."),
-        SystemPromptConfig::Preset(_) => panic!("expected Text variant"),
+        SystemPromptConfig::Preset(_) | SystemPromptConfig::TextWithAppend { .. } => {
+            panic!("expected Text variant")
+        },
+    }
+}
+
+#[test]
+fn system_prompt_config_text_with_append_serde_roundtrip() {
+    let json = r#"{"text":"You are a reviewer.","append":"Be terse."}"#;
+    let cfg: SystemPromptConfig = serde_json::from_str(json).unwrap();
+    match &cfg {
+        SystemPromptConfig::TextWithAppend { text, append } => {
+            assert_eq!(text, "You are a reviewer.");
+            assert_eq!(append, "Be terse.");
+        },
+        other => panic!("expected TextWithAppend, got {other:?}"),
     }
+    assert_eq!(serde_json::to_string(&cfg).unwrap(), json);
 }
 
 #[test]
@@ -417,7 +507,9 @@ fn system_prompt_config_preset_serde_roundtrip() {
     let back: SystemPromptConfig = serde_json::from_str(json).unwrap();
     match back {
         SystemPromptConfig::Preset(_) => {},
-        SystemPromptConfig::Text(_) => panic!("expected Preset variant"),
+        SystemPromptConfig::Text(_) | SystemPromptConfig::TextWithAppend { .. } => {
+            panic!("expected Preset variant")
+        },
     }
 }
 
@@ -568,3 +660,87 @@ fn json_schema_for_sandbox_settings() {
     let json = serde_json::to_string(&schema).unwrap();
     assert!(json.contains("SandboxSettings"));
 }
+
+#[test]
+fn test_inherit_anthropic_env_copies_allowlisted_vars() {
+    for var in ANTHROPIC_ENV_VARS {
+        std::env::set_var(var, format!("value-of-{var}"));
+    }
+    std::env::set_var("ANTHROPIC_UNRELATED_SETTING", "ignored");
+
+    let mut options = ClaudeAgentOptions::default();
+    options.env.insert("ANTHROPIC_MODEL".to_string(), "explicit-model".to_string());
+    options.inherit_anthropic_env();
+
+    assert_eq!(options.env["ANTHROPIC_AUTH_TOKEN"], "value-of-ANTHROPIC_AUTH_TOKEN");
+    assert_eq!(options.env["ANTHROPIC_BASE_URL"], "value-of-ANTHROPIC_BASE_URL");
+    assert_eq!(
+        options.env["ANTHROPIC_DEFAULT_SONNET_MODEL"],
+        "value-of-ANTHROPIC_DEFAULT_SONNET_MODEL"
+    );
+    assert_eq!(options.env["ANTHROPIC_MODEL"], "explicit-model");
+    assert!(!options.env.contains_key("ANTHROPIC_UNRELATED_SETTING"));
+    assert_eq!(options.env.len(), ANTHROPIC_ENV_VARS.len());
+}
+
+#[test]
+fn merge_setting_sources_local_overrides_project_overrides_user() {
+    let home = tempfile::tempdir().unwrap();
+    let project = tempfile::tempdir().unwrap();
+    let write = |path: PathBuf, json: serde_json::Value| {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, json.to_string()).unwrap();
+    };
+    write(
+        SettingSource::User.settings_path(home.path(), project.path()),
+        serde_json::json!({
+            "model": "user-model",
+            "theme": "dark",
+            "permissions": {"allow": ["Read"], "defaultMode": "default"},
+            "env": {"A": "user", "B": "user"}
+        }),
+    );
+    write(
+        SettingSource::Project.settings_path(home.path(), project.path()),
+        serde_json::json!({
+            "model": "project-model",
+            "permissions": {"allow": ["Bash"]},
+            "env": {"B": "project", "C": "project"}
+        }),
+    );
+    write(
+        SettingSource::Local.settings_path(home.path(), project.path()),
+        serde_json::json!({"model": "local-model", "env": {"C": "local"}}),
+    );
+
+    // Listed out of order on purpose: precedence is fixed
+    let sources = [SettingSource::Local, SettingSource::User, SettingSource::Project];
+    let merged = merge_setting_sources(&sources, home.path(), project.path()).unwrap();
+    assert_eq!(
+        serde_json::Value::Object(merged),
+        serde_json::json!({
+            "model": "local-model",
+            "theme": "dark",
+            "permissions": {"allow": ["Bash"], "defaultMode": "default"},
+            "env": {"A": "user", "B": "project", "C": "local"}
+        })
+    );
+
+    let merged =
+        merge_setting_sources(&[SettingSource::User], home.path(), project.path()).unwrap();
+    assert_eq!(merged["model"], "user-model");
+}
+
+#[test]
+fn merge_setting_sources_skips_missing_files_and_rejects_invalid_ones() {
+    let home = tempfile::tempdir().unwrap();
+    let project = tempfile::tempdir().unwrap();
+    let all = [SettingSource::User, SettingSource::Project, SettingSource::Local];
+    assert!(merge_setting_sources(&all, home.path(), project.path()).unwrap().is_empty());
+
+    let local = SettingSource::Local.settings_path(home.path(), project.path());
+    std::fs::create_dir_all(local.parent().unwrap()).unwrap();
+    std::fs::write(&local, "not json").unwrap();
+    let err = merge_setting_sources(&all, home.path(), project.path()).unwrap_err();
+    assert!(err.to_string().contains("settings.local.json"));
+}
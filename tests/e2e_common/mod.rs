@@ -26,18 +26,7 @@ pub const STANDARD_TIMEOUT: Duration = Duration::from_secs(60);
 /// Passes through authentication-related env vars to the Claude subprocess.
 pub fn live_options() -> ClaudeAgentOptions {
     let mut opts = ClaudeAgentOptions::default();
-    for var in &[
-        "ANTHROPIC_AUTH_TOKEN",
-        "ANTHROPIC_BASE_URL",
-        "ANTHROPIC_MODEL",
-        "ANTHROPIC_DEFAULT_HAIKU_MODEL",
-        "ANTHROPIC_DEFAULT_SONNET_MODEL",
-        "ANTHROPIC_DEFAULT_OPUS_MODEL",
-    ] {
-        if let Ok(val) = std::env::var(var) {
-            opts.env.insert(var.to_string(), val);
-        }
-    }
+    opts.inherit_anthropic_env();
     if let Ok(path) = std::env::var("CLAUDE_CLI_PATH") {
         opts.cli_path = Some(std::path::PathBuf::from(path));
     }
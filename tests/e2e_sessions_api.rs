@@ -14,6 +14,10 @@ async fn test_live_session_listing() {
     e2e_common::init_tracing();
     let mut client = ClaudeAgentClient::new(Some(e2e_common::live_options()));
     client.connect().await.expect("connect failed");
+    {
+        let mut stream = client.query("Say hello").await.expect("query failed");
+        e2e_common::collect_until_result(&mut stream, e2e_common::STANDARD_TIMEOUT).await;
+    }
     let session_id = client.session_id().expect("session_id should be set").to_string();
     client.disconnect().await.ok();
 
@@ -32,13 +36,13 @@ async fn test_live_session_messages_persisted() {
     e2e_common::init_tracing();
     let mut client = ClaudeAgentClient::new(Some(e2e_common::live_options()));
     client.connect().await.expect("connect failed");
-    let session_id = client.session_id().expect("session_id should be set").to_string();
 
     // Send a query to generate messages
     {
         let mut stream = client.query("Say hello").await.expect("query failed");
         e2e_common::collect_until_result(&mut stream, e2e_common::STANDARD_TIMEOUT).await;
     } // stream dropped here, releasing borrow on client
+    let session_id = client.session_id().expect("session_id should be set").to_string();
     client.disconnect().await.ok();
 
     // Verify messages were persisted
@@ -54,6 +58,10 @@ async fn test_live_session_info_retrieval() {
     e2e_common::init_tracing();
     let mut client = ClaudeAgentClient::new(Some(e2e_common::live_options()));
     client.connect().await.expect("connect failed");
+    {
+        let mut stream = client.query("Say hello").await.expect("query failed");
+        e2e_common::collect_until_result(&mut stream, e2e_common::STANDARD_TIMEOUT).await;
+    }
     let session_id = client.session_id().expect("session_id should be set").to_string();
     client.disconnect().await.ok();
 
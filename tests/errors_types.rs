@@ -36,3 +36,106 @@ fn test_unknown_error() {
     assert!(error.to_string().contains("Something weird happened"));
     assert!(error.to_string().contains("Unknown error"));
 }
+
+#[test]
+fn test_lagged_error_carries_skip_count() {
+    let error = ClaudeAgentError::Lagged(42);
+    assert!(error.to_string().contains("42 messages skipped"));
+}
+
+#[test]
+fn test_rate_limited_error() {
+    let error =
+        ClaudeAgentError::RateLimited { retry_after: Some(std::time::Duration::from_secs(2)) };
+    assert!(error.to_string().contains("Rate limited"));
+    assert!(error.to_string().contains("retry after 2s"));
+    assert_eq!(ClaudeAgentError::RateLimited { retry_after: None }.to_string(), "Rate limited");
+}
+
+#[test]
+fn test_is_fatal_classification() {
+    let recoverable = [
+        ClaudeAgentError::JSONDecode("bad line".to_string()),
+        ClaudeAgentError::MessageParse("unknown shape".to_string()),
+        ClaudeAgentError::Lagged(3),
+        ClaudeAgentError::RateLimited { retry_after: None },
+    ];
+    for error in &recoverable {
+        assert!(!error.is_fatal(), "{error} should not be fatal");
+    }
+
+    let fatal = [
+        ClaudeAgentError::Transport("broken pipe".to_string()),
+        ClaudeAgentError::Process("exited".to_string()),
+        ClaudeAgentError::CLIConnection("closed".to_string()),
+    ];
+    for error in &fatal {
+        assert!(error.is_fatal(), "{error} should be fatal");
+    }
+}
+
+#[test]
+fn test_timeout_error() {
+    let error = ClaudeAgentError::Timeout(std::time::Duration::from_millis(250));
+    assert!(error.to_string().contains("250ms"));
+    assert!(error.is_fatal());
+}
+
+#[test]
+fn test_message_limit_error() {
+    let error = ClaudeAgentError::MessageLimit(50);
+    assert!(error.to_string().contains("50 messages"));
+    assert!(error.is_fatal());
+}
+
+#[test]
+fn test_authentication_error_points_at_env_vars() {
+    let error = ClaudeAgentError::Authentication("Invalid API key".to_string());
+    let message = error.to_string();
+    assert!(message.contains("Invalid API key"));
+    assert!(message.contains("ANTHROPIC_API_KEY"));
+    assert!(message.contains("ANTHROPIC_AUTH_TOKEN"));
+    assert!(error.is_fatal());
+}
+
+#[test]
+fn test_error_kind_predicates() {
+    type Predicate = fn(&ClaudeAgentError) -> bool;
+    let predicates: [(&str, Predicate); 5] = [
+        ("is_cli_not_found", ClaudeAgentError::is_cli_not_found),
+        ("is_connection", ClaudeAgentError::is_connection),
+        ("is_json_decode", ClaudeAgentError::is_json_decode),
+        ("is_mcp", ClaudeAgentError::is_mcp),
+        ("is_timeout", ClaudeAgentError::is_timeout),
+    ];
+    // Each error matches exactly the predicate at the same index
+    let errors = [
+        ClaudeAgentError::CLINotFound("claude".to_string()),
+        ClaudeAgentError::CLIConnection("closed".to_string()),
+        ClaudeAgentError::JSONDecode("bad line".to_string()),
+        ClaudeAgentError::Mcp("server crashed".to_string()),
+        ClaudeAgentError::Timeout(std::time::Duration::from_secs(5)),
+    ];
+    for (i, error) in errors.iter().enumerate() {
+        for (j, (name, predicate)) in predicates.iter().enumerate() {
+            assert_eq!(predicate(error), i == j, "{name}({error:?})");
+        }
+    }
+
+    let other = ClaudeAgentError::Process("exited".to_string());
+    assert!(predicates.iter().all(|(_, predicate)| !predicate(&other)));
+
+    // A failed write to the CLI is a connection problem too
+    let write_failed = ClaudeAgentError::Transport("broken pipe".to_string());
+    assert!(write_failed.is_connection());
+    assert!(!write_failed.is_timeout());
+
+    // So is a CLI that does not start in time, which is also a timeout
+    let connect_timeout = ClaudeAgentError::ConnectTimeout(std::time::Duration::from_secs(30));
+    assert!(connect_timeout.is_connection());
+    assert!(connect_timeout.is_timeout());
+
+    // Other connection errors are not timeouts, whatever their message says
+    let refused = ClaudeAgentError::CLIConnection("Connection timeout after 30 seconds".into());
+    assert!(!refused.is_timeout());
+}
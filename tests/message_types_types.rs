@@ -246,8 +246,11 @@ fn assistant_message_error_variants_serde() {
 
 #[test]
 fn system_message_serde_roundtrip() {
-    let msg =
-        SystemMessage { subtype: "init".to_string(), data: serde_json::json!({"key": "value"}) };
+    let msg = SystemMessage {
+        subtype: "init".to_string(),
+        data: serde_json::json!({"key": "value"}),
+        extra: Default::default(),
+    };
     let json = serde_json::to_string(&msg).unwrap();
     let back: SystemMessage = serde_json::from_str(&json).unwrap();
     assert_eq!(back.subtype, "init");
@@ -270,6 +273,7 @@ fn result_message_full_serde_roundtrip() {
         usage: Some(usage),
         result: Some("Task completed".to_string()),
         structured_output: Some(serde_json::json!({"answer": 42})),
+        permission_denials: None,
     };
     let json = serde_json::to_string(&msg).unwrap();
     let back: ResultMessage = serde_json::from_str(&json).unwrap();
@@ -298,6 +302,7 @@ fn result_message_minimal() {
         usage: None,
         result: None,
         structured_output: None,
+        permission_denials: None,
     };
     let json = serde_json::to_string(&msg).unwrap();
     let back: ResultMessage = serde_json::from_str(&json).unwrap();
@@ -367,8 +372,11 @@ fn message_assistant_variant() {
 
 #[test]
 fn message_system_variant() {
-    let msg =
-        Message::System(SystemMessage { subtype: "init".to_string(), data: serde_json::json!({}) });
+    let msg = Message::System(SystemMessage {
+        subtype: "init".to_string(),
+        data: serde_json::json!({}),
+        extra: Default::default(),
+    });
     let json = serde_json::to_string(&msg).unwrap();
     let back: Message = serde_json::from_str(&json).unwrap();
     match back {
@@ -390,6 +398,7 @@ fn message_result_variant() {
         usage: None,
         result: None,
         structured_output: None,
+        permission_denials: None,
     });
     let json = serde_json::to_string(&msg).unwrap();
     let back: Message = serde_json::from_str(&json).unwrap();
@@ -585,7 +594,7 @@ fn delta_tool_use_direct() {
 
 #[test]
 fn usage_full_serde_roundtrip() {
-    let usage = Usage { input_tokens: Some(100), output_tokens: 200 };
+    let usage = Usage { input_tokens: Some(100), output_tokens: 200, ..Default::default() };
     let json = serde_json::to_string(&usage).unwrap();
     let back: Usage = serde_json::from_str(&json).unwrap();
     assert_eq!(back.input_tokens, Some(100));
@@ -594,9 +603,220 @@ fn usage_full_serde_roundtrip() {
 
 #[test]
 fn usage_none_input() {
-    let usage = Usage { input_tokens: None, output_tokens: 50 };
+    let usage = Usage { input_tokens: None, output_tokens: 50, ..Default::default() };
     let json = serde_json::to_string(&usage).unwrap();
     let back: Usage = serde_json::from_str(&json).unwrap();
     assert!(back.input_tokens.is_none());
     assert_eq!(back.output_tokens, 50);
 }
+
+#[test]
+fn usage_with_cache_fields() {
+    let json = r#"{
+        "input_tokens": 12,
+        "output_tokens": 340,
+        "cache_creation_input_tokens": 2048,
+        "cache_read_input_tokens": 8192
+    }"#;
+    let usage: Usage = serde_json::from_str(json).unwrap();
+    assert_eq!(usage.input_tokens, Some(12));
+    assert_eq!(usage.output_tokens, 340);
+    assert_eq!(usage.cache_creation_input_tokens, Some(2048));
+    assert_eq!(usage.cache_read_input_tokens, Some(8192));
+
+    let back = serde_json::to_value(&usage).unwrap();
+    assert_eq!(back["cache_read_input_tokens"], 8192);
+}
+
+#[test]
+fn usage_tolerates_missing_output_tokens() {
+    let usage: Usage = serde_json::from_str(r#"{"input_tokens": 5}"#).unwrap();
+    assert_eq!(usage.output_tokens, 0);
+    assert!(usage.cache_creation_input_tokens.is_none());
+}
+
+fn assistant(content: Vec<ContentBlock>) -> Message {
+    Message::Assistant(AssistantMessage {
+        content,
+        model: "claude-test".to_string(),
+        parent_tool_use_id: None,
+        error: None,
+    })
+}
+
+fn tool_use_block() -> ContentBlock {
+    ContentBlock::ToolUse(ToolUseBlock {
+        id: "tool-1".to_string(),
+        name: "Read".to_string(),
+        input: serde_json::json!({"file_path": "/tmp/x"}),
+    })
+}
+
+#[test]
+fn content_block_as_text() {
+    let text = ContentBlock::Text(TextBlock { text: "hi".to_string() });
+    assert_eq!(text.as_text(), Some("hi"));
+    assert_eq!(tool_use_block().as_text(), None);
+}
+
+#[test]
+fn message_text_concatenates_text_blocks_and_skips_others() {
+    let message = assistant(vec![
+        ContentBlock::Text(TextBlock { text: "Hello, ".to_string() }),
+        ContentBlock::Thinking(ThinkingBlock {
+            thinking: "hidden".to_string(),
+            signature: "sig".to_string(),
+        }),
+        tool_use_block(),
+        ContentBlock::Text(TextBlock { text: "world".to_string() }),
+    ]);
+    assert_eq!(message.text().as_deref(), Some("Hello, world"));
+}
+
+#[test]
+fn message_text_none_for_tool_only_assistant() {
+    assert_eq!(assistant(vec![tool_use_block()]).text(), None);
+    assert_eq!(assistant(vec![]).text(), None);
+}
+
+#[test]
+fn message_text_none_for_non_assistant_variants() {
+    let user = Message::User(UserMessage {
+        content: MessageContent::Text("question".to_string()),
+        uuid: None,
+        parent_tool_use_id: None,
+    });
+    assert_eq!(user.text(), None);
+
+    let result: Message = serde_json::from_value(serde_json::json!({
+        "type": "result",
+        "subtype": "success",
+        "duration_ms": 1,
+        "duration_api_ms": 1,
+        "is_error": false,
+        "num_turns": 1,
+        "session_id": "s",
+        "result": "done"
+    }))
+    .unwrap();
+    assert_eq!(result.text(), None);
+}
+
+// CLI-shaped user echoes carrying tool results
+
+#[test]
+fn cli_tool_result_user_message_parses() {
+    let raw = r#"{"type":"user","message":{"role":"user","content":[{"tool_use_id":"toolu_01","type":"tool_result","content":"total 8\ndrwxr-xr-x  src","is_error":false}]},"parent_tool_use_id":null,"session_id":"sess-1","uuid":"b7c4e1d0","tool_use_result":{"stdout":"total 8","stderr":"","interrupted":false}}"#;
+    let message: Message = serde_json::from_str(raw).unwrap();
+    let Message::User(user) = message else { panic!("expected user message") };
+    assert_eq!(user.uuid.as_deref(), Some("b7c4e1d0"));
+    let MessageContent::Blocks(blocks) = &user.content else { panic!("expected blocks") };
+    let ContentBlock::ToolResult(result) = &blocks[0] else { panic!("expected tool_result") };
+    assert_eq!(result.tool_use_id, "toolu_01");
+    assert_eq!(result.is_error, Some(false));
+    assert!(
+        matches!(&result.content, Some(ToolResultContent::Text(t)) if t.starts_with("total 8"))
+    );
+}
+
+#[test]
+fn cli_tool_result_with_block_content_and_error_parses() {
+    let raw = r#"{"type":"user","message":{"role":"user","content":[{"type":"tool_result","tool_use_id":"toolu_02","content":[{"type":"text","text":"<tool_use_error>File not found</tool_use_error>"}],"is_error":true}]},"parent_tool_use_id":"toolu_00","session_id":"sess-1","uuid":"c1","tool_use_result":"Error: File not found"}"#;
+    let Message::User(user) = serde_json::from_str::<Message>(raw).unwrap() else {
+        panic!("expected user message")
+    };
+    assert_eq!(user.parent_tool_use_id.as_deref(), Some("toolu_00"));
+    let MessageContent::Blocks(blocks) = &user.content else { panic!("expected blocks") };
+    let ContentBlock::ToolResult(result) = &blocks[0] else { panic!("expected tool_result") };
+    assert_eq!(result.is_error, Some(true));
+    assert!(matches!(&result.content, Some(ToolResultContent::Blocks(b)) if b.len() == 1));
+}
+
+#[test]
+fn unknown_content_blocks_are_kept_as_raw_json() {
+    let raw = r#"{"type":"user","message":{"role":"user","content":[{"type":"tool_result","tool_use_id":"t","content":"x"},{"type":"document","source":{"type":"text","data":"d"}}]},"session_id":"s"}"#;
+    let Message::User(user) = serde_json::from_str::<Message>(raw).unwrap() else {
+        panic!("expected user message")
+    };
+    let MessageContent::Blocks(blocks) = &user.content else { panic!("expected blocks") };
+    assert!(matches!(&blocks[1], ContentBlock::Unknown(v) if v["type"] == "document"));
+
+    let assistant = r#"{"type":"assistant","message":{"content":[{"type":"redacted_thinking","data":"zz"},{"type":"text","text":"ok"}],"model":"m"},"session_id":"s"}"#;
+    let message: Message = serde_json::from_str(assistant).unwrap();
+    assert_eq!(message.text().as_deref(), Some("ok"));
+}
+
+#[test]
+fn thinking_block_without_signature_parses() {
+    let block: ContentBlock =
+        serde_json::from_str(r#"{"type":"thinking","thinking":"hmm"}"#).unwrap();
+    assert!(matches!(block, ContentBlock::Thinking(t) if t.signature.is_empty()));
+}
+
+// Forward compatibility
+
+#[test]
+fn unknown_message_type_becomes_unknown_variant() {
+    let raw = serde_json::json!({"type": "future_event", "payload": {"answer": 42}});
+    let message: Message = serde_json::from_value(raw.clone()).unwrap();
+    assert!(matches!(&message, Message::Unknown(value) if value == &raw));
+    assert_eq!(serde_json::to_value(&message).unwrap(), raw);
+}
+
+#[test]
+fn message_without_type_becomes_unknown_variant() {
+    let message: Message = serde_json::from_value(serde_json::json!({"data": 1})).unwrap();
+    assert!(matches!(message, Message::Unknown(_)));
+}
+
+#[test]
+fn malformed_known_message_type_is_still_an_error() {
+    let err = serde_json::from_value::<Message>(serde_json::json!({
+        "type": "result",
+        "subtype": "success"
+    }))
+    .unwrap_err();
+    assert!(err.to_string().contains("missing field"), "{err}");
+}
+
+#[test]
+fn known_message_round_trips_through_custom_serde() {
+    let raw = serde_json::json!({
+        "type": "result",
+        "subtype": "success",
+        "duration_ms": 1,
+        "duration_api_ms": 1,
+        "is_error": false,
+        "num_turns": 1,
+        "session_id": "s"
+    });
+    let message: Message = serde_json::from_value(raw).unwrap();
+    assert!(matches!(message, Message::Result(_)));
+    let value = serde_json::to_value(&message).unwrap();
+    assert_eq!(value["type"], "result");
+    assert_eq!(value["session_id"], "s");
+}
+
+#[test]
+fn result_message_with_permission_denials() {
+    let raw = r#"{"type":"result","subtype":"success","duration_ms":10,"duration_api_ms":8,"is_error":false,"num_turns":2,"session_id":"s","result":"done","permission_denials":[{"tool_name":"Bash","tool_use_id":"toolu_01","tool_input":{"command":"rm -rf /tmp/x"}},{"tool_name":"Write","tool_input":{"file_path":"/etc/hosts"},"reason":"outside workspace"}]}"#;
+    let Message::Result(result) = serde_json::from_str::<Message>(raw).unwrap() else {
+        panic!("expected result message")
+    };
+    let denials = result.permission_denials.expect("denials should populate");
+    assert_eq!(denials.len(), 2);
+    assert_eq!(denials[0].tool_name, "Bash");
+    assert_eq!(denials[0].tool_use_id.as_deref(), Some("toolu_01"));
+    assert_eq!(denials[0].tool_input["command"], "rm -rf /tmp/x");
+    assert_eq!(denials[0].reason, None);
+    assert_eq!(denials[1].reason.as_deref(), Some("outside workspace"));
+}
+
+#[test]
+fn result_message_without_permission_denials() {
+    let raw = r#"{"type":"result","subtype":"success","duration_ms":1,"duration_api_ms":1,"is_error":false,"num_turns":1,"session_id":"s"}"#;
+    let Message::Result(result) = serde_json::from_str::<Message>(raw).unwrap() else {
+        panic!("expected result message")
+    };
+    assert!(result.permission_denials.is_none());
+}
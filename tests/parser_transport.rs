@@ -1,4 +1,6 @@
-use claude_agent::types::message::{ContentBlock, Message, MessageContent, ToolResultContent};
+use claude_agent::types::message::{
+    ContentBlock, ImageSource, Message, MessageContent, ToolResultBlock, ToolResultContent,
+};
 use serde_json::json;
 
 #[test]
@@ -40,19 +42,38 @@ fn test_parse_user_message_with_uuid() {
     let message: Message = serde_json::from_value(data).unwrap();
 
     if let Message::User(user_msg) = message {
-        assert_eq!(
-            user_msg.uuid.unwrap().to_string(),
-            "msg-abc123-def456" // Note: valid uuid format required? "msg-..." is NOT valid UUID.
-                                // Python usage of "uuid" field might be loose string, but Rust expects Uuid type.
-                                // Let's check if Uuid::parse_str works with "msg-..." -> No it doesn't.
-                                // If Python SDK allows arbitrary strings for "uuid", then Rust type definition Option<Uuid> is wrong.
-                                // Let's check Python definition in types.py.
-        );
+        // The CLI emits ids that are not RFC 4122 UUIDs; they must parse as plain strings.
+        assert_eq!(user_msg.uuid.as_deref(), Some("msg-abc123-def456"));
     } else {
         panic!("Expected UserMessage");
     }
 }
 
+#[test]
+fn test_non_uuid_format_ids_round_trip() {
+    let user = json!({
+        "type": "user",
+        "uuid": "not-a-uuid",
+        "message": {"content": [{"type": "text", "text": "Hello"}]}
+    });
+    let message: Message = serde_json::from_value(user).unwrap();
+    let back = serde_json::to_value(&message).unwrap();
+    assert_eq!(back["uuid"], "not-a-uuid");
+
+    let event = json!({
+        "type": "stream_event",
+        "uuid": "evt_42",
+        "session_id": "session_123",
+        "event": {"type": "message_stop"}
+    });
+    let message: Message = serde_json::from_value(event).unwrap();
+    if let Message::StreamEvent(stream_event) = message {
+        assert_eq!(stream_event.uuid, "evt_42");
+    } else {
+        panic!("Expected StreamEvent");
+    }
+}
+
 #[test]
 fn test_parse_user_message_with_tool_use() {
     let data = json!({
@@ -125,6 +146,177 @@ fn test_parse_user_message_with_tool_result() {
     }
 }
 
+#[test]
+fn test_parse_edit_tool_result() {
+    let edit = json!({
+        "filePath": "/repo/src/lib.rs",
+        "oldString": "fn old() {}",
+        "newString": "fn new() {}",
+        "replaceAll": false
+    });
+    let data = json!({
+        "type": "user",
+        "message": {
+            "content": [
+                {
+                    "type": "tool_result",
+                    "tool_use_id": "toolu_edit",
+                    "content": [{"type": "text", "text": edit.to_string()}]
+                }
+            ]
+        },
+    });
+
+    let message: Message = serde_json::from_value(data).unwrap();
+
+    let Message::User(user_msg) = message else {
+        panic!("Expected UserMessage");
+    };
+    let MessageContent::Blocks(blocks) = user_msg.content else {
+        panic!("Expected block content");
+    };
+    let ContentBlock::ToolResult(result) = &blocks[0] else {
+        panic!("Expected ToolResultBlock");
+    };
+    let edit = result.as_edit().expect("edit payload");
+    assert_eq!(edit.file_path, "/repo/src/lib.rs");
+    assert_eq!(edit.old_string, "fn old() {}");
+    assert_eq!(edit.new_string, "fn new() {}");
+    assert!(!edit.replace_all);
+}
+
+#[test]
+fn test_parse_multi_edit_tool_result() {
+    let multi_edit = json!({
+        "filePath": "/repo/src/lib.rs",
+        "edits": [
+            {"old_string": "fn a() {}", "new_string": "fn b() {}"},
+            {"oldString": "x", "newString": "y", "replaceAll": true}
+        ]
+    });
+    let result: ToolResultBlock = serde_json::from_value(json!({
+        "tool_use_id": "toolu_multi",
+        "content": multi_edit.to_string()
+    }))
+    .unwrap();
+
+    // Several edits do not fit a single EditResult
+    assert!(result.as_edit().is_none());
+    let edits = result.as_edits().expect("multi-edit payload");
+    assert_eq!(edits.len(), 2);
+    assert!(edits.iter().all(|edit| edit.file_path == "/repo/src/lib.rs"));
+    assert_eq!(
+        (edits[0].old_string.as_str(), edits[0].new_string.as_str()),
+        ("fn a() {}", "fn b() {}")
+    );
+    assert!(!edits[0].replace_all);
+    assert_eq!((edits[1].old_string.as_str(), edits[1].new_string.as_str()), ("x", "y"));
+    assert!(edits[1].replace_all);
+
+    // A single Edit result comes back as one edit
+    let single: ToolResultBlock = serde_json::from_value(json!({
+        "tool_use_id": "toolu_edit",
+        "content": json!({"file_path": "/a", "old_string": "1", "new_string": "2"}).to_string()
+    }))
+    .unwrap();
+    assert_eq!(single.as_edits(), single.as_edit().map(|edit| vec![edit]));
+}
+
+#[test]
+fn test_plain_tool_result_is_not_an_edit() {
+    let data = json!({
+        "type": "user",
+        "message": {
+            "content": [
+                {"type": "tool_result", "tool_use_id": "toolu_read", "content": "File contents here"}
+            ]
+        },
+    });
+
+    let message: Message = serde_json::from_value(data).unwrap();
+
+    let Message::User(user_msg) = message else {
+        panic!("Expected UserMessage");
+    };
+    let MessageContent::Blocks(blocks) = user_msg.content else {
+        panic!("Expected block content");
+    };
+    let ContentBlock::ToolResult(result) = &blocks[0] else {
+        panic!("Expected ToolResultBlock");
+    };
+    assert!(result.as_edit().is_none());
+}
+
+#[test]
+fn test_parse_user_message_with_image() {
+    let data = json!({
+        "type": "user",
+        "message": {
+            "content": [
+                {"type": "text", "text": "What is in this image?"},
+                {
+                    "type": "image",
+                    "source": {"type": "base64", "media_type": "image/png", "data": "iVBORw0KGgo="}
+                }
+            ]
+        },
+    });
+
+    let message: Message = serde_json::from_value(data.clone()).unwrap();
+
+    if let Message::User(user_msg) = &message {
+        if let MessageContent::Blocks(blocks) = &user_msg.content {
+            assert_eq!(blocks.len(), 2);
+            if let ContentBlock::Image(image) = &blocks[1] {
+                assert_eq!(
+                    image.source,
+                    ImageSource::Base64 {
+                        media_type: "image/png".to_string(),
+                        data: "iVBORw0KGgo=".to_string(),
+                    }
+                );
+            } else {
+                panic!("Expected ImageBlock");
+            }
+        } else {
+            panic!("Expected block content");
+        }
+    } else {
+        panic!("Expected UserMessage");
+    }
+
+    let round_trip = serde_json::to_value(&message).unwrap();
+    assert_eq!(round_trip["message"]["content"][1], data["message"]["content"][1]);
+}
+
+#[test]
+fn test_parse_assistant_message_with_url_image() {
+    let data = json!({
+        "type": "assistant",
+        "message": {
+            "model": "claude-opus-4-1-20250805",
+            "content": [
+                {"type": "image", "source": {"type": "url", "url": "https://example.com/a.png"}}
+            ]
+        }
+    });
+
+    let message: Message = serde_json::from_value(data).unwrap();
+
+    if let Message::Assistant(assistant_msg) = message {
+        if let ContentBlock::Image(image) = &assistant_msg.content[0] {
+            assert_eq!(
+                image.source,
+                ImageSource::Url { url: "https://example.com/a.png".to_string() }
+            );
+        } else {
+            panic!("Expected ImageBlock");
+        }
+    } else {
+        panic!("Expected AssistantMessage");
+    }
+}
+
 #[test]
 fn test_parse_user_message_inside_subagent() {
     let data = json!({
@@ -206,21 +398,40 @@ fn test_parse_assistant_message_with_thinking() {
 
 #[test]
 fn test_parse_valid_system_message() {
-    let _data = json!({
+    let data = json!({
         "type": "system",
         "subtype": "start"
     });
 
-    // Note: Rust SystemMessage struct expects "data" field?
-    // Let's check message.rs: pub struct SystemMessage { pub subtype: String, pub data: serde_json::Value }
-    // If "data" is missing in JSON, deserialization fails if not optional/default.
-    // Python test says: {"type": "system", "subtype": "start"}
-    // Rust struct has "data".
-    // I should check if "data" should be optional or use #[serde(flatten)] for extra fields.
-    // For now, let's try to parse. If it fails, I need to update SystemMessage.
+    let message: Message = serde_json::from_value(data).unwrap();
+
+    if let Message::System(system_msg) = message {
+        assert_eq!(system_msg.subtype, "start");
+        assert!(system_msg.data.is_null());
+        assert!(system_msg.extra.is_empty());
+    } else {
+        panic!("Expected SystemMessage");
+    }
+}
+
+#[test]
+fn test_parse_system_message_preserves_extra_fields() {
+    let data = json!({
+        "type": "system",
+        "subtype": "init",
+        "session_id": "session_123",
+        "model": "claude-sonnet-4-5"
+    });
+
+    let message: Message = serde_json::from_value(data).unwrap();
 
-    // Assumption: Update SystemMessage to have optional data or use flatten.
-    // But wait, creating a separate test file to verify SystemMessage structure.
+    if let Message::System(system_msg) = message {
+        assert_eq!(system_msg.subtype, "init");
+        assert_eq!(system_msg.extra.get("session_id"), Some(&json!("session_123")));
+        assert_eq!(system_msg.extra.get("model"), Some(&json!("claude-sonnet-4-5")));
+    } else {
+        panic!("Expected SystemMessage");
+    }
 }
 
 #[test]
@@ -245,3 +456,91 @@ fn test_parse_valid_result_message() {
         panic!("Expected ResultMessage");
     }
 }
+
+#[test]
+fn test_parse_result_message_with_structured_output() {
+    let data = json!({
+        "type": "result",
+        "subtype": "success",
+        "duration_ms": 1000,
+        "duration_api_ms": 500,
+        "is_error": false,
+        "num_turns": 1,
+        "session_id": "session_123",
+        "result": "{\"answer\": 4}",
+        "structured_output": {"answer": 4}
+    });
+
+    let message: Message = serde_json::from_value(data).unwrap();
+
+    if let Message::Result(result_msg) = message {
+        assert_eq!(result_msg.structured_output, Some(json!({"answer": 4})));
+    } else {
+        panic!("Expected ResultMessage");
+    }
+}
+
+#[test]
+fn test_parse_system_init_message() {
+    use claude_agent::types::message::{SystemInit, SystemInitMcpServer};
+
+    let data = json!({
+        "type": "system",
+        "subtype": "init",
+        "cwd": "/home/dev/project",
+        "session_id": "9c4f6a2e-1b7d-4e8a-a3f0-5d2c8b1e7f40",
+        "tools": ["Task", "Bash", "Glob", "Grep", "Read", "Edit", "Write", "mcp__github__search"],
+        "mcp_servers": [
+            {"name": "github", "status": "connected"},
+            {"name": "jira", "status": "failed"}
+        ],
+        "model": "claude-sonnet-4-5-20250929",
+        "permissionMode": "default",
+        "slash_commands": ["compact", "context", "cost"],
+        "apiKeySource": "ANTHROPIC_API_KEY",
+        "claude_code_version": "2.0.14",
+        "output_style": "default",
+        "uuid": "3f0d1c5e-77aa-4b61-9c0e-2a8f4e6d9b13"
+    });
+
+    let init = SystemInit::from_message(&data).expect("init message");
+    assert_eq!(init.session_id.as_deref(), Some("9c4f6a2e-1b7d-4e8a-a3f0-5d2c8b1e7f40"));
+    assert_eq!(init.model.as_deref(), Some("claude-sonnet-4-5-20250929"));
+    assert_eq!(init.cwd.as_deref(), Some("/home/dev/project"));
+    assert_eq!(init.tools.len(), 8);
+    assert_eq!(
+        init.mcp_servers,
+        vec![
+            SystemInitMcpServer { name: "github".to_string(), status: "connected".to_string() },
+            SystemInitMcpServer { name: "jira".to_string(), status: "failed".to_string() },
+        ]
+    );
+    assert_eq!(init.permission_mode.as_deref(), Some("default"));
+    assert_eq!(init.api_key_source.as_deref(), Some("ANTHROPIC_API_KEY"));
+    assert_eq!(init.slash_commands, vec!["compact", "context", "cost"]);
+    assert_eq!(init.extra["claude_code_version"], "2.0.14");
+    assert!(!init.extra.contains_key("type"));
+
+    // The same payload through the generic message type
+    let Message::System(system) = serde_json::from_value(data).unwrap() else {
+        panic!("Expected SystemMessage");
+    };
+    assert_eq!(system.init(), Some(init));
+
+    // Other system messages are not init
+    assert!(SystemInit::from_message(&json!({"type": "system", "subtype": "status"})).is_none());
+}
+
+#[test]
+fn test_parse_legacy_system_init_under_data() {
+    use claude_agent::types::message::SystemInit;
+
+    let data = json!({
+        "type": "system",
+        "subtype": "init",
+        "data": {"session_id": "old-1", "model": "m", "tools": ["Read"]}
+    });
+    let init = SystemInit::from_message(&data).unwrap();
+    assert_eq!(init.session_id.as_deref(), Some("old-1"));
+    assert_eq!(init.tools, vec!["Read"]);
+}
@@ -0,0 +1,83 @@
+//! Tests for how the query stream treats recoverable and fatal errors.
+
+use async_trait::async_trait;
+use claude_agent::core::ClaudeAgent;
+use claude_agent::transport::Transport;
+use claude_agent::types::{ClaudeAgentError, Message};
+use claude_agent::ClaudeAgentOptions;
+use futures::stream::{self, BoxStream};
+use futures::StreamExt;
+use serde_json::json;
+
+/// Replays a fixed sequence of read results to every reader.
+struct ScriptedTransport {
+    script: Vec<Result<serde_json::Value, ClaudeAgentError>>,
+}
+
+#[async_trait]
+impl Transport for ScriptedTransport {
+    async fn connect(&mut self) -> Result<(), ClaudeAgentError> {
+        Ok(())
+    }
+
+    async fn write(&self, _data: &str) -> Result<(), ClaudeAgentError> {
+        Ok(())
+    }
+
+    async fn read_messages(&self) -> BoxStream<'_, Result<serde_json::Value, ClaudeAgentError>> {
+        Box::pin(stream::iter(self.script.clone()))
+    }
+
+    async fn close(&mut self) -> Result<(), ClaudeAgentError> {
+        Ok(())
+    }
+}
+
+fn assistant(text: &str) -> serde_json::Value {
+    json!({
+        "type": "assistant",
+        "message": {"content": [{"type": "text", "text": text}], "role": "assistant", "model": "m"}
+    })
+}
+
+async fn run_query(
+    script: Vec<Result<serde_json::Value, ClaudeAgentError>>,
+) -> Vec<Result<Message, ClaudeAgentError>> {
+    let mut agent = ClaudeAgent::new(ClaudeAgentOptions::default());
+    agent.set_transport(Box::new(ScriptedTransport { script }));
+    agent.connect(None).await.unwrap();
+    let items = agent.query("hi").await.unwrap().collect().await;
+    agent.disconnect().await.unwrap();
+    items
+}
+
+#[tokio::test]
+async fn test_stream_recovers_after_garbage_line() {
+    let items = run_query(vec![
+        Ok(assistant("before")),
+        Err(ClaudeAgentError::JSONDecode("Parse error: not json".to_string())),
+        Ok(json!({"type": "assistant", "message": "not an object"})),
+        Ok(assistant("after")),
+    ])
+    .await;
+
+    assert_eq!(items.len(), 4);
+    assert_eq!(items[0].as_ref().unwrap().text().as_deref(), Some("before"));
+    assert!(matches!(items[1], Err(ClaudeAgentError::JSONDecode(_))));
+    assert!(matches!(items[2], Err(ClaudeAgentError::MessageParse(_))));
+    assert_eq!(items[3].as_ref().unwrap().text().as_deref(), Some("after"));
+}
+
+#[tokio::test]
+async fn test_stream_ends_after_transport_error() {
+    let items = run_query(vec![
+        Ok(assistant("before")),
+        Err(ClaudeAgentError::Transport("broken pipe".to_string())),
+        Ok(assistant("never delivered")),
+    ])
+    .await;
+
+    assert_eq!(items.len(), 2);
+    let err = items[1].as_ref().unwrap_err();
+    assert!(err.is_fatal());
+}
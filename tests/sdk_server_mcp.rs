@@ -58,3 +58,112 @@ async fn test_tool_error_handling() {
         _ => panic!("Expected message error"),
     }
 }
+
+#[tokio::test]
+async fn test_resources_unsupported_by_default() {
+    let server = SdkMcpServer::new("no-resources");
+    assert!(server.list_resources().await.is_err());
+    assert!(server.read_resource("file:///x").await.is_err());
+
+    let response = server
+        .handle_client_message(serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "resources/list"
+        }))
+        .await
+        .unwrap();
+    assert!(response["error"]["message"].as_str().unwrap().contains("does not support resources"));
+}
+
+#[tokio::test]
+async fn test_initialize_advertises_only_tools_by_default() {
+    let server = SdkMcpServer::new("plain");
+    let response = server
+        .handle_client_message(json!({"jsonrpc": "2.0", "id": 0, "method": "initialize"}))
+        .await
+        .unwrap();
+    assert_eq!(response["result"]["capabilities"], json!({"tools": {}}));
+}
+
+#[tokio::test]
+async fn test_prompts_unsupported_by_default() {
+    let server = SdkMcpServer::new("no-prompts");
+    assert!(server.list_prompts().await.is_err());
+    assert!(server.get_prompt("x", Default::default()).await.is_err());
+}
+
+#[tokio::test]
+async fn test_sdk_server_loopback_through_control_requests() {
+    use claude_agent::core::ClaudeAgent;
+    use claude_agent::transport::MockTransport;
+    use claude_agent::ClaudeAgentOptions;
+    use std::time::Duration;
+
+    let mut server = SdkMcpServer::new("calc");
+    server.register_tool("add", None, json!({"type": "object"}), |args| {
+        Box::pin(async move {
+            let sum = args["a"].as_i64().unwrap_or(0) + args["b"].as_i64().unwrap_or(0);
+            Ok(json!({"content": [{"type": "text", "text": sum.to_string()}]}))
+        })
+    });
+
+    let mut agent = ClaudeAgent::new(ClaudeAgentOptions::default());
+    agent.add_mcp_server(Box::new(server)).await.unwrap();
+    assert_eq!(agent.options().mcp_servers["calc"], json!({"type": "sdk", "name": "calc"}));
+
+    let transport = MockTransport::new(vec![]);
+    agent.set_transport(Box::new(transport.clone()));
+    agent.connect(None).await.unwrap();
+
+    transport.push_incoming(json!({
+        "type": "control_request",
+        "request_id": "mcp-1",
+        "request": {
+            "subtype": "mcp_message",
+            "server_name": "calc",
+            "message": {
+                "jsonrpc": "2.0",
+                "id": 7,
+                "method": "tools/call",
+                "params": {"name": "add", "arguments": {"a": 2, "b": 3}}
+            }
+        }
+    }));
+
+    let response = tokio::time::timeout(Duration::from_secs(2), async {
+        loop {
+            let reply = transport.sent_messages().iter().find_map(|sent| {
+                let value: serde_json::Value = serde_json::from_str(sent).ok()?;
+                (value["response"]["request_id"] == "mcp-1").then_some(value)
+            });
+            if let Some(reply) = reply {
+                return reply;
+            }
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+    })
+    .await
+    .expect("control loop should answer the mcp_message");
+
+    let mcp_response = &response["response"]["response"]["mcp_response"];
+    assert_eq!(mcp_response["id"], 7);
+    assert_eq!(mcp_response["result"]["content"][0]["text"], "5");
+
+    let err = agent
+        .add_mcp_server(Box::new(SdkMcpServer::new("late")))
+        .await
+        .expect_err("servers cannot be added after connecting");
+    assert!(matches!(err, ClaudeAgentError::Config(_)));
+    agent.disconnect().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_sdk_server_acknowledges_notifications() {
+    let server = SdkMcpServer::new("quiet");
+    let reply = server
+        .handle_client_message(json!({"jsonrpc": "2.0", "method": "notifications/initialized"}))
+        .await
+        .unwrap();
+    assert!(reply.get("error").is_none());
+}
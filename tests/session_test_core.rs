@@ -173,3 +173,187 @@ fn manager_current_session_mut() {
     mgr.current_session_mut().unwrap().deactivate();
     assert!(!mgr.current_session().unwrap().is_active);
 }
+
+// Persistence tests
+
+#[test]
+fn session_json_round_trip() {
+    let mut session = Session::with_id("persisted");
+    session.add_checkpoint("msg-1".to_string());
+    session.metadata.insert("k".to_string(), serde_json::json!({"nested": true}));
+    session.last_message = Some("hello".to_string());
+    session.deactivate();
+
+    let restored = Session::from_json(&session.to_json().unwrap()).unwrap();
+    assert_eq!(restored.id, "persisted");
+    assert!(!restored.is_active);
+    assert_eq!(restored.checkpoints.len(), 1);
+    assert_eq!(restored.checkpoints[0].user_message_id, "msg-1");
+    assert_eq!(restored.checkpoints[0].timestamp, session.checkpoints[0].timestamp);
+    assert_eq!(restored.metadata.get("k"), Some(&serde_json::json!({"nested": true})));
+    assert_eq!(restored.last_message.as_deref(), Some("hello"));
+}
+
+#[test]
+fn session_from_json_fills_optional_fields() {
+    let session = Session::from_json(r#"{"id": "minimal", "is_active": true}"#).unwrap();
+    assert_eq!(session.id, "minimal");
+    assert!(session.checkpoints.is_empty());
+    assert!(session.metadata.is_empty());
+    assert!(session.last_message.is_none());
+}
+
+#[test]
+fn session_from_json_rejects_invalid_input() {
+    let err = Session::from_json("not json").unwrap_err();
+    assert!(matches!(err, claude_agent::types::ClaudeAgentError::JSONDecode(_)));
+}
+
+#[test]
+fn manager_save_and_load_round_trip() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("sessions.json");
+
+    let mut mgr = SessionManager::new();
+    let first = mgr.create_session().id.clone();
+    mgr.create_session_with_id("second");
+    mgr.current_session_mut().unwrap().add_checkpoint("cp".to_string());
+    mgr.current_session_mut().unwrap().cli_session_id = Some("cli-7".to_string());
+    mgr.save_to_path(&path).unwrap();
+
+    let loaded = SessionManager::load_from_path(&path).unwrap();
+    assert_eq!(loaded.current_session().unwrap().id, "second");
+    assert_eq!(loaded.current_session().unwrap().cli_session_id.as_deref(), Some("cli-7"));
+    assert_eq!(loaded.current_session().unwrap().checkpoints.len(), 1);
+    assert!(loaded.get_session(&first).is_some());
+}
+
+#[test]
+fn manager_load_missing_file_errors() {
+    let dir = tempfile::tempdir().unwrap();
+    let err = SessionManager::load_from_path(dir.path().join("missing.json")).unwrap_err();
+    assert!(matches!(err, claude_agent::types::ClaudeAgentError::Io(_)), "got {err:?}");
+}
+
+#[test]
+fn manager_load_invalid_file_is_a_config_error() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("sessions.json");
+    std::fs::write(&path, "not json").unwrap();
+    let err = SessionManager::load_from_path(&path).unwrap_err();
+    assert!(matches!(err, claude_agent::types::ClaudeAgentError::Config(_)), "got {err:?}");
+}
+
+#[tokio::test]
+async fn agent_saved_session_keeps_the_cli_session_id() {
+    use claude_agent::core::ClaudeAgent;
+    use claude_agent::transport::MockTransport;
+    use claude_agent::ClaudeAgentOptions;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("sessions.json");
+    let mock = MockTransport::new(vec![]);
+    let mut agent = ClaudeAgent::new(ClaudeAgentOptions::default());
+    agent.set_transport(Box::new(mock.clone()));
+    agent.connect(None).await.unwrap();
+    mock.push_incoming(serde_json::json!({
+        "type": "system",
+        "subtype": "init",
+        "session_id": "cli-session-42"
+    }));
+    tokio::time::timeout(std::time::Duration::from_secs(2), async {
+        while agent.cli_session_id().is_none() {
+            tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+        }
+    })
+    .await
+    .expect("the agent should see the CLI session id");
+    agent.save_sessions(&path).unwrap();
+
+    let loaded = SessionManager::load_from_path(&path).unwrap();
+    let session = loaded.current_session().unwrap();
+    assert_ne!(session.id, "cli-session-42");
+    assert_eq!(session.cli_session_id.as_deref(), Some("cli-session-42"));
+}
+
+#[tokio::test]
+async fn agent_records_last_message() {
+    use claude_agent::core::ClaudeAgent;
+    use claude_agent::transport::MockTransport;
+    use claude_agent::ClaudeAgentOptions;
+
+    let mut agent = ClaudeAgent::new(ClaudeAgentOptions::default());
+    agent.set_transport(Box::new(MockTransport::new(vec![])));
+    agent.connect(None).await.unwrap();
+    drop(agent.query("remember me").await.unwrap());
+
+    let session = agent.current_session().unwrap();
+    assert_eq!(session.last_message.as_deref(), Some("remember me"));
+}
+
+// Multiple sessions on one agent
+
+fn sent_session_ids(mock: &claude_agent::transport::MockTransport) -> Vec<String> {
+    mock.sent_messages()
+        .iter()
+        .filter_map(|sent| serde_json::from_str::<serde_json::Value>(sent).ok())
+        .filter(|value| value["type"] == "user")
+        .map(|value| value["session_id"].as_str().unwrap_or_default().to_string())
+        .collect()
+}
+
+#[tokio::test]
+async fn agent_switch_session_attributes_prompts() {
+    use claude_agent::core::ClaudeAgent;
+    use claude_agent::transport::MockTransport;
+    use claude_agent::ClaudeAgentOptions;
+
+    let mock = MockTransport::new(vec![]);
+    let mut agent = ClaudeAgent::new(ClaudeAgentOptions::default());
+    agent.set_transport(Box::new(mock.clone()));
+
+    agent.switch_session("chat-a");
+    agent.connect(None).await.unwrap();
+    drop(agent.query("first for a").await.unwrap());
+    agent.switch_session("chat-b");
+    drop(agent.query("only for b").await.unwrap());
+    agent.switch_session("chat-a");
+    drop(agent.query("second for a").await.unwrap());
+
+    assert_eq!(sent_session_ids(&mock), vec!["chat-a", "chat-b", "chat-a"]);
+
+    let ids: Vec<&str> = agent.list_sessions().iter().map(|s| s.id.as_str()).collect();
+    assert_eq!(ids, vec!["chat-a", "chat-b"]);
+    assert_eq!(agent.current_session().unwrap().id, "chat-a");
+    let last = |id: &str| {
+        agent.list_sessions().into_iter().find(|s| s.id == id).unwrap().last_message.clone()
+    };
+    assert_eq!(last("chat-a").as_deref(), Some("second for a"));
+    assert_eq!(last("chat-b").as_deref(), Some("only for b"));
+}
+
+#[tokio::test]
+async fn agent_connect_creates_session_when_none_selected() {
+    use claude_agent::core::ClaudeAgent;
+    use claude_agent::transport::MockTransport;
+    use claude_agent::ClaudeAgentOptions;
+
+    let mock = MockTransport::new(vec![]);
+    let mut agent = ClaudeAgent::new(ClaudeAgentOptions::default());
+    agent.set_transport(Box::new(mock.clone()));
+    agent.connect(None).await.unwrap();
+    drop(agent.query("hello").await.unwrap());
+
+    let sessions = agent.list_sessions();
+    assert_eq!(sessions.len(), 1);
+    assert_eq!(sent_session_ids(&mock), vec![sessions[0].id.clone()]);
+}
+
+#[test]
+fn manager_list_sessions_sorted_by_id() {
+    let mut mgr = SessionManager::new();
+    mgr.create_session_with_id("b");
+    mgr.create_session_with_id("a");
+    let ids: Vec<&str> = mgr.list_sessions().iter().map(|s| s.id.as_str()).collect();
+    assert_eq!(ids, vec!["a", "b"]);
+}
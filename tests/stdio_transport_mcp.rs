@@ -0,0 +1,527 @@
+//! Integration tests for the stdio MCP transport.
+//!
+//! These avoid a live MCP server: the command does not exist, so calls that get
+//! past the rate limiter fail at spawn time instead.
+
+use claude_agent::mcp::manager::McpServer;
+use claude_agent::mcp::transports::StdioMcpServer;
+use claude_agent::mcp::{is_tool_error, RateLimitConfig, RateLimiter, ToolCallResult};
+use claude_agent::types::ClaudeAgentError;
+use serde_json::json;
+
+#[cfg(unix)]
+mod common_mcp;
+
+fn missing_server() -> StdioMcpServer {
+    StdioMcpServer::new("stdio".to_string(), "/nonexistent/mcp-server".to_string(), vec![]).unwrap()
+}
+
+fn is_rate_limited(error: &ClaudeAgentError) -> bool {
+    matches!(error, ClaudeAgentError::Mcp(msg) if msg.contains("rate limited"))
+}
+
+#[tokio::test]
+async fn test_stdio_rate_limiter_rejects_second_rapid_call() {
+    let server =
+        missing_server().with_rate_limiter(RateLimiter::new(RateLimitConfig::per_second(1)));
+
+    let first = server.call_tool("echo", json!({})).await.unwrap_err();
+    assert!(!is_rate_limited(&first), "first call should reach the server: {first}");
+
+    let second = server.call_tool("echo", json!({})).await.unwrap_err();
+    assert!(is_rate_limited(&second), "second call should be rate limited: {second}");
+}
+
+#[tokio::test]
+async fn test_stdio_without_rate_limiter_is_unthrottled() {
+    let server = missing_server();
+    for _ in 0..3 {
+        let error = server.call_tool("echo", json!({})).await.unwrap_err();
+        assert!(!is_rate_limited(&error));
+    }
+}
+
+#[cfg(unix)]
+fn echo_tool_server(dir: &std::path::Path) -> common_mcp::MockMcpServer {
+    let tools = json!({"tools": [{
+        "name": "echo",
+        "inputSchema": {
+            "type": "object",
+            "properties": {"message": {"type": "string"}, "count": {"type": "integer"}},
+            "required": ["message"]
+        }
+    }]});
+    let call = json!({"content": [{"type": "text", "text": "ok"}]});
+    common_mcp::mock_mcp_server(
+        dir,
+        &[("tools/list", tools.to_string()), ("tools/call", call.to_string())],
+    )
+}
+
+#[cfg(unix)]
+fn validating_server(mock: &common_mcp::MockMcpServer) -> StdioMcpServer {
+    StdioMcpServer::new("mock".to_string(), mock.script.display().to_string(), vec![])
+        .unwrap()
+        .with_argument_validation(true)
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_stdio_validation_rejects_missing_required_field() {
+    let dir = tempfile::tempdir().unwrap();
+    let mock = echo_tool_server(dir.path());
+    let server = validating_server(&mock);
+
+    let error = server.call_tool("echo", json!({"count": 1})).await.unwrap_err();
+    assert!(
+        matches!(&error, ClaudeAgentError::Mcp(msg) if msg.contains("$.message: missing required property")),
+        "unexpected error: {error}"
+    );
+    assert_eq!(mock.count("tools/call"), 0);
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_stdio_validation_rejects_wrong_type() {
+    let dir = tempfile::tempdir().unwrap();
+    let mock = echo_tool_server(dir.path());
+    let server = validating_server(&mock);
+
+    let error =
+        server.call_tool("echo", json!({"message": "hi", "count": "two"})).await.unwrap_err();
+    assert!(
+        matches!(&error, ClaudeAgentError::Mcp(msg) if msg.contains("$.count: expected integer, got string")),
+        "unexpected error: {error}"
+    );
+    assert_eq!(mock.count("tools/call"), 0);
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_stdio_validation_forwards_valid_arguments() {
+    let dir = tempfile::tempdir().unwrap();
+    let mock = echo_tool_server(dir.path());
+    let server = validating_server(&mock);
+
+    let result = server.call_tool("echo", json!({"message": "hi"})).await.unwrap();
+    assert_eq!(result["content"][0]["text"], "ok");
+    assert_eq!(mock.count("tools/call"), 1);
+}
+
+#[cfg(unix)]
+fn resource_server(dir: &std::path::Path) -> common_mcp::MockMcpServer {
+    let list = json!({"resources": [
+        {"uri": "file:///docs/readme.md", "name": "readme", "mimeType": "text/markdown"},
+        {"uri": "file:///docs/logo.png", "name": "logo", "description": "Project logo"}
+    ]});
+    let read = json!({"contents": [
+        {"uri": "file:///docs/readme.md", "mimeType": "text/markdown", "text": "# Hello"}
+    ]});
+    common_mcp::mock_mcp_server(
+        dir,
+        &[("resources/list", list.to_string()), ("resources/read", read.to_string())],
+    )
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_stdio_lists_and_reads_resources() {
+    let dir = tempfile::tempdir().unwrap();
+    let mock = resource_server(dir.path());
+    let server =
+        StdioMcpServer::new("docs".to_string(), mock.script.display().to_string(), vec![]).unwrap();
+
+    let resources = server.list_resources().await.unwrap();
+    assert_eq!(resources.len(), 2);
+    assert_eq!(resources[0].uri, "file:///docs/readme.md");
+    assert_eq!(resources[0].mime_type.as_deref(), Some("text/markdown"));
+    assert_eq!(resources[1].description.as_deref(), Some("Project logo"));
+
+    let contents = server.read_resource("file:///docs/readme.md").await.unwrap();
+    assert_eq!(contents.len(), 1);
+    assert_eq!(contents[0].text.as_deref(), Some("# Hello"));
+    assert!(contents[0].blob.is_none());
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_stdio_routes_resource_messages() {
+    let dir = tempfile::tempdir().unwrap();
+    let mock = resource_server(dir.path());
+    let server =
+        StdioMcpServer::new("docs".to_string(), mock.script.display().to_string(), vec![]).unwrap();
+
+    let listed = server
+        .handle_client_message(json!({"jsonrpc": "2.0", "id": 7, "method": "resources/list"}))
+        .await
+        .unwrap();
+    assert_eq!(listed["id"], 7);
+    assert_eq!(listed["result"]["resources"][1]["name"], "logo");
+
+    let read = server
+        .handle_client_message(json!({
+            "jsonrpc": "2.0",
+            "id": 8,
+            "method": "resources/read",
+            "params": {"uri": "file:///docs/readme.md"}
+        }))
+        .await
+        .unwrap();
+    assert_eq!(read["result"]["contents"][0]["text"], "# Hello");
+
+    let initialized = server
+        .handle_client_message(json!({"jsonrpc": "2.0", "id": 0, "method": "initialize"}))
+        .await
+        .unwrap();
+    assert!(initialized["result"]["capabilities"]["resources"].is_object());
+}
+
+#[cfg(unix)]
+fn prompt_server(dir: &std::path::Path) -> common_mcp::MockMcpServer {
+    let list = json!({"prompts": [
+        {"name": "review", "description": "Review code", "arguments": [
+            {"name": "language", "description": "Source language", "required": true}
+        ]},
+        {"name": "summarize"}
+    ]});
+    let get = json!({
+        "description": "Review Rust code",
+        "messages": [
+            {"role": "user", "content": {"type": "text", "text": "Review this rust code"}}
+        ]
+    });
+    common_mcp::mock_mcp_server(
+        dir,
+        &[("prompts/list", list.to_string()), ("prompts/get", get.to_string())],
+    )
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_stdio_lists_prompts() {
+    let dir = tempfile::tempdir().unwrap();
+    let mock = prompt_server(dir.path());
+    let server =
+        StdioMcpServer::new("prompts".to_string(), mock.script.display().to_string(), vec![])
+            .unwrap();
+
+    let prompts = server.list_prompts().await.unwrap();
+    assert_eq!(prompts.len(), 2);
+    assert_eq!(prompts[0].name, "review");
+    assert_eq!(prompts[0].arguments[0].name, "language");
+    assert!(prompts[0].arguments[0].required);
+    assert!(prompts[1].arguments.is_empty());
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_stdio_renders_parameterized_prompt() {
+    use claude_agent::mcp::PromptContent;
+    use std::collections::HashMap;
+
+    let dir = tempfile::tempdir().unwrap();
+    let mock = prompt_server(dir.path());
+    let server =
+        StdioMcpServer::new("prompts".to_string(), mock.script.display().to_string(), vec![])
+            .unwrap();
+
+    let args = HashMap::from([("language".to_string(), "rust".to_string())]);
+    let prompt = server.get_prompt("review", args).await.unwrap();
+    assert_eq!(prompt.description.as_deref(), Some("Review Rust code"));
+    assert_eq!(prompt.messages.len(), 1);
+    assert_eq!(prompt.messages[0].role, "user");
+    assert_eq!(
+        prompt.messages[0].content,
+        PromptContent::Text { text: "Review this rust code".to_string() }
+    );
+
+    let routed = server
+        .handle_client_message(json!({
+            "jsonrpc": "2.0",
+            "id": 3,
+            "method": "prompts/get",
+            "params": {"name": "review", "arguments": {"language": "rust"}}
+        }))
+        .await
+        .unwrap();
+    assert_eq!(routed["result"]["messages"][0]["content"]["text"], "Review this rust code");
+
+    let initialized = server
+        .handle_client_message(json!({"jsonrpc": "2.0", "id": 0, "method": "initialize"}))
+        .await
+        .unwrap();
+    assert!(initialized["result"]["capabilities"]["prompts"].is_object());
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_stdio_delivers_server_notifications() {
+    use std::time::Duration;
+
+    let dir = tempfile::tempdir().unwrap();
+    let mock = common_mcp::mock_mcp_server_with_notifications(
+        dir.path(),
+        &[("tools/list", json!({"tools": []}).to_string())],
+        &[
+            (
+                "notifications/initialized",
+                json!({"jsonrpc": "2.0", "method": "notifications/tools/list_changed"})
+                    .to_string(),
+            ),
+            (
+                "notifications/initialized",
+                json!({"jsonrpc": "2.0", "method": "notifications/indexing", "params": {"files": 3}})
+                    .to_string(),
+            ),
+        ],
+    );
+    let server =
+        StdioMcpServer::new("notify".to_string(), mock.script.display().to_string(), vec![])
+            .unwrap();
+    let mut notifications = server.subscribe_notifications();
+
+    server.list_tools().await.unwrap();
+
+    let first = tokio::time::timeout(Duration::from_secs(2), notifications.recv())
+        .await
+        .expect("notification should arrive")
+        .unwrap();
+    assert_eq!(first.method, "notifications/tools/list_changed");
+    assert!(first.params.is_null());
+
+    let second = tokio::time::timeout(Duration::from_secs(2), notifications.recv())
+        .await
+        .expect("notification should arrive")
+        .unwrap();
+    assert_eq!(second.method, "notifications/indexing");
+    assert_eq!(second.params, json!({"files": 3}));
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_stdio_tool_cache_queries_once_until_refresh() {
+    let dir = tempfile::tempdir().unwrap();
+    let mock = echo_tool_server(dir.path());
+    let server =
+        StdioMcpServer::new("cached".to_string(), mock.script.display().to_string(), vec![])
+            .unwrap()
+            .with_tool_cache(true);
+
+    assert_eq!(server.list_tools().await.unwrap().len(), 1);
+    assert_eq!(server.list_tools().await.unwrap()[0].name, "echo");
+    assert_eq!(mock.count("tools/list"), 1);
+
+    server.refresh_tools().await.unwrap();
+    server.list_tools().await.unwrap();
+    assert_eq!(mock.count("tools/list"), 2);
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_stdio_tool_cache_invalidated_by_list_changed() {
+    use std::time::Duration;
+
+    let dir = tempfile::tempdir().unwrap();
+    let mock = common_mcp::mock_mcp_server_with_notifications(
+        dir.path(),
+        &[
+            ("tools/list", json!({"tools": []}).to_string()),
+            ("tools/call", json!({"content": [{"type": "text", "text": "ok"}]}).to_string()),
+        ],
+        &[(
+            "tools/call",
+            json!({"jsonrpc": "2.0", "method": "notifications/tools/list_changed"}).to_string(),
+        )],
+    );
+    let server =
+        StdioMcpServer::new("cached".to_string(), mock.script.display().to_string(), vec![])
+            .unwrap()
+            .with_tool_cache(true);
+    let mut notifications = server.subscribe_notifications();
+
+    server.list_tools().await.unwrap();
+    server.list_tools().await.unwrap();
+    assert_eq!(mock.count("tools/list"), 1);
+
+    server.call_tool("anything", json!({})).await.unwrap();
+    let notification = tokio::time::timeout(Duration::from_secs(2), notifications.recv())
+        .await
+        .expect("list_changed should arrive")
+        .unwrap();
+    assert_eq!(notification.method, "notifications/tools/list_changed");
+
+    server.list_tools().await.unwrap();
+    assert_eq!(mock.count("tools/list"), 2);
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_stdio_without_tool_cache_always_queries() {
+    let dir = tempfile::tempdir().unwrap();
+    let mock = echo_tool_server(dir.path());
+    let server =
+        StdioMcpServer::new("uncached".to_string(), mock.script.display().to_string(), vec![])
+            .unwrap();
+
+    server.list_tools().await.unwrap();
+    server.list_tools().await.unwrap();
+    assert_eq!(mock.count("tools/list"), 2);
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_auto_transport_falls_back_to_stdio() {
+    use claude_agent::mcp::create_mcp_server;
+    use claude_agent::types::config::{McpServerConfig, McpTransportType};
+
+    let dir = tempfile::tempdir().unwrap();
+    let mock = echo_tool_server(dir.path());
+    let config = McpServerConfig {
+        transport: McpTransportType::Auto,
+        // Nothing listens on port 1, so the HTTP attempt fails fast.
+        url: Some("http://127.0.0.1:1/mcp".to_string()),
+        command: Some(mock.script.display().to_string()),
+        ..Default::default()
+    };
+    let server = create_mcp_server("auto".to_string(), config).unwrap();
+
+    let tools = server.list_tools().await.unwrap();
+    assert_eq!(tools[0].name, "echo");
+    assert_eq!(mock.count("tools/list"), 1);
+}
+
+#[cfg(unix)]
+fn is_running(pid: u32) -> bool {
+    std::process::Command::new("kill")
+        .args(["-0", &pid.to_string()])
+        .stderr(std::process::Stdio::null())
+        .status()
+        .map(|status| status.success())
+        .unwrap_or(false)
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_stdio_shutdown_reaps_subprocess() {
+    let dir = tempfile::tempdir().unwrap();
+    let mock = echo_tool_server(dir.path());
+    let server =
+        StdioMcpServer::new("mock".to_string(), mock.script.display().to_string(), vec![]).unwrap();
+
+    server.list_tools().await.unwrap();
+    let pid = server.pid().await.expect("subprocess should be running");
+    assert!(is_running(pid));
+
+    server.shutdown().await.unwrap();
+    assert!(!is_running(pid), "subprocess {pid} should be gone");
+    assert_eq!(server.pid().await, None);
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_stdio_shutdown_terminates_server_ignoring_stdin_close() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    let mock = echo_tool_server(dir.path());
+    // Keep running after stdin closes, so only the signal stops it.
+    let lingering = dir.path().join("lingering.sh");
+    std::fs::write(&lingering, format!("#!/bin/sh\n'{}'\nexec sleep 30\n", mock.script.display()))
+        .unwrap();
+    std::fs::set_permissions(&lingering, std::fs::Permissions::from_mode(0o755)).unwrap();
+    let server =
+        StdioMcpServer::new("lingering".to_string(), lingering.display().to_string(), vec![])
+            .unwrap();
+
+    server.list_tools().await.unwrap();
+    let pid = server.pid().await.unwrap();
+
+    server.shutdown().await.unwrap();
+    assert!(!is_running(pid), "subprocess {pid} should be gone");
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_manager_deregister_shuts_down_stdio_server() {
+    use claude_agent::mcp::McpServerManager;
+    use std::sync::Arc;
+
+    let dir = tempfile::tempdir().unwrap();
+    let mock = echo_tool_server(dir.path());
+    let server = Arc::new(
+        StdioMcpServer::new("mock".to_string(), mock.script.display().to_string(), vec![]).unwrap(),
+    );
+    server.list_tools().await.unwrap();
+    let pid = server.pid().await.unwrap();
+
+    let manager = McpServerManager::new();
+    manager.register(Box::new(SharedServer(server.clone()))).await;
+
+    assert!(manager.deregister("mock").await.unwrap());
+    assert!(manager.list_servers().await.is_empty());
+    assert!(!is_running(pid), "subprocess {pid} should be gone");
+    assert!(!manager.deregister("mock").await.unwrap());
+}
+
+/// Registers a server with the manager while the test keeps a handle to it.
+#[cfg(unix)]
+struct SharedServer(std::sync::Arc<StdioMcpServer>);
+
+#[cfg(unix)]
+#[async_trait::async_trait]
+impl McpServer for SharedServer {
+    fn name(&self) -> &str {
+        self.0.name()
+    }
+
+    async fn list_tools(&self) -> Result<Vec<claude_agent::mcp::ToolInfo>, ClaudeAgentError> {
+        self.0.list_tools().await
+    }
+
+    async fn call_tool(
+        &self,
+        name: &str,
+        arguments: serde_json::Value,
+    ) -> Result<serde_json::Value, ClaudeAgentError> {
+        self.0.call_tool(name, arguments).await
+    }
+
+    async fn shutdown(&self) -> Result<(), ClaudeAgentError> {
+        self.0.shutdown().await
+    }
+}
+
+#[cfg(unix)]
+fn failing_tool_server(dir: &std::path::Path) -> common_mcp::MockMcpServer {
+    let call = json!({"content": [{"type": "text", "text": "disk full"}], "isError": true});
+    common_mcp::mock_mcp_server(dir, &[("tools/call", call.to_string())])
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_stdio_call_tool_success_is_not_tool_error() {
+    let dir = tempfile::tempdir().unwrap();
+    let mock = echo_tool_server(dir.path());
+    let server = validating_server(&mock);
+
+    let result = server.call_tool("echo", json!({"message": "hi"})).await.unwrap();
+    assert!(!is_tool_error(&result));
+    let typed = ToolCallResult::from_value(result).unwrap();
+    assert!(!typed.is_error);
+    assert_eq!(typed.content, vec![json!({"type": "text", "text": "ok"})]);
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_stdio_call_tool_reports_is_error_payload() {
+    let dir = tempfile::tempdir().unwrap();
+    let mock = failing_tool_server(dir.path());
+    let server =
+        StdioMcpServer::new("mock".to_string(), mock.script.display().to_string(), vec![]).unwrap();
+
+    let result = server.call_tool("write", json!({})).await.unwrap();
+    assert!(is_tool_error(&result));
+    let typed = ToolCallResult::from_value(result).unwrap();
+    assert!(typed.is_error);
+    assert_eq!(typed.content[0]["text"], "disk full");
+}
@@ -81,3 +81,181 @@ async fn test_streaming_empty_response() {
     }
     assert_eq!(count, 0);
 }
+
+#[tokio::test]
+async fn test_split_thinking_routes_blocks_to_their_streams() {
+    use claude_agent::api::split_thinking;
+
+    let messages: Vec<Result<Message, ClaudeAgentError>> = vec![
+        json!({
+            "type": "assistant",
+            "message": {
+                "content": [
+                    {"type": "thinking", "thinking": "User wants a greeting.", "signature": "sig"},
+                    {"type": "text", "text": "Hello!"}
+                ],
+                "model": "claude-sonnet-4-5"
+            }
+        }),
+        json!({
+            "type": "assistant",
+            "message": {"content": [{"type": "text", "text": "Anything else?"}], "model": "m"}
+        }),
+        json!({
+            "type": "result",
+            "subtype": "success",
+            "duration_ms": 10,
+            "duration_api_ms": 5,
+            "is_error": false,
+            "num_turns": 1,
+            "session_id": "s1"
+        }),
+        json!({
+            "type": "assistant",
+            "message": {"content": [{"type": "text", "text": "after the result"}], "model": "m"}
+        }),
+    ]
+    .into_iter()
+    .map(|value| Ok(serde_json::from_value(value).unwrap()))
+    .collect();
+
+    let (text, thinking) = split_thinking(Box::pin(stream::iter(messages)));
+
+    // Drain thinking first so text chunks have to be queued for later
+    let thinking: Vec<String> = thinking.map(Result::unwrap).collect().await;
+    let text: Vec<String> = text.map(Result::unwrap).collect().await;
+    assert_eq!(thinking, vec!["User wants a greeting."]);
+    assert_eq!(text, vec!["Hello!", "Anything else?"]);
+}
+
+fn tool_use_message(ids: &[&str]) -> Result<Message, ClaudeAgentError> {
+    let content: Vec<_> = ids
+        .iter()
+        .map(|id| json!({"type": "tool_use", "id": id, "name": "Bash", "input": {"command": id}}))
+        .collect();
+    Ok(serde_json::from_value(
+        json!({"type": "assistant", "message": {"content": content, "model": "m"}}),
+    )
+    .unwrap())
+}
+
+fn tool_result_message(id: &str) -> Result<Message, ClaudeAgentError> {
+    Ok(serde_json::from_value(json!({
+        "type": "user",
+        "message": {"content": [{"type": "tool_result", "tool_use_id": id, "content": format!("out-{id}")}]}
+    }))
+    .unwrap())
+}
+
+fn completed_ids(
+    events: &[Result<claude_agent::api::ToolCallEvent, ClaudeAgentError>],
+) -> Vec<String> {
+    use claude_agent::api::ToolCallEvent;
+
+    events
+        .iter()
+        .map(|event| match event {
+            Ok(ToolCallEvent::Completed { tool_use, result }) => {
+                assert_eq!(tool_use.id, result.tool_use_id);
+                tool_use.id.clone()
+            },
+            other => panic!("expected a completed call, got {:?}", other),
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn test_tool_call_correlator_pairs_in_order_results() {
+    use claude_agent::api::ToolCallCorrelator;
+
+    let messages = vec![
+        tool_use_message(&["a"]),
+        tool_result_message("a"),
+        tool_use_message(&["b"]),
+        tool_result_message("b"),
+    ];
+    let events: Vec<_> =
+        ToolCallCorrelator::default().correlate(Box::pin(stream::iter(messages))).collect().await;
+    assert_eq!(completed_ids(&events), ["a", "b"]);
+}
+
+#[tokio::test]
+async fn test_tool_call_correlator_pairs_out_of_order_results() {
+    use claude_agent::api::ToolCallCorrelator;
+
+    let messages = vec![
+        tool_result_message("c"),
+        tool_use_message(&["a", "b"]),
+        tool_result_message("b"),
+        tool_result_message("a"),
+        tool_use_message(&["c"]),
+    ];
+    let events: Vec<_> =
+        ToolCallCorrelator::default().correlate(Box::pin(stream::iter(messages))).collect().await;
+    assert_eq!(completed_ids(&events), ["b", "a", "c"]);
+}
+
+#[tokio::test]
+async fn test_tool_call_correlator_reports_unmatched_tool_uses() {
+    use claude_agent::api::{ToolCallCorrelator, ToolCallEvent};
+    use std::time::Duration;
+
+    // The source stays open, so "a" can only be released by the timeout
+    let messages = stream::iter(vec![tool_use_message(&["a"])]).chain(stream::pending());
+    let mut events =
+        ToolCallCorrelator::new(Duration::from_millis(20)).correlate(Box::pin(messages));
+    let event = tokio::time::timeout(Duration::from_secs(2), events.next())
+        .await
+        .expect("timed-out tool use should be reported");
+    assert!(
+        matches!(event, Some(Ok(ToolCallEvent::Unmatched(ref tool_use))) if tool_use.id == "a")
+    );
+
+    // Tool uses still pending when the source ends are reported too
+    let messages = vec![tool_use_message(&["b"]), tool_result_message("unknown")];
+    let events: Vec<_> =
+        ToolCallCorrelator::default().correlate(Box::pin(stream::iter(messages))).collect().await;
+    assert_eq!(events.len(), 1);
+    assert!(matches!(&events[0], Ok(ToolCallEvent::Unmatched(tool_use)) if tool_use.id == "b"));
+}
+
+#[tokio::test]
+async fn test_subagent_lineage_attributes_nested_messages() {
+    use claude_agent::api::with_subagent_lineage;
+
+    let assistant =
+        |parent: Option<&str>, tool_use_id: Option<&str>| -> Result<Message, ClaudeAgentError> {
+            let content = match tool_use_id {
+                Some(id) => json!([{"type": "tool_use", "id": id, "name": "Task", "input": {}}]),
+                None => json!([{"type": "text", "text": "done"}]),
+            };
+            Ok(serde_json::from_value(json!({
+                "type": "assistant",
+                "parent_tool_use_id": parent,
+                "message": {"content": content, "model": "m"}
+            }))
+            .unwrap())
+        };
+    let messages = vec![
+        assistant(None, Some("task-1")),
+        assistant(Some("task-1"), Some("task-2")),
+        assistant(Some("task-2"), None),
+        assistant(Some("task-1"), None),
+        assistant(None, None),
+    ];
+
+    let tagged: Vec<_> = with_subagent_lineage(Box::pin(stream::iter(messages)))
+        .map(|item| item.expect("message").lineage)
+        .collect()
+        .await;
+    assert_eq!(
+        tagged,
+        vec![
+            vec![],
+            vec!["task-1".to_string()],
+            vec!["task-1".to_string(), "task-2".to_string()],
+            vec!["task-1".to_string()],
+            vec![],
+        ]
+    );
+}
@@ -1,8 +1,10 @@
 //! Tests for message streaming: MessageSender, MessageReceiver, message_channel.
 
-use claude_agent::core::streaming::message_channel;
+use claude_agent::core::streaming::{message_channel, StreamAccumulator};
+use claude_agent::types::message::ContentBlock;
 use claude_agent::types::{ClaudeAgentError, Message};
 use futures::StreamExt;
+use serde_json::json;
 
 fn make_message() -> Message {
     serde_json::from_value(serde_json::json!({"type": "message_stop"})).expect("valid message")
@@ -102,3 +104,161 @@ async fn small_buffer_channel() {
     });
     assert!(receiver.recv().await.unwrap().is_ok());
 }
+
+// --- StreamAccumulator ---
+
+fn parse(value: serde_json::Value) -> Message {
+    serde_json::from_value(value).expect("valid message")
+}
+
+fn delta_sequence() -> Vec<Message> {
+    vec![
+        parse(json!({"type": "message_start", "message": {"role": "assistant", "content": []}})),
+        parse(json!({
+            "type": "content_block_start",
+            "index": 0,
+            "content_block": {"type": "text", "text": ""}
+        })),
+        parse(json!({
+            "type": "content_block_delta",
+            "index": 0,
+            "delta": {"type": "text_delta", "text": "Hello, "}
+        })),
+        parse(json!({
+            "type": "content_block_delta",
+            "index": 0,
+            "delta": {"type": "text_delta", "text": "world!"}
+        })),
+        parse(json!({"type": "content_block_stop", "index": 0})),
+        parse(json!({
+            "type": "content_block_start",
+            "index": 1,
+            "content_block": {"type": "tool_use", "id": "tool-1", "name": "Read", "input": {}}
+        })),
+        parse(json!({
+            "type": "content_block_delta",
+            "index": 1,
+            "delta": {"type": "input_json_delta", "partial_json": "{\"file_path\": "}
+        })),
+        parse(json!({
+            "type": "content_block_delta",
+            "index": 1,
+            "delta": {"type": "input_json_delta", "partial_json": "\"/tmp/a.txt\"}"}
+        })),
+        parse(json!({"type": "content_block_stop", "index": 1})),
+        parse(json!({
+            "type": "message_delta",
+            "delta": {"stop_reason": "tool_use", "stop_sequence": null},
+            "usage": {"output_tokens": 12}
+        })),
+        parse(json!({"type": "message_stop"})),
+    ]
+}
+
+#[test]
+fn accumulator_reassembles_text_and_tool_input() {
+    let mut accumulator = StreamAccumulator::new();
+    let mut outputs = Vec::new();
+    for message in delta_sequence() {
+        if let Some(out) = accumulator.push(message) {
+            outputs.push(out.unwrap());
+        }
+    }
+
+    assert_eq!(outputs.len(), 1);
+    let Message::Assistant(assistant) = &outputs[0] else {
+        panic!("expected assembled assistant message");
+    };
+    assert_eq!(assistant.content.len(), 2);
+    match &assistant.content[0] {
+        ContentBlock::Text(text) => assert_eq!(text.text, "Hello, world!"),
+        other => panic!("expected text block, got {:?}", other),
+    }
+    match &assistant.content[1] {
+        ContentBlock::ToolUse(tool) => {
+            assert_eq!(tool.id, "tool-1");
+            assert_eq!(tool.name, "Read");
+            assert_eq!(tool.input, json!({"file_path": "/tmp/a.txt"}));
+        },
+        other => panic!("expected tool_use block, got {:?}", other),
+    }
+}
+
+#[test]
+fn accumulator_unwraps_stream_events() {
+    let mut accumulator = StreamAccumulator::new();
+    let mut outputs = Vec::new();
+    for message in delta_sequence() {
+        let wrapped = parse(json!({
+            "type": "stream_event",
+            "uuid": "evt-1",
+            "session_id": "sess-1",
+            "event": serde_json::to_value(&message).unwrap(),
+            "parent_tool_use_id": "parent-1"
+        }));
+        if let Some(out) = accumulator.push(wrapped) {
+            outputs.push(out.unwrap());
+        }
+    }
+
+    assert_eq!(outputs.len(), 1);
+    let Message::Assistant(assistant) = &outputs[0] else {
+        panic!("expected assembled assistant message");
+    };
+    assert_eq!(assistant.parent_tool_use_id.as_deref(), Some("parent-1"));
+    assert_eq!(assistant.content.len(), 2);
+}
+
+#[test]
+fn accumulator_passes_through_complete_messages() {
+    let mut accumulator = StreamAccumulator::new();
+    let result = parse(json!({
+        "type": "result",
+        "subtype": "success",
+        "duration_ms": 10,
+        "duration_api_ms": 5,
+        "is_error": false,
+        "num_turns": 1,
+        "session_id": "sess-1"
+    }));
+    let out = accumulator.push(result).unwrap().unwrap();
+    assert!(matches!(out, Message::Result(_)));
+}
+
+#[test]
+fn accumulator_reports_invalid_tool_json() {
+    let mut accumulator = StreamAccumulator::new();
+    let messages = vec![
+        parse(json!({
+            "type": "content_block_start",
+            "index": 0,
+            "content_block": {"type": "tool_use", "id": "tool-1", "name": "Bash", "input": {}}
+        })),
+        parse(json!({
+            "type": "content_block_delta",
+            "index": 0,
+            "delta": {"type": "input_json_delta", "partial_json": "{\"command\": "}
+        })),
+        parse(json!({"type": "message_stop"})),
+    ];
+    let outputs: Vec<_> = messages.into_iter().filter_map(|m| accumulator.push(m)).collect();
+    assert_eq!(outputs.len(), 1);
+    assert!(matches!(outputs[0], Err(ClaudeAgentError::MessageParse(_))));
+}
+
+#[tokio::test]
+async fn accumulator_wraps_stream() {
+    let (sender, receiver) = message_channel(32);
+    for message in delta_sequence() {
+        sender.send(message).await.unwrap();
+    }
+    drop(sender);
+
+    let mut stream = StreamAccumulator::wrap(receiver.into_stream());
+    let first = stream.next().await.unwrap().unwrap();
+    match first {
+        Message::Assistant(assistant) => assert_eq!(assistant.content.len(), 2),
+        other => panic!("expected assistant message, got {:?}", other),
+    }
+    assert!(stream.next().await.is_none());
+}
@@ -0,0 +1,151 @@
+//! Tests that agent errors are reported through `tracing` inside session spans.
+
+use async_trait::async_trait;
+use claude_agent::core::ClaudeAgent;
+use claude_agent::transport::Transport;
+use claude_agent::types::ClaudeAgentError;
+use claude_agent::ClaudeAgentOptions;
+use futures::stream::BoxStream;
+use serde_json::json;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::field::{Field, Visit};
+use tracing_subscriber::layer::{Context, SubscriberExt};
+use tracing_subscriber::Layer;
+
+mod common_core;
+use common_core::MockTransport;
+
+/// A captured event: level, message, and the names of enclosing spans.
+#[derive(Debug, Clone)]
+struct CapturedEvent {
+    level: tracing::Level,
+    message: String,
+    spans: Vec<String>,
+}
+
+#[derive(Clone, Default)]
+struct CaptureLayer {
+    events: Arc<Mutex<Vec<CapturedEvent>>>,
+}
+
+struct MessageVisitor(String);
+
+impl Visit for MessageVisitor {
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        if field.name() == "message" {
+            self.0 = format!("{:?}", value);
+        }
+    }
+}
+
+impl<S> Layer<S> for CaptureLayer
+where
+    S: tracing::Subscriber + for<'a> tracing_subscriber::registry::LookupSpan<'a>,
+{
+    fn on_event(&self, event: &tracing::Event<'_>, ctx: Context<'_, S>) {
+        let mut visitor = MessageVisitor(String::new());
+        event.record(&mut visitor);
+        let spans = ctx
+            .event_scope(event)
+            .map(|scope| scope.map(|span| span.name().to_string()).collect())
+            .unwrap_or_default();
+        self.events.lock().unwrap().push(CapturedEvent {
+            level: *event.metadata().level(),
+            message: visitor.0,
+            spans,
+        });
+    }
+}
+
+/// Accepts reads from the inner mock but fails every write.
+struct FailingWriteTransport {
+    inner: MockTransport,
+}
+
+#[async_trait]
+impl Transport for FailingWriteTransport {
+    async fn connect(&mut self) -> Result<(), ClaudeAgentError> {
+        Ok(())
+    }
+
+    async fn write(&self, _message: &str) -> Result<(), ClaudeAgentError> {
+        Err(ClaudeAgentError::Transport("broken pipe".to_string()))
+    }
+
+    async fn read_messages(&self) -> BoxStream<'_, Result<serde_json::Value, ClaudeAgentError>> {
+        self.inner.read_messages().await
+    }
+
+    async fn close(&mut self) -> Result<(), ClaudeAgentError> {
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn test_control_loop_write_error_emits_event_in_span() {
+    let layer = CaptureLayer::default();
+    let events = layer.events.clone();
+    let _guard = tracing::subscriber::set_default(tracing_subscriber::registry().with(layer));
+
+    let inner = MockTransport::new();
+    let mut agent = ClaudeAgent::new(ClaudeAgentOptions {
+        session_id: Some("traced-session".to_string()),
+        ..Default::default()
+    });
+    agent.set_transport(Box::new(FailingWriteTransport { inner: inner.clone() }));
+    agent.connect(None).await.expect("Connect should succeed");
+    tokio::time::sleep(Duration::from_millis(20)).await;
+
+    inner
+        .push_incoming(json!({
+            "type": "control_request",
+            "request_id": "req-1",
+            "request": {"subtype": "unknown_subtype"}
+        }))
+        .await;
+
+    let event = tokio::time::timeout(Duration::from_secs(2), async {
+        loop {
+            let found = events
+                .lock()
+                .unwrap()
+                .iter()
+                .find(|e| e.message.contains("Control loop write response error"))
+                .cloned();
+            if let Some(event) = found {
+                return event;
+            }
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+    })
+    .await
+    .expect("write error should be logged");
+
+    assert_eq!(event.level, tracing::Level::ERROR);
+    assert!(event.spans.contains(&"control_loop".to_string()));
+    assert!(event.spans.contains(&"connect".to_string()));
+
+    agent.disconnect().await.expect("Disconnect should succeed");
+}
+
+#[tokio::test]
+async fn test_query_write_error_emits_event() {
+    let layer = CaptureLayer::default();
+    let events = layer.events.clone();
+    let _guard = tracing::subscriber::set_default(tracing_subscriber::registry().with(layer));
+
+    let mut agent = ClaudeAgent::new(ClaudeAgentOptions::default());
+    agent.set_transport(Box::new(FailingWriteTransport { inner: MockTransport::new() }));
+    agent.connect(None).await.expect("Connect should succeed");
+
+    assert!(agent.query("hello").await.is_err());
+
+    let events = events.lock().unwrap();
+    let event = events
+        .iter()
+        .find(|e| e.message.contains("Failed to write prompt"))
+        .expect("prompt write error should be logged");
+    assert_eq!(event.level, tracing::Level::ERROR);
+    assert!(event.spans.contains(&"query".to_string()));
+}
@@ -8,3 +8,39 @@ async fn test_transport_instantiation() {
     // We can't easily test connect() here without a real Claude binary or complex mocking
     // but at least we verify the public API compiles and runs.
 }
+
+#[tokio::test]
+async fn test_mock_transport_replays_turns_in_order() {
+    use claude_agent::transport::{MockTransport, Transport};
+    use futures::StreamExt;
+    use serde_json::json;
+
+    let mut transport =
+        MockTransport::with_turns(vec![vec![json!({"turn": 1})], vec![json!({"turn": 2})]]);
+    let user = json!({"type": "user", "message": {"role": "user", "content": []}}).to_string();
+
+    transport.write(&user).await.unwrap();
+    let first = transport.read_messages().await.next().await.unwrap().unwrap();
+    assert_eq!(first, json!({"turn": 1}));
+
+    transport.write(&user).await.unwrap();
+    let second = transport.read_messages().await.next().await.unwrap().unwrap();
+    assert_eq!(second, json!({"turn": 2}));
+
+    assert_eq!(transport.sent_messages().len(), 2);
+    transport.close().await.unwrap();
+    let remaining: Vec<_> = transport.read_messages().await.collect().await;
+    assert_eq!(remaining.len(), 1, "closed streams drain the current turn, then end");
+}
+
+#[tokio::test]
+async fn test_default_write_bytes_requires_utf8() {
+    use claude_agent::transport::{MockTransport, Transport};
+
+    let transport = MockTransport::new(vec![]);
+    transport.write_bytes(b"{\"type\":\"ping\"}").await.unwrap();
+    assert_eq!(transport.sent_messages(), vec!["{\"type\":\"ping\"}".to_string()]);
+
+    let err = transport.write_bytes(&[0xff, 0xfe]).await.unwrap_err();
+    assert!(err.to_string().contains("not UTF-8"));
+}
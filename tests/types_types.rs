@@ -110,6 +110,7 @@ fn test_result_message() {
         usage: None,
         result: None,
         structured_output: None,
+        permission_denials: None,
     };
 
     assert_eq!(msg.subtype, "success");